@@ -82,6 +82,15 @@ pub struct Channels {
     pub outs: c_long,
 }
 
+/// A clock source of the ASIO driver, e.g. internal, word clock or S/PDIF.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ClockSource {
+    /// The reference passed to `Driver::set_clock_source`.
+    pub index: c_long,
+    pub name: String,
+    pub is_current: bool,
+}
+
 /// Sample rate of the ASIO driver.
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct SampleRate {
@@ -475,6 +484,38 @@ impl Driver {
         Ok(())
     }
 
+    /// The clock sources of the driver.
+    pub fn clock_sources(&self) -> Result<Vec<ClockSource>, AsioError> {
+        // The SDK lets drivers report at most as many sources as the array holds.
+        let mut clocks: [ai::ASIOClockSource; 32] = unsafe { std::mem::zeroed() };
+        let mut num_sources = clocks.len() as c_long;
+        unsafe {
+            asio_result!(ai::ASIOGetClockSources(
+                clocks.as_mut_ptr(),
+                &mut num_sources
+            ))?;
+        }
+        let num_sources = num_sources.clamp(0, clocks.len() as c_long) as usize;
+        Ok(clocks[..num_sources]
+            .iter()
+            .map(|clock| ClockSource {
+                index: clock.index,
+                name: unsafe { CStr::from_ptr(clock.name.as_ptr()) }
+                    .to_string_lossy()
+                    .into_owned(),
+                is_current: clock.isCurrentSource != 0,
+            })
+            .collect())
+    }
+
+    /// Make the driver run on the clock source with `index`.
+    pub fn set_clock_source(&self, index: c_long) -> Result<(), AsioError> {
+        unsafe {
+            asio_result!(ai::ASIOSetClockSource(index))?;
+        }
+        Ok(())
+    }
+
     /// Get the current data type of the driver's input stream.
     ///
     /// This queries a single channel's type assuming all channels have the same sample type.
@@ -21,6 +21,10 @@ fn main() -> Result<(), anyhow::Error> {
         println!("  Devices: ");
         for (device_index, device) in devices.enumerate() {
             println!("  {}. \"{}\"", device_index + 1, device.name()?);
+            println!("    Id: {}", device.id()?);
+            if host.is_default_input_device(&device) || host.is_default_output_device(&device) {
+                println!("    (default)");
+            }
 
             // Input configs
             if let Ok(conf) = device.default_input_config() {
@@ -94,18 +94,22 @@ pub fn stream_setup_for() -> Result<cpal::Stream, anyhow::Error>
 where
 {
     let (_host, device, config) = host_device_setup()?;
+    let stream_config = config
+        .config()
+        .with_usage(cpal::StreamUsage::Media)
+        .with_name("synth_tones");
 
     match config.sample_format() {
-        cpal::SampleFormat::I8 => make_stream::<i8>(&device, &config.into()),
-        cpal::SampleFormat::I16 => make_stream::<i16>(&device, &config.into()),
-        cpal::SampleFormat::I32 => make_stream::<i32>(&device, &config.into()),
-        cpal::SampleFormat::I64 => make_stream::<i64>(&device, &config.into()),
-        cpal::SampleFormat::U8 => make_stream::<u8>(&device, &config.into()),
-        cpal::SampleFormat::U16 => make_stream::<u16>(&device, &config.into()),
-        cpal::SampleFormat::U32 => make_stream::<u32>(&device, &config.into()),
-        cpal::SampleFormat::U64 => make_stream::<u64>(&device, &config.into()),
-        cpal::SampleFormat::F32 => make_stream::<f32>(&device, &config.into()),
-        cpal::SampleFormat::F64 => make_stream::<f64>(&device, &config.into()),
+        cpal::SampleFormat::I8 => make_stream::<i8>(&device, &stream_config),
+        cpal::SampleFormat::I16 => make_stream::<i16>(&device, &stream_config),
+        cpal::SampleFormat::I32 => make_stream::<i32>(&device, &stream_config),
+        cpal::SampleFormat::I64 => make_stream::<i64>(&device, &stream_config),
+        cpal::SampleFormat::U8 => make_stream::<u8>(&device, &stream_config),
+        cpal::SampleFormat::U16 => make_stream::<u16>(&device, &stream_config),
+        cpal::SampleFormat::U32 => make_stream::<u32>(&device, &stream_config),
+        cpal::SampleFormat::U64 => make_stream::<u64>(&device, &stream_config),
+        cpal::SampleFormat::F32 => make_stream::<f32>(&device, &stream_config),
+        cpal::SampleFormat::F64 => make_stream::<f64>(&device, &stream_config),
         sample_format => Err(anyhow::Error::msg(format!(
             "Unsupported sample format '{sample_format}'"
         ))),
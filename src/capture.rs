@@ -0,0 +1,131 @@
+//! Recording by reading the captured audio from a queue, see [`CaptureVoice`].
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use ringbuf::traits::{Consumer, Observer, Producer, Split};
+use ringbuf::{HeapCons, HeapRb};
+
+use crate::traits::{DeviceTrait, StreamTrait};
+use crate::{
+    BuildStreamError, Device, InputCallbackInfo, PauseStreamError, PlayStreamError, SizedSample,
+    Stream, StreamConfig, StreamError,
+};
+
+/// An input stream whose audio is read by the application with [`read_data`](Self::read_data)
+/// rather than handed to a data callback, e.g. to record from a microphone on the thread that
+/// writes the recording.
+///
+/// The input stream's data callback queues the captured samples, up to `capacity` frames, in a
+/// lock-free queue so the audio thread never waits for the application. When the application
+/// reads too slowly the newly captured frames that don't fit are dropped and counted by
+/// [`dropped_frames`](Self::dropped_frames).
+#[must_use = "If the voice is not stored it will not record."]
+pub struct CaptureVoice<T> {
+    stream: Stream,
+    queue: Mutex<HeapCons<T>>,
+    dropped_frames: Arc<AtomicU64>,
+    channels: usize,
+}
+
+impl<T> CaptureVoice<T>
+where
+    T: SizedSample + Send + 'static,
+{
+    /// Build an input stream on `device` that queues up to `capacity` frames of its audio.
+    ///
+    /// Like any stream the voice may need to be [played](Self::play) before it records.
+    pub fn build<E>(
+        device: &Device,
+        config: &StreamConfig,
+        capacity: usize,
+        error_callback: E,
+    ) -> Result<Self, BuildStreamError>
+    where
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let channels = match &config.input_channels {
+            Some(input_channels) => input_channels.len(),
+            None => config.channels as usize,
+        }
+        .max(1);
+        let (mut producer, queue) = HeapRb::new(capacity.max(1) * channels).split();
+        let dropped_frames = Arc::new(AtomicU64::new(0));
+        let dropped = dropped_frames.clone();
+        let stream = device.build_input_stream(
+            config,
+            move |data: &[T], _: &InputCallbackInfo| {
+                // Queue whole frames, so the queue stays aligned to the channels.
+                let len = data.len().min(producer.vacant_len()) / channels * channels;
+                producer.push_slice(&data[..len]);
+                let skipped = (data.len() - len) / channels;
+                dropped.fetch_add(skipped as u64, Ordering::Relaxed);
+            },
+            error_callback,
+            None,
+        )?;
+        Ok(CaptureVoice {
+            stream,
+            queue: Mutex::new(queue),
+            dropped_frames,
+            channels,
+        })
+    }
+
+    /// Move the oldest captured frames into `data` and return the number of samples written,
+    /// which is a multiple of the channel count.
+    pub fn read_data(&self, data: &mut [T]) -> usize {
+        let mut queue = self.queue.lock().unwrap();
+        let len = queue.occupied_len().min(data.len()) / self.channels * self.channels;
+        queue.pop_slice(&mut data[..len])
+    }
+
+    /// The number of captured frames waiting to be read.
+    pub fn available_frames(&self) -> usize {
+        self.queue.lock().unwrap().occupied_len() / self.channels
+    }
+
+    /// The number of frames dropped because the queue was full.
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames.load(Ordering::Relaxed)
+    }
+
+    /// Start recording.
+    pub fn play(&self) -> Result<(), PlayStreamError> {
+        self.stream.play()
+    }
+
+    /// Stop recording. The frames captured so far can still be read.
+    pub fn pause(&self) -> Result<(), PauseStreamError> {
+        self.stream.pause()
+    }
+
+    /// The input stream, e.g. to read its statistics.
+    pub fn stream(&self) -> &Stream {
+        &self.stream
+    }
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn test_capture_voice() {
+    use crate::traits::HostTrait;
+
+    let host = crate::platform::MockHost::new().unwrap();
+    let device = Device::from(host.default_input_device().unwrap());
+    let config = device.default_input_config().unwrap().config();
+    let voice = CaptureVoice::<f32>::build(&device, &config, 3, |_| ()).unwrap();
+    voice.play().unwrap();
+    let crate::platform::StreamInner::Mock(mock) = voice.stream().as_inner() else {
+        unreachable!()
+    };
+    assert!(mock.capture(&[1.0f32, 1.0, 2.0, 2.0]));
+    assert!(mock.capture(&[3.0f32, 3.0, 4.0, 4.0]));
+    assert_eq!(voice.available_frames(), 3);
+    assert_eq!(voice.dropped_frames(), 1);
+
+    let mut data = [0.0f32; 5];
+    assert_eq!(voice.read_data(&mut data), 4);
+    assert_eq!(data[..4], [1.0, 1.0, 2.0, 2.0]);
+    assert_eq!(voice.available_frames(), 1);
+}
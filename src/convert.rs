@@ -0,0 +1,957 @@
+//! Converting the output of the data callback from its own format to the stream's, see
+//! [`Stream::schedule_format_change`](crate::platform::Stream::schedule_format_change), and
+//! selecting the [`input_channels`](StreamConfig::input_channels) of input streams and the sample
+//! format conversion of
+//! [`build_output_stream_converted`](crate::traits::DeviceTrait::build_output_stream_converted).
+//!
+//! While the data callback renders in the stream's format it writes straight into the backend's
+//! buffer. Once it renders in another sample rate or channel count, its output is mapped to the
+//! stream's channels and resampled to the stream's rate by linear interpolation on the audio
+//! thread, so the device is never reconfigured and playback never stops.
+//!
+//! The buffers of the conversion are sized on the stream's thread when a change is scheduled, for
+//! the longest period the data callback was asked to fill so far, so the audio thread doesn't
+//! allocate.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::relay::Buffer;
+use crate::{
+    BufferSize, BuildStreamError, ChannelCount, Data, FromSample, InputCallbackInfo,
+    OutputCallbackInfo, SampleFormat, SampleRate, SizedSample, StreamConfig, SupportedBufferSize,
+    SupportedStreamConfigRange,
+};
+
+/// A change of the format the data callback renders in.
+#[derive(Clone, Copy, Debug)]
+struct Change {
+    /// The number of frames the data callback renders before the change.
+    frame: u64,
+    /// The new sample rate and channel count, `None` for the one rendered before the change.
+    sample_rate: Option<SampleRate>,
+    channels: Option<ChannelCount>,
+}
+
+/// The format changes scheduled on a stream, shared with its audio thread.
+#[derive(Debug, Default)]
+pub(crate) struct FormatChanges {
+    scheduled: Mutex<Scheduled>,
+    /// The largest period the data callback was asked to fill so far, in frames.
+    period: AtomicUsize,
+}
+
+#[derive(Debug, Default)]
+struct Scheduled {
+    /// The changes not taken by the audio thread yet, in the order of their frames.
+    changes: VecDeque<Change>,
+    /// The formats the buffers are sized for, `None` until the data callback is wrapped.
+    bounds: Option<Bounds>,
+    /// Buffers sized on the stream's thread for the scheduled changes, for the audio thread to
+    /// swap for its own.
+    buffers: Option<Buffers>,
+    /// The buffers the audio thread swapped out, freed with the next change.
+    retired: Option<Buffers>,
+}
+
+/// The stream's format and the extremes of the formats the data callback renders in.
+#[derive(Clone, Copy, Debug)]
+struct Bounds {
+    sample_format: SampleFormat,
+    sample_rate: u32,
+    channels: usize,
+    min_source_rate: u32,
+    max_source_rate: u32,
+    max_source_channels: usize,
+}
+
+/// The buffers of the conversion on the audio thread.
+#[derive(Debug, Default)]
+struct Buffers {
+    buffer: Buffer,
+    source: Vec<f32>,
+    mapped: Vec<f32>,
+    /// Converted samples rendered ahead of the current period.
+    queue: VecDeque<f32>,
+}
+
+impl Buffers {
+    /// Reserve what converting periods of up to `period` frames needs within `bounds`, so the
+    /// audio thread doesn't allocate.
+    fn reserve(&mut self, bounds: &Bounds, period: usize) {
+        let ceil_div = |a: usize, b: usize| (a + b - 1) / b;
+        let (rate, channels) = (bounds.sample_rate as usize, bounds.channels);
+        // The longest chunk the data callback renders for a period, see `Converter::render`,
+        // and the most frames it is resampled to on top of the frames already queued.
+        let chunk = period * ceil_div(bounds.max_source_rate as usize, rate) + 1;
+        let queue = 2 * period + 2 * ceil_div(rate, bounds.min_source_rate as usize) + 2;
+        let source = chunk * bounds.max_source_channels;
+        self.buffer
+            .reserve(source * bounds.sample_format.sample_size());
+        self.source
+            .reserve(source.saturating_sub(self.source.len()));
+        self.mapped
+            .reserve((chunk * channels).saturating_sub(self.mapped.len()));
+        self.queue
+            .reserve((queue * channels).saturating_sub(self.queue.len()));
+    }
+}
+
+impl FormatChanges {
+    pub(crate) fn schedule(&self, frame: u64, sample_rate: SampleRate, channels: ChannelCount) {
+        self.push(Change {
+            frame,
+            sample_rate: Some(sample_rate),
+            channels: Some(channels.max(1)),
+        });
+    }
+
+    /// Change the sample rate with the next buffer the data callback renders.
+    pub(crate) fn set_sample_rate(&self, sample_rate: SampleRate) {
+        self.push(Change {
+            frame: 0,
+            sample_rate: Some(sample_rate),
+            channels: None,
+        });
+    }
+
+    /// Change the channel count with the next buffer the data callback renders.
+    pub(crate) fn set_channels(&self, channels: ChannelCount) {
+        self.push(Change {
+            frame: 0,
+            sample_rate: None,
+            channels: Some(channels.max(1)),
+        });
+    }
+
+    /// Queue `change` in the order of the frames and size buffers for it, on the stream's thread.
+    fn push(&self, change: Change) {
+        let mut scheduled = self.scheduled.lock().unwrap();
+        scheduled.retired = None;
+        let index = scheduled
+            .changes
+            .partition_point(|queued| queued.frame <= change.frame);
+        scheduled.changes.insert(index, change);
+        let Some(bounds) = &mut scheduled.bounds else {
+            return;
+        };
+        if let Some(sample_rate) = change.sample_rate {
+            let sample_rate = sample_rate.0.max(1);
+            bounds.min_source_rate = bounds.min_source_rate.min(sample_rate);
+            bounds.max_source_rate = bounds.max_source_rate.max(sample_rate);
+        }
+        if let Some(channels) = change.channels {
+            bounds.max_source_channels = bounds.max_source_channels.max(channels as usize);
+        }
+        let bounds = *bounds;
+        let period = self.period.load(Ordering::Relaxed);
+        scheduled
+            .buffers
+            .get_or_insert_with(Buffers::default)
+            .reserve(&bounds, period);
+    }
+
+    /// Wrap an output data callback to convert its output to the stream's format once it renders
+    /// in another one.
+    pub(crate) fn output_callback<D>(
+        self: &Arc<Self>,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        mut data_callback: D,
+    ) -> impl FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+    {
+        let changes = self.clone();
+        let mut converter = Converter::new(config, sample_format);
+        if let BufferSize::Fixed(frames) = config.buffer_size {
+            self.period.fetch_max(frames as usize, Ordering::Relaxed);
+        }
+        self.scheduled.lock().unwrap().bounds = Some(Bounds {
+            sample_format,
+            sample_rate: converter.sample_rate,
+            channels: converter.channels,
+            min_source_rate: converter.sample_rate,
+            max_source_rate: converter.sample_rate,
+            max_source_channels: converter.channels,
+        });
+        move |data, info| {
+            let frames = data.len() / converter.channels;
+            changes.period.fetch_max(frames, Ordering::Relaxed);
+            // The audio thread never waits for the stream's thread, a change scheduled during
+            // this callback is picked up by the next one.
+            if let Ok(mut scheduled) = changes.scheduled.try_lock() {
+                converter.take(&mut scheduled);
+            }
+            converter.render(data, info, &mut data_callback);
+        }
+    }
+}
+
+/// The most changes the audio thread holds, the rest wait in the shared queue.
+const PENDING_CAPACITY: usize = 16;
+
+struct Converter {
+    sample_format: SampleFormat,
+    sample_rate: u32,
+    channels: usize,
+    /// The format the data callback currently renders in.
+    source_rate: u32,
+    source_channels: usize,
+    /// The frames rendered by the data callback so far.
+    frames: u64,
+    /// The changes not applied yet, in the order of their frames.
+    pending: VecDeque<Change>,
+    buffers: Buffers,
+    /// The last frame of the previous chunk and the position of the next output frame relative to
+    /// the first frame of the next chunk, between `-1.0` and `0.0`.
+    previous: Vec<f32>,
+    position: f64,
+}
+
+impl Converter {
+    fn new(config: &StreamConfig, sample_format: SampleFormat) -> Self {
+        let channels = config.channels.max(1) as usize;
+        Converter {
+            sample_format,
+            sample_rate: config.sample_rate.0.max(1),
+            channels,
+            source_rate: config.sample_rate.0.max(1),
+            source_channels: channels,
+            frames: 0,
+            pending: VecDeque::with_capacity(PENDING_CAPACITY),
+            buffers: Buffers::default(),
+            previous: Vec::with_capacity(channels),
+            position: 0.0,
+        }
+    }
+
+    /// Take the scheduled changes that fit in `pending` and the buffers sized for them, without
+    /// allocating.
+    fn take(&mut self, scheduled: &mut Scheduled) {
+        while self.pending.len() < self.pending.capacity() {
+            let Some(change) = scheduled.changes.pop_front() else {
+                break;
+            };
+            let index = self
+                .pending
+                .partition_point(|pending| pending.frame <= change.frame);
+            self.pending.insert(index, change);
+        }
+        if scheduled.retired.is_none() {
+            if let Some(mut buffers) = scheduled.buffers.take() {
+                buffers.queue.extend(self.buffers.queue.drain(..));
+                scheduled.retired = Some(std::mem::replace(&mut self.buffers, buffers));
+            }
+        }
+    }
+
+    fn render<D>(&mut self, data: &mut Data, info: &OutputCallbackInfo, data_callback: &mut D)
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo),
+    {
+        let frames = (data.len() / self.channels) as u64;
+        let unchanged =
+            self.source_rate == self.sample_rate && self.source_channels == self.channels;
+        let change_due = self
+            .pending
+            .front()
+            .is_some_and(|change| change.frame < self.frames + frames);
+        if unchanged && self.buffers.queue.is_empty() && !change_due {
+            self.previous.clear();
+            self.position = 0.0;
+            data_callback(data, info);
+            self.frames += frames;
+            return;
+        }
+
+        while self.buffers.queue.len() < data.len() {
+            while let Some(change) = self.pending.front().filter(|c| c.frame <= self.frames) {
+                if let Some(sample_rate) = change.sample_rate {
+                    self.source_rate = sample_rate.0.max(1);
+                }
+                if let Some(channels) = change.channels {
+                    self.source_channels = channels as usize;
+                }
+                self.pending.pop_front();
+            }
+            // Render enough to fill the period, but stop at the next change so that each buffer
+            // passed to the data callback is in one format.
+            let missing = ((data.len() - self.buffers.queue.len()) / self.channels) as u64;
+            let (rate, source_rate) = (self.sample_rate as u64, self.source_rate as u64);
+            let mut chunk = (missing * source_rate + rate - 1) / rate;
+            if rate != source_rate {
+                chunk += 1;
+            }
+            if let Some(change) = self.pending.front() {
+                chunk = chunk.min(change.frame - self.frames);
+            }
+            let len = chunk as usize * self.source_channels;
+            let bytes = len * self.sample_format.sample_size();
+            let mut source = self.buffers.buffer.data(bytes, self.sample_format);
+            data_callback(&mut source, info);
+            self.frames += chunk;
+            self.buffers.source.clear();
+            read(&source, &mut self.buffers.source);
+            self.map_channels();
+            self.resample();
+        }
+        let len = data.len();
+        write(self.buffers.queue.drain(..len), data);
+    }
+
+    /// Map the frames of `source` to the stream's channels in `mapped`: mono is copied to every
+    /// channel, everything is averaged to mono, and otherwise channels are matched by index.
+    fn map_channels(&mut self) {
+        self.buffers.mapped.clear();
+        for frame in self.buffers.source.chunks_exact(self.source_channels) {
+            if self.source_channels == self.channels {
+                self.buffers.mapped.extend_from_slice(frame);
+            } else if self.source_channels == 1 {
+                self.buffers
+                    .mapped
+                    .extend(std::iter::repeat(frame[0]).take(self.channels));
+            } else if self.channels == 1 {
+                self.buffers
+                    .mapped
+                    .push(frame.iter().sum::<f32>() / self.source_channels as f32);
+            } else {
+                self.buffers.mapped.extend(
+                    (0..self.channels).map(|channel| frame.get(channel).copied().unwrap_or(0.0)),
+                );
+            }
+        }
+    }
+
+    /// Resample the frames of `mapped` from the source rate to the stream's rate into the queue.
+    fn resample(&mut self) {
+        let channels = self.channels;
+        let frames = self.buffers.mapped.len() / channels;
+        if frames == 0 {
+            return;
+        }
+        if self.previous.len() != channels {
+            self.previous.clear();
+            self.previous
+                .extend_from_slice(&self.buffers.mapped[..channels]);
+        }
+        let step = self.source_rate as f64 / self.sample_rate as f64;
+        while self.position <= (frames - 1) as f64 {
+            let index = self.position.floor();
+            let fraction = (self.position - index) as f32;
+            let index = index as isize;
+            for channel in 0..channels {
+                let a = match index {
+                    -1 => self.previous[channel],
+                    index => self.buffers.mapped[index as usize * channels + channel],
+                };
+                let b = match index + 1 {
+                    next if (next as usize) < frames => {
+                        self.buffers.mapped[next as usize * channels + channel]
+                    }
+                    _ => a,
+                };
+                self.buffers.queue.push_back(a + (b - a) * fraction);
+            }
+            self.position += step;
+        }
+        self.position -= frames as f64;
+        self.previous.clear();
+        self.previous
+            .extend_from_slice(&self.buffers.mapped[(frames - 1) * channels..]);
+    }
+}
+
+/// Wrap an input data callback to pass it only the
+/// [`input_channels`](StreamConfig::input_channels) of the config, if it selects any.
+pub(crate) fn input_channels<D>(
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    mut data_callback: D,
+) -> Result<impl FnMut(&Data, &InputCallbackInfo) + Send + 'static, BuildStreamError>
+where
+    D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+{
+    let selected = config.input_channels.clone();
+    if let Some(selected) = &selected {
+        if selected.iter().any(|&channel| channel >= config.channels) {
+            return Err(BuildStreamError::InvalidArgument);
+        }
+    }
+    let sample_size = sample_format.sample_size();
+    let frame_size = config.channels.max(1) as usize * sample_size;
+    let mut buffer = Buffer::default();
+    Ok(move |data: &Data, info: &InputCallbackInfo| {
+        let Some(selected) = &selected else {
+            return data_callback(data, info);
+        };
+        let frames = data.bytes().len() / frame_size;
+        let mut subset = buffer.data(frames * selected.len() * sample_size, sample_format);
+        let out = subset.bytes_mut().chunks_exact_mut(sample_size);
+        let samples = data.bytes().chunks_exact(frame_size).flat_map(|frame| {
+            selected.iter().map(move |&channel| {
+                let start = channel as usize * sample_size;
+                &frame[start..start + sample_size]
+            })
+        });
+        for (out, sample) in out.zip(samples) {
+            out.copy_from_slice(sample);
+        }
+        data_callback(&subset, info);
+    })
+}
+
+/// The sample format to open the device in for a stream of `config` whose data callback uses
+/// `sample_format`: that one if the device supports it, otherwise `f32` or the first one the device
+/// supports with the config's channels and sample rate.
+pub(crate) fn native_format(
+    ranges: &[SupportedStreamConfigRange],
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+) -> Option<SampleFormat> {
+    let formats: Vec<_> = ranges
+        .iter()
+        .filter(|range| supports(range, config))
+        .map(|range| range.sample_format)
+        .collect();
+    [sample_format, SampleFormat::F32]
+        .into_iter()
+        .find(|format| formats.contains(format))
+        .or_else(|| formats.first().copied())
+}
+
+fn supports(range: &SupportedStreamConfigRange, config: &StreamConfig) -> bool {
+    range.channels == config.channels
+        && range.min_sample_rate <= config.sample_rate
+        && config.sample_rate <= range.max_sample_rate
+}
+
+/// The period reserved for when neither the config nor the device bounds it, in frames.
+const UNBOUNDED_PERIOD: usize = 4096;
+
+/// The longest period in frames a stream of `config` opened in `sample_format` passes its data
+/// callback, as far as the config or the device's `ranges` bound it.
+pub(crate) fn max_period(
+    ranges: &[SupportedStreamConfigRange],
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+) -> usize {
+    match config.buffer_size {
+        BufferSize::Fixed(frames) => frames as usize,
+        BufferSize::Default => ranges
+            .iter()
+            .filter(|range| range.sample_format == sample_format && supports(range, config))
+            .map(|range| match range.buffer_size {
+                SupportedBufferSize::Range { max, .. } => max as usize,
+                SupportedBufferSize::Unknown => UNBOUNDED_PERIOD,
+            })
+            .max()
+            .unwrap_or(UNBOUNDED_PERIOD),
+    }
+}
+
+/// Reserve `buffer` for the samples of type `T` of the longest period a stream of `config` opened
+/// in `sample_format` passes its data callback, so the audio thread doesn't allocate.
+fn reserve<T: SizedSample>(
+    buffer: &mut Buffer,
+    ranges: &[SupportedStreamConfigRange],
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+) {
+    let period = max_period(ranges, config, sample_format);
+    buffer.reserve(period * config.channels as usize * std::mem::size_of::<T>());
+}
+
+/// Wrap an output data callback that renders samples of type `T` to write them to the device's
+/// buffer in `sample_format`, which the device is opened in.
+pub(crate) fn output_samples<T, D>(
+    ranges: &[SupportedStreamConfigRange],
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    mut data_callback: D,
+) -> impl FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static
+where
+    T: SizedSample + Send + 'static,
+    D: FnMut(&mut [T], &OutputCallbackInfo) + Send + 'static,
+{
+    let mut buffer = Buffer::default();
+    reserve::<T>(&mut buffer, ranges, config, sample_format);
+    move |data, info| {
+        let mut samples = buffer.data(data.len() * T::FORMAT.sample_size(), T::FORMAT);
+        let slice = samples.as_slice_mut::<T>().unwrap();
+        slice.fill(T::EQUILIBRIUM);
+        data_callback(slice, info);
+        convert(&samples, data);
+    }
+}
+
+/// Wrap an input data callback that takes samples of type `T` to pass it the device's samples
+/// converted from `sample_format`, which the device is opened in.
+pub(crate) fn input_samples<T, D>(
+    ranges: &[SupportedStreamConfigRange],
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    mut data_callback: D,
+) -> impl FnMut(&Data, &InputCallbackInfo) + Send + 'static
+where
+    T: SizedSample + Send + 'static,
+    D: FnMut(&[T], &InputCallbackInfo) + Send + 'static,
+{
+    let mut buffer = Buffer::default();
+    reserve::<T>(&mut buffer, ranges, config, sample_format);
+    move |data, info| {
+        let mut samples = buffer.data(data.len() * T::FORMAT.sample_size(), T::FORMAT);
+        convert(data, &mut samples);
+        data_callback(samples.as_slice::<T>().unwrap(), info);
+    }
+}
+
+/// Convert the samples of `source` to the sample format of `out`.
+///
+/// Each pair of formats converts directly, so integer samples don't lose precision to `f32`.
+fn convert(source: &Data, out: &mut Data) {
+    fn samples<S, U>(source: &Data, out: &mut Data)
+    where
+        S: SizedSample,
+        U: SizedSample + FromSample<S>,
+    {
+        if let (Some(source), Some(out)) = (source.as_slice::<S>(), out.as_slice_mut::<U>()) {
+            for (out, &sample) in out.iter_mut().zip(source) {
+                *out = U::from_sample(sample);
+            }
+        }
+    }
+    macro_rules! to {
+        ($source:ty) => {
+            match out.sample_format() {
+                SampleFormat::I8 => samples::<$source, i8>(source, out),
+                SampleFormat::I16 => samples::<$source, i16>(source, out),
+                SampleFormat::I32 => samples::<$source, i32>(source, out),
+                SampleFormat::I64 => samples::<$source, i64>(source, out),
+                SampleFormat::U8 => samples::<$source, u8>(source, out),
+                SampleFormat::U16 => samples::<$source, u16>(source, out),
+                SampleFormat::U32 => samples::<$source, u32>(source, out),
+                SampleFormat::U64 => samples::<$source, u64>(source, out),
+                SampleFormat::F32 => samples::<$source, f32>(source, out),
+                SampleFormat::F64 => samples::<$source, f64>(source, out),
+            }
+        };
+    }
+    match source.sample_format() {
+        SampleFormat::I8 => to!(i8),
+        SampleFormat::I16 => to!(i16),
+        SampleFormat::I32 => to!(i32),
+        SampleFormat::I64 => to!(i64),
+        SampleFormat::U8 => to!(u8),
+        SampleFormat::U16 => to!(u16),
+        SampleFormat::U32 => to!(u32),
+        SampleFormat::U64 => to!(u64),
+        SampleFormat::F32 => to!(f32),
+        SampleFormat::F64 => to!(f64),
+    }
+}
+
+/// Append the samples of `data` to `out` as `f32`.
+fn read(data: &Data, out: &mut Vec<f32>) {
+    fn extend<T>(data: &Data, out: &mut Vec<f32>)
+    where
+        T: SizedSample,
+        f32: FromSample<T>,
+    {
+        if let Some(samples) = data.as_slice::<T>() {
+            out.extend(samples.iter().map(|&sample| sample.to_sample::<f32>()));
+        }
+    }
+    match data.sample_format() {
+        SampleFormat::I8 => extend::<i8>(data, out),
+        SampleFormat::I16 => extend::<i16>(data, out),
+        SampleFormat::I32 => extend::<i32>(data, out),
+        SampleFormat::I64 => extend::<i64>(data, out),
+        SampleFormat::U8 => extend::<u8>(data, out),
+        SampleFormat::U16 => extend::<u16>(data, out),
+        SampleFormat::U32 => extend::<u32>(data, out),
+        SampleFormat::U64 => extend::<u64>(data, out),
+        SampleFormat::F32 => extend::<f32>(data, out),
+        SampleFormat::F64 => extend::<f64>(data, out),
+    }
+}
+
+/// Write `samples` to `data` in its sample format.
+fn write(samples: impl Iterator<Item = f32>, data: &mut Data) {
+    fn fill<T>(samples: impl Iterator<Item = f32>, data: &mut Data)
+    where
+        T: SizedSample + FromSample<f32>,
+    {
+        if let Some(out) = data.as_slice_mut::<T>() {
+            for (out, sample) in out.iter_mut().zip(samples) {
+                *out = T::from_sample(sample);
+            }
+        }
+    }
+    match data.sample_format() {
+        SampleFormat::I8 => fill::<i8>(samples, data),
+        SampleFormat::I16 => fill::<i16>(samples, data),
+        SampleFormat::I32 => fill::<i32>(samples, data),
+        SampleFormat::I64 => fill::<i64>(samples, data),
+        SampleFormat::U8 => fill::<u8>(samples, data),
+        SampleFormat::U16 => fill::<u16>(samples, data),
+        SampleFormat::U32 => fill::<u32>(samples, data),
+        SampleFormat::U64 => fill::<u64>(samples, data),
+        SampleFormat::F32 => fill::<f32>(samples, data),
+        SampleFormat::F64 => fill::<f64>(samples, data),
+    }
+}
+
+#[test]
+fn test_format_change_at_frame() {
+    let config = crate::SupportedStreamConfig::new(
+        1,
+        SampleRate(100),
+        crate::SupportedBufferSize::Unknown,
+        SampleFormat::F32,
+    )
+    .config();
+    let changes = Arc::new(FormatChanges::default());
+    changes.schedule(2, SampleRate(50), 1);
+    let lens = Arc::new(Mutex::new(Vec::new()));
+    let rendered = lens.clone();
+    let mut next = 0.0;
+    let mut callback = changes.output_callback(
+        &config,
+        SampleFormat::F32,
+        move |data: &mut Data, _: &OutputCallbackInfo| {
+            rendered.lock().unwrap().push(data.len());
+            for sample in data.as_slice_mut::<f32>().unwrap() {
+                *sample = next;
+                next += 1.0;
+            }
+        },
+    );
+    let timestamp = crate::OutputStreamTimestamp {
+        callback: crate::StreamInstant::new(0, 0),
+        playback: crate::StreamInstant::new(0, 0),
+    };
+    let mut samples = [0f32; 4];
+    let mut data = unsafe {
+        Data::from_parts(
+            samples.as_mut_ptr() as *mut (),
+            samples.len(),
+            SampleFormat::F32,
+        )
+    };
+    callback(&mut data, &OutputCallbackInfo { timestamp });
+    // The first buffer ends at the change, after which each frame lasts two frames of the stream.
+    assert_eq!(*lens.lock().unwrap(), [2, 2]);
+    assert_eq!(samples, [0.0, 1.0, 2.0, 2.5]);
+}
+
+#[test]
+fn test_zero_channels_and_rate_are_clamped() {
+    let config = crate::SupportedStreamConfig::new(
+        2,
+        SampleRate(100),
+        crate::SupportedBufferSize::Unknown,
+        SampleFormat::F32,
+    )
+    .config();
+    let changes = Arc::new(FormatChanges::default());
+    changes.set_channels(0);
+    changes.set_sample_rate(SampleRate(0));
+    changes.schedule(1, SampleRate(100), 0);
+    let mut callback = changes.output_callback(
+        &config,
+        SampleFormat::F32,
+        |data: &mut Data, _: &OutputCallbackInfo| data.as_slice_mut::<f32>().unwrap().fill(1.0),
+    );
+    let timestamp = crate::OutputStreamTimestamp {
+        callback: crate::StreamInstant::new(0, 0),
+        playback: crate::StreamInstant::new(0, 0),
+    };
+    let mut samples = [0f32; 8];
+    let mut data = unsafe {
+        Data::from_parts(
+            samples.as_mut_ptr() as *mut (),
+            samples.len(),
+            SampleFormat::F32,
+        )
+    };
+    callback(&mut data, &OutputCallbackInfo { timestamp });
+    // The callback renders mono, which is played on both channels.
+    assert_eq!(samples, [1.0; 8]);
+}
+
+/// Render `periods` periods of `frames` frames of a stream of `channels` channels through
+/// `callback` and return the output.
+#[cfg(test)]
+fn render_periods(
+    callback: &mut impl FnMut(&mut Data, &OutputCallbackInfo),
+    channels: usize,
+    frames: usize,
+    periods: usize,
+) -> Vec<f32> {
+    let timestamp = crate::OutputStreamTimestamp {
+        callback: crate::StreamInstant::new(0, 0),
+        playback: crate::StreamInstant::new(0, 0),
+    };
+    let mut output = Vec::new();
+    for _ in 0..periods {
+        let mut samples = vec![0f32; frames * channels];
+        let mut data = unsafe {
+            Data::from_parts(
+                samples.as_mut_ptr() as *mut (),
+                samples.len(),
+                SampleFormat::F32,
+            )
+        };
+        callback(&mut data, &OutputCallbackInfo { timestamp });
+        output.extend(samples);
+    }
+    output
+}
+
+#[test]
+fn test_downmix_to_mono() {
+    let config = crate::SupportedStreamConfig::new(
+        1,
+        SampleRate(100),
+        crate::SupportedBufferSize::Unknown,
+        SampleFormat::F32,
+    )
+    .config();
+    let changes = Arc::new(FormatChanges::default());
+    let mut callback = changes.output_callback(
+        &config,
+        SampleFormat::F32,
+        |data: &mut Data, _: &OutputCallbackInfo| {
+            for frame in data.as_slice_mut::<f32>().unwrap().chunks_mut(3) {
+                frame.copy_from_slice(&[0.3, 0.6, 0.9]);
+            }
+        },
+    );
+    changes.set_channels(3);
+    let output = render_periods(&mut callback, 1, 4, 2);
+    assert!(output.iter().all(|&sample| (sample - 0.6).abs() < 1e-6));
+}
+
+#[test]
+fn test_upmix_from_mono() {
+    let config = crate::SupportedStreamConfig::new(
+        4,
+        SampleRate(100),
+        crate::SupportedBufferSize::Unknown,
+        SampleFormat::F32,
+    )
+    .config();
+    let changes = Arc::new(FormatChanges::default());
+    let mut next = 0.0;
+    let mut callback = changes.output_callback(
+        &config,
+        SampleFormat::F32,
+        move |data: &mut Data, _: &OutputCallbackInfo| {
+            for sample in data.as_slice_mut::<f32>().unwrap() {
+                next += 1.0;
+                *sample = next;
+            }
+        },
+    );
+    changes.set_channels(1);
+    let output = render_periods(&mut callback, 4, 2, 2);
+    let frames: Vec<_> = output.chunks(4).collect();
+    assert_eq!(frames, [[1.0; 4], [2.0; 4], [3.0; 4], [4.0; 4]]);
+}
+
+#[test]
+fn test_rate_change_mid_period() {
+    let config = crate::SupportedStreamConfig::new(
+        1,
+        SampleRate(100),
+        crate::SupportedBufferSize::Unknown,
+        SampleFormat::F32,
+    )
+    .config();
+    let changes = Arc::new(FormatChanges::default());
+    let mut next = 0.0;
+    let mut callback = changes.output_callback(
+        &config,
+        SampleFormat::F32,
+        move |data: &mut Data, _: &OutputCallbackInfo| {
+            for sample in data.as_slice_mut::<f32>().unwrap() {
+                *sample = next;
+                next += 1.0;
+            }
+        },
+    );
+    // Halfway through the second period the callback renders at twice the stream's rate, so
+    // every other frame is played.
+    changes.schedule(6, SampleRate(200), 1);
+    let output = render_periods(&mut callback, 1, 4, 3);
+    assert_eq!(
+        output,
+        [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 8.0, 10.0, 12.0, 14.0, 16.0]
+    );
+}
+
+#[test]
+fn test_conversion_does_not_grow_buffers() {
+    let mut config = crate::SupportedStreamConfig::new(
+        2,
+        SampleRate(48_000),
+        crate::SupportedBufferSize::Unknown,
+        SampleFormat::F32,
+    )
+    .config();
+    config.buffer_size = BufferSize::Fixed(64);
+    let changes = Arc::new(FormatChanges::default());
+    let _ = changes.output_callback(&config, SampleFormat::F32, |_: &mut Data, _: &_| ());
+    changes.schedule(10, SampleRate(44_100), 1);
+    changes.schedule(300, SampleRate(96_000), 2);
+    changes.schedule(700, SampleRate(8_000), 6);
+
+    let mut converter = Converter::new(&config, SampleFormat::F32);
+    converter.take(&mut changes.scheduled.lock().unwrap());
+    let capacities = |buffers: &Buffers| {
+        (
+            buffers.source.capacity(),
+            buffers.mapped.capacity(),
+            buffers.queue.capacity(),
+        )
+    };
+    let reserved = capacities(&converter.buffers);
+    let mut callback = move |data: &mut Data, info: &OutputCallbackInfo| {
+        converter.render(data, info, &mut |source: &mut Data, _: &_| {
+            source.as_slice_mut::<f32>().unwrap().fill(0.5)
+        });
+        assert_eq!(capacities(&converter.buffers), reserved);
+    };
+    render_periods(&mut callback, 2, 64, 20);
+}
+
+#[test]
+fn test_input_channels_of_64() {
+    let mut config = crate::SupportedStreamConfig::new(
+        64,
+        SampleRate(48_000),
+        crate::SupportedBufferSize::Unknown,
+        SampleFormat::I32,
+    )
+    .config();
+    config.input_channels = Some(vec![41, 2]);
+    let captured = Arc::new(Mutex::new(Vec::new()));
+    let received = captured.clone();
+    let mut callback = input_channels(
+        &config,
+        SampleFormat::I32,
+        move |data: &Data, _: &InputCallbackInfo| {
+            received
+                .lock()
+                .unwrap()
+                .extend_from_slice(data.as_slice::<i32>().unwrap());
+        },
+    )
+    .unwrap();
+    // Two frames, each sample holding its frame and channel.
+    let mut samples: Vec<i32> = (0..2)
+        .flat_map(|frame| (0..64).map(move |channel| frame * 100 + channel))
+        .collect();
+    let data = unsafe {
+        Data::from_parts(
+            samples.as_mut_ptr() as *mut (),
+            samples.len(),
+            SampleFormat::I32,
+        )
+    };
+    let timestamp = crate::InputStreamTimestamp {
+        callback: crate::StreamInstant::new(0, 0),
+        capture: crate::StreamInstant::new(0, 0),
+    };
+    callback(&data, &InputCallbackInfo { timestamp });
+    assert_eq!(*captured.lock().unwrap(), [41, 2, 141, 102]);
+
+    config.input_channels = Some(vec![64]);
+    let result = input_channels(&config, SampleFormat::I32, |_: &Data, _: &_| ());
+    assert!(matches!(result, Err(BuildStreamError::InvalidArgument)));
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn test_sample_format_conversion() {
+    use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+    let host = crate::platform::MockHost::new().unwrap();
+    let device = crate::Device::from(host.default_output_device().unwrap());
+    let config = device.default_output_config().unwrap().config();
+    let stream = device
+        .build_output_stream_converted(
+            &config,
+            |data: &mut [i32], _: &OutputCallbackInfo| data.fill(i32::MAX / 2),
+            |_| (),
+            None,
+        )
+        .unwrap();
+    stream.play().unwrap();
+    let crate::platform::StreamInner::Mock(mock) = stream.as_inner() else {
+        unreachable!()
+    };
+    assert_eq!(mock.render::<i32>(1), None);
+    let rendered = mock.render::<f32>(1).unwrap();
+    assert!((rendered[0] - 0.5).abs() < 1e-6);
+}
+
+#[test]
+fn test_integer_conversion_keeps_precision() {
+    let config = crate::SupportedStreamConfig::new(
+        1,
+        SampleRate(48_000),
+        crate::SupportedBufferSize::Unknown,
+        SampleFormat::I64,
+    )
+    .config()
+    .with_buffer_size(BufferSize::Fixed(4));
+    let info = OutputCallbackInfo {
+        timestamp: crate::OutputStreamTimestamp {
+            callback: crate::StreamInstant::new(0, 0),
+            playback: crate::StreamInstant::new(0, 0),
+        },
+    };
+    let pointers = Arc::new(Mutex::new(Vec::new()));
+    let rendered = pointers.clone();
+    let mut callback = output_samples(
+        &[],
+        &config,
+        SampleFormat::I64,
+        move |samples: &mut [i32], _: &OutputCallbackInfo| {
+            rendered.lock().unwrap().push(samples.as_ptr() as usize);
+            samples.fill(0x1234_5679);
+        },
+    );
+    let mut output = [0i64; 4];
+    for _ in 0..3 {
+        let mut data =
+            unsafe { Data::from_parts(output.as_mut_ptr() as *mut (), 4, SampleFormat::I64) };
+        callback(&mut data, &info);
+        assert_eq!(output, [0x1234_5679 << 32; 4]);
+    }
+    // The samples are rendered into the buffer reserved when building the callback.
+    let pointers = pointers.lock().unwrap();
+    assert!(pointers.iter().all(|&pointer| pointer == pointers[0]));
+
+    let captured = Arc::new(Mutex::new(Vec::new()));
+    let received = captured.clone();
+    let mut callback = input_samples(
+        &[],
+        &config,
+        SampleFormat::I64,
+        move |samples: &[i32], _: &InputCallbackInfo| {
+            received.lock().unwrap().extend_from_slice(samples)
+        },
+    );
+    let mut input = [0x7654_3211i64 << 32, i64::MIN];
+    let data = unsafe { Data::from_parts(input.as_mut_ptr() as *mut (), 2, SampleFormat::I64) };
+    let timestamp = crate::InputStreamTimestamp {
+        callback: crate::StreamInstant::new(0, 0),
+        capture: crate::StreamInstant::new(0, 0),
+    };
+    callback(&data, &InputCallbackInfo { timestamp });
+    assert_eq!(*captured.lock().unwrap(), [0x7654_3211, i32::MIN]);
+}
@@ -0,0 +1,124 @@
+//! A report of the audio setup of the system, for bug reports.
+
+use crate::traits::{DeviceTrait, HostTrait};
+use crate::{
+    available_hosts, host_from_id, Device, HostId, SupportedStreamConfig,
+    SupportedStreamConfigRange, ALL_HOSTS,
+};
+
+/// The hosts, devices and configs cpal sees on this system, as returned by [`diagnostics`].
+///
+/// Failed queries are recorded as the `Display` output of their error, so the whole report can
+/// be printed with `{:#?}` or serialized as is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostics {
+    /// The version of cpal.
+    pub cpal_version: &'static str,
+    /// The operating system, as in [`std::env::consts::OS`].
+    pub os: &'static str,
+    /// The architecture, as in [`std::env::consts::ARCH`].
+    pub arch: &'static str,
+    /// The id of the host [`default_host`](crate::default_host) picks.
+    pub default_host: HostId,
+    /// Every host compiled into cpal, available or not.
+    pub hosts: Vec<HostReport>,
+}
+
+/// A host in [`Diagnostics`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct HostReport {
+    pub id: HostId,
+    /// The name of the host, as in [`HostId::name`].
+    pub name: &'static str,
+    /// Whether the host is available on this system. Unavailable hosts have no devices.
+    pub available: bool,
+    /// The version of the audio system, as in [`HostTrait::version`].
+    pub version: Option<String>,
+    /// The name of the default input device.
+    pub default_input_device: Option<String>,
+    /// The name of the default output device.
+    pub default_output_device: Option<String>,
+    /// The devices of the host, or the error enumerating them.
+    pub devices: Result<Vec<DeviceReport>, String>,
+}
+
+/// A device in [`Diagnostics`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceReport {
+    /// The name of the device, or the error getting it.
+    pub name: Result<String, String>,
+    /// The supported input configs, or the error getting them.
+    pub supported_input_configs: Result<Vec<SupportedStreamConfigRange>, String>,
+    /// The supported output configs, or the error getting them.
+    pub supported_output_configs: Result<Vec<SupportedStreamConfigRange>, String>,
+    /// The config cpal picks for input streams, or the error picking it.
+    pub default_input_config: Result<SupportedStreamConfig, String>,
+    /// The config cpal picks for output streams, or the error picking it.
+    pub default_output_config: Result<SupportedStreamConfig, String>,
+}
+
+/// Query every host and device for a report to attach to bug reports.
+///
+/// This opens each available host and queries each of its devices, so it can take a while and
+/// should not be called from a real-time thread.
+pub fn diagnostics() -> Diagnostics {
+    let available = available_hosts();
+    Diagnostics {
+        cpal_version: env!("CARGO_PKG_VERSION"),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        default_host: crate::default_host().id(),
+        hosts: ALL_HOSTS
+            .iter()
+            .map(|&id| host_report(id, available.contains(&id)))
+            .collect(),
+    }
+}
+
+fn host_report(id: HostId, available: bool) -> HostReport {
+    let mut report = HostReport {
+        id,
+        name: id.name(),
+        available,
+        version: None,
+        default_input_device: None,
+        default_output_device: None,
+        devices: Ok(Vec::new()),
+    };
+    if !available {
+        return report;
+    }
+    let host = match host_from_id(id) {
+        Ok(host) => host,
+        Err(err) => {
+            report.devices = Err(err.to_string());
+            return report;
+        }
+    };
+    report.version = host.version();
+    report.default_input_device = host.default_input_device().and_then(|d| d.name().ok());
+    report.default_output_device = host.default_output_device().and_then(|d| d.name().ok());
+    report.devices = host
+        .devices()
+        .map(|devices| devices.map(|device| device_report(&device)).collect())
+        .map_err(|err| err.to_string());
+    report
+}
+
+fn device_report(device: &Device) -> DeviceReport {
+    DeviceReport {
+        name: device.name().map_err(|err| err.to_string()),
+        supported_input_configs: device
+            .supported_input_configs()
+            .map(Iterator::collect)
+            .map_err(|err| err.to_string()),
+        supported_output_configs: device
+            .supported_output_configs()
+            .map(Iterator::collect)
+            .map_err(|err| err.to_string()),
+        default_input_config: device.default_input_config().map_err(|err| err.to_string()),
+        default_output_config: device
+            .default_output_config()
+            .map_err(|err| err.to_string()),
+    }
+}
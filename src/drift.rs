@@ -0,0 +1,195 @@
+//! A queue of audio between two clocks, resampled slightly to follow their drift.
+//!
+//! Two devices never run at exactly the same rate, so a queue that one fills and the other
+//! drains slowly runs dry or overflows. [`DriftQueue::pop_resampled`] reads the queue a little
+//! faster or slower, by linear interpolation, to keep its fill level at a target.
+
+use std::collections::VecDeque;
+
+use crate::StreamInstant;
+
+/// How quickly the measured fill level follows the actual one, per callback.
+const FILL_SMOOTHING: f64 = 0.02;
+/// The rate correction per relative deviation of the fill level from the target.
+const GAIN: f64 = 0.002;
+/// The largest rate correction, 0.5% is far beyond the drift of working clocks.
+const MAX_CORRECTION: f64 = 0.005;
+
+/// Interleaved `f32` frames queued between a producer and a consumer with different clocks.
+#[derive(Debug)]
+pub(crate) struct DriftQueue {
+    samples: VecDeque<f32>,
+    channels: usize,
+    /// The fill level in frames the consumer keeps the queue at.
+    target: usize,
+    /// The smoothed fill level in frames, `None` until the queue was first read from.
+    fill: Option<f64>,
+    /// The fractional read position between the first two queued frames.
+    position: f64,
+    /// Queued frames read per frame returned.
+    ratio: f64,
+}
+
+impl DriftQueue {
+    pub(crate) fn new(channels: usize) -> Self {
+        DriftQueue::with_capacity(channels, 0)
+    }
+
+    /// A queue that holds up to `frames` frames without allocating.
+    pub(crate) fn with_capacity(channels: usize, frames: usize) -> Self {
+        DriftQueue {
+            samples: VecDeque::with_capacity(frames * channels.max(1)),
+            channels: channels.max(1),
+            target: 0,
+            fill: None,
+            position: 0.0,
+            ratio: 1.0,
+        }
+    }
+
+    pub(crate) fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// The number of whole frames queued.
+    pub(crate) fn frames(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    /// The fill level in frames the queue is resampled to keep.
+    pub(crate) fn target(&self) -> usize {
+        self.target
+    }
+
+    pub(crate) fn set_target(&mut self, frames: usize) {
+        self.target = frames;
+    }
+
+    /// The number of queued frames currently read per frame returned, above 1 when the producer
+    /// runs faster than the consumer.
+    pub(crate) fn ratio(&self) -> f64 {
+        self.ratio
+    }
+
+    /// Queue interleaved samples, dropping the oldest frames beyond `max_frames`.
+    ///
+    /// The queue never grows beyond `max_frames`, so it doesn't allocate within its capacity.
+    pub(crate) fn push(&mut self, samples: impl IntoIterator<Item = f32>, max_frames: usize) {
+        let max = max_frames.max(1) * self.channels;
+        if self.samples.len() > max {
+            let excess = self.samples.len() - max;
+            self.samples.drain(..excess);
+        }
+        for sample in samples {
+            if self.samples.len() == max {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(sample);
+        }
+    }
+
+    /// Fill `out` with queued frames as they are. Returns false, and fills `out` with silence,
+    /// if not enough frames are queued.
+    pub(crate) fn pop_exact(&mut self, out: &mut [f32]) -> bool {
+        if self.samples.len() < out.len() {
+            out.fill(0.0);
+            return false;
+        }
+        let len = out.len();
+        for (sample, queued) in out.iter_mut().zip(self.samples.drain(..len)) {
+            *sample = queued;
+        }
+        true
+    }
+
+    /// Fill `out` with queued frames resampled at the current ratio, then adjust the ratio
+    /// towards keeping the target fill level. Returns false, and fills `out` with silence, if not
+    /// enough frames are queued.
+    pub(crate) fn pop_resampled(&mut self, out: &mut [f32]) -> bool {
+        let frames = self.frames() as f64;
+        let fill = match self.fill {
+            Some(fill) => fill + (frames - fill) * FILL_SMOOTHING,
+            None => frames,
+        };
+        self.fill = Some(fill);
+        if self.target > 0 {
+            let deviation = (fill - self.target as f64) / self.target as f64;
+            self.ratio = 1.0 + (deviation * GAIN).clamp(-MAX_CORRECTION, MAX_CORRECTION);
+        }
+
+        let channels = self.channels;
+        let out_frames = out.len() / channels;
+        // The last frame read is interpolated with the one after it.
+        let needed = (self.position + out_frames as f64 * self.ratio).floor() as usize + 1;
+        if self.frames() < needed + 1 {
+            out.fill(0.0);
+            return false;
+        }
+        for (frame, out) in out.chunks_exact_mut(channels).enumerate() {
+            let position = self.position + frame as f64 * self.ratio;
+            let index = position.floor() as usize;
+            let fraction = (position - index as f64) as f32;
+            for (channel, sample) in out.iter_mut().enumerate() {
+                let a = self.samples[index * channels + channel];
+                let b = self.samples[(index + 1) * channels + channel];
+                *sample = a + (b - a) * fraction;
+            }
+        }
+        let position = self.position + out_frames as f64 * self.ratio;
+        let consumed = position.floor() as usize;
+        self.samples.drain(..consumed * channels);
+        self.position = position - consumed as f64;
+        true
+    }
+}
+
+/// Measures the rate at which a device delivers or consumes frames against the callback
+/// timestamps, which all streams of a host take from the same system clock.
+#[derive(Debug, Default)]
+pub(crate) struct RateMeter {
+    /// The timestamp of the first callback, and the frames passed since.
+    start: Option<StreamInstant>,
+    frames: u64,
+    /// The timestamp of the latest callback, and the frames passed before it.
+    latest: Option<(StreamInstant, u64)>,
+}
+
+impl RateMeter {
+    /// Record a callback of `frames` frames at `instant`.
+    pub(crate) fn record(&mut self, instant: StreamInstant, frames: usize) {
+        if self.start.is_none() {
+            self.start = Some(instant);
+        }
+        self.latest = Some((instant, self.frames));
+        self.frames += frames as u64;
+    }
+
+    /// The time from the latest callback recorded to `instant`.
+    pub(crate) fn since_latest(&self, instant: StreamInstant) -> Option<std::time::Duration> {
+        instant.duration_since(&self.latest?.0)
+    }
+
+    /// The measured frames per second, `None` until callbacks spanning a second were recorded.
+    pub(crate) fn rate(&self) -> Option<f64> {
+        let (start, (latest, frames)) = (self.start?, self.latest?);
+        let elapsed = latest.duration_since(&start)?.as_secs_f64();
+        (elapsed >= 1.0).then(|| frames as f64 / elapsed)
+    }
+}
+
+#[test]
+fn test_drift_queue_tracks_faster_producer() {
+    let mut queue = DriftQueue::new(1);
+    queue.set_target(2_000);
+    let mut out = vec![0.0; 500];
+    // The producer runs 0.1% fast: 1001 frames for every 1000 the consumer reads.
+    for i in 0..20_000 {
+        let frames = if i % 2 == 0 { 501 } else { 500 };
+        queue.push(std::iter::repeat(0.5).take(frames), 100_000);
+        if i > 4 {
+            assert!(queue.pop_resampled(&mut out), "ran dry at {}", i);
+        }
+    }
+    assert!((queue.ratio() - 1.001).abs() < 0.0002, "{}", queue.ratio());
+    assert!(queue.frames().abs_diff(queue.target()) < 2_000);
+}
@@ -0,0 +1,250 @@
+//! Processing the input of one device into the output of another, see [`DuplexStream`].
+
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use ringbuf::traits::{Consumer, Observer, Producer, Split};
+use ringbuf::HeapRb;
+
+use crate::drift::{DriftQueue, RateMeter};
+use crate::traits::{DeviceTrait, StreamTrait};
+use crate::{
+    BuildStreamError, Device, FromSample, InputCallbackInfo, OutputCallbackInfo, PauseStreamError,
+    PlayStreamError, SizedSample, Stream, StreamConfig, StreamError,
+};
+
+/// An input stream and an output stream on two devices, with a data callback that turns the
+/// latest input into output, e.g. for live effects or monitoring.
+///
+/// Separate devices run on separate clocks, so over a long session the input arrives slightly
+/// faster or slower than the output plays. The input is queued between the two streams; without
+/// compensation the queue slowly runs dry or overflows and audio is lost. With
+/// [`set_drift_compensation`](Self::set_drift_compensation) the input is resampled by a tiny
+/// amount to keep the queue at a fixed level, so input and output stay aligned. The drift itself
+/// is measured from the callback timestamps and reported by [`drift`](Self::drift).
+#[must_use = "If the streams are not stored they will not play."]
+pub struct DuplexStream {
+    input: Stream,
+    output: Stream,
+    shared: Arc<Shared>,
+}
+
+/// The state shared by the two streams. The input itself is handed to the output stream through a
+/// lock-free ring buffer, so neither audio thread waits for the other.
+#[derive(Default)]
+struct Shared {
+    compensate: AtomicBool,
+    input_period: AtomicUsize,
+    output_period: AtomicUsize,
+    /// The bits of the rates measured by each stream, `0.0` until measured.
+    input_rate: AtomicU64,
+    output_rate: AtomicU64,
+}
+
+impl DuplexStream {
+    /// Build an input stream on `input_device` and an output stream on `output_device` with the
+    /// same config.
+    ///
+    /// The data callback runs on the output stream's thread with the input for the period,
+    /// followed by the output to fill, both interleaved with the config's channels. The input
+    /// reaches the callback about two periods after it was captured. The error callback receives
+    /// whether the error came from the input stream.
+    pub fn build<T, D, E>(
+        input_device: &Device,
+        output_device: &Device,
+        config: &StreamConfig,
+        mut data_callback: D,
+        error_callback: E,
+    ) -> Result<Self, BuildStreamError>
+    where
+        T: SizedSample + FromSample<f32> + Send + 'static,
+        f32: FromSample<T>,
+        D: FnMut(&[T], &mut [T], &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(bool, StreamError) + Send + 'static,
+    {
+        let channels = config.channels.max(1) as usize;
+        // The buffers are sized for the longest periods of both devices, so the audio threads
+        // don't allocate.
+        let input_ranges: Vec<_> = input_device
+            .supported_input_configs()
+            .map(Iterator::collect)
+            .unwrap_or_default();
+        let output_ranges: Vec<_> = output_device
+            .supported_output_configs()
+            .map(Iterator::collect)
+            .unwrap_or_default();
+        let period = crate::convert::max_period(&input_ranges, config, T::FORMAT).max(
+            crate::convert::max_period(&output_ranges, config, T::FORMAT),
+        );
+        let capacity = Shared::max_frames(2 * period, period);
+        let (mut producer, mut consumer) = HeapRb::<f32>::new(capacity * channels).split();
+        let shared = Arc::new(Shared::default());
+        let error_callback = Arc::new(Mutex::new(error_callback));
+
+        let input_shared = shared.clone();
+        let input_error_callback = error_callback.clone();
+        let mut input_rate = RateMeter::default();
+        let input = input_device.build_input_stream(
+            config,
+            move |data: &[T], info: &InputCallbackInfo| {
+                let frames = data.len() / channels;
+                input_shared.input_period.store(frames, Ordering::Relaxed);
+                input_rate.record(info.timestamp().capture, frames);
+                store_rate(&input_shared.input_rate, &input_rate);
+                // Only whole frames are queued, those that don't fit are dropped.
+                let fit = producer.vacant_len() / channels * channels;
+                producer.push_iter(data.iter().take(fit).map(|&s| f32::from_sample_(s)));
+            },
+            move |err| (input_error_callback.lock().unwrap())(true, err),
+            None,
+        )?;
+
+        let output_shared = shared.clone();
+        let mut output_rate = RateMeter::default();
+        let mut queue = DriftQueue::with_capacity(channels, capacity);
+        let mut samples: Vec<f32> = Vec::with_capacity(period * channels);
+        let mut recorded: Vec<T> = Vec::with_capacity(period * channels);
+        let output = output_device.build_output_stream(
+            config,
+            move |data: &mut [T], info: &OutputCallbackInfo| {
+                let frames = data.len() / channels;
+                output_shared.output_period.store(frames, Ordering::Relaxed);
+                output_rate.record(info.timestamp().playback, frames);
+                store_rate(&output_shared.output_rate, &output_rate);
+                let target = output_shared.target();
+                queue.set_target(target);
+                queue.push(
+                    consumer.pop_iter(),
+                    Shared::max_frames(target, frames).min(capacity),
+                );
+                samples.clear();
+                samples.resize(data.len(), 0.0);
+                if output_shared.compensate.load(Ordering::Relaxed) {
+                    queue.pop_resampled(&mut samples);
+                } else if queue.frames() >= target {
+                    queue.pop_exact(&mut samples);
+                }
+                recorded.clear();
+                recorded.extend(samples.iter().map(|&s| T::from_sample_(s)));
+                data_callback(&recorded, data, info);
+            },
+            move |err| (error_callback.lock().unwrap())(false, err),
+            None,
+        )?;
+
+        Ok(DuplexStream {
+            input,
+            output,
+            shared,
+        })
+    }
+
+    /// Resample the input to follow the clock of the output device (default is `false`).
+    pub fn set_drift_compensation(&self, enabled: bool) {
+        self.shared.compensate.store(enabled, Ordering::Relaxed);
+    }
+
+    /// The relative clock drift of the input device against the output device, e.g. `0.0001`
+    /// when the input delivers 100 ppm more frames per second than the output plays.
+    ///
+    /// Measured from the callback timestamps of both streams since they started, so it is `None`
+    /// for the first second and becomes more accurate the longer the streams run.
+    pub fn drift(&self) -> Option<f64> {
+        let rate = |rate: &AtomicU64| {
+            Some(f64::from_bits(rate.load(Ordering::Relaxed))).filter(|&rate| rate > 0.0)
+        };
+        Some(rate(&self.shared.input_rate)? / rate(&self.shared.output_rate)? - 1.0)
+    }
+
+    /// Start both streams, the input first.
+    pub fn play(&self) -> Result<(), PlayStreamError> {
+        self.input.play()?;
+        self.output.play()
+    }
+
+    /// Pause both streams, the output first.
+    pub fn pause(&self) -> Result<(), PauseStreamError> {
+        self.output.pause()?;
+        self.input.pause()
+    }
+
+    /// The input stream.
+    pub fn input(&self) -> &Stream {
+        &self.input
+    }
+
+    /// The output stream.
+    pub fn output(&self) -> &Stream {
+        &self.output
+    }
+}
+
+impl Shared {
+    /// The number of input frames kept queued: two of the larger period of the two streams.
+    fn target(&self) -> usize {
+        2 * self
+            .input_period
+            .load(Ordering::Relaxed)
+            .max(self.output_period.load(Ordering::Relaxed))
+    }
+
+    /// The most input frames queued before the oldest are dropped.
+    fn max_frames(target: usize, period: usize) -> usize {
+        4 * target + period
+    }
+}
+
+/// Publish the rate measured by `meter` for [`DuplexStream::drift`].
+fn store_rate(rate: &AtomicU64, meter: &RateMeter) {
+    rate.store(meter.rate().unwrap_or(0.0).to_bits(), Ordering::Relaxed);
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn test_duplex_stream_passes_input_to_output() {
+    use crate::traits::HostTrait;
+
+    let host = crate::platform::MockHost::new().unwrap();
+    let device = crate::Device::from(host.default_output_device().unwrap());
+    let config = StreamConfig {
+        channels: 1,
+        ..StreamConfig::default()
+    }
+    .with_buffer_size(crate::BufferSize::Fixed(64));
+    let duplex = DuplexStream::build(
+        &device,
+        &device,
+        &config,
+        |input: &[f32], output: &mut [f32], _: &OutputCallbackInfo| {
+            for (output, &input) in output.iter_mut().zip(input) {
+                *output = 2.0 * input;
+            }
+        },
+        |_, _| (),
+    )
+    .unwrap();
+    duplex.play().unwrap();
+    fn mock(stream: &Stream) -> &crate::platform::MockStream {
+        let crate::platform::StreamInner::Mock(mock) = stream.as_inner() else {
+            unreachable!()
+        };
+        mock
+    }
+    let (input, output) = (mock(duplex.input()), mock(duplex.output()));
+    let period = |index: usize| -> Vec<f32> {
+        (0..64)
+            .map(|frame| (index * 64 + frame) as f32 / 1024.0)
+            .collect()
+    };
+    let mut rendered = Vec::new();
+    for index in 0..4 {
+        assert!(input.capture(&period(index)));
+        rendered.push(output.render::<f32>(64).unwrap());
+    }
+    // The output waits for two periods of input to be queued.
+    assert_eq!(rendered[0], [0.0; 64]);
+    for (index, rendered) in rendered.iter().enumerate().skip(1) {
+        let expected: Vec<f32> = period(index - 1).iter().map(|s| 2.0 * s).collect();
+        assert_eq!(*rendered, expected);
+    }
+}
@@ -263,11 +263,30 @@ impl From<BackendSpecificError> for PauseStreamError {
 }
 
 /// Errors that might occur while a stream is running.
+///
+/// More errors may be reported in the future, so matches need a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum StreamError {
     /// The device no longer exists. This can happen if the device is disconnected while the
     /// program is running.
     DeviceNotAvailable,
+    /// The stream's buffer ran empty (output) or overflowed (input) and the backend recovered
+    /// from it. Some audio was lost, but the stream keeps running.
+    Xrun,
+    /// The system default device changed while the stream was running. Streams that follow the
+    /// default device have been moved to the new device, other streams keep running on the
+    /// device they were built on.
+    DeviceChanged,
+    /// No data callback ran for longer than the stream's watchdog allows, see
+    /// [`Stream::set_watchdog`](crate::Stream::set_watchdog). The driver or the data callback
+    /// may be stuck.
+    Stalled,
+    /// The system muted or ducked the stream's audio session, or stopped doing so. The stream
+    /// keeps running, an application may pause producing audio while it is muted.
+    ///
+    /// Only reported by WASAPI.
+    SessionStateChanged { muted: bool, ducked: bool },
     /// See the [`BackendSpecificError`] docs for more information about this error variant.
     BackendSpecific { err: BackendSpecificError },
 }
@@ -279,6 +298,16 @@ impl Display for StreamError {
             StreamError::DeviceNotAvailable => f.write_str(
                 "The requested device is no longer available. For example, it has been unplugged.",
             ),
+            StreamError::Xrun => {
+                f.write_str("A buffer underrun or overrun occurred and the stream was recovered.")
+            }
+            StreamError::DeviceChanged => f.write_str("The default device has changed."),
+            StreamError::Stalled => f.write_str("The stream stopped calling its data callback."),
+            StreamError::SessionStateChanged { muted, ducked } => write!(
+                f,
+                "The audio session's state changed (muted: {}, ducked: {}).",
+                muted, ducked
+            ),
         }
     }
 }
@@ -290,3 +319,49 @@ impl From<BackendSpecificError> for StreamError {
         Self::BackendSpecific { err }
     }
 }
+
+/// Errors that might occur while measuring the round-trip latency with
+/// [`measure_round_trip_latency`](crate::measure_round_trip_latency).
+#[derive(Debug)]
+pub enum LatencyTestError {
+    /// One of the streams could not be built.
+    BuildStream { err: BuildStreamError },
+    /// One of the streams could not be started.
+    PlayStream { err: PlayStreamError },
+    /// One of the streams reported an error while the test was running.
+    Stream { err: StreamError },
+    /// The chirp was not found in the recording, e.g. because the output and the input are not
+    /// connected or the recording was too noisy.
+    NotDetected,
+}
+
+impl Display for LatencyTestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BuildStream { err } => err.fmt(f),
+            Self::PlayStream { err } => err.fmt(f),
+            Self::Stream { err } => err.fmt(f),
+            Self::NotDetected => f.write_str("The test signal was not found in the recording."),
+        }
+    }
+}
+
+impl Error for LatencyTestError {}
+
+impl From<BuildStreamError> for LatencyTestError {
+    fn from(err: BuildStreamError) -> Self {
+        Self::BuildStream { err }
+    }
+}
+
+impl From<PlayStreamError> for LatencyTestError {
+    fn from(err: PlayStreamError) -> Self {
+        Self::PlayStream { err }
+    }
+}
+
+impl From<StreamError> for LatencyTestError {
+    fn from(err: StreamError) -> Self {
+        Self::Stream { err }
+    }
+}
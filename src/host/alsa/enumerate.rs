@@ -1,7 +1,6 @@
 use super::alsa;
 use super::{Device, DeviceHandles};
 use crate::{BackendSpecificError, DevicesError};
-use std::sync::{Arc, Mutex};
 
 /// ALSA's implementation for `Devices`.
 pub struct Devices {
@@ -35,10 +34,7 @@ impl Iterator for Devices {
                     };
 
                     if let Ok(handles) = DeviceHandles::open(&name) {
-                        return Some(Device {
-                            name,
-                            handles: Arc::new(Mutex::new(handles)),
-                        });
+                        return Some(Device::new(name, handles));
                     }
                 }
             }
@@ -48,18 +44,12 @@ impl Iterator for Devices {
 
 #[inline]
 pub fn default_input_device() -> Option<Device> {
-    Some(Device {
-        name: "default".to_owned(),
-        handles: Arc::new(Mutex::new(Default::default())),
-    })
+    Some(Device::new("default".to_owned(), Default::default()))
 }
 
 #[inline]
 pub fn default_output_device() -> Option<Device> {
-    Some(Device {
-        name: "default".to_owned(),
-        handles: Arc::new(Mutex::new(Default::default())),
-    })
+    Some(Device::new("default".to_owned(), Default::default()))
 }
 
 impl From<alsa::Error> for DevicesError {
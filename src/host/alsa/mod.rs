@@ -2,19 +2,22 @@ extern crate alsa;
 extern crate libc;
 
 use self::alsa::poll::Descriptors;
+use crate::shutdown::Worker;
+use crate::trace::trace_call;
 use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
 use crate::{
-    BackendSpecificError, BufferSize, BuildStreamError, ChannelCount, Data,
-    DefaultStreamConfigError, DeviceNameError, DevicesError, InputCallbackInfo, OutputCallbackInfo,
-    PauseStreamError, PlayStreamError, SampleFormat, SampleRate, StreamConfig, StreamError,
-    SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
-    SupportedStreamConfigsError,
+    BackendSpecificError, BufferSize, BuildStreamError, Capabilities, ChannelCount,
+    ChannelPosition, Data, DefaultStreamConfigError, DeviceNameError, DevicesError, FrameCount,
+    InputCallbackInfo, OutputCallbackInfo, PauseStreamError, PerformanceMode, PlayStreamError,
+    SampleFormat, SampleRate, StreamConfig, StreamError, SupportedBufferSize,
+    SupportedStreamConfig, SupportedStreamConfigRange, SupportedStreamConfigsError,
 };
 use std::cmp;
 use std::convert::TryInto;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::vec::IntoIter as VecIntoIter;
 
 pub use self::enumerate::{default_input_device, default_output_device, Devices};
@@ -22,6 +25,9 @@ pub use self::enumerate::{default_input_device, default_output_device, Devices};
 pub type SupportedInputConfigs = VecIntoIter<SupportedStreamConfigRange>;
 pub type SupportedOutputConfigs = VecIntoIter<SupportedStreamConfigRange>;
 
+// How long a stream without a timeout waits for its descriptors before checking the PCM state.
+const POLL_INTERVAL_MS: i32 = 1000;
+
 mod enumerate;
 
 /// The default linux, dragonfly, freebsd and netbsd host type.
@@ -32,6 +38,15 @@ impl Host {
     pub fn new() -> Result<Self, crate::HostUnavailable> {
         Ok(Host)
     }
+
+    /// Create a device for an arbitrary ALSA PCM name, e.g. `"hw:1,0"`, `"plughw:CARD=USB"` or
+    /// `"dmix"`.
+    ///
+    /// The PCM is not opened until it is used, so an invalid name is only reported when querying
+    /// configs or building a stream.
+    pub fn device_with_name(&self, name: &str) -> Device {
+        Device::new(name.to_owned(), Default::default())
+    }
 }
 
 impl HostTrait for Host {
@@ -47,12 +62,43 @@ impl HostTrait for Host {
         Devices::new()
     }
 
+    // `CPAL_DEVICE` may name any PCM, e.g. `hw:1`, not only the enumerated ones.
+
     fn default_input_device(&self) -> Option<Self::Device> {
-        default_input_device()
+        match crate::platform::device_override() {
+            Some(name) => Some(self.device_with_name(&name)),
+            None => default_input_device(),
+        }
     }
 
     fn default_output_device(&self) -> Option<Self::Device> {
-        default_output_device()
+        match crate::platform::device_override() {
+            Some(name) => Some(self.device_with_name(&name)),
+            None => default_output_device(),
+        }
+    }
+
+    fn version(&self) -> Option<String> {
+        extern "C" {
+            fn snd_asoundlib_version() -> *const libc::c_char;
+        }
+        let lib = unsafe { std::ffi::CStr::from_ptr(snd_asoundlib_version()) };
+        let mut version = format!("alsa-lib {}", lib.to_string_lossy());
+        // "Advanced Linux Sound Architecture Driver Version k6.1.0."
+        if let Ok(driver) = std::fs::read_to_string("/proc/asound/version") {
+            if let Some(driver) = driver.trim().trim_end_matches('.').split("Version ").nth(1) {
+                version.push_str(", driver ");
+                version.push_str(driver);
+            }
+        }
+        Some(version)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            exclusive_mode: true,
+            ..Default::default()
+        }
     }
 }
 
@@ -85,6 +131,39 @@ impl DeviceTrait for Device {
         Device::default_output_config(self)
     }
 
+    fn capabilities(&self) -> Capabilities {
+        // Only the hardware PCMs bypass dmix and dsnoop, which is also why they can't be shared.
+        Capabilities {
+            exclusive_mode: self.name.starts_with("hw:") || self.name.starts_with("plughw:"),
+            ..Default::default()
+        }
+    }
+
+    fn is_held_exclusively(&self) -> Option<bool> {
+        // The handles cached since enumeration hold the device themselves. Release them, they are
+        // reopened when needed, and probe with a handle that is closed right away so asking
+        // doesn't keep other processes out.
+        *self.handles.lock().unwrap() = DeviceHandles::default();
+        for direction in [alsa::Direction::Playback, alsa::Direction::Capture] {
+            match trace_call!(
+                "snd_pcm_open",
+                (&self.name, direction),
+                alsa::pcm::PCM::new(&self.name, direction, true),
+                |r| r.as_ref().map(|_| ())
+            ) {
+                Ok(_) => return Some(false),
+                Err(e) if e.errno() == libc::EBUSY => return Some(true),
+                Err(_) => {}
+            }
+        }
+        None
+    }
+
+    fn bit_perfect(&self) -> Option<Self> {
+        // `plughw:` and the other plugins may convert the samples.
+        self.name.starts_with("hw:").then(|| self.clone())
+    }
+
     fn build_input_stream_raw<D, E>(
         &self,
         conf: &StreamConfig,
@@ -99,11 +178,14 @@ impl DeviceTrait for Device {
     {
         let stream_inner =
             self.build_stream_inner(conf, sample_format, alsa::Direction::Capture)?;
+        let thread_name = conf.thread_name("cpal_alsa_in", &stream_inner.pcm_name);
         let stream = Stream::new_input(
             Arc::new(stream_inner),
+            thread_name,
             data_callback,
             error_callback,
             timeout,
+            self.params.external_processing,
         );
         Ok(stream)
     }
@@ -122,11 +204,14 @@ impl DeviceTrait for Device {
     {
         let stream_inner =
             self.build_stream_inner(conf, sample_format, alsa::Direction::Playback)?;
+        let thread_name = conf.thread_name("cpal_alsa_out", &stream_inner.pcm_name);
         let stream = Stream::new_output(
             Arc::new(stream_inner),
+            thread_name,
             data_callback,
             error_callback,
             timeout,
+            self.params.external_processing,
         );
         Ok(stream)
     }
@@ -211,7 +296,12 @@ impl DeviceHandles {
         };
 
         if handle.is_none() {
-            *handle = Some(alsa::pcm::PCM::new(name, stream_type, true)?);
+            *handle = Some(trace_call!(
+                "snd_pcm_open",
+                (name, stream_type),
+                alsa::pcm::PCM::new(name, stream_type, true),
+                |r| r.as_ref().map(|_| ())
+            )?);
         }
 
         Ok(handle)
@@ -234,13 +324,121 @@ impl DeviceHandles {
     }
 }
 
+/// Stream parameters that override the defaults cpal picks for hw_params and sw_params.
+#[derive(Clone, Copy, Debug, Default)]
+struct StreamParams {
+    start_threshold: Option<FrameCount>,
+    avail_min: Option<FrameCount>,
+    stop_threshold: Option<FrameCount>,
+    xrun_policy: XrunPolicy,
+    busy_policy: BusyPolicy,
+    silence_padding: bool,
+    idle_timeout: Option<Duration>,
+    external_processing: bool,
+}
+
+/// What a stream does when its ring buffer under- or overruns.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum XrunPolicy {
+    /// Recover with `snd_pcm_recover`, report [`StreamError::Xrun`] and keep the stream running.
+    #[default]
+    Recover,
+    /// Report the xrun as a [`StreamError::BackendSpecific`] error and stop the stream.
+    Fail,
+}
+
+/// What building a stream does when a `hw` or `plughw` PCM is already opened exclusively by
+/// another process.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BusyPolicy {
+    /// Fail with [`BuildStreamError::DeviceNotAvailable`].
+    #[default]
+    Fail,
+    /// Retry through the `dmix` (playback) or `dsnoop` (capture) plugin on the same card and
+    /// device, which share the hardware with other clients. The name of the PCM that was
+    /// eventually opened is available from [`StreamExt::pcm_name`].
+    Share,
+}
+
 #[derive(Clone)]
 pub struct Device {
     name: String,
     handles: Arc<Mutex<DeviceHandles>>,
+    params: StreamParams,
 }
 
 impl Device {
+    fn new(name: String, handles: DeviceHandles) -> Self {
+        Device {
+            name,
+            handles: Arc::new(Mutex::new(handles)),
+            params: StreamParams::default(),
+        }
+    }
+
+    /// Set the number of queued frames at which a playback stream starts automatically.
+    ///
+    /// When unset, playback starts once the buffer is filled up to its last period. Capture
+    /// streams are always started explicitly (default is `None`).
+    pub fn set_start_threshold(&mut self, start_threshold: Option<FrameCount>) {
+        self.params.start_threshold = start_threshold;
+    }
+
+    /// Set the minimum number of available frames before the stream thread is woken up.
+    ///
+    /// When unset, one period is used (default is `None`).
+    pub fn set_avail_min(&mut self, avail_min: Option<FrameCount>) {
+        self.params.avail_min = avail_min;
+    }
+
+    /// Set the number of available frames at which ALSA stops the stream with an xrun.
+    ///
+    /// When unset, the ALSA default of the buffer size is kept (default is `None`).
+    pub fn set_stop_threshold(&mut self, stop_threshold: Option<FrameCount>) {
+        self.params.stop_threshold = stop_threshold;
+    }
+
+    /// Set how streams built from this device handle xruns (default is [`XrunPolicy::Recover`]).
+    pub fn set_xrun_policy(&mut self, xrun_policy: XrunPolicy) {
+        self.params.xrun_policy = xrun_policy;
+    }
+
+    /// Set what happens when the device is busy (default is [`BusyPolicy::Fail`]).
+    pub fn set_busy_policy(&mut self, busy_policy: BusyPolicy) {
+        self.params.busy_policy = busy_policy;
+    }
+
+    /// Set whether playback streams pad the ring buffer with silence when their thread wakes up
+    /// late (default is false).
+    ///
+    /// When less than half the ring buffer is queued before the data callback runs, silence is
+    /// written up to half the buffer first, so the device keeps playing while the callback
+    /// produces the next audio instead of running into an xrun. Useful for applications that
+    /// produce audio sporadically, at the cost of the extra latency the silence adds.
+    pub fn set_silence_padding(&mut self, silence_padding: bool) {
+        self.params.silence_padding = silence_padding;
+    }
+
+    /// Set how long a playback stream plays nothing but silence before its hardware is stopped
+    /// to save power (default is `None`).
+    ///
+    /// While the hardware is stopped, the data callback keeps running once per period on the
+    /// stream's thread. As soon as it renders audio that is not silent, the hardware is started
+    /// again and plays that audio after the latency of a full buffer.
+    pub fn set_idle_timeout(&mut self, idle_timeout: Option<Duration>) {
+        self.params.idle_timeout = idle_timeout;
+    }
+
+    /// Set whether streams built from this device are processed by the application instead of
+    /// a thread spawned by cpal (default is false).
+    ///
+    /// No thread is spawned for such streams. The application calls
+    /// [`StreamExt::process_pending`] from its own real-time thread instead, which runs the data
+    /// callback whenever the device is ready. The idle timeout has no effect on these streams.
+    pub fn set_external_processing(&mut self, external_processing: bool) {
+        self.params.external_processing = external_processing;
+    }
+
     fn build_stream_inner(
         &self,
         conf: &StreamConfig,
@@ -254,6 +452,27 @@ impl Device {
             .take(&self.name, stream_type)
             .map_err(|e| (e, e.errno()));
 
+        let mut pcm_name = self.name.clone();
+        let handle_result = match handle_result {
+            Err((_, libc::EBUSY)) if self.params.busy_policy == BusyPolicy::Share => {
+                match shared_pcm_name(&self.name, stream_type) {
+                    Some(name) => {
+                        let result = trace_call!(
+                            "snd_pcm_open",
+                            (&name, stream_type),
+                            alsa::pcm::PCM::new(&name, stream_type, true),
+                            |r| r.as_ref().map(|_| ())
+                        )
+                        .map_err(|e| (e, e.errno()));
+                        pcm_name = name;
+                        result
+                    }
+                    None => handle_result,
+                }
+            }
+            result => result,
+        };
+
         let handle = match handle_result {
             Err((_, libc::EBUSY)) => return Err(BuildStreamError::DeviceNotAvailable),
             Err((_, libc::EINVAL)) => return Err(BuildStreamError::InvalidArgument),
@@ -261,9 +480,9 @@ impl Device {
             Ok(handle) => handle,
         };
         let can_pause = set_hw_params_from_format(&handle, conf, sample_format)?;
-        let period_len = set_sw_params_from_format(&handle, conf, stream_type)?;
+        let period_len = set_sw_params_from_format(&handle, conf, stream_type, &self.params)?;
 
-        handle.prepare()?;
+        trace_call!("snd_pcm_prepare", (), handle.prepare())?;
 
         let num_descriptors = handle.count();
         if num_descriptors == 0 {
@@ -281,10 +500,20 @@ impl Device {
         };
 
         if let alsa::Direction::Capture = stream_type {
-            handle.start()?;
+            trace_call!("snd_pcm_start", (), handle.start())?;
         }
 
+        let silence_padding = match stream_type {
+            alsa::Direction::Playback if self.params.silence_padding => {
+                let (buffer, _) = handle.get_params()?;
+                Some(buffer as usize / 2)
+            }
+            _ => None,
+        };
+
+        let channel_order = channel_order(&handle, conf.channels, stream_type);
         let stream_inner = StreamInner {
+            channel_order,
             channel: handle,
             sample_format,
             num_descriptors,
@@ -292,6 +521,11 @@ impl Device {
             period_len,
             can_pause,
             creation_instant,
+            xrun_policy: self.params.xrun_policy,
+            silence_padding,
+            idle_timeout: self.params.idle_timeout,
+            paused: AtomicBool::new(false),
+            pcm_name,
         };
 
         Ok(stream_inner)
@@ -399,8 +633,8 @@ impl Device {
         let min_channels = hw_params.get_channels_min()?;
         let max_channels = hw_params.get_channels_max()?;
 
-        let max_channels = cmp::min(max_channels, 32); // TODO: limiting to 32 channels or too much stuff is returned
-        let supported_channels = (min_channels..max_channels + 1)
+        let supported_channels = channel_count_candidates(min_channels, max_channels)
+            .into_iter()
             .filter_map(|num| {
                 if hw_params.test_channels(num).is_ok() {
                     Some(num as ChannelCount)
@@ -426,9 +660,9 @@ impl Device {
                 for &(min_rate, max_rate) in sample_rates.iter() {
                     output.push(SupportedStreamConfigRange {
                         channels,
-                        min_sample_rate: SampleRate(min_rate as u32),
-                        max_sample_rate: SampleRate(max_rate as u32),
-                        buffer_size: buffer_size_range.clone(),
+                        min_sample_rate: SampleRate(min_rate),
+                        max_sample_rate: SampleRate(max_rate),
+                        buffer_size: buffer_size_range,
                         sample_format,
                     });
                 }
@@ -530,6 +764,28 @@ struct StreamInner {
     // If this field is `None` then the elapsed duration between `get_trigger_htstamp` and
     // `get_htstamp` is used.
     creation_instant: Option<std::time::Instant>,
+
+    // How to react to buffer under- and overruns.
+    xrun_policy: XrunPolicy,
+
+    // The number of frames a playback stream keeps queued by writing silence before the data
+    // callback runs, if silence padding is enabled.
+    silence_padding: Option<usize>,
+
+    // How long a playback stream plays silence before its hardware is stopped.
+    idle_timeout: Option<Duration>,
+
+    // Whether the stream was paused by the user, so an idle stream stops running the data
+    // callback.
+    paused: AtomicBool,
+
+    // The name of the PCM that was opened, which differs from the device name when a busy
+    // device was shared through `dmix` or `dsnoop`.
+    pcm_name: String,
+
+    // For each channel written to (playback) or passed to the user (capture), the channel it
+    // takes its sample from, when the PCM's channel map differs from the standard layout.
+    channel_order: Option<Box<[usize]>>,
 }
 
 // Assume that the ALSA library is built with thread safe option.
@@ -543,38 +799,83 @@ enum StreamType {
 
 pub struct Stream {
     /// The high-priority audio processing thread calling callbacks.
-    /// Option used for moving out in destructor.
-    thread: Option<JoinHandle<()>>,
+    thread: Arc<StreamThread>,
 
     /// Handle to the underlying stream for playback controls.
     inner: Arc<StreamInner>,
+}
+
+/// The audio thread of a stream, stopped when the stream is dropped or the host shut down.
+struct StreamThread {
+    /// `None` once the thread was joined or the external worker dropped.
+    handle: Mutex<Option<Runner>>,
 
     /// Used to signal to stop processing.
     trigger: TriggerSender,
 }
 
+/// What processes a stream.
+enum Runner {
+    /// The thread spawned for the stream.
+    Thread(JoinHandle<()>),
+    /// One step of the worker, run by [`StreamExt::process_pending`] with the poll timeout.
+    /// Returns `false` once the stream stopped.
+    External(Box<dyn FnMut(i32) -> bool + Send>),
+}
+
+impl StreamThread {
+    fn new(runner: Runner, trigger: TriggerSender) -> Arc<Self> {
+        let thread = Arc::new(StreamThread {
+            handle: Mutex::new(Some(runner)),
+            trigger,
+        });
+        crate::shutdown::register(crate::HostId::Alsa, &thread);
+        thread
+    }
+}
+
+impl Worker for StreamThread {
+    fn stop(&self) {
+        // Wake the worker up first, an external one holds the lock while it polls.
+        self.trigger.wakeup();
+        if let Some(Runner::Thread(handle)) = self.handle.lock().unwrap().take() {
+            handle.join().unwrap();
+        }
+    }
+}
+
 struct StreamWorkerContext {
     descriptors: Vec<libc::pollfd>,
     buffer: Vec<u8>,
     poll_timeout: i32,
+    // Whether the application processes the stream, for which a poll may time out.
+    external: bool,
+    // The number of frames of silence the data callback rendered in a row.
+    silent_frames: usize,
 }
 
 impl StreamWorkerContext {
-    fn new(poll_timeout: &Option<Duration>) -> Self {
-        let poll_timeout: i32 = if let Some(d) = poll_timeout {
-            d.as_millis().try_into().unwrap()
-        } else {
-            -1
-        };
-
+    fn new(poll_timeout: &Option<Duration>, external: bool) -> Self {
         Self {
             descriptors: Vec::new(),
             buffer: Vec::new(),
-            poll_timeout,
+            poll_timeout: poll_timeout_millis(poll_timeout),
+            external,
+            silent_frames: 0,
         }
     }
 }
 
+/// The timeout for `poll` in milliseconds, -1 for none. Longer timeouts than `poll` takes are
+/// clamped.
+fn poll_timeout_millis(timeout: &Option<Duration>) -> i32 {
+    if let Some(d) = timeout {
+        d.as_millis().try_into().unwrap_or(i32::MAX)
+    } else {
+        -1
+    }
+}
+
 fn input_stream_worker(
     rx: TriggerReceiver,
     stream: &StreamInner,
@@ -582,46 +883,54 @@ fn input_stream_worker(
     error_callback: &mut (dyn FnMut(StreamError) + Send + 'static),
     timeout: Option<Duration>,
 ) {
-    let mut ctxt = StreamWorkerContext::new(&timeout);
-    loop {
-        let flow =
-            poll_descriptors_and_prepare_buffer(&rx, stream, &mut ctxt).unwrap_or_else(|err| {
-                error_callback(err.into());
-                PollDescriptorsFlow::Continue
-            });
+    let mut ctxt = StreamWorkerContext::new(&timeout, false);
+    while input_stream_step(&rx, stream, &mut ctxt, data_callback, error_callback) {}
+}
 
-        match flow {
-            PollDescriptorsFlow::Continue => {
-                continue;
-            }
-            PollDescriptorsFlow::XRun => {
-                if let Err(err) = stream.channel.prepare() {
-                    error_callback(err.into());
-                }
-                continue;
-            }
-            PollDescriptorsFlow::Return => return,
-            PollDescriptorsFlow::Ready {
+// Wait until the input stream is ready and process it once.
+//
+// Returns `false` if the stream should stop.
+fn input_stream_step(
+    rx: &TriggerReceiver,
+    stream: &StreamInner,
+    ctxt: &mut StreamWorkerContext,
+    data_callback: &mut (dyn FnMut(&Data, &InputCallbackInfo) + Send + 'static),
+    error_callback: &mut (dyn FnMut(StreamError) + Send + 'static),
+) -> bool {
+    let flow = poll_descriptors_and_prepare_buffer(rx, stream, ctxt).unwrap_or_else(|err| {
+        error_callback(err.into());
+        PollDescriptorsFlow::Continue
+    });
+
+    match flow {
+        PollDescriptorsFlow::Continue => true,
+        PollDescriptorsFlow::XRun(err) => handle_xrun(stream, err, error_callback),
+        PollDescriptorsFlow::Return => false,
+        PollDescriptorsFlow::Disconnected => {
+            error_callback(StreamError::DeviceNotAvailable);
+            false
+        }
+        PollDescriptorsFlow::Ready {
+            status,
+            avail_frames: _,
+            delay_frames,
+            stream_type,
+        } => {
+            assert_eq!(
+                stream_type,
+                StreamType::Input,
+                "expected input stream, but polling descriptors indicated output",
+            );
+            if let Err(err) = process_input(
+                stream,
+                &mut ctxt.buffer,
                 status,
-                avail_frames: _,
                 delay_frames,
-                stream_type,
-            } => {
-                assert_eq!(
-                    stream_type,
-                    StreamType::Input,
-                    "expected input stream, but polling descriptors indicated output",
-                );
-                if let Err(err) = process_input(
-                    stream,
-                    &mut ctxt.buffer,
-                    status,
-                    delay_frames,
-                    data_callback,
-                ) {
-                    error_callback(err.into());
-                }
+                data_callback,
+            ) {
+                error_callback(err.into());
             }
+            true
         }
     }
 }
@@ -633,46 +942,72 @@ fn output_stream_worker(
     error_callback: &mut (dyn FnMut(StreamError) + Send + 'static),
     timeout: Option<Duration>,
 ) {
-    let mut ctxt = StreamWorkerContext::new(&timeout);
-    loop {
-        let flow =
-            poll_descriptors_and_prepare_buffer(&rx, stream, &mut ctxt).unwrap_or_else(|err| {
-                error_callback(err.into());
-                PollDescriptorsFlow::Continue
-            });
+    let mut ctxt = StreamWorkerContext::new(&timeout, false);
+    while output_stream_step(&rx, stream, &mut ctxt, data_callback, error_callback) {}
+}
 
-        match flow {
-            PollDescriptorsFlow::Continue => continue,
-            PollDescriptorsFlow::XRun => {
-                if let Err(err) = stream.channel.prepare() {
-                    error_callback(err.into());
-                }
-                continue;
-            }
-            PollDescriptorsFlow::Return => return,
-            PollDescriptorsFlow::Ready {
+// Wait until the output stream is ready and process it once.
+//
+// Returns `false` if the stream should stop.
+fn output_stream_step(
+    rx: &TriggerReceiver,
+    stream: &StreamInner,
+    ctxt: &mut StreamWorkerContext,
+    data_callback: &mut (dyn FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static),
+    error_callback: &mut (dyn FnMut(StreamError) + Send + 'static),
+) -> bool {
+    let flow = poll_descriptors_and_prepare_buffer(rx, stream, ctxt).unwrap_or_else(|err| {
+        error_callback(err.into());
+        PollDescriptorsFlow::Continue
+    });
+
+    match flow {
+        PollDescriptorsFlow::Continue => true,
+        PollDescriptorsFlow::XRun(err) => handle_xrun(stream, err, error_callback),
+        PollDescriptorsFlow::Return => false,
+        PollDescriptorsFlow::Disconnected => {
+            error_callback(StreamError::DeviceNotAvailable);
+            false
+        }
+        PollDescriptorsFlow::Ready {
+            status,
+            avail_frames,
+            delay_frames,
+            stream_type,
+        } => {
+            assert_eq!(
+                stream_type,
+                StreamType::Output,
+                "expected output stream, but polling descriptors indicated input",
+            );
+            match process_output(
+                stream,
+                &mut ctxt.buffer,
                 status,
                 avail_frames,
                 delay_frames,
-                stream_type,
-            } => {
-                assert_eq!(
-                    stream_type,
-                    StreamType::Output,
-                    "expected output stream, but polling descriptors indicated input",
-                );
-                if let Err(err) = process_output(
-                    stream,
-                    &mut ctxt.buffer,
-                    status,
-                    avail_frames,
-                    delay_frames,
-                    data_callback,
-                    error_callback,
-                ) {
-                    error_callback(err.into());
-                }
+                data_callback,
+                error_callback,
+            ) {
+                Ok(true) => (),
+                Ok(false) => return false,
+                Err(err) => error_callback(err.into()),
+            }
+            // Idling blocks until the audio resumes, which an external worker must not do.
+            let idle_timeout = match stream.idle_timeout {
+                Some(idle_timeout) if !ctxt.external => idle_timeout,
+                _ => return true,
+            };
+            ctxt.silent_frames = if is_silent(stream, &mut ctxt.buffer) {
+                ctxt.silent_frames + avail_frames
+            } else {
+                0
+            };
+            if frames_to_duration(ctxt.silent_frames, stream.conf.sample_rate) >= idle_timeout {
+                ctxt.silent_frames = 0;
+                return idle_output(rx, stream, &mut ctxt.buffer, data_callback, error_callback);
             }
+            true
         }
     }
 }
@@ -686,7 +1021,40 @@ enum PollDescriptorsFlow {
         avail_frames: usize,
         delay_frames: usize,
     },
-    XRun,
+    XRun(alsa::Error),
+    Disconnected,
+}
+
+// Whether the error returned by an ALSA call indicates an xrun or a suspended stream.
+fn is_xrun(err: &alsa::Error) -> bool {
+    matches!(err.errno(), libc::EPIPE | libc::ESTRPIPE)
+}
+
+// Handle an xrun according to the stream's policy.
+//
+// Returns `false` if the stream should stop.
+fn handle_xrun(
+    stream: &StreamInner,
+    err: alsa::Error,
+    error_callback: &mut dyn FnMut(StreamError),
+) -> bool {
+    match stream.xrun_policy {
+        XrunPolicy::Recover => {
+            match trace_call!(
+                "snd_pcm_recover",
+                (err,),
+                stream.channel.try_recover(err, true)
+            ) {
+                Ok(()) => error_callback(StreamError::Xrun),
+                Err(err) => error_callback(err.into()),
+            }
+            true
+        }
+        XrunPolicy::Fail => {
+            error_callback(err.into());
+            false
+        }
+    }
 }
 
 // This block is shared between both input and output stream worker functions.
@@ -699,6 +1067,8 @@ fn poll_descriptors_and_prepare_buffer(
         ref mut descriptors,
         ref mut buffer,
         ref poll_timeout,
+        external,
+        ..
     } = *ctxt;
 
     descriptors.clear();
@@ -723,8 +1093,24 @@ fn poll_descriptors_and_prepare_buffer(
     let filled = stream.channel.fill(&mut descriptors[len..])?;
     debug_assert_eq!(filled, stream.num_descriptors);
 
-    // Don't timeout, wait forever.
-    let res = alsa::poll::poll(descriptors, *poll_timeout)?;
+    // Without a timeout, wake up regularly to notice a device that went away without waking up
+    // the descriptors.
+    let wait_forever = *poll_timeout < 0 && !external;
+    let timeout = if wait_forever {
+        POLL_INTERVAL_MS
+    } else {
+        *poll_timeout
+    };
+    let res = alsa::poll::poll(descriptors, timeout)?;
+    if res == 0 && wait_forever {
+        return match stream.channel.state() {
+            alsa::pcm::State::Disconnected => Ok(PollDescriptorsFlow::Disconnected),
+            _ => Ok(PollDescriptorsFlow::Continue),
+        };
+    }
+    if res == 0 && external {
+        return Ok(PollDescriptorsFlow::Continue);
+    }
     if res == 0 {
         let description = String::from("`alsa::poll()` spuriously returned");
         return Err(BackendSpecificError { description });
@@ -750,9 +1136,11 @@ fn poll_descriptors_and_prepare_buffer(
         }
     };
 
-    let status = stream.channel.status()?;
-    let avail_frames = match stream.channel.avail() {
-        Err(err) if err.errno() == libc::EPIPE => return Ok(PollDescriptorsFlow::XRun),
+    let status = trace_call!("snd_pcm_status", (), stream.channel.status(), |r| r
+        .as_ref()
+        .map(|s| (s.get_state(), s.get_delay(), s.get_avail())))?;
+    let avail_frames = match trace_call!("snd_pcm_avail", (), stream.channel.avail()) {
+        Err(err) if is_xrun(&err) => return Ok(PollDescriptorsFlow::XRun(err)),
         res => res,
     }? as usize;
     let delay_frames = match status.get_delay() {
@@ -787,7 +1175,12 @@ fn process_input(
     delay_frames: usize,
     data_callback: &mut (dyn FnMut(&Data, &InputCallbackInfo) + Send + 'static),
 ) -> Result<(), BackendSpecificError> {
-    stream.channel.io_bytes().readi(buffer)?;
+    trace_call!(
+        "snd_pcm_readi",
+        (buffer.len(),),
+        stream.channel.io_bytes().readi(buffer)
+    )?;
+    reorder_channels(stream, buffer);
     let sample_format = stream.sample_format;
     let data = buffer.as_mut_ptr() as *mut ();
     let len = buffer.len() / sample_format.sample_size();
@@ -806,16 +1199,32 @@ fn process_input(
 
 // Request data from the user's function and write it via ALSA.
 //
-// Returns `true`
+// Returns `false` if the stream should stop.
 fn process_output(
     stream: &StreamInner,
-    buffer: &mut [u8],
+    mut buffer: &mut [u8],
     status: alsa::pcm::Status,
-    available_frames: usize,
-    delay_frames: usize,
+    mut available_frames: usize,
+    mut delay_frames: usize,
     data_callback: &mut (dyn FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static),
     error_callback: &mut dyn FnMut(StreamError),
-) -> Result<(), BackendSpecificError> {
+) -> Result<bool, BackendSpecificError> {
+    if let Some(padding) = stream.silence_padding {
+        let silent_frames = padding.saturating_sub(delay_frames).min(available_frames);
+        if silent_frames > 0 {
+            let frame_size = buffer.len() / available_frames;
+            let (silence, rest) = buffer.split_at_mut(silent_frames * frame_size);
+            if !write_silence(stream, silence, error_callback)? {
+                return Ok(false);
+            }
+            if rest.is_empty() {
+                return Ok(true);
+            }
+            buffer = rest;
+            available_frames -= silent_frames;
+            delay_frames += silent_frames;
+        }
+    }
     {
         // We're now sure that we're ready to write data.
         let sample_format = stream.sample_format;
@@ -831,12 +1240,17 @@ fn process_output(
         let info = crate::OutputCallbackInfo { timestamp };
         data_callback(&mut data, &info);
     }
+    reorder_channels(stream, buffer);
     loop {
-        match stream.channel.io_bytes().writei(buffer) {
-            Err(err) if err.errno() == libc::EPIPE => {
-                // buffer underrun
-                // TODO: Notify the user of this.
-                let _ = stream.channel.try_recover(err, false);
+        match trace_call!(
+            "snd_pcm_writei",
+            (buffer.len(),),
+            stream.channel.io_bytes().writei(buffer)
+        ) {
+            Err(err) if is_xrun(&err) => {
+                if !handle_xrun(stream, err, error_callback) {
+                    return Ok(false);
+                }
             }
             Err(err) => {
                 error_callback(err.into());
@@ -856,7 +1270,122 @@ fn process_output(
             }
         }
     }
-    Ok(())
+    Ok(true)
+}
+
+// Write silence to keep a playback stream running while the data callback is late.
+//
+// Returns `false` if the stream should stop.
+fn write_silence(
+    stream: &StreamInner,
+    buffer: &mut [u8],
+    error_callback: &mut dyn FnMut(StreamError),
+) -> Result<bool, BackendSpecificError> {
+    let sample_format = stream.sample_format;
+    let len = buffer.len() / sample_format.sample_size();
+    unsafe { Data::from_parts(buffer.as_mut_ptr() as *mut (), len, sample_format) }
+        .fill_equilibrium();
+    match trace_call!(
+        "snd_pcm_writei",
+        (buffer.len(),),
+        stream.channel.io_bytes().writei(buffer)
+    ) {
+        Err(err) if is_xrun(&err) => Ok(handle_xrun(stream, err, error_callback)),
+        Err(err) => Err(err.into()),
+        Ok(_) => Ok(true),
+    }
+}
+
+// Whether the buffer written to a playback stream holds nothing but silence.
+fn is_silent(stream: &StreamInner, buffer: &mut [u8]) -> bool {
+    let sample_format = stream.sample_format;
+    let len = buffer.len() / sample_format.sample_size();
+    unsafe { Data::from_parts(buffer.as_mut_ptr() as *mut (), len, sample_format) }.is_equilibrium()
+}
+
+// Stop the hardware of a playback stream that played silence for its idle timeout, then run the
+// data callback once per period until it renders audio that is not silent and write that audio
+// to the restarted stream.
+//
+// Returns `false` if the stream should stop.
+fn idle_output(
+    rx: &TriggerReceiver,
+    stream: &StreamInner,
+    buffer: &mut Vec<u8>,
+    data_callback: &mut (dyn FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static),
+    error_callback: &mut dyn FnMut(StreamError),
+) -> bool {
+    // Timestamps continue from the last callback, the stopped stream has no clock.
+    let start = match stream.channel.status() {
+        Ok(status) => stream_timestamp(&status, stream.creation_instant),
+        Err(err) => Err(err.into()),
+    };
+    let start = match start {
+        Ok(start) => (start, Instant::now()),
+        Err(err) => {
+            error_callback(err.into());
+            return true;
+        }
+    };
+    if let Err(err) = trace_call!("snd_pcm_drop", (), stream.channel.drop()) {
+        error_callback(err.into());
+        return true;
+    }
+
+    let sample_format = stream.sample_format;
+    let period_frames = stream.period_len / stream.conf.channels as usize;
+    let period = frames_to_duration(period_frames, stream.conf.sample_rate);
+    buffer.resize(stream.period_len * sample_format.sample_size(), 0);
+    let mut descriptors = [libc::pollfd {
+        fd: rx.0,
+        events: libc::POLLIN,
+        revents: 0,
+    }];
+    loop {
+        // Wait a period, or until the stream is destroyed.
+        match alsa::poll::poll(&mut descriptors, period.as_millis().max(1) as i32) {
+            Ok(0) => (),
+            Ok(_) => {
+                rx.clear_pipe();
+                return false;
+            }
+            Err(err) => {
+                error_callback(err.into());
+                return true;
+            }
+        }
+        if stream.paused.load(Ordering::Relaxed) {
+            continue;
+        }
+
+        let len = buffer.len() / sample_format.sample_size();
+        let mut data =
+            unsafe { Data::from_parts(buffer.as_mut_ptr() as *mut (), len, sample_format) };
+        let callback = start.0.add(start.1.elapsed()).unwrap_or(start.0);
+        let delay = frames_to_duration(stream.period_len, stream.conf.sample_rate);
+        let timestamp = crate::OutputStreamTimestamp {
+            callback,
+            playback: callback.add(delay).unwrap_or(callback),
+        };
+        data_callback(&mut data, &crate::OutputCallbackInfo { timestamp });
+        if data.is_equilibrium() {
+            continue;
+        }
+        reorder_channels(stream, buffer);
+
+        // The stream starts again once the normal loop filled it up to its start threshold.
+        let result = trace_call!("snd_pcm_prepare", (), stream.channel.prepare()).and_then(|()| {
+            trace_call!(
+                "snd_pcm_writei",
+                (buffer.len(),),
+                stream.channel.io_bytes().writei(buffer)
+            )
+        });
+        if let Err(err) = result {
+            error_callback(err.into());
+        }
+        return true;
+    }
 }
 
 // Use the elapsed duration since the start of the stream.
@@ -895,6 +1424,8 @@ fn stream_timestamp(
 
 // Adapted from `timestamp2ns` here:
 // https://fossies.org/linux/alsa-lib/test/audio_time.c
+// The width of `time_t` and `c_long` differs between targets, hence the casts.
+#[allow(clippy::unnecessary_cast)]
 fn timespec_to_nanos(ts: libc::timespec) -> i64 {
     ts.tv_sec as i64 * 1_000_000_000 + ts.tv_nsec as i64
 }
@@ -916,9 +1447,11 @@ fn frames_to_duration(frames: usize, rate: crate::SampleRate) -> std::time::Dura
 impl Stream {
     fn new_input<D, E>(
         inner: Arc<StreamInner>,
+        thread_name: String,
         mut data_callback: D,
         mut error_callback: E,
         timeout: Option<Duration>,
+        external: bool,
     ) -> Stream
     where
         D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
@@ -927,30 +1460,46 @@ impl Stream {
         let (tx, rx) = trigger();
         // Clone the handle for passing into worker thread.
         let stream = inner.clone();
-        let thread = thread::Builder::new()
-            .name("cpal_alsa_in".to_owned())
-            .spawn(move || {
-                input_stream_worker(
-                    rx,
+        let runner = if external {
+            let mut ctxt = StreamWorkerContext::new(&timeout, true);
+            Runner::External(Box::new(move |poll_timeout| {
+                ctxt.poll_timeout = poll_timeout;
+                input_stream_step(
+                    &rx,
                     &stream,
+                    &mut ctxt,
                     &mut data_callback,
                     &mut error_callback,
-                    timeout,
-                );
-            })
-            .unwrap();
+                )
+            }))
+        } else {
+            let thread = thread::Builder::new()
+                .name(thread_name)
+                .spawn(move || {
+                    input_stream_worker(
+                        rx,
+                        &stream,
+                        &mut data_callback,
+                        &mut error_callback,
+                        timeout,
+                    );
+                })
+                .unwrap();
+            Runner::Thread(thread)
+        };
         Stream {
-            thread: Some(thread),
+            thread: StreamThread::new(runner, tx),
             inner,
-            trigger: tx,
         }
     }
 
     fn new_output<D, E>(
         inner: Arc<StreamInner>,
+        thread_name: String,
         mut data_callback: D,
         mut error_callback: E,
         timeout: Option<Duration>,
+        external: bool,
     ) -> Stream
     where
         D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
@@ -959,44 +1508,237 @@ impl Stream {
         let (tx, rx) = trigger();
         // Clone the handle for passing into worker thread.
         let stream = inner.clone();
-        let thread = thread::Builder::new()
-            .name("cpal_alsa_out".to_owned())
-            .spawn(move || {
-                output_stream_worker(
-                    rx,
+        let runner = if external {
+            let mut ctxt = StreamWorkerContext::new(&timeout, true);
+            Runner::External(Box::new(move |poll_timeout| {
+                ctxt.poll_timeout = poll_timeout;
+                output_stream_step(
+                    &rx,
                     &stream,
+                    &mut ctxt,
                     &mut data_callback,
                     &mut error_callback,
-                    timeout,
-                );
-            })
-            .unwrap();
+                )
+            }))
+        } else {
+            let thread = thread::Builder::new()
+                .name(thread_name)
+                .spawn(move || {
+                    output_stream_worker(
+                        rx,
+                        &stream,
+                        &mut data_callback,
+                        &mut error_callback,
+                        timeout,
+                    );
+                })
+                .unwrap();
+            Runner::Thread(thread)
+        };
         Stream {
-            thread: Some(thread),
+            thread: StreamThread::new(runner, tx),
             inner,
-            trigger: tx,
         }
     }
 }
 
+// The order in which to pick the channels of each frame so the user sees the standard layout.
+fn channel_order(
+    pcm: &alsa::PCM,
+    channels: ChannelCount,
+    stream_type: alsa::Direction,
+) -> Option<Box<[usize]>> {
+    let layout = ChannelPosition::standard_layout(channels)?;
+    // The positions are read from the printed map, `alsa` can't convert all of them.
+    let chmap = pcm.get_chmap().ok()?.to_string();
+    let device: Vec<&str> = chmap.split_whitespace().collect();
+    let device_order = map_channels(layout, &device)?;
+    let order: Box<[usize]> = match stream_type {
+        // Device channel `i` takes the user's channel of its position.
+        alsa::Direction::Playback => (0..device.len())
+            .map(|i| device_order.iter().position(|&d| d == i))
+            .collect::<Option<_>>()?,
+        // User channel `i` takes the device channel of its position.
+        alsa::Direction::Capture => device_order.into(),
+    };
+    let identity = order.iter().enumerate().all(|(i, &from)| i == from);
+    (!identity).then_some(order)
+}
+
+// The device channel of each position of `layout`, given the chmap names of the device channels.
+fn map_channels(layout: &[ChannelPosition], device: &[&str]) -> Option<Vec<usize>> {
+    if device.len() != layout.len() {
+        return None;
+    }
+    let mut order: Vec<usize> = Vec::with_capacity(layout.len());
+    for &position in layout {
+        let (name, fallback) = match position {
+            ChannelPosition::FrontLeft => ("FL", None),
+            ChannelPosition::FrontRight => ("FR", None),
+            ChannelPosition::FrontCenter => ("FC", Some("MONO")),
+            ChannelPosition::LowFrequency => ("LFE", None),
+            ChannelPosition::BackLeft => ("RL", Some("SL")),
+            ChannelPosition::BackRight => ("RR", Some("SR")),
+            ChannelPosition::FrontLeftOfCenter => ("FLC", None),
+            ChannelPosition::FrontRightOfCenter => ("FRC", None),
+            ChannelPosition::BackCenter => ("RC", None),
+            ChannelPosition::SideLeft => ("SL", None),
+            ChannelPosition::SideRight => ("SR", None),
+        };
+        let find =
+            |name: &str| (0..device.len()).find(|&i| device[i] == name && !order.contains(&i));
+        order.push(find(name).or_else(|| find(fallback?))?);
+    }
+    Some(order)
+}
+
+// Reorder the interleaved channels of `buffer` according to the stream's channel order.
+fn reorder_channels(stream: &StreamInner, buffer: &mut [u8]) {
+    if let Some(order) = &stream.channel_order {
+        reorder_frames(buffer, stream.sample_format.sample_size(), order);
+    }
+}
+
+// Channel `i` of each frame takes the sample of channel `order[i]`. Standard layouts have at most
+// 8 channels of at most 8 bytes.
+fn reorder_frames(buffer: &mut [u8], sample_size: usize, order: &[usize]) {
+    let frame_size = sample_size * order.len();
+    let mut source = [0u8; 64];
+    for frame in buffer.chunks_exact_mut(frame_size) {
+        source[..frame_size].copy_from_slice(frame);
+        for (sample, &from) in frame.chunks_exact_mut(sample_size).zip(order) {
+            sample.copy_from_slice(&source[from * sample_size..][..sample_size]);
+        }
+    }
+}
+
+/// A snapshot of the PCM ring buffer state as reported by `snd_pcm_status`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamStatus {
+    /// The distance in frames between the application pointer and the hardware pointer.
+    ///
+    /// This may be negative after an xrun.
+    pub delay: isize,
+    /// The number of frames that can currently be written (playback) or read (capture).
+    pub avail: usize,
+    /// The maximum `avail` observed since the previous status query.
+    pub avail_max: usize,
+    /// Whether the PCM is currently in the xrun state.
+    pub xrun: bool,
+}
+
+/// Extension trait exposing ALSA specific information about a stream.
+pub trait StreamExt {
+    /// Query the current state of the stream's ring buffer.
+    fn status(&self) -> Result<StreamStatus, StreamError>;
+
+    /// The name of the PCM the stream was opened on, e.g. `dmix:0,0` after falling back from
+    /// a busy `hw:` device. Empty for platform streams of other hosts.
+    fn pcm_name(&self) -> &str;
+
+    /// Wait up to `timeout`, or indefinitely if `None`, until the device is ready and run the
+    /// data callback once, for streams of a device with
+    /// [external processing](Device::set_external_processing).
+    ///
+    /// Returns `false` once the stream stopped, e.g. after an unrecoverable xrun or a shutdown of
+    /// the host. Streams that are processed by their own thread always return `false`.
+    fn process_pending(&self, timeout: Option<Duration>) -> bool;
+
+    /// Run the data callback of a stream with external processing until the stream stopped,
+    /// blocking the calling thread.
+    fn run(&self) {
+        while self.process_pending(None) {}
+    }
+
+    /// Run the data callback for the audio the device is ready for, if any, and return without
+    /// waiting, e.g. once per iteration of a GUI main loop.
+    ///
+    /// Returns `false` once the stream stopped, see [`process_pending`](Self::process_pending).
+    fn run_return(&self) -> bool {
+        self.process_pending(Some(Duration::ZERO))
+    }
+
+    /// Run the data callback whenever the device is ready during `timeout`, and return once
+    /// `timeout` passed.
+    ///
+    /// Returns `false` once the stream stopped, see [`process_pending`](Self::process_pending).
+    fn run_with_timeout(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if !self.process_pending(Some(remaining)) {
+                return false;
+            }
+            if remaining.is_zero() {
+                return true;
+            }
+        }
+    }
+}
+
+impl StreamExt for Stream {
+    fn status(&self) -> Result<StreamStatus, StreamError> {
+        let status = self.inner.channel.status()?;
+        Ok(StreamStatus {
+            delay: status.get_delay() as isize,
+            avail: status.get_avail() as usize,
+            avail_max: status.get_avail_max() as usize,
+            xrun: status.get_state() == alsa::pcm::State::XRun,
+        })
+    }
+
+    fn pcm_name(&self) -> &str {
+        &self.inner.pcm_name
+    }
+
+    fn process_pending(&self, timeout: Option<Duration>) -> bool {
+        let mut runner = self.thread.handle.lock().unwrap();
+        let Some(Runner::External(step)) = runner.as_mut() else {
+            return false;
+        };
+        if step(poll_timeout_millis(&timeout)) {
+            return true;
+        }
+        *runner = None;
+        false
+    }
+}
+
 impl Drop for Stream {
     fn drop(&mut self) {
-        self.trigger.wakeup();
-        self.thread.take().unwrap().join().unwrap();
+        self.thread.stop();
     }
 }
 
 impl StreamTrait for Stream {
     fn play(&self) -> Result<(), PlayStreamError> {
-        self.inner.channel.pause(false).ok();
+        if self.thread.handle.lock().unwrap().is_none() {
+            let description = "the host was shut down".to_string();
+            return Err(BackendSpecificError { description }.into());
+        }
+        trace_call!("snd_pcm_pause", (false,), self.inner.channel.pause(false)).ok();
+        self.inner.paused.store(false, Ordering::Relaxed);
         Ok(())
     }
     fn pause(&self) -> Result<(), PauseStreamError> {
-        self.inner.channel.pause(true).ok();
+        trace_call!("snd_pcm_pause", (true,), self.inner.channel.pause(true)).ok();
+        self.inner.paused.store(true, Ordering::Relaxed);
         Ok(())
     }
 }
 
+/// The `dmix` or `dsnoop` PCM sharing the card and device of a `hw` or `plughw` PCM.
+fn shared_pcm_name(name: &str, stream_type: alsa::Direction) -> Option<String> {
+    let args = name
+        .strip_prefix("hw:")
+        .or_else(|| name.strip_prefix("plughw:"))?;
+    let plugin = match stream_type {
+        alsa::Direction::Playback => "dmix",
+        alsa::Direction::Capture => "dsnoop",
+    };
+    Some(format!("{}:{}", plugin, args))
+}
+
 fn set_hw_params_from_format(
     pcm_handle: &alsa::pcm::PCM,
     config: &StreamConfig,
@@ -1061,20 +1803,51 @@ fn set_hw_params_from_format(
     hw_params.set_rate(config.sample_rate.0, alsa::ValueOr::Nearest)?;
     hw_params.set_channels(config.channels as u32)?;
 
+    let StreamConfig {
+        period_size,
+        periods,
+        ..
+    } = *config;
+    if let Some(periods) = periods {
+        hw_params.set_periods(periods, alsa::ValueOr::Nearest)?;
+    }
+    if let Some(period_size) = period_size {
+        hw_params.set_period_size_near(period_size as alsa::pcm::Frames, alsa::ValueOr::Nearest)?;
+    }
+
     match config.buffer_size {
         BufferSize::Fixed(v) => {
-            hw_params.set_period_size_near((v / 4) as alsa::pcm::Frames, alsa::ValueOr::Nearest)?;
+            if period_size.is_none() {
+                let periods = periods.unwrap_or(4);
+                hw_params.set_period_size_near(
+                    (v / periods) as alsa::pcm::Frames,
+                    alsa::ValueOr::Nearest,
+                )?;
+            }
             hw_params.set_buffer_size(v as alsa::pcm::Frames)?;
         }
         BufferSize::Default => {
             // These values together represent a moderate latency and wakeup interval.
-            // Without them, we are at the mercy of the device
-            hw_params.set_period_time_near(25_000, alsa::ValueOr::Nearest)?;
-            hw_params.set_buffer_time_near(100_000, alsa::ValueOr::Nearest)?;
+            // Without them, we are at the mercy of the device. Power saving streams wake up less
+            // often at the cost of latency.
+            let (period_time, buffer_time) = match config.performance_mode {
+                PerformanceMode::PowerSaving => (100_000, 400_000),
+                _ => (25_000, 100_000),
+            };
+            if period_size.is_none() {
+                hw_params.set_period_time_near(period_time, alsa::ValueOr::Nearest)?;
+            }
+            if periods.is_none() {
+                hw_params.set_buffer_time_near(buffer_time, alsa::ValueOr::Nearest)?;
+            }
         }
     }
 
-    pcm_handle.hw_params(&hw_params)?;
+    trace_call!(
+        "snd_pcm_hw_params",
+        (&hw_params,),
+        pcm_handle.hw_params(&hw_params)
+    )?;
 
     Ok(hw_params.can_pause())
 }
@@ -1083,6 +1856,7 @@ fn set_sw_params_from_format(
     pcm_handle: &alsa::pcm::PCM,
     config: &StreamConfig,
     stream_type: alsa::Direction,
+    params: &StreamParams,
 ) -> Result<usize, BackendSpecificError> {
     let sw_params = pcm_handle.sw_params_current()?;
 
@@ -1093,10 +1867,11 @@ fn set_sw_params_from_format(
                 description: "initialization resulted in a null buffer".to_string(),
             });
         }
-        sw_params.set_avail_min(period as alsa::pcm::Frames)?;
+        let avail_min = params.avail_min.map_or(period as _, |v| v as _);
+        sw_params.set_avail_min(avail_min)?;
 
         let start_threshold = match stream_type {
-            alsa::Direction::Playback => buffer - period,
+            alsa::Direction::Playback => params.start_threshold.map_or(buffer - period, |v| v as _),
 
             // For capture streams, the start threshold is irrelevant and ignored,
             // because build_stream_inner() starts the stream before process_input()
@@ -1106,6 +1881,10 @@ fn set_sw_params_from_format(
         };
         sw_params.set_start_threshold(start_threshold.try_into().unwrap())?;
 
+        if let Some(stop_threshold) = params.stop_threshold {
+            sw_params.set_stop_threshold(stop_threshold as alsa::pcm::Frames)?;
+        }
+
         period as usize * config.channels as usize
     };
 
@@ -1115,9 +1894,18 @@ fn set_sw_params_from_format(
     // tstamp_type param cannot be changed after the device is opened.
     // The default tstamp_type value on most Linux systems is "monotonic",
     // let's try to use it if setting the tstamp_type fails.
-    if pcm_handle.sw_params(&sw_params).is_err() {
+    let sw_params_result = trace_call!(
+        "snd_pcm_sw_params",
+        (&sw_params,),
+        pcm_handle.sw_params(&sw_params)
+    );
+    if sw_params_result.is_err() {
         sw_params.set_tstamp_type(alsa::pcm::TstampType::Monotonic)?;
-        pcm_handle.sw_params(&sw_params)?;
+        trace_call!(
+            "snd_pcm_sw_params",
+            (&sw_params,),
+            pcm_handle.sw_params(&sw_params)
+        )?;
     }
 
     Ok(period_len)
@@ -1165,3 +1953,58 @@ impl From<alsa::Error> for StreamError {
         err.into()
     }
 }
+
+/// The channel counts to test a device for between `min` and `max`: every count up to 8, the
+/// counts of common multichannel interfaces beyond that, and the device's maximum up to
+/// `MAX_CHANNELS`. Testing every count of a plug device, which accepts up to 10000, would
+/// return far too many configs.
+fn channel_count_candidates(min: u32, max: u32) -> Vec<u32> {
+    const MULTICHANNEL_COUNTS: [u32; 10] = [10, 12, 16, 18, 20, 24, 32, 48, 64, 128];
+    const MAX_CHANNELS: u32 = 128;
+    let max = cmp::min(max, MAX_CHANNELS);
+    let mut counts: Vec<u32> = (min..=cmp::min(max, 8))
+        .chain(MULTICHANNEL_COUNTS)
+        .chain([max])
+        .filter(|count| (min..=max).contains(count))
+        .collect();
+    counts.sort_unstable();
+    counts.dedup();
+    counts
+}
+
+#[test]
+fn test_channel_count_candidates() {
+    assert_eq!(channel_count_candidates(2, 2), [2]);
+    assert_eq!(
+        channel_count_candidates(1, 18),
+        [1, 2, 3, 4, 5, 6, 7, 8, 10, 12, 16, 18]
+    );
+    assert_eq!(channel_count_candidates(64, 64), [64]);
+    assert_eq!(channel_count_candidates(1, 10000).last(), Some(&128));
+}
+
+#[test]
+fn test_channel_order() {
+    use ChannelPosition::*;
+    let layout = ChannelPosition::standard_layout(6).unwrap();
+    let device = ["FL", "FR", "RL", "RR", "FC", "LFE"];
+    assert_eq!(map_channels(layout, &device), Some(vec![0, 1, 4, 5, 2, 3]));
+    // Side speakers stand in for missing back speakers.
+    let device = ["FL", "FR", "FC", "LFE", "SL", "SR"];
+    assert_eq!(map_channels(layout, &device), Some(vec![0, 1, 2, 3, 4, 5]));
+    assert_eq!(map_channels(&[FrontLeft, FrontRight], &["FL", "FC"]), None);
+
+    let mut buffer = [1u8, 2, 3, 4, 5, 6];
+    reorder_frames(&mut buffer, 1, &[0, 2, 1]);
+    assert_eq!(buffer, [1, 3, 2, 4, 6, 5]);
+}
+
+#[test]
+fn test_poll_timeout_millis() {
+    assert_eq!(poll_timeout_millis(&None), -1);
+    assert_eq!(
+        poll_timeout_millis(&Some(Duration::from_millis(1500))),
+        1500
+    );
+    assert_eq!(poll_timeout_millis(&Some(Duration::MAX)), i32::MAX);
+}
@@ -3,6 +3,7 @@ pub type SupportedOutputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>
 
 use super::sys;
 use crate::BackendSpecificError;
+use crate::ClockSource;
 use crate::DefaultStreamConfigError;
 use crate::DeviceNameError;
 use crate::DevicesError;
@@ -149,6 +150,36 @@ impl Device {
         })
     }
 
+    /// The clock sources of the driver, e.g. its internal clock, word clock or S/PDIF.
+    pub fn clock_sources(&self) -> Result<Vec<ClockSource>, BackendSpecificError> {
+        let sources = self
+            .driver
+            .clock_sources()
+            .map_err(|e| BackendSpecificError {
+                description: format!("{}", e),
+            })?;
+        Ok(sources
+            .into_iter()
+            .map(|source| ClockSource {
+                id: source.index as u32,
+                name: source.name,
+                current: source.is_current,
+            })
+            .collect())
+    }
+
+    /// Make the driver run on the clock source with `id`, one of the
+    /// [`clock_sources`](Self::clock_sources), e.g. to slave a recording rig to word clock.
+    ///
+    /// This affects all streams of the driver.
+    pub fn set_clock_source(&self, id: u32) -> Result<(), BackendSpecificError> {
+        self.driver
+            .set_clock_source(id as _)
+            .map_err(|e| BackendSpecificError {
+                description: format!("{}", e),
+            })
+    }
+
     /// Returns the default output config
     pub fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
         let channels = self.driver.channels().map_err(default_config_err)?.outs as u16;
@@ -2,7 +2,7 @@ extern crate asio_sys as sys;
 
 use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
 use crate::{
-    BuildStreamError, Data, DefaultStreamConfigError, DeviceNameError, DevicesError,
+    BuildStreamError, Capabilities, Data, DefaultStreamConfigError, DeviceNameError, DevicesError,
     InputCallbackInfo, OutputCallbackInfo, PauseStreamError, PlayStreamError, SampleFormat,
     StreamConfig, StreamError, SupportedStreamConfig, SupportedStreamConfigsError,
 };
@@ -51,6 +51,14 @@ impl HostTrait for Host {
         // ASIO has no concept of a default device, so just use the first.
         self.output_devices().ok().and_then(|mut ds| ds.next())
     }
+
+    // ASIO drivers always own the device.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            exclusive_mode: true,
+            ..Default::default()
+        }
+    }
 }
 
 impl DeviceTrait for Device {
@@ -82,6 +90,17 @@ impl DeviceTrait for Device {
         Device::default_output_config(self)
     }
 
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            exclusive_mode: true,
+            ..Default::default()
+        }
+    }
+
+    fn bit_perfect(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+
     fn build_input_stream_raw<D, E>(
         &self,
         config: &StreamConfig,
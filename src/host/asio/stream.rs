@@ -638,6 +638,14 @@ fn check_config(
         channels,
         sample_rate,
         buffer_size: _,
+        period_size: _,
+        periods: _,
+        usage: _,
+        performance_mode: _,
+        name: _,
+        callback_relay: _,
+        // The platform `Device` passes only the selected channels to the data callback.
+        input_channels: _,
     } = config;
     // Try and set the sample rate to what the user selected.
     let sample_rate = sample_rate.0.into();
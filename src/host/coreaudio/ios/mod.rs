@@ -10,7 +10,7 @@
 extern crate core_foundation_sys;
 extern crate coreaudio;
 
-use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
 
 use self::coreaudio::audio_unit::render_callback::data;
 use self::coreaudio::audio_unit::{render_callback, AudioUnit, Element, Scope};
@@ -29,6 +29,8 @@ use crate::{
     SupportedStreamConfig, SupportedStreamConfigRange, SupportedStreamConfigsError,
 };
 
+use self::session::{SessionEvent, SessionListener};
+
 use self::enumerate::{
     default_input_device, default_output_device, Devices, SupportedInputConfigs,
     SupportedOutputConfigs,
@@ -37,6 +39,7 @@ use std::slice;
 use std::time::Duration;
 
 pub mod enumerate;
+mod session;
 
 // These days the default of iOS is now F32 and no longer I16
 const SUPPORTED_SAMPLE_FORMAT: SampleFormat = SampleFormat::F32;
@@ -174,7 +177,7 @@ impl DeviceTrait for Device {
         config: &StreamConfig,
         sample_format: SampleFormat,
         mut data_callback: D,
-        mut error_callback: E,
+        error_callback: E,
         _timeout: Option<Duration>,
     ) -> Result<Self::Stream, BuildStreamError>
     where
@@ -185,6 +188,8 @@ impl DeviceTrait for Device {
         let scope = Scope::Output;
         let element = Element::Input;
 
+        session::configure(config.usage, true)?;
+
         let mut audio_unit = create_audio_unit()?;
         audio_unit.uninitialize()?;
         configure_for_recording(&mut audio_unit)?;
@@ -206,6 +211,8 @@ impl DeviceTrait for Device {
         // fed to the audio buffer.
         let bytes_per_channel = sample_format.sample_size();
         let sample_rate = config.sample_rate;
+        let error_callback = Arc::new(Mutex::new(error_callback));
+        let error_callback_session = error_callback.clone();
         type Args = render_callback::Args<data::Raw>;
         audio_unit.set_input_callback(move |args: Args| unsafe {
             let ptr = (*args.data.data).mBuffers.as_ptr() as *const AudioBuffer;
@@ -226,7 +233,7 @@ impl DeviceTrait for Device {
             // TODO: Need a better way to get delay, for now we assume a double-buffer offset.
             let callback = match host_time_to_stream_instant(args.time_stamp.mHostTime) {
                 Err(err) => {
-                    error_callback(err.into());
+                    (error_callback.lock().unwrap())(err.into());
                     return Err(());
                 }
                 Ok(cb) => cb,
@@ -245,10 +252,14 @@ impl DeviceTrait for Device {
 
         audio_unit.start()?;
 
-        Ok(Stream::new(StreamInner {
+        let stream = Stream::new(StreamInner {
             playing: true,
+            interrupted: false,
             audio_unit,
-        }))
+            _session_listener: None,
+        });
+        add_session_listener(&stream, error_callback_session);
+        Ok(stream)
     }
 
     /// Create an output stream.
@@ -257,7 +268,7 @@ impl DeviceTrait for Device {
         config: &StreamConfig,
         sample_format: SampleFormat,
         mut data_callback: D,
-        mut error_callback: E,
+        error_callback: E,
         _timeout: Option<Duration>,
     ) -> Result<Self::Stream, BuildStreamError>
     where
@@ -271,6 +282,8 @@ impl DeviceTrait for Device {
             BufferSize::Default => (),
         };
 
+        session::configure(config.usage, false)?;
+
         let mut audio_unit = create_audio_unit()?;
 
         // The scope and element for working with a device's output stream.
@@ -285,6 +298,8 @@ impl DeviceTrait for Device {
         // fed to the audio buffer.
         let bytes_per_channel = sample_format.sample_size();
         let sample_rate = config.sample_rate;
+        let error_callback = Arc::new(Mutex::new(error_callback));
+        let error_callback_session = error_callback.clone();
         type Args = render_callback::Args<data::Raw>;
         audio_unit.set_render_callback(move |args: Args| unsafe {
             // If `run()` is currently running, then a callback will be available from this list.
@@ -302,7 +317,7 @@ impl DeviceTrait for Device {
 
             let callback = match host_time_to_stream_instant(args.time_stamp.mHostTime) {
                 Err(err) => {
-                    error_callback(err.into());
+                    (error_callback.lock().unwrap())(err.into());
                     return Err(());
                 }
                 Ok(cb) => cb,
@@ -322,28 +337,84 @@ impl DeviceTrait for Device {
 
         audio_unit.start()?;
 
-        Ok(Stream::new(StreamInner {
+        let stream = Stream::new(StreamInner {
             playing: true,
+            interrupted: false,
             audio_unit,
-        }))
+            _session_listener: None,
+        });
+        add_session_listener(&stream, error_callback_session);
+        Ok(stream)
     }
 }
 
 pub struct Stream {
-    inner: RefCell<StreamInner>,
+    inner: Arc<Mutex<StreamInner>>,
 }
 
 impl Stream {
     fn new(inner: StreamInner) -> Self {
         Self {
-            inner: RefCell::new(inner),
+            inner: Arc::new(Mutex::new(inner)),
         }
     }
 }
 
+/// Pause the stream while the audio session is interrupted and resume it afterwards if the
+/// system allows it, and report route changes to the error callback.
+fn add_session_listener<E>(stream: &Stream, error_callback: Arc<Mutex<E>>)
+where
+    E: FnMut(StreamError) + Send + 'static,
+{
+    // Hold a weak reference, the listener is owned by the stream itself.
+    let weak_inner = Arc::downgrade(&stream.inner);
+    let listener = SessionListener::new(move |event| {
+        let inner = match weak_inner.upgrade() {
+            Some(inner) => inner,
+            None => return,
+        };
+        let mut stream = inner.lock().unwrap();
+        match event {
+            SessionEvent::InterruptionBegan => {
+                // The system has already stopped the audio unit, keep our state in sync so
+                // that `play` starts it again.
+                if stream.playing {
+                    let _ = stream.audio_unit.stop();
+                    stream.playing = false;
+                    stream.interrupted = true;
+                }
+            }
+            SessionEvent::InterruptionEnded { should_resume } => {
+                if !std::mem::take(&mut stream.interrupted) || !should_resume {
+                    return;
+                }
+                let result = session::activate().and_then(|()| {
+                    stream.audio_unit.start().map_err(|e| BackendSpecificError {
+                        description: e.to_string(),
+                    })
+                });
+                match result {
+                    Ok(()) => stream.playing = true,
+                    Err(err) => {
+                        drop(stream);
+                        (error_callback.lock().unwrap())(err.into());
+                    }
+                }
+            }
+            SessionEvent::RouteChanged => {
+                // RemoteIO follows the new route on its own.
+                drop(stream);
+                (error_callback.lock().unwrap())(StreamError::DeviceChanged);
+            }
+        }
+    });
+    stream.inner.lock().unwrap()._session_listener = Some(listener);
+}
+
 impl StreamTrait for Stream {
     fn play(&self) -> Result<(), PlayStreamError> {
-        let mut stream = self.inner.borrow_mut();
+        let mut stream = self.inner.lock().unwrap();
+        stream.interrupted = false;
 
         if !stream.playing {
             if let Err(e) = stream.audio_unit.start() {
@@ -357,7 +428,8 @@ impl StreamTrait for Stream {
     }
 
     fn pause(&self) -> Result<(), PauseStreamError> {
-        let mut stream = self.inner.borrow_mut();
+        let mut stream = self.inner.lock().unwrap();
+        stream.interrupted = false;
 
         if stream.playing {
             if let Err(e) = stream.audio_unit.stop() {
@@ -374,7 +446,10 @@ impl StreamTrait for Stream {
 
 struct StreamInner {
     playing: bool,
+    /// Set when the stream was paused by an audio session interruption rather than by the user.
+    interrupted: bool,
     audio_unit: AudioUnit,
+    _session_listener: Option<SessionListener>,
 }
 
 fn create_audio_unit() -> Result<AudioUnit, coreaudio::Error> {
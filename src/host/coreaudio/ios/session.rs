@@ -0,0 +1,312 @@
+//! Minimal bindings to `AVAudioSession` used to apply a `StreamUsage` hint and to observe
+//! interruptions and route changes.
+//!
+//! `AVAudioSession` is an Objective-C API, so the calls go through the objc runtime directly
+//! rather than pulling in a full set of bindings for a handful of messages.
+
+use std::ffi::{c_char, c_ulong, c_void};
+use std::ptr;
+use std::sync::{Arc, Mutex, Once};
+
+use crate::{BackendSpecificError, StreamUsage};
+
+type Id = *mut c_void;
+type Sel = *mut c_void;
+
+#[link(name = "objc")]
+extern "C" {
+    fn objc_getClass(name: *const c_char) -> Id;
+    fn sel_registerName(name: *const c_char) -> Sel;
+    fn objc_msgSend();
+}
+
+#[link(name = "AVFoundation", kind = "framework")]
+extern "C" {
+    static AVAudioSessionCategoryAmbient: Id;
+    static AVAudioSessionCategoryPlayback: Id;
+    static AVAudioSessionCategoryPlayAndRecord: Id;
+    static AVAudioSessionModeDefault: Id;
+    static AVAudioSessionModeVoiceChat: Id;
+    static AVAudioSessionInterruptionNotification: Id;
+    static AVAudioSessionInterruptionTypeKey: Id;
+    static AVAudioSessionInterruptionOptionKey: Id;
+    static AVAudioSessionRouteChangeNotification: Id;
+    static AVAudioSessionRouteChangeReasonKey: Id;
+}
+
+extern "C" {
+    static _NSConcreteGlobalBlock: c_void;
+}
+
+// Values of `AVAudioSessionInterruptionType`, `AVAudioSessionInterruptionOptions` and
+// `AVAudioSessionRouteChangeReason`.
+const INTERRUPTION_TYPE_BEGAN: usize = 1;
+const INTERRUPTION_OPTION_SHOULD_RESUME: usize = 1;
+const ROUTE_CHANGE_NEW_DEVICE_AVAILABLE: usize = 1;
+const ROUTE_CHANGE_OLD_DEVICE_UNAVAILABLE: usize = 2;
+
+/// Configure the shared audio session for a stream with the given usage.
+///
+/// The session is process wide, so the most recently built stream wins. Input streams always
+/// need a category that allows recording.
+pub(super) fn configure(usage: StreamUsage, is_input: bool) -> Result<(), BackendSpecificError> {
+    unsafe {
+        let (category, mode) = match usage {
+            StreamUsage::Default => return Ok(()),
+            StreamUsage::VoiceCommunication => (
+                AVAudioSessionCategoryPlayAndRecord,
+                AVAudioSessionModeVoiceChat,
+            ),
+            _ if is_input => (
+                AVAudioSessionCategoryPlayAndRecord,
+                AVAudioSessionModeDefault,
+            ),
+            StreamUsage::Game | StreamUsage::Notification => {
+                (AVAudioSessionCategoryAmbient, AVAudioSessionModeDefault)
+            }
+            StreamUsage::Media | StreamUsage::Alarm => {
+                (AVAudioSessionCategoryPlayback, AVAudioSessionModeDefault)
+            }
+        };
+
+        let session = shared_session()?;
+        check(
+            send_id_error(session, b"setCategory:error:\0", category),
+            "setCategory",
+        )?;
+        check(send_id_error(session, b"setMode:error:\0", mode), "setMode")?;
+        check(
+            send_bool_error(session, b"setActive:error:\0", true),
+            "setActive",
+        )?;
+    }
+    Ok(())
+}
+
+/// Reactivate the shared audio session, which is required before restarting audio units after
+/// an interruption.
+pub(super) fn activate() -> Result<(), BackendSpecificError> {
+    unsafe {
+        let session = shared_session()?;
+        check(
+            send_bool_error(session, b"setActive:error:\0", true),
+            "setActive",
+        )
+    }
+}
+
+/// A change of the shared audio session reported by the notification center.
+#[derive(Clone, Copy, Debug)]
+pub(super) enum SessionEvent {
+    /// Another app (a phone call, Siri, an alarm) took over the session and the system stopped
+    /// our audio units.
+    InterruptionBegan,
+    /// The interruption is over. `should_resume` is false when the system suggests not to resume
+    /// playback automatically.
+    InterruptionEnded { should_resume: bool },
+    /// An audio route was added or removed, e.g. headphones were plugged in or unplugged.
+    RouteChanged,
+}
+
+type SessionCallback = Arc<Mutex<dyn FnMut(SessionEvent) + Send>>;
+
+static SESSION_LISTENERS: Mutex<Vec<(usize, SessionCallback)>> = Mutex::new(Vec::new());
+static REGISTER_OBSERVERS: Once = Once::new();
+
+/// Keeps a callback subscribed to session events. The callback is removed when this is dropped.
+pub(super) struct SessionListener {
+    id: usize,
+}
+
+impl SessionListener {
+    pub(super) fn new<F>(callback: F) -> Self
+    where
+        F: FnMut(SessionEvent) + Send + 'static,
+    {
+        // The observers are process wide and live as long as the process, like the session.
+        REGISTER_OBSERVERS.call_once(|| unsafe { add_observers() });
+
+        let mut listeners = SESSION_LISTENERS.lock().unwrap();
+        let id = listeners.iter().map(|&(id, _)| id + 1).max().unwrap_or(0);
+        listeners.push((id, Arc::new(Mutex::new(callback))));
+        SessionListener { id }
+    }
+}
+
+impl Drop for SessionListener {
+    fn drop(&mut self) {
+        SESSION_LISTENERS
+            .lock()
+            .unwrap()
+            .retain(|&(id, _)| id != self.id);
+    }
+}
+
+/// The layout of a block literal, as used by the blocks ABI.
+#[repr(C)]
+struct Block {
+    isa: *const c_void,
+    flags: i32,
+    reserved: i32,
+    invoke: unsafe extern "C" fn(*const Block, Id),
+    descriptor: *const BlockDescriptor,
+}
+
+#[repr(C)]
+struct BlockDescriptor {
+    reserved: c_ulong,
+    size: c_ulong,
+}
+
+const BLOCK_IS_GLOBAL: i32 = 1 << 28;
+
+static BLOCK_DESCRIPTOR: BlockDescriptor = BlockDescriptor {
+    reserved: 0,
+    size: std::mem::size_of::<Block>() as c_ulong,
+};
+
+unsafe fn add_observers() {
+    // The block doesn't capture anything so it can be a global block, which is never copied
+    // or released by the runtime.
+    let block: &'static Block = Box::leak(Box::new(Block {
+        isa: &_NSConcreteGlobalBlock as *const c_void,
+        flags: BLOCK_IS_GLOBAL,
+        reserved: 0,
+        invoke: on_notification,
+        descriptor: &BLOCK_DESCRIPTOR,
+    }));
+
+    let center = send_id(
+        objc_getClass(b"NSNotificationCenter\0".as_ptr() as *const c_char),
+        b"defaultCenter\0",
+    );
+    if center.is_null() {
+        return;
+    }
+    let f: unsafe extern "C" fn(Id, Sel, Id, Id, Id, *const Block) -> Id =
+        std::mem::transmute(objc_msgSend as *const ());
+    for name in [
+        AVAudioSessionInterruptionNotification,
+        AVAudioSessionRouteChangeNotification,
+    ] {
+        f(
+            center,
+            sel(b"addObserverForName:object:queue:usingBlock:\0"),
+            name,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            block,
+        );
+    }
+}
+
+unsafe extern "C" fn on_notification(_block: *const Block, notification: Id) {
+    let name = send_id(notification, b"name\0");
+    let user_info = send_id(notification, b"userInfo\0");
+    let event = if send_bool_id(
+        name,
+        b"isEqualToString:\0",
+        AVAudioSessionInterruptionNotification,
+    ) {
+        if user_info_value(user_info, AVAudioSessionInterruptionTypeKey) == INTERRUPTION_TYPE_BEGAN
+        {
+            SessionEvent::InterruptionBegan
+        } else {
+            let options = user_info_value(user_info, AVAudioSessionInterruptionOptionKey);
+            SessionEvent::InterruptionEnded {
+                should_resume: options & INTERRUPTION_OPTION_SHOULD_RESUME != 0,
+            }
+        }
+    } else {
+        match user_info_value(user_info, AVAudioSessionRouteChangeReasonKey) {
+            ROUTE_CHANGE_NEW_DEVICE_AVAILABLE | ROUTE_CHANGE_OLD_DEVICE_UNAVAILABLE => {
+                SessionEvent::RouteChanged
+            }
+            _ => return,
+        }
+    };
+
+    // Call the listeners without holding the list, a callback may drop a stream.
+    let listeners: Vec<SessionCallback> = SESSION_LISTENERS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(_, callback)| callback.clone())
+        .collect();
+    for callback in listeners {
+        (callback.lock().unwrap())(event);
+    }
+}
+
+/// Read an `NSNumber` from a notification's user info dictionary, 0 if it's missing.
+unsafe fn user_info_value(user_info: Id, key: Id) -> usize {
+    if user_info.is_null() {
+        return 0;
+    }
+    let value = send_id_id(user_info, b"objectForKey:\0", key);
+    if value.is_null() {
+        return 0;
+    }
+    send_usize(value, b"unsignedIntegerValue\0")
+}
+
+unsafe fn shared_session() -> Result<Id, BackendSpecificError> {
+    let session = send_id(
+        objc_getClass(b"AVAudioSession\0".as_ptr() as *const c_char),
+        b"sharedInstance\0",
+    );
+    if session.is_null() {
+        return Err(BackendSpecificError {
+            description: "failed to get the shared AVAudioSession".to_owned(),
+        });
+    }
+    Ok(session)
+}
+
+fn check(ok: bool, what: &str) -> Result<(), BackendSpecificError> {
+    if ok {
+        Ok(())
+    } else {
+        Err(BackendSpecificError {
+            description: format!("AVAudioSession {} failed", what),
+        })
+    }
+}
+
+unsafe fn sel(name: &[u8]) -> Sel {
+    sel_registerName(name.as_ptr() as *const c_char)
+}
+
+// `objc_msgSend` must be cast to the exact signature of the method being called.
+
+unsafe fn send_id(receiver: Id, selector: &[u8]) -> Id {
+    let f: unsafe extern "C" fn(Id, Sel) -> Id = std::mem::transmute(objc_msgSend as *const ());
+    f(receiver, sel(selector))
+}
+
+unsafe fn send_id_id(receiver: Id, selector: &[u8], arg: Id) -> Id {
+    let f: unsafe extern "C" fn(Id, Sel, Id) -> Id = std::mem::transmute(objc_msgSend as *const ());
+    f(receiver, sel(selector), arg)
+}
+
+unsafe fn send_bool_id(receiver: Id, selector: &[u8], arg: Id) -> bool {
+    let f: unsafe extern "C" fn(Id, Sel, Id) -> i8 = std::mem::transmute(objc_msgSend as *const ());
+    f(receiver, sel(selector), arg) != 0
+}
+
+unsafe fn send_usize(receiver: Id, selector: &[u8]) -> usize {
+    let f: unsafe extern "C" fn(Id, Sel) -> usize = std::mem::transmute(objc_msgSend as *const ());
+    f(receiver, sel(selector))
+}
+
+unsafe fn send_id_error(receiver: Id, selector: &[u8], arg: Id) -> bool {
+    let f: unsafe extern "C" fn(Id, Sel, Id, *mut Id) -> i8 =
+        std::mem::transmute(objc_msgSend as *const ());
+    f(receiver, sel(selector), arg, ptr::null_mut()) != 0
+}
+
+unsafe fn send_bool_error(receiver: Id, selector: &[u8], arg: bool) -> bool {
+    let f: unsafe extern "C" fn(Id, Sel, i8, *mut Id) -> i8 =
+        std::mem::transmute(objc_msgSend as *const ());
+    f(receiver, sel(selector), arg as i8, ptr::null_mut()) != 0
+}
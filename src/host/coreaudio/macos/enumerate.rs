@@ -84,6 +84,10 @@ impl Iterator for Devices {
         self.0.next().map(|id| Device {
             audio_device_id: id,
             is_default: false,
+            follow_default: false,
+            change_nominal_sample_rate: false,
+            hog_mode: false,
+            channel_map: None,
         })
     }
 }
@@ -114,6 +118,10 @@ pub fn default_input_device() -> Option<Device> {
     let device = Device {
         audio_device_id,
         is_default: true,
+        follow_default: true,
+        change_nominal_sample_rate: false,
+        hog_mode: false,
+        channel_map: None,
     };
     Some(device)
 }
@@ -144,6 +152,10 @@ pub fn default_output_device() -> Option<Device> {
     let device = Device {
         audio_device_id,
         is_default: true,
+        follow_default: true,
+        change_nominal_sample_rate: false,
+        hog_mode: false,
+        channel_map: None,
     };
     Some(device)
 }
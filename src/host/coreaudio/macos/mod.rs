@@ -3,26 +3,32 @@ extern crate coreaudio;
 
 use super::{asbd_from_config, check_os_status, frames_to_duration, host_time_to_stream_instant};
 
+use self::core_foundation_sys::base::CFRelease;
 use self::core_foundation_sys::string::{CFStringGetCString, CFStringGetCStringPtr, CFStringRef};
 use self::coreaudio::audio_unit::render_callback::{self, data};
 use self::coreaudio::audio_unit::{AudioUnit, Element, Scope};
 use self::coreaudio::sys::{
     kAudioDevicePropertyAvailableNominalSampleRates, kAudioDevicePropertyBufferFrameSize,
-    kAudioDevicePropertyBufferFrameSizeRange, kAudioDevicePropertyDeviceIsAlive,
-    kAudioDevicePropertyDeviceNameCFString, kAudioDevicePropertyNominalSampleRate,
+    kAudioDevicePropertyBufferFrameSizeRange, kAudioDevicePropertyClockSource,
+    kAudioDevicePropertyClockSourceNameForIDCFString, kAudioDevicePropertyClockSources,
+    kAudioDevicePropertyDeviceIsAlive, kAudioDevicePropertyDeviceNameCFString,
+    kAudioDevicePropertyHogMode, kAudioDevicePropertyNominalSampleRate,
     kAudioDevicePropertyScopeOutput, kAudioDevicePropertyStreamConfiguration,
-    kAudioDevicePropertyStreamFormat, kAudioObjectPropertyElementMaster,
-    kAudioObjectPropertyScopeGlobal, kAudioObjectPropertyScopeInput,
-    kAudioObjectPropertyScopeOutput, kAudioOutputUnitProperty_CurrentDevice,
-    kAudioOutputUnitProperty_EnableIO, kAudioUnitProperty_StreamFormat, kCFStringEncodingUTF8,
-    AudioBuffer, AudioBufferList, AudioDeviceID, AudioObjectGetPropertyData,
-    AudioObjectGetPropertyDataSize, AudioObjectID, AudioObjectPropertyAddress,
-    AudioObjectPropertyScope, AudioObjectSetPropertyData, AudioStreamBasicDescription,
-    AudioValueRange, OSStatus,
+    kAudioDevicePropertyStreamFormat, kAudioDevicePropertySupportsMixing,
+    kAudioHardwarePropertyDefaultInputDevice, kAudioHardwarePropertyDefaultOutputDevice,
+    kAudioObjectPropertyElementMaster, kAudioObjectPropertyScopeGlobal,
+    kAudioObjectPropertyScopeInput, kAudioObjectPropertyScopeOutput, kAudioObjectSystemObject,
+    kAudioOutputUnitProperty_ChannelMap, kAudioOutputUnitProperty_CurrentDevice,
+    kAudioOutputUnitProperty_EnableIO, kAudioUnitProperty_MaximumFramesPerSlice,
+    kAudioUnitProperty_StreamFormat, kCFStringEncodingUTF8, AudioBuffer, AudioBufferList,
+    AudioDeviceID, AudioObjectGetPropertyData, AudioObjectGetPropertyDataSize, AudioObjectID,
+    AudioObjectPropertyAddress, AudioObjectPropertyScope, AudioObjectSetPropertyData,
+    AudioStreamBasicDescription, AudioUnitSetProperty, AudioValueRange, AudioValueTranslation,
+    OSStatus,
 };
 use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
 use crate::{
-    BackendSpecificError, BufferSize, BuildStreamError, ChannelCount, Data,
+    BackendSpecificError, BufferSize, BuildStreamError, ChannelCount, ClockSource, Data,
     DefaultStreamConfigError, DeviceNameError, DevicesError, InputCallbackInfo, OutputCallbackInfo,
     PauseStreamError, PlayStreamError, SampleFormat, SampleRate, StreamConfig, StreamError,
     SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
@@ -79,6 +85,34 @@ impl HostTrait for Host {
     fn default_output_device(&self) -> Option<Self::Device> {
         default_output_device()
     }
+
+    fn version(&self) -> Option<String> {
+        extern "C" {
+            fn sysctlbyname(
+                name: *const c_char,
+                oldp: *mut std::ffi::c_void,
+                oldlenp: *mut usize,
+                newp: *mut std::ffi::c_void,
+                newlen: usize,
+            ) -> i32;
+        }
+        let mut version = [0u8; 32];
+        let mut len = version.len();
+        let result = unsafe {
+            sysctlbyname(
+                b"kern.osproductversion\0".as_ptr() as *const c_char,
+                version.as_mut_ptr() as *mut std::ffi::c_void,
+                &mut len,
+                std::ptr::null_mut(),
+                0,
+            )
+        };
+        if result != 0 {
+            return None;
+        }
+        let version = CStr::from_bytes_until_nul(&version).ok()?;
+        Some(format!("macOS {}", version.to_string_lossy()))
+    }
 }
 
 impl DeviceTrait for Device {
@@ -110,6 +144,19 @@ impl DeviceTrait for Device {
         Device::default_output_config(self)
     }
 
+    fn is_held_exclusively(&self) -> Option<bool> {
+        // The hog mode property holds the process hogging the device, or -1.
+        let owner: i32 =
+            get_device_property(self.audio_device_id, kAudioDevicePropertyHogMode).ok()?;
+        Some(owner != -1 && owner != std::process::id() as i32)
+    }
+
+    fn bit_perfect(&self) -> Option<Self> {
+        let mut device = self.clone();
+        device.hog_mode = true;
+        Some(device)
+    }
+
     fn build_input_stream_raw<D, E>(
         &self,
         config: &StreamConfig,
@@ -159,9 +206,123 @@ impl DeviceTrait for Device {
 pub struct Device {
     pub(crate) audio_device_id: AudioDeviceID,
     is_default: bool,
+    follow_default: bool,
+    change_nominal_sample_rate: bool,
+    hog_mode: bool,
+    channel_map: Option<Vec<u32>>,
 }
 
 impl Device {
+    /// Set whether streams built from a default device move to the new default device when the
+    /// system default changes (default is true).
+    ///
+    /// Either way, the error callback of such streams receives [`StreamError::DeviceChanged`]
+    /// when the default changes. Has no effect on devices that were not obtained as a default
+    /// device.
+    pub fn set_follow_default(&mut self, follow_default: bool) {
+        self.follow_default = follow_default;
+    }
+
+    /// Set whether output streams switch the device's nominal sample rate to the rate of the
+    /// stream, instead of letting the audio unit resample (default is false).
+    ///
+    /// Input streams always do this. The original rate is restored once the last stream that
+    /// changed it is dropped, and building fails with `StreamConfigNotSupported` if another stream
+    /// of this process holds the device at a different rate.
+    pub fn set_change_nominal_sample_rate(&mut self, change: bool) {
+        self.change_nominal_sample_rate = change;
+    }
+
+    /// Set whether output streams take exclusive access of the device (hog mode) and disable
+    /// mixing, for bit-perfect output (default is false).
+    ///
+    /// Implies [`set_change_nominal_sample_rate`](Self::set_change_nominal_sample_rate). Other
+    /// processes can't use the device until the last such stream is dropped, and building fails
+    /// with `DeviceNotAvailable` if another process already hogs it.
+    pub fn set_hog_mode(&mut self, hog_mode: bool) {
+        self.hog_mode = hog_mode;
+    }
+
+    /// Set the device channels the channels of streams built from this device are routed to,
+    /// through `kAudioOutputUnitProperty_ChannelMap` (default is `None`).
+    ///
+    /// The map holds one zero-based device channel per stream channel, e.g. `[2, 3]` plays a
+    /// stereo stream on outputs 3 and 4. The map must have as many entries as the stream has
+    /// channels, otherwise building fails with `StreamConfigNotSupported`. When unset, the
+    /// stream's channels use the first device channels in order.
+    ///
+    /// Output streams with a channel map stay on the device they were built on, even if it was
+    /// obtained as the default device.
+    pub fn set_channel_map(&mut self, channel_map: Option<Vec<u32>>) {
+        self.channel_map = channel_map;
+    }
+
+    /// The clock sources the device can run on, e.g. its internal clock, word clock or S/PDIF,
+    /// through `kAudioDevicePropertyClockSources`. Empty if the device has no selectable clock.
+    pub fn clock_sources(&self) -> Result<Vec<ClockSource>, BackendSpecificError> {
+        let property_address = AudioObjectPropertyAddress {
+            mSelector: kAudioDevicePropertyClockSources,
+            mScope: kAudioObjectPropertyScopeGlobal,
+            mElement: kAudioObjectPropertyElementMaster,
+        };
+        let data_size = 0u32;
+        let status = unsafe {
+            AudioObjectGetPropertyDataSize(
+                self.audio_device_id,
+                &property_address as *const _,
+                0,
+                null(),
+                &data_size as *const _ as *mut _,
+            )
+        };
+        // Devices without a choice of clock don't have the property.
+        if status != 0 || data_size == 0 {
+            return Ok(vec![]);
+        }
+        let mut ids = vec![0u32; data_size as usize / mem::size_of::<u32>()];
+        let status = unsafe {
+            AudioObjectGetPropertyData(
+                self.audio_device_id,
+                &property_address as *const _,
+                0,
+                null(),
+                &data_size as *const _ as *mut _,
+                ids.as_mut_ptr() as *mut _,
+            )
+        };
+        check_os_status(status)?;
+        ids.truncate(data_size as usize / mem::size_of::<u32>());
+        let current =
+            get_device_property::<u32>(self.audio_device_id, kAudioDevicePropertyClockSource).ok();
+        Ok(ids
+            .into_iter()
+            .map(|id| ClockSource {
+                id,
+                name: clock_source_name(self.audio_device_id, id).unwrap_or_default(),
+                current: current == Some(id),
+            })
+            .collect())
+    }
+
+    /// Make the device run on the clock source with `id`, one of the
+    /// [`clock_sources`](Self::clock_sources), e.g. to slave a recording rig to word clock.
+    ///
+    /// This is a setting of the device, it affects all its streams and other applications and
+    /// outlasts the process.
+    pub fn set_clock_source(&self, id: u32) -> Result<(), BackendSpecificError> {
+        set_device_property(self.audio_device_id, kAudioDevicePropertyClockSource, &id).map_err(
+            |err| BackendSpecificError {
+                description: err.to_string(),
+            },
+        )
+    }
+
+    /// Whether output streams use the default output unit, which follows the default device on
+    /// its own. A channel map needs a unit bound to the device.
+    fn follows_default_output(&self) -> bool {
+        self.is_default && self.follow_default && self.channel_map.is_none()
+    }
+
     fn name(&self) -> Result<String, DeviceNameError> {
         let property_address = AudioObjectPropertyAddress {
             mSelector: kAudioDevicePropertyDeviceNameCFString,
@@ -219,58 +380,27 @@ impl Device {
         &self,
         scope: AudioObjectPropertyScope,
     ) -> Result<SupportedOutputConfigs, SupportedStreamConfigsError> {
-        let mut property_address = AudioObjectPropertyAddress {
-            mSelector: kAudioDevicePropertyStreamConfiguration,
+        let property_address = AudioObjectPropertyAddress {
+            mSelector: kAudioDevicePropertyAvailableNominalSampleRates,
             mScope: scope,
             mElement: kAudioObjectPropertyElementMaster,
         };
 
         unsafe {
-            // Retrieve the devices audio buffer list.
-            let data_size = 0u32;
-            let status = AudioObjectGetPropertyDataSize(
-                self.audio_device_id,
-                &property_address as *const _,
-                0,
-                null(),
-                &data_size as *const _ as *mut _,
-            );
-            check_os_status(status)?;
-
-            let mut audio_buffer_list: Vec<u8> = vec![];
-            audio_buffer_list.reserve_exact(data_size as usize);
-            let status = AudioObjectGetPropertyData(
-                self.audio_device_id,
-                &property_address as *const _,
-                0,
-                null(),
-                &data_size as *const _ as *mut _,
-                audio_buffer_list.as_mut_ptr() as *mut _,
-            );
-            check_os_status(status)?;
-
-            let audio_buffer_list = audio_buffer_list.as_mut_ptr() as *mut AudioBufferList;
-
-            // If there's no buffers, skip.
-            if (*audio_buffer_list).mNumberBuffers == 0 {
+            // Count the number of channels as the sum of all channels in all streams. Aggregate
+            // and AirPlay devices can report streams without channels, those don't count.
+            let n_channels: u32 = stream_channel_counts(self.audio_device_id, scope)?
+                .iter()
+                .sum();
+            if n_channels == 0 {
                 return Ok(vec![].into_iter());
             }
 
-            // Count the number of channels as the sum of all channels in all output buffers.
-            let n_buffers = (*audio_buffer_list).mNumberBuffers as usize;
-            let first: *const AudioBuffer = (*audio_buffer_list).mBuffers.as_ptr();
-            let buffers: &'static [AudioBuffer] = slice::from_raw_parts(first, n_buffers);
-            let mut n_channels = 0;
-            for buffer in buffers {
-                n_channels += buffer.mNumberChannels as usize;
-            }
-
             // TODO: macOS should support U8, I16, I32, F32 and F64. This should allow for using
             // I16 but just use F32 for now as it's the default anyway.
             let sample_format = SampleFormat::F32;
 
             // Get available sample rate ranges.
-            property_address.mSelector = kAudioDevicePropertyAvailableNominalSampleRates;
             let data_size = 0u32;
             let status = AudioObjectGetPropertyDataSize(
                 self.audio_device_id,
@@ -435,6 +565,13 @@ struct StreamInner {
     audio_unit: AudioUnit,
     /// Manage the lifetime of the closure that handles device disconnection.
     _disconnect_listener: Option<AudioObjectPropertyListener>,
+    /// Manage the lifetime of the closure that handles default device changes.
+    _default_device_listener: Option<AudioObjectPropertyListener>,
+    /// Restores the nominal sample rate of the device if this stream changed it.
+    _sample_rate_guard: Option<NominalSampleRateGuard>,
+    /// Releases hog mode on the device if this stream took it. Declared after the sample rate
+    /// guard so the rate is restored while we still have exclusive access.
+    _hog_mode_guard: Option<HogModeGuard>,
     // Track the device with which the audio unit was spawned.
     //
     // We must do this so that we can avoid changing the device sample rate if there is already
@@ -470,8 +607,180 @@ where
     Ok(())
 }
 
+/// Register the default-device-changed callback.
+/// Input streams that follow the default are moved to the new device, output streams that follow
+/// the default use the `DefaultOutput` unit which migrates on its own. In all cases the error
+/// callback is called with DeviceChanged.
+/// This function should only be called once per stream.
+fn add_default_device_listener<E>(
+    stream: &Stream,
+    error_callback: Arc<Mutex<E>>,
+    input: bool,
+    follow_default: bool,
+    sample_rate: SampleRate,
+) -> Result<(), BuildStreamError>
+where
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let selector = if input {
+        kAudioHardwarePropertyDefaultInputDevice
+    } else {
+        kAudioHardwarePropertyDefaultOutputDevice
+    };
+    // Hold a weak reference, the listener is owned by the stream itself.
+    let weak_inner = Arc::downgrade(&stream.inner);
+    let mut stream_inner = stream.inner.lock().unwrap();
+    stream_inner._default_device_listener = Some(AudioObjectPropertyListener::new(
+        kAudioObjectSystemObject,
+        AudioObjectPropertyAddress {
+            mSelector: selector,
+            mScope: kAudioObjectPropertyScopeGlobal,
+            mElement: kAudioObjectPropertyElementMaster,
+        },
+        move || {
+            let inner = match weak_inner.upgrade() {
+                Some(inner) => inner,
+                None => return,
+            };
+            if input && follow_default {
+                if let Some(device) = default_input_device() {
+                    let result =
+                        Stream { inner }.set_input_device(device.audio_device_id, sample_rate);
+                    if let Err(err) = result {
+                        (error_callback.lock().unwrap())(err);
+                        return;
+                    }
+                }
+            }
+            (error_callback.lock().unwrap())(StreamError::DeviceChanged);
+        },
+    )?);
+    Ok(())
+}
+
+/// The channel counts of the device's streams in the given scope. Multi-output and aggregate
+/// devices have one or more streams per sub-device, which don't need to be the same size.
+#[allow(clippy::cast_ptr_alignment)]
+fn stream_channel_counts(
+    audio_device_id: AudioDeviceID,
+    scope: AudioObjectPropertyScope,
+) -> Result<Vec<u32>, BackendSpecificError> {
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: kAudioDevicePropertyStreamConfiguration,
+        mScope: scope,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+
+    unsafe {
+        // Retrieve the devices audio buffer list.
+        let data_size = 0u32;
+        let status = AudioObjectGetPropertyDataSize(
+            audio_device_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+        );
+        check_os_status(status)?;
+
+        let mut audio_buffer_list: Vec<u8> = vec![];
+        audio_buffer_list.reserve_exact(data_size as usize);
+        let status = AudioObjectGetPropertyData(
+            audio_device_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+            audio_buffer_list.as_mut_ptr() as *mut _,
+        );
+        check_os_status(status)?;
+
+        let audio_buffer_list = audio_buffer_list.as_mut_ptr() as *mut AudioBufferList;
+        let n_buffers = (*audio_buffer_list).mNumberBuffers as usize;
+        if n_buffers == 0 {
+            return Ok(vec![]);
+        }
+        let first: *const AudioBuffer = (*audio_buffer_list).mBuffers.as_ptr();
+        let buffers: &[AudioBuffer] = slice::from_raw_parts(first, n_buffers);
+        Ok(buffers.iter().map(|b| b.mNumberChannels).collect())
+    }
+}
+
+/// Build the output channel map for a device with the given stream layout. Without a routing the
+/// stream's channels go to the first device channels across all streams in order, otherwise
+/// stream channel `i` goes to device channel `routing[i]`. Other device channels are unused.
+///
+/// Returns `None` if the routing refers to a channel the device doesn't have.
+fn output_channel_map(
+    stream_channels: &[u32],
+    channels: ChannelCount,
+    routing: Option<&[u32]>,
+) -> Option<Vec<i32>> {
+    let device_channels: u32 = stream_channels.iter().sum();
+    let mut map = vec![-1; device_channels as usize];
+    for c in 0..channels as usize {
+        let device_channel = routing.map_or(c, |routing| routing[c] as usize);
+        if let Some(entry) = map.get_mut(device_channel) {
+            *entry = c as i32;
+        } else if routing.is_some() {
+            return None;
+        }
+    }
+    Some(map)
+}
+
+/// Pin the mapping between the stream's channels and the device's channels, so the unit doesn't
+/// guess for devices with several streams, and apply the device's channel map if any.
+fn set_channel_map(
+    audio_unit: &mut AudioUnit,
+    device: &Device,
+    channels: ChannelCount,
+    input: bool,
+) -> Result<(), BuildStreamError> {
+    let routing = device.channel_map.as_deref();
+    if routing.is_some_and(|routing| routing.len() != channels as usize) {
+        return Err(BuildStreamError::StreamConfigNotSupported);
+    }
+    let (map, element) = if input {
+        // One entry per stream channel, holding the device channel to read.
+        let map = match routing {
+            Some(routing) => {
+                let scope = kAudioObjectPropertyScopeInput;
+                let device_channels: u32 = stream_channel_counts(device.audio_device_id, scope)?
+                    .iter()
+                    .sum();
+                if routing.iter().any(|&c| c >= device_channels) {
+                    return Err(BuildStreamError::StreamConfigNotSupported);
+                }
+                routing.iter().map(|&c| c as i32).collect()
+            }
+            None => (0..channels as i32).collect(),
+        };
+        (map, Element::Input)
+    } else {
+        // One entry per device channel, holding the stream channel to play or -1.
+        let scope = kAudioObjectPropertyScopeOutput;
+        let layout = stream_channel_counts(device.audio_device_id, scope)?;
+        let map = output_channel_map(&layout, channels, routing)
+            .ok_or(BuildStreamError::StreamConfigNotSupported)?;
+        (map, Element::Output)
+    };
+    let status = unsafe {
+        AudioUnitSetProperty(
+            *audio_unit.as_ref(),
+            kAudioOutputUnitProperty_ChannelMap,
+            Scope::Output as u32,
+            element as u32,
+            map.as_ptr() as *const _,
+            (map.len() * mem::size_of::<i32>()) as u32,
+        )
+    };
+    check_os_status(status)?;
+    Ok(())
+}
+
 fn audio_unit_from_device(device: &Device, input: bool) -> Result<AudioUnit, coreaudio::Error> {
-    let output_type = if device.is_default && !input {
+    let output_type = if device.follows_default_output() && !input {
         coreaudio::audio_unit::IOType::DefaultOutput
     } else {
         coreaudio::audio_unit::IOType::HalOutput
@@ -529,36 +838,18 @@ impl Device {
         let element = Element::Input;
 
         // Potentially change the device sample rate to match the config.
-        set_sample_rate(self.audio_device_id, config.sample_rate)?;
+        let sample_rate_guard =
+            NominalSampleRateGuard::acquire(self.audio_device_id, config.sample_rate)?;
 
         let mut audio_unit = audio_unit_from_device(self, true)?;
 
         // Set the stream in interleaved mode.
         let asbd = asbd_from_config(config, sample_format);
         audio_unit.set_property(kAudioUnitProperty_StreamFormat, scope, element, Some(&asbd))?;
+        set_channel_map(&mut audio_unit, self, config.channels, true)?;
 
         // Set the buffersize
-        match config.buffer_size {
-            BufferSize::Fixed(v) => {
-                let buffer_size_range = get_io_buffer_frame_size_range(&audio_unit)?;
-                match buffer_size_range {
-                    SupportedBufferSize::Range { min, max } => {
-                        if v >= min && v <= max {
-                            audio_unit.set_property(
-                                kAudioDevicePropertyBufferFrameSize,
-                                scope,
-                                element,
-                                Some(&v),
-                            )?
-                        } else {
-                            return Err(BuildStreamError::StreamConfigNotSupported);
-                        }
-                    }
-                    SupportedBufferSize::Unknown => (),
-                }
-            }
-            BufferSize::Default => (),
-        }
+        set_buffer_frame_size(&mut audio_unit, &config.buffer_size, scope, element)?;
 
         let error_callback = Arc::new(Mutex::new(error_callback));
         let error_callback_disconnect = error_callback.clone();
@@ -571,6 +862,9 @@ impl Device {
         audio_unit.set_input_callback(move |args: Args| unsafe {
             let ptr = (*args.data.data).mBuffers.as_ptr();
             let len = (*args.data.data).mNumberBuffers as usize;
+            if len == 0 {
+                return Ok(());
+            }
             let buffers: &[AudioBuffer] = slice::from_raw_parts(ptr, len);
 
             // TODO: Perhaps loop over all buffers instead?
@@ -579,6 +873,9 @@ impl Device {
                 mDataByteSize: data_byte_size,
                 mData: data,
             } = buffers[0];
+            if channels == 0 {
+                return Ok(());
+            }
 
             let data = data as *mut ();
             let len = data_byte_size as usize / bytes_per_channel;
@@ -607,14 +904,26 @@ impl Device {
         let stream = Stream::new(StreamInner {
             playing: true,
             _disconnect_listener: None,
+            _default_device_listener: None,
+            _sample_rate_guard: sample_rate_guard,
+            // Only output streams hog the device.
+            _hog_mode_guard: None,
             audio_unit,
             device_id: self.audio_device_id,
         });
 
         // If we didn't request the default device, stop the stream if the
-        // device disconnects.
+        // device disconnects. Otherwise watch for changes of the default device.
         if !self.is_default {
             add_disconnect_listener(&stream, error_callback_disconnect)?;
+        } else {
+            add_default_device_listener(
+                &stream,
+                error_callback_disconnect,
+                true,
+                self.follow_default,
+                config.sample_rate,
+            )?;
         }
 
         stream.inner.lock().unwrap().audio_unit.start()?;
@@ -634,6 +943,19 @@ impl Device {
         D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
+        let hog_mode_guard = if self.hog_mode {
+            Some(HogModeGuard::acquire(self.audio_device_id)?)
+        } else {
+            None
+        };
+        // The output unit resamples on its own, only change the device rate if asked to. Bit
+        // perfect output in hog mode needs the device to run at the stream rate.
+        let sample_rate_guard = if self.change_nominal_sample_rate || self.hog_mode {
+            NominalSampleRateGuard::acquire(self.audio_device_id, config.sample_rate)?
+        } else {
+            None
+        };
+
         let mut audio_unit = audio_unit_from_device(self, false)?;
 
         // The scope and element for working with a device's output stream.
@@ -643,29 +965,13 @@ impl Device {
         // Set the stream in interleaved mode.
         let asbd = asbd_from_config(config, sample_format);
         audio_unit.set_property(kAudioUnitProperty_StreamFormat, scope, element, Some(&asbd))?;
+        // The default output unit follows the default device, whose layout may differ.
+        if !self.follows_default_output() {
+            set_channel_map(&mut audio_unit, self, config.channels, false)?;
+        }
 
         // Set the buffersize
-        match config.buffer_size {
-            BufferSize::Fixed(v) => {
-                let buffer_size_range = get_io_buffer_frame_size_range(&audio_unit)?;
-                match buffer_size_range {
-                    SupportedBufferSize::Range { min, max } => {
-                        if v >= min && v <= max {
-                            audio_unit.set_property(
-                                kAudioDevicePropertyBufferFrameSize,
-                                scope,
-                                element,
-                                Some(&v),
-                            )?
-                        } else {
-                            return Err(BuildStreamError::StreamConfigNotSupported);
-                        }
-                    }
-                    SupportedBufferSize::Unknown => (),
-                }
-            }
-            BufferSize::Default => (),
-        }
+        set_buffer_frame_size(&mut audio_unit, &config.buffer_size, scope, element)?;
 
         let error_callback = Arc::new(Mutex::new(error_callback));
         let error_callback_disconnect = error_callback.clone();
@@ -684,6 +990,9 @@ impl Device {
                 mDataByteSize: data_byte_size,
                 mData: data,
             } = (*args.data.data).mBuffers[0];
+            if (*args.data.data).mNumberBuffers == 0 || channels == 0 {
+                return Ok(());
+            }
 
             let data = data as *mut ();
             let len = data_byte_size as usize / bytes_per_channel;
@@ -712,14 +1021,25 @@ impl Device {
         let stream = Stream::new(StreamInner {
             playing: true,
             _disconnect_listener: None,
+            _default_device_listener: None,
+            _sample_rate_guard: sample_rate_guard,
+            _hog_mode_guard: hog_mode_guard,
             audio_unit,
             device_id: self.audio_device_id,
         });
 
         // If we didn't request the default device, stop the stream if the
-        // device disconnects.
+        // device disconnects. Otherwise watch for changes of the default device.
         if !self.is_default {
             add_disconnect_listener(&stream, error_callback_disconnect)?;
+        } else {
+            add_default_device_listener(
+                &stream,
+                error_callback_disconnect,
+                false,
+                self.follow_default,
+                config.sample_rate,
+            )?;
         }
 
         stream.inner.lock().unwrap().audio_unit.start()?;
@@ -728,14 +1048,252 @@ impl Device {
     }
 }
 
-/// Attempt to set the device sample rate to the provided rate.
-/// Return an error if the requested sample rate is not supported by the device.
-fn set_sample_rate(
-    audio_device_id: AudioObjectID,
-    target_sample_rate: SampleRate,
-) -> Result<(), BuildStreamError> {
-    // Get the current sample rate.
-    let mut property_address = AudioObjectPropertyAddress {
+/// A device this process has taken hog mode on.
+struct HogModeLock {
+    device_id: AudioDeviceID,
+    /// The mixing setting before we disabled it, restored with hog mode.
+    supported_mixing: Option<u32>,
+    users: usize,
+}
+
+/// Devices hogged by streams of this process.
+static HOG_MODE_LOCKS: Mutex<Vec<HogModeLock>> = Mutex::new(Vec::new());
+
+/// Holds exclusive access to a device, released when the last guard for the device is dropped.
+struct HogModeGuard {
+    device_id: AudioDeviceID,
+}
+
+impl HogModeGuard {
+    /// Take hog mode on the device and disable mixing where the device allows it.
+    ///
+    /// Fails with `DeviceNotAvailable` if another process hogs the device.
+    fn acquire(audio_device_id: AudioDeviceID) -> Result<Self, BuildStreamError> {
+        let mut locks = HOG_MODE_LOCKS.lock().unwrap();
+        if let Some(lock) = locks.iter_mut().find(|l| l.device_id == audio_device_id) {
+            lock.users += 1;
+            return Ok(HogModeGuard {
+                device_id: audio_device_id,
+            });
+        }
+
+        let pid = std::process::id() as i32;
+        let owner: i32 = get_device_property(audio_device_id, kAudioDevicePropertyHogMode)?;
+        if owner != -1 && owner != pid {
+            return Err(BuildStreamError::DeviceNotAvailable);
+        }
+        // Setting the property toggles hog mode, so only do it if we don't hold it yet.
+        if owner == -1 {
+            set_device_property(audio_device_id, kAudioDevicePropertyHogMode, &pid)?;
+        }
+        // Not every device lets mixing be turned off, that only costs bit-perfectness.
+        let supported_mixing =
+            get_device_property::<u32>(audio_device_id, kAudioDevicePropertySupportsMixing)
+                .ok()
+                .filter(|_| {
+                    set_device_property(audio_device_id, kAudioDevicePropertySupportsMixing, &0u32)
+                        .is_ok()
+                });
+        locks.push(HogModeLock {
+            device_id: audio_device_id,
+            supported_mixing,
+            users: 1,
+        });
+        Ok(HogModeGuard {
+            device_id: audio_device_id,
+        })
+    }
+}
+
+impl Drop for HogModeGuard {
+    fn drop(&mut self) {
+        let mut locks = HOG_MODE_LOCKS.lock().unwrap();
+        let index = match locks.iter().position(|l| l.device_id == self.device_id) {
+            Some(index) => index,
+            None => return,
+        };
+        locks[index].users -= 1;
+        if locks[index].users == 0 {
+            let lock = locks.remove(index);
+            if let Some(mixing) = lock.supported_mixing {
+                let _ = set_device_property(
+                    lock.device_id,
+                    kAudioDevicePropertySupportsMixing,
+                    &mixing,
+                );
+            }
+            let _ = set_device_property(lock.device_id, kAudioDevicePropertyHogMode, &-1i32);
+        }
+    }
+}
+
+/// The name of the clock source with `id` of a device.
+fn clock_source_name(audio_device_id: AudioDeviceID, id: u32) -> Option<String> {
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: kAudioDevicePropertyClockSourceNameForIDCFString,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+    let mut id = id;
+    let mut name: CFStringRef = null();
+    let mut translation = AudioValueTranslation {
+        mInputData: &mut id as *mut _ as *mut _,
+        mInputDataSize: mem::size_of::<u32>() as u32,
+        mOutputData: &mut name as *mut _ as *mut _,
+        mOutputDataSize: mem::size_of::<CFStringRef>() as u32,
+    };
+    let data_size = mem::size_of::<AudioValueTranslation>() as u32;
+    unsafe {
+        let status = AudioObjectGetPropertyData(
+            audio_device_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+            &mut translation as *mut _ as *mut _,
+        );
+        if status != 0 || name.is_null() {
+            return None;
+        }
+        let mut buf: [c_char; 255] = [0; 255];
+        let result = CFStringGetCString(
+            name,
+            buf.as_mut_ptr(),
+            buf.len() as _,
+            kCFStringEncodingUTF8,
+        );
+        CFRelease(name as *const _);
+        (result != 0).then(|| CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned())
+    }
+}
+
+/// Read a global property of a device.
+fn get_device_property<T: Copy + Default>(
+    audio_device_id: AudioDeviceID,
+    selector: u32,
+) -> Result<T, coreaudio::Error> {
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: selector,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+    let value = T::default();
+    let data_size = mem::size_of::<T>() as u32;
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            audio_device_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+            &value as *const _ as *mut _,
+        )
+    };
+    coreaudio::Error::from_os_status(status)?;
+    Ok(value)
+}
+
+/// Write a global property of a device.
+fn set_device_property<T>(
+    audio_device_id: AudioDeviceID,
+    selector: u32,
+    value: &T,
+) -> Result<(), coreaudio::Error> {
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: selector,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+    let status = unsafe {
+        AudioObjectSetPropertyData(
+            audio_device_id,
+            &property_address as *const _,
+            0,
+            null(),
+            mem::size_of::<T>() as u32,
+            value as *const _ as *const _,
+        )
+    };
+    coreaudio::Error::from_os_status(status)
+}
+
+/// A nominal sample rate this process has set on a device.
+struct NominalSampleRateLock {
+    device_id: AudioDeviceID,
+    /// The rate the device had before we changed it, restored once `users` drops to zero.
+    original_rate: f64,
+    rate: SampleRate,
+    users: usize,
+}
+
+/// Nominal sample rates changed by streams of this process.
+///
+/// The nominal rate is a property of the whole device, so streams sharing a device have to agree
+/// on it.
+static NOMINAL_SAMPLE_RATE_LOCKS: Mutex<Vec<NominalSampleRateLock>> = Mutex::new(Vec::new());
+
+/// Keeps the nominal sample rate of a device at the rate of the stream that owns it.
+///
+/// The original rate is restored when the last guard for the device is dropped.
+struct NominalSampleRateGuard {
+    device_id: AudioDeviceID,
+}
+
+impl NominalSampleRateGuard {
+    /// Set the nominal sample rate of the device, unless it already runs at `sample_rate`.
+    ///
+    /// Fails with `StreamConfigNotSupported` if another stream of this process holds the device
+    /// at a different rate.
+    fn acquire(
+        audio_device_id: AudioDeviceID,
+        sample_rate: SampleRate,
+    ) -> Result<Option<Self>, BuildStreamError> {
+        let mut locks = NOMINAL_SAMPLE_RATE_LOCKS.lock().unwrap();
+        if let Some(lock) = locks.iter_mut().find(|l| l.device_id == audio_device_id) {
+            if lock.rate != sample_rate {
+                return Err(BuildStreamError::StreamConfigNotSupported);
+            }
+            lock.users += 1;
+            return Ok(Some(NominalSampleRateGuard {
+                device_id: audio_device_id,
+            }));
+        }
+
+        let original_rate = get_nominal_sample_rate(audio_device_id)?;
+        if original_rate as u32 == sample_rate.0 {
+            return Ok(None);
+        }
+        set_sample_rate(audio_device_id, sample_rate)?;
+        locks.push(NominalSampleRateLock {
+            device_id: audio_device_id,
+            original_rate,
+            rate: sample_rate,
+            users: 1,
+        });
+        Ok(Some(NominalSampleRateGuard {
+            device_id: audio_device_id,
+        }))
+    }
+}
+
+impl Drop for NominalSampleRateGuard {
+    fn drop(&mut self) {
+        let mut locks = NOMINAL_SAMPLE_RATE_LOCKS.lock().unwrap();
+        let index = match locks.iter().position(|l| l.device_id == self.device_id) {
+            Some(index) => index,
+            None => return,
+        };
+        locks[index].users -= 1;
+        if locks[index].users == 0 {
+            let lock = locks.remove(index);
+            // Nothing sensible to do if the device refuses its previous rate.
+            let _ = set_sample_rate(lock.device_id, SampleRate(lock.original_rate as u32));
+        }
+    }
+}
+
+fn get_nominal_sample_rate(audio_device_id: AudioObjectID) -> Result<f64, coreaudio::Error> {
+    let property_address = AudioObjectPropertyAddress {
         mSelector: kAudioDevicePropertyNominalSampleRate,
         mScope: kAudioObjectPropertyScopeGlobal,
         mElement: kAudioObjectPropertyElementMaster,
@@ -753,6 +1311,22 @@ fn set_sample_rate(
         )
     };
     coreaudio::Error::from_os_status(status)?;
+    Ok(sample_rate)
+}
+
+/// Attempt to set the device sample rate to the provided rate.
+/// Return an error if the requested sample rate is not supported by the device.
+fn set_sample_rate(
+    audio_device_id: AudioObjectID,
+    target_sample_rate: SampleRate,
+) -> Result<(), BuildStreamError> {
+    // Get the current sample rate.
+    let sample_rate = get_nominal_sample_rate(audio_device_id)?;
+    let mut property_address = AudioObjectPropertyAddress {
+        mSelector: kAudioDevicePropertyNominalSampleRate,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
 
     // If the requested sample rate is different to the device sample rate, update the device.
     if sample_rate as u32 != target_sample_rate.0 {
@@ -892,6 +1466,43 @@ impl Stream {
             inner: Arc::new(Mutex::new(inner)),
         }
     }
+
+    /// Point the input audio unit at another device, keeping the play state.
+    fn set_input_device(
+        &self,
+        audio_device_id: AudioDeviceID,
+        sample_rate: SampleRate,
+    ) -> Result<(), StreamError> {
+        fn to_stream_error(err: impl fmt::Display) -> StreamError {
+            let description = err.to_string();
+            BackendSpecificError { description }.into()
+        }
+
+        let mut stream = self.inner.lock().unwrap();
+        if stream.device_id == audio_device_id {
+            return Ok(());
+        }
+        let sample_rate_guard = NominalSampleRateGuard::acquire(audio_device_id, sample_rate)
+            .map_err(to_stream_error)?;
+        stream.audio_unit.stop().map_err(to_stream_error)?;
+        stream.audio_unit.uninitialize().map_err(to_stream_error)?;
+        stream
+            .audio_unit
+            .set_property(
+                kAudioOutputUnitProperty_CurrentDevice,
+                Scope::Global,
+                Element::Output,
+                Some(&audio_device_id),
+            )
+            .map_err(to_stream_error)?;
+        stream.audio_unit.initialize().map_err(to_stream_error)?;
+        if stream.playing {
+            stream.audio_unit.start().map_err(to_stream_error)?;
+        }
+        stream.device_id = audio_device_id;
+        stream._sample_rate_guard = sample_rate_guard;
+        Ok(())
+    }
 }
 
 impl StreamTrait for Stream {
@@ -925,6 +1536,51 @@ impl StreamTrait for Stream {
     }
 }
 
+/// Apply a fixed buffer size to the device's I/O buffer and the audio unit's maximum slice size.
+///
+/// The render callback is never asked for more than `kAudioUnitProperty_MaximumFramesPerSlice`
+/// frames, so it has to be raised along with large I/O buffers.
+fn set_buffer_frame_size(
+    audio_unit: &mut AudioUnit,
+    buffer_size: &BufferSize,
+    scope: Scope,
+    element: Element,
+) -> Result<(), BuildStreamError> {
+    let v = match *buffer_size {
+        BufferSize::Fixed(v) => v,
+        BufferSize::Default => return Ok(()),
+    };
+    match get_io_buffer_frame_size_range(audio_unit)? {
+        SupportedBufferSize::Range { min, max } if v < min || v > max => {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        _ => (),
+    }
+    audio_unit.set_property(
+        kAudioDevicePropertyBufferFrameSize,
+        scope,
+        element,
+        Some(&v),
+    )?;
+    let max_frames: u32 = audio_unit.get_property(
+        kAudioUnitProperty_MaximumFramesPerSlice,
+        Scope::Global,
+        Element::Output,
+    )?;
+    if max_frames < v {
+        // The slice size can only be changed while the audio unit is uninitialized.
+        audio_unit.uninitialize()?;
+        audio_unit.set_property(
+            kAudioUnitProperty_MaximumFramesPerSlice,
+            Scope::Global,
+            Element::Output,
+            Some(&v),
+        )?;
+        audio_unit.initialize()?;
+    }
+    Ok(())
+}
+
 fn get_io_buffer_frame_size_range(
     audio_unit: &AudioUnit,
 ) -> Result<SupportedBufferSize, coreaudio::Error> {
@@ -939,3 +1595,25 @@ fn get_io_buffer_frame_size_range(
         max: buffer_size_range.mMaximum as u32,
     })
 }
+
+#[test]
+fn test_output_channel_map() {
+    assert_eq!(output_channel_map(&[2], 2, None), Some(vec![0, 1]));
+    assert_eq!(
+        output_channel_map(&[2, 2], 2, None),
+        Some(vec![0, 1, -1, -1])
+    );
+    // Asymmetric layout with an empty stream, as reported by some AirPlay devices.
+    assert_eq!(
+        output_channel_map(&[2, 0, 6], 4, None),
+        Some(vec![0, 1, 2, 3, -1, -1, -1, -1])
+    );
+    assert_eq!(output_channel_map(&[], 2, None), Some(Vec::new()));
+    // Stereo on outputs 3 and 4, and swapped.
+    assert_eq!(
+        output_channel_map(&[2, 2], 2, Some(&[2, 3])),
+        Some(vec![-1, -1, 0, 1])
+    );
+    assert_eq!(output_channel_map(&[2], 2, Some(&[1, 0])), Some(vec![1, 0]));
+    assert_eq!(output_channel_map(&[2], 2, Some(&[1, 2])), None);
+}
@@ -0,0 +1,612 @@
+//! A host that writes everything played on its devices to a file.
+//!
+//! Each device is a single output that renders into a WAV or headerless raw file, either at the
+//! pace of a real device or as fast as the data callback allows. This is meant for regression
+//! tests that compare rendered output against a known good file.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::shutdown::Worker;
+use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
+use crate::{
+    BackendSpecificError, BufferSize, BuildStreamError, Data, DefaultStreamConfigError,
+    DeviceNameError, DevicesError, FrameCount, InputCallbackInfo, OutputCallbackInfo,
+    OutputStreamTimestamp, PauseStreamError, PlayStreamError, SampleFormat, SampleRate,
+    StreamConfig, StreamError, StreamInstant, SupportedBufferSize, SupportedStreamConfig,
+    SupportedStreamConfigRange, SupportedStreamConfigsError,
+};
+
+pub type SupportedInputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+pub type SupportedOutputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+
+const SUPPORTED_SAMPLE_FORMATS: [SampleFormat; 5] = [
+    SampleFormat::U8,
+    SampleFormat::I16,
+    SampleFormat::I32,
+    SampleFormat::F32,
+    SampleFormat::F64,
+];
+const MIN_CHANNELS: u16 = 1;
+const MAX_CHANNELS: u16 = 32;
+const MIN_SAMPLE_RATE: SampleRate = SampleRate(8_000);
+const MAX_SAMPLE_RATE: SampleRate = SampleRate(192_000);
+const DEFAULT_SAMPLE_RATE: SampleRate = SampleRate(48_000);
+const MIN_BUFFER_SIZE: FrameCount = 1;
+const MAX_BUFFER_SIZE: FrameCount = 65_536;
+const DEFAULT_BUFFER_SIZE: FrameCount = 512;
+const DEFAULT_PATH: &str = "cpal-output.wav";
+
+// `WAVE_FORMAT_PCM` and `WAVE_FORMAT_IEEE_FLOAT`.
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAV_HEADER_LEN: u32 = 44;
+
+/// The host. Its devices are set with [`Host::set_devices`].
+#[derive(Clone, Debug)]
+pub struct Host {
+    devices: Vec<Device>,
+}
+
+pub struct Devices(std::vec::IntoIter<Device>);
+
+/// The layout of the files written by a device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileFormat {
+    /// A RIFF WAVE file.
+    Wav,
+    /// Interleaved little-endian samples without a header.
+    Raw,
+}
+
+/// An output device writing to the file at a given path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Device {
+    path: PathBuf,
+    file_format: FileFormat,
+    real_time: bool,
+    frame_limit: Option<u64>,
+}
+
+pub struct Stream {
+    shared: Arc<Shared>,
+}
+
+/// State shared with the rendering thread.
+struct Shared {
+    state: Mutex<State>,
+    condvar: Condvar,
+    /// The rendering thread, `None` once it was joined.
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+struct State {
+    playing: bool,
+    stop: bool,
+    frames_written: u64,
+}
+
+impl Host {
+    /// Create a host with a single device writing to `cpal-output.wav` in the current directory.
+    pub fn new() -> Result<Self, crate::HostUnavailable> {
+        Ok(Host {
+            devices: vec![Device::new(DEFAULT_PATH)],
+        })
+    }
+
+    /// Replace the devices of the host. The first device is the default output device.
+    pub fn set_devices(&mut self, devices: Vec<Device>) {
+        self.devices = devices;
+    }
+}
+
+impl Device {
+    /// Create a device writing to `path`.
+    ///
+    /// The file is created, or truncated, when a stream is built. Files with a `.wav` extension are
+    /// written as WAV, any other as raw samples.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_owned();
+        let is_wav = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("wav"));
+        Device {
+            path,
+            file_format: if is_wav {
+                FileFormat::Wav
+            } else {
+                FileFormat::Raw
+            },
+            real_time: true,
+            frame_limit: None,
+        }
+    }
+
+    /// Set the layout of the file, overriding the one derived from the path.
+    pub fn set_file_format(&mut self, file_format: FileFormat) {
+        self.file_format = file_format;
+    }
+
+    /// Set whether streams call the data callback at the pace of a real device with the stream's
+    /// sample rate, rather than as fast as possible (default is true).
+    pub fn set_real_time(&mut self, real_time: bool) {
+        self.real_time = real_time;
+    }
+
+    /// Set the number of frames after which streams stop and finish the file (default is `None`).
+    ///
+    /// Without a limit the file is finished when the stream is dropped.
+    pub fn set_frame_limit(&mut self, frame_limit: Option<u64>) {
+        self.frame_limit = frame_limit;
+    }
+
+    fn supported_configs() -> Vec<SupportedStreamConfigRange> {
+        let mut configs = vec![];
+        for &sample_format in SUPPORTED_SAMPLE_FORMATS.iter() {
+            for channels in MIN_CHANNELS..=MAX_CHANNELS {
+                configs.push(SupportedStreamConfigRange {
+                    channels,
+                    min_sample_rate: MIN_SAMPLE_RATE,
+                    max_sample_rate: MAX_SAMPLE_RATE,
+                    buffer_size: SupportedBufferSize::Range {
+                        min: MIN_BUFFER_SIZE,
+                        max: MAX_BUFFER_SIZE,
+                    },
+                    sample_format,
+                });
+            }
+        }
+        configs
+    }
+}
+
+impl Stream {
+    /// The number of frames written to the file so far.
+    pub fn frames_written(&self) -> u64 {
+        self.shared.state.lock().unwrap().frames_written
+    }
+}
+
+impl HostTrait for Host {
+    type Devices = Devices;
+    type Device = Device;
+
+    fn is_available() -> bool {
+        true
+    }
+
+    fn devices(&self) -> Result<Self::Devices, DevicesError> {
+        Ok(Devices(self.devices.clone().into_iter()))
+    }
+
+    fn default_input_device(&self) -> Option<Device> {
+        None
+    }
+
+    fn default_output_device(&self) -> Option<Device> {
+        self.devices.first().cloned()
+    }
+}
+
+impl DeviceTrait for Device {
+    type SupportedInputConfigs = SupportedInputConfigs;
+    type SupportedOutputConfigs = SupportedOutputConfigs;
+    type Stream = Stream;
+
+    fn name(&self) -> Result<String, DeviceNameError> {
+        Ok(self.path.display().to_string())
+    }
+
+    fn supported_input_configs(
+        &self,
+    ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
+        Ok(Vec::new().into_iter())
+    }
+
+    fn supported_output_configs(
+        &self,
+    ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError> {
+        Ok(Device::supported_configs().into_iter())
+    }
+
+    fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        Err(DefaultStreamConfigError::StreamTypeNotSupported)
+    }
+
+    fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        Ok(SupportedStreamConfig {
+            channels: 2,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            buffer_size: SupportedBufferSize::Range {
+                min: MIN_BUFFER_SIZE,
+                max: MAX_BUFFER_SIZE,
+            },
+            sample_format: SampleFormat::F32,
+        })
+    }
+
+    fn build_input_stream_raw<D, E>(
+        &self,
+        _config: &StreamConfig,
+        _sample_format: SampleFormat,
+        _data_callback: D,
+        _error_callback: E,
+        _timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        Err(BuildStreamError::StreamConfigNotSupported)
+    }
+
+    fn build_output_stream_raw<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+        _timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let buffer_size = match config.buffer_size {
+            BufferSize::Fixed(size) if (MIN_BUFFER_SIZE..=MAX_BUFFER_SIZE).contains(&size) => size,
+            BufferSize::Fixed(_) => return Err(BuildStreamError::StreamConfigNotSupported),
+            BufferSize::Default => DEFAULT_BUFFER_SIZE,
+        };
+        if !SUPPORTED_SAMPLE_FORMATS.contains(&sample_format)
+            || !(MIN_CHANNELS..=MAX_CHANNELS).contains(&config.channels)
+            || config.sample_rate < MIN_SAMPLE_RATE
+            || config.sample_rate > MAX_SAMPLE_RATE
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+
+        let mut writer =
+            File::create(&self.path)
+                .map(BufWriter::new)
+                .map_err(|e| BackendSpecificError {
+                    description: format!("failed to create {}: {}", self.path.display(), e),
+                })?;
+        if self.file_format == FileFormat::Wav {
+            write_wav_header(&mut writer, config, sample_format, 0).map_err(to_backend_error)?;
+        }
+
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                playing: false,
+                stop: false,
+                frames_written: 0,
+            }),
+            condvar: Condvar::new(),
+            thread: Mutex::new(None),
+        });
+        let render = Render {
+            shared: shared.clone(),
+            writer,
+            file_format: self.file_format,
+            real_time: self.real_time,
+            frame_limit: self.frame_limit,
+            config: config.clone(),
+            sample_format,
+            buffer_size,
+        };
+        let thread = thread::Builder::new()
+            .name(config.thread_name("cpal_file_out", &self.path.to_string_lossy()))
+            .spawn(move || render.run(data_callback, error_callback))
+            .map_err(to_backend_error)?;
+
+        *shared.thread.lock().unwrap() = Some(thread);
+        crate::shutdown::register(crate::HostId::File, &shared);
+        Ok(Stream { shared })
+    }
+}
+
+/// The rendering thread's side of a stream.
+struct Render {
+    shared: Arc<Shared>,
+    writer: BufWriter<File>,
+    file_format: FileFormat,
+    real_time: bool,
+    frame_limit: Option<u64>,
+    config: StreamConfig,
+    sample_format: SampleFormat,
+    buffer_size: FrameCount,
+}
+
+impl Render {
+    fn run<D, E>(mut self, mut data_callback: D, mut error_callback: E)
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let channels = self.config.channels as usize;
+        let sample_rate = self.config.sample_rate.0 as u64;
+        let sample_size = self.sample_format.sample_size();
+        let mut buffer = vec![0u8; self.buffer_size as usize * channels * sample_size];
+        let mut frames_written = 0u64;
+        // The time and frame count when playback last resumed, to pace real-time rendering.
+        let mut resumed: Option<(Instant, u64)> = None;
+
+        loop {
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                while !state.playing && !state.stop {
+                    resumed = None;
+                    state = self.shared.condvar.wait(state).unwrap();
+                }
+                if state.stop {
+                    break;
+                }
+            }
+
+            let frames = match self.frame_limit {
+                Some(limit) if frames_written >= limit => break,
+                Some(limit) => (limit - frames_written).min(self.buffer_size as u64) as usize,
+                None => self.buffer_size as usize,
+            };
+
+            if self.real_time {
+                let (instant, frame) = *resumed.get_or_insert((Instant::now(), frames_written));
+                let due = instant + frames_to_duration(frames_written - frame, sample_rate);
+                if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                    thread::sleep(wait);
+                }
+            }
+
+            let len = frames * channels * sample_size;
+            let bytes = &mut buffer[..len];
+            fill_equilibrium(bytes, self.sample_format);
+            let instant = frames_to_instant(frames_written, sample_rate);
+            let info = OutputCallbackInfo {
+                timestamp: OutputStreamTimestamp {
+                    callback: instant,
+                    playback: instant,
+                },
+            };
+            let mut data = unsafe {
+                Data::from_parts(
+                    bytes.as_mut_ptr() as *mut (),
+                    frames * channels,
+                    self.sample_format,
+                )
+            };
+            data_callback(&mut data, &info);
+
+            #[cfg(target_endian = "big")]
+            for sample in bytes.chunks_mut(sample_size) {
+                sample.reverse();
+            }
+            if let Err(err) = self.writer.write_all(bytes) {
+                error_callback(to_backend_error(err).into());
+                break;
+            }
+            frames_written += frames as u64;
+            self.shared.state.lock().unwrap().frames_written = frames_written;
+        }
+
+        if let Err(err) = self.finish(frames_written) {
+            error_callback(to_backend_error(err).into());
+        }
+    }
+
+    /// Flush the file and fill in the sizes of the WAV header.
+    fn finish(&mut self, frames_written: u64) -> io::Result<()> {
+        if self.file_format == FileFormat::Wav {
+            let frame_size = self.config.channels as u64 * self.sample_format.sample_size() as u64;
+            let data_len = frames_written * frame_size;
+            self.writer.seek(SeekFrom::Start(0))?;
+            write_wav_header(&mut self.writer, &self.config, self.sample_format, data_len)?;
+        }
+        self.writer.flush()
+    }
+}
+
+impl StreamTrait for Stream {
+    fn play(&self) -> Result<(), PlayStreamError> {
+        self.shared.state.lock().unwrap().playing = true;
+        self.shared.condvar.notify_one();
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), PauseStreamError> {
+        self.shared.state.lock().unwrap().playing = false;
+        Ok(())
+    }
+}
+
+impl Worker for Shared {
+    fn stop(&self) {
+        self.state.lock().unwrap().stop = true;
+        self.condvar.notify_one();
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        self.shared.stop();
+    }
+}
+
+impl Iterator for Devices {
+    type Item = Device;
+
+    fn next(&mut self) -> Option<Device> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+/// Write a 44 byte WAV header for `data_len` bytes of samples. Sizes that don't fit are clamped.
+fn write_wav_header<W: Write>(
+    writer: &mut W,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    data_len: u64,
+) -> io::Result<()> {
+    let format_tag = if sample_format.is_float() {
+        WAVE_FORMAT_IEEE_FLOAT
+    } else {
+        WAVE_FORMAT_PCM
+    };
+    let channels = config.channels;
+    let block_align = channels as u32 * sample_format.sample_size() as u32;
+    let data_len = data_len.min((u32::MAX - WAV_HEADER_LEN) as u64) as u32;
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(WAV_HEADER_LEN - 8 + data_len).to_le_bytes())?;
+    writer.write_all(b"WAVEfmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    writer.write_all(&format_tag.to_le_bytes())?;
+    writer.write_all(&channels.to_le_bytes())?;
+    writer.write_all(&config.sample_rate.0.to_le_bytes())?;
+    writer.write_all(&(config.sample_rate.0 * block_align).to_le_bytes())?;
+    writer.write_all(&(block_align as u16).to_le_bytes())?;
+    writer.write_all(&(sample_format.sample_size() as u16 * 8).to_le_bytes())?;
+    writer.write_all(b"data")?;
+    writer.write_all(&data_len.to_le_bytes())
+}
+
+/// Fill a buffer with silence. Unsigned formats are centered around half their range.
+fn fill_equilibrium(bytes: &mut [u8], sample_format: SampleFormat) {
+    let value = if sample_format == SampleFormat::U8 {
+        0x80
+    } else {
+        0
+    };
+    bytes.fill(value);
+}
+
+fn frames_to_duration(frames: u64, sample_rate: u64) -> Duration {
+    Duration::from_nanos((frames as u128 * 1_000_000_000 / sample_rate as u128) as u64)
+}
+
+fn frames_to_instant(frames: u64, sample_rate: u64) -> StreamInstant {
+    let nanos = frames as u128 * 1_000_000_000 / sample_rate as u128;
+    StreamInstant::from_nanos_i128(nanos as i128).expect("stream time out of range")
+}
+
+fn to_backend_error(err: io::Error) -> BackendSpecificError {
+    BackendSpecificError {
+        description: err.to_string(),
+    }
+}
+
+#[cfg(test)]
+fn render_to_file(
+    name: &str,
+    frame_limit: Option<u64>,
+    sample_format: SampleFormat,
+    wait_for: u64,
+) -> Vec<u8> {
+    let _tests = crate::shutdown::FILE_HOST_TESTS
+        .read()
+        .unwrap_or_else(|e| e.into_inner());
+    let path = std::env::temp_dir().join(format!("cpal-{}-{}", std::process::id(), name));
+    let mut device = Device::new(&path);
+    // Without a limit, rendering in real time keeps the file small until the stream is dropped.
+    device.set_real_time(frame_limit.is_none());
+    device.set_frame_limit(frame_limit);
+    let mut config = device.default_output_config().unwrap().config();
+    config.sample_rate = SampleRate(44_100);
+    config.buffer_size = BufferSize::Fixed(32);
+    let mut next = 0i16;
+    let stream = device
+        .build_output_stream_raw(
+            &config,
+            sample_format,
+            move |data: &mut Data, _: &OutputCallbackInfo| {
+                for sample in data.as_slice_mut::<i16>().into_iter().flatten() {
+                    *sample = next;
+                    next = next.wrapping_add(1);
+                }
+                for sample in data.as_slice_mut::<f32>().into_iter().flatten() {
+                    *sample = next as f32;
+                    next = next.wrapping_add(1);
+                }
+            },
+            |err| panic!("{}", err),
+            None,
+        )
+        .unwrap();
+    stream.play().unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while stream.frames_written() < wait_for {
+        assert!(Instant::now() < deadline, "the stream stopped rendering");
+        thread::sleep(Duration::from_millis(1));
+    }
+    // Dropping the stream finishes the file.
+    drop(stream);
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    bytes
+}
+
+#[cfg(test)]
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+#[test]
+fn test_wav_file() {
+    // 100 frames are not a whole number of 32 frame buffers.
+    let bytes = render_to_file("test.wav", Some(100), SampleFormat::I16, 100);
+    let data_len = 100 * 2 * 2;
+    assert_eq!(bytes.len(), WAV_HEADER_LEN as usize + data_len);
+    assert_eq!(&bytes[0..4], b"RIFF");
+    assert_eq!(read_u32(&bytes, 4), 36 + data_len as u32);
+    assert_eq!(&bytes[8..16], b"WAVEfmt ");
+    assert_eq!(read_u32(&bytes, 16), 16);
+    assert_eq!(bytes[20..22], WAVE_FORMAT_PCM.to_le_bytes());
+    assert_eq!(bytes[22..24], 2u16.to_le_bytes());
+    assert_eq!(read_u32(&bytes, 24), 44_100);
+    assert_eq!(read_u32(&bytes, 28), 44_100 * 4);
+    assert_eq!(bytes[32..34], 4u16.to_le_bytes());
+    assert_eq!(bytes[34..36], 16u16.to_le_bytes());
+    assert_eq!(&bytes[36..40], b"data");
+    assert_eq!(read_u32(&bytes, 40), data_len as u32);
+    let samples: Vec<i16> = bytes[44..]
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect();
+    assert_eq!(samples, (0..200).collect::<Vec<i16>>());
+}
+
+#[test]
+fn test_float_wav_header() {
+    let bytes = render_to_file("test-float.wav", Some(10), SampleFormat::F32, 10);
+    assert_eq!(bytes.len(), WAV_HEADER_LEN as usize + 10 * 2 * 4);
+    assert_eq!(bytes[20..22], WAVE_FORMAT_IEEE_FLOAT.to_le_bytes());
+    assert_eq!(read_u32(&bytes, 28), 44_100 * 8);
+    assert_eq!(bytes[32..34], 8u16.to_le_bytes());
+    assert_eq!(bytes[34..36], 32u16.to_le_bytes());
+    assert_eq!(read_u32(&bytes, 40), 10 * 2 * 4);
+}
+
+#[test]
+fn test_raw_file() {
+    // Without a frame limit, the stream renders until it is dropped.
+    let bytes = render_to_file("test.raw", None, SampleFormat::F32, 100);
+    assert!(bytes.len() >= 100 * 2 * 4);
+    assert_eq!(bytes.len() % (32 * 2 * 4), 0);
+    let samples: Vec<f32> = bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    for (i, &sample) in samples.iter().enumerate() {
+        assert_eq!(sample, i as i16 as f32);
+    }
+}
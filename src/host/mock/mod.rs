@@ -0,0 +1,782 @@
+//! A scriptable host for testing code built on cpal without audio hardware.
+//!
+//! Devices and their configs are set up from Rust and streams never run on their own: the data
+//! callback is only called from [`Stream::render`] and [`Stream::capture`], and the timestamps it
+//! receives advance with the number of frames processed rather than with the wall clock. This
+//! makes tests of callbacks deterministic. To drive the time of several streams manually, give
+//! their device a shared [`Clock`].
+//!
+//! Failures can be scheduled at chosen frames with [`Stream::schedule_fault`], to exercise error
+//! handling and recovery paths.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
+use crate::{
+    BackendSpecificError, BufferSize, BuildStreamError, Capabilities, Data,
+    DefaultStreamConfigError, DeviceNameError, DevicesError, InputCallbackInfo,
+    InputStreamTimestamp, OutputCallbackInfo, OutputStreamTimestamp, PauseStreamError,
+    PlayStreamError, SampleFormat, SampleRate, SizedSample, StreamConfig, StreamError,
+    StreamInstant, SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
+    SupportedStreamConfigsError,
+};
+
+pub type SupportedInputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+pub type SupportedOutputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+
+/// The host. Its devices are set with [`Host::set_devices`].
+#[derive(Clone, Debug)]
+pub struct Host {
+    devices: Vec<Device>,
+}
+
+pub struct Devices(std::vec::IntoIter<Device>);
+
+/// A device with scripted configs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Device {
+    name: String,
+    input_configs: Vec<SupportedStreamConfigRange>,
+    output_configs: Vec<SupportedStreamConfigRange>,
+    available: bool,
+    clock: Option<Clock>,
+    latency: Duration,
+    capabilities: Capabilities,
+}
+
+/// A manually advanced device time, shared by the streams of the devices it is set on with
+/// [`Device::set_clock`].
+///
+/// Cloning a clock returns a handle to the same time.
+#[derive(Clone, Debug, Default)]
+pub struct Clock {
+    now: Arc<Mutex<Duration>>,
+}
+
+/// A stream whose data callback is driven by the test.
+///
+/// Streams are created paused, like a device that only starts on `play`.
+pub struct Stream {
+    inner: Arc<Mutex<StreamInner>>,
+}
+
+/// A failure a stream reports once it has processed a given number of frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// The device is unplugged. The stream reports [`StreamError::DeviceNotAvailable`] and stops
+    /// for good.
+    DeviceRemoved,
+    /// The device switches to another format, so the stream has to be rebuilt. The stream reports
+    /// a [`StreamError::BackendSpecific`] error and stops for good.
+    FormatChanged,
+    /// The stream misses a period. It reports [`StreamError::Xrun`] and skips the data callback
+    /// once: output streams render silence and input streams drop the captured samples.
+    Underrun,
+    /// A backend call fails with the given `HRESULT`. The stream reports a
+    /// [`StreamError::BackendSpecific`] error and keeps running.
+    Hresult(i32),
+}
+
+struct StreamInner {
+    playing: bool,
+    config: StreamConfig,
+    sample_format: SampleFormat,
+    frames_processed: u64,
+    clock: Option<Clock>,
+    latency: Duration,
+    /// Scheduled faults, sorted by frame.
+    faults: Vec<(u64, Fault)>,
+    /// The fault that stopped the stream for good.
+    failed: Option<Fault>,
+    data_callback: DataCallback,
+    error_callback: Box<dyn FnMut(StreamError) + Send + 'static>,
+}
+
+type InputDataCallback = Box<dyn FnMut(&Data, &InputCallbackInfo) + Send + 'static>;
+type OutputDataCallback = Box<dyn FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static>;
+
+enum DataCallback {
+    Input(InputDataCallback),
+    Output(OutputDataCallback),
+}
+
+impl Host {
+    /// Create a host with a single "Mock" device that supports stereo and mono `f32` and `i16`
+    /// input and output at 44.1 and 48 kHz. Its default configs are stereo `f32` at 48 kHz.
+    pub fn new() -> Result<Self, crate::HostUnavailable> {
+        let mut device = Device::new("Mock");
+        let configs: Vec<_> = [SampleFormat::F32, SampleFormat::I16]
+            .iter()
+            .flat_map(|&sample_format| {
+                [2, 1].map(move |channels| SupportedStreamConfigRange {
+                    channels,
+                    min_sample_rate: SampleRate(44_100),
+                    max_sample_rate: SampleRate(48_000),
+                    buffer_size: SupportedBufferSize::Range { min: 16, max: 4096 },
+                    sample_format,
+                })
+            })
+            .collect();
+        device.set_input_configs(configs.clone());
+        device.set_output_configs(configs);
+        Ok(Host {
+            devices: vec![device],
+        })
+    }
+
+    /// Replace the devices of the host.
+    ///
+    /// The first device with input configs is the default input device and the first device with
+    /// output configs is the default output device.
+    pub fn set_devices(&mut self, devices: Vec<Device>) {
+        self.devices = devices;
+    }
+}
+
+impl Device {
+    /// Create a device without any supported configs.
+    pub fn new(name: impl Into<String>) -> Self {
+        Device {
+            name: name.into(),
+            input_configs: Vec::new(),
+            output_configs: Vec::new(),
+            available: true,
+            clock: None,
+            latency: Duration::ZERO,
+            capabilities: Capabilities::default(),
+        }
+    }
+
+    /// Set the supported input configs. The default input config is the first of them at its
+    /// maximum sample rate.
+    pub fn set_input_configs(&mut self, configs: Vec<SupportedStreamConfigRange>) {
+        self.input_configs = configs;
+    }
+
+    /// Set the supported output configs. The default output config is the first of them at its
+    /// maximum sample rate.
+    pub fn set_output_configs(&mut self, configs: Vec<SupportedStreamConfigRange>) {
+        self.output_configs = configs;
+    }
+
+    /// Set whether the device is available (default is true).
+    ///
+    /// Config queries and stream creation on an unavailable device fail with
+    /// `DeviceNotAvailable`, as if it had been unplugged.
+    pub fn set_available(&mut self, available: bool) {
+        self.available = available;
+    }
+
+    /// Set the clock new streams take their time from (default is none).
+    ///
+    /// Without a clock, every stream has its own time that advances with the frames it
+    /// processes. With a clock, [`Stream::render`] and [`Stream::capture`] leave the time alone
+    /// and the test advances it with [`Clock::advance`] or [`Clock::advance_frames`].
+    pub fn set_clock(&mut self, clock: Option<Clock>) {
+        self.clock = clock;
+    }
+
+    /// Set the latency reported in the timestamps of new streams (default is zero).
+    ///
+    /// Output streams report a playback time `latency` after the callback, input streams report
+    /// a capture time `latency` before it.
+    pub fn set_latency(&mut self, latency: Duration) {
+        self.latency = latency;
+    }
+
+    /// Set the capabilities the device reports (default is none).
+    ///
+    /// The host reports the capabilities any of its devices has. The mock host doesn't
+    /// implement them, they only let tests check how code reacts to them.
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+    }
+
+    fn supported_configs(
+        &self,
+        configs: &[SupportedStreamConfigRange],
+    ) -> Result<std::vec::IntoIter<SupportedStreamConfigRange>, SupportedStreamConfigsError> {
+        if !self.available {
+            return Err(SupportedStreamConfigsError::DeviceNotAvailable);
+        }
+        Ok(Vec::from(configs).into_iter())
+    }
+
+    fn default_config(
+        &self,
+        configs: &[SupportedStreamConfigRange],
+    ) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        if !self.available {
+            return Err(DefaultStreamConfigError::DeviceNotAvailable);
+        }
+        configs
+            .first()
+            .cloned()
+            .map(SupportedStreamConfigRange::with_max_sample_rate)
+            .ok_or(DefaultStreamConfigError::StreamTypeNotSupported)
+    }
+
+    fn build_stream(
+        &self,
+        configs: &[SupportedStreamConfigRange],
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: DataCallback,
+        error_callback: Box<dyn FnMut(StreamError) + Send + 'static>,
+    ) -> Result<Stream, BuildStreamError> {
+        if !self.available {
+            return Err(BuildStreamError::DeviceNotAvailable);
+        }
+        let supported = configs.iter().any(|range| {
+            range.channels == config.channels
+                && range.sample_format == sample_format
+                && range.min_sample_rate <= config.sample_rate
+                && config.sample_rate <= range.max_sample_rate
+                && match (&config.buffer_size, &range.buffer_size) {
+                    (BufferSize::Fixed(size), SupportedBufferSize::Range { min, max }) => {
+                        min <= size && size <= max
+                    }
+                    (BufferSize::Fixed(_), SupportedBufferSize::Unknown) => false,
+                    (BufferSize::Default, _) => true,
+                }
+        });
+        if !supported {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        Ok(Stream {
+            inner: Arc::new(Mutex::new(StreamInner {
+                playing: false,
+                config: config.clone(),
+                sample_format,
+                frames_processed: 0,
+                clock: self.clock.clone(),
+                latency: self.latency,
+                faults: Vec::new(),
+                failed: None,
+                data_callback,
+                error_callback,
+            })),
+        })
+    }
+}
+
+impl Stream {
+    /// Call the data callback of a playing output stream for `frames` frames and return the
+    /// interleaved samples it wrote.
+    ///
+    /// Returns `None` if this is an input stream, if it is paused or failed, or if `T` isn't the
+    /// stream's sample format.
+    pub fn render<T: SizedSample>(&self, frames: usize) -> Option<Vec<T>> {
+        let mut stream = self.inner.lock().unwrap();
+        if !stream.playing
+            || stream.sample_format != T::FORMAT
+            || matches!(stream.data_callback, DataCallback::Input(_))
+        {
+            return None;
+        }
+        let mut buffer = vec![T::EQUILIBRIUM; frames * stream.config.channels as usize];
+        if !stream.report_faults() {
+            if stream.failed.is_some() {
+                return None;
+            }
+            stream.frames_processed += frames as u64;
+            return Some(buffer);
+        }
+        let callback = stream.now();
+        let info = OutputCallbackInfo {
+            timestamp: OutputStreamTimestamp {
+                callback,
+                playback: callback.add(stream.latency).unwrap_or(callback),
+            },
+        };
+        match stream.data_callback {
+            DataCallback::Output(ref mut data_callback) => {
+                let mut data = unsafe {
+                    Data::from_parts(buffer.as_mut_ptr() as *mut (), buffer.len(), T::FORMAT)
+                };
+                data_callback(&mut data, &info);
+            }
+            DataCallback::Input(_) => return None,
+        }
+        stream.frames_processed += frames as u64;
+        Some(buffer)
+    }
+
+    /// Pass interleaved samples to the data callback of a playing input stream.
+    ///
+    /// Returns false if this is an output stream, if it is paused or failed, if `T` isn't the
+    /// stream's sample format, or if `samples` doesn't hold a whole number of frames.
+    pub fn capture<T: SizedSample>(&self, samples: &[T]) -> bool {
+        let mut stream = self.inner.lock().unwrap();
+        let channels = stream.config.channels as usize;
+        if !stream.playing
+            || stream.sample_format != T::FORMAT
+            || matches!(stream.data_callback, DataCallback::Output(_))
+            || channels == 0
+            || samples.len() % channels != 0
+        {
+            return false;
+        }
+        if !stream.report_faults() {
+            if stream.failed.is_some() {
+                return false;
+            }
+            stream.frames_processed += (samples.len() / channels) as u64;
+            return true;
+        }
+        let mut buffer = samples.to_vec();
+        let callback = stream.now();
+        let info = InputCallbackInfo {
+            timestamp: InputStreamTimestamp {
+                callback,
+                capture: callback.sub(stream.latency).unwrap_or(callback),
+            },
+        };
+        match stream.data_callback {
+            DataCallback::Input(ref mut data_callback) => {
+                let data = unsafe {
+                    Data::from_parts(buffer.as_mut_ptr() as *mut (), buffer.len(), T::FORMAT)
+                };
+                data_callback(&data, &info);
+            }
+            DataCallback::Output(_) => return false,
+        }
+        stream.frames_processed += (samples.len() / channels) as u64;
+        true
+    }
+
+    /// Pass an error to the error callback of the stream.
+    pub fn inject_error(&self, err: StreamError) {
+        let mut stream = self.inner.lock().unwrap();
+        (stream.error_callback)(err);
+    }
+
+    /// Make the stream report `fault` at the first [`render`](Self::render) or
+    /// [`capture`](Self::capture) call once `frame` frames have been processed.
+    ///
+    /// Faults only affect the stream. To make rebuilding the stream fail as well after a
+    /// [`Fault::DeviceRemoved`], also make the device unavailable with
+    /// [`Device::set_available`].
+    pub fn schedule_fault(&self, frame: u64, fault: Fault) {
+        let mut stream = self.inner.lock().unwrap();
+        let index = stream.faults.partition_point(|&(f, _)| f <= frame);
+        stream.faults.insert(index, (frame, fault));
+    }
+
+    /// The fault that stopped the stream for good, if any.
+    pub fn failed(&self) -> Option<Fault> {
+        self.inner.lock().unwrap().failed
+    }
+
+    /// The number of frames passed to or from the data callback so far.
+    pub fn frames_processed(&self) -> u64 {
+        self.inner.lock().unwrap().frames_processed
+    }
+}
+
+impl StreamInner {
+    /// Report the faults that are due. Returns false if the data callback must not be called.
+    fn report_faults(&mut self) -> bool {
+        let mut call = true;
+        while self
+            .faults
+            .first()
+            .is_some_and(|&(frame, _)| frame <= self.frames_processed)
+        {
+            let (_, fault) = self.faults.remove(0);
+            let err = match fault {
+                Fault::DeviceRemoved => StreamError::DeviceNotAvailable,
+                Fault::FormatChanged => BackendSpecificError {
+                    description: "the device format changed".to_string(),
+                }
+                .into(),
+                Fault::Underrun => StreamError::Xrun,
+                Fault::Hresult(code) => BackendSpecificError {
+                    description: format!("backend call failed with HRESULT 0x{:08X}", code),
+                }
+                .into(),
+            };
+            (self.error_callback)(err);
+            match fault {
+                Fault::DeviceRemoved | Fault::FormatChanged => {
+                    self.playing = false;
+                    self.failed = Some(fault);
+                    self.faults.clear();
+                    return false;
+                }
+                Fault::Underrun => call = false,
+                Fault::Hresult(_) => (),
+            }
+        }
+        call
+    }
+
+    /// The stream time, from the clock of the device or derived from the number of frames
+    /// processed.
+    fn now(&self) -> StreamInstant {
+        let nanos = match self.clock {
+            Some(ref clock) => clock.elapsed().as_nanos(),
+            None => {
+                self.frames_processed as u128 * 1_000_000_000 / self.config.sample_rate.0 as u128
+            }
+        };
+        StreamInstant::from_nanos_i128(nanos as i128).expect("stream time out of range")
+    }
+}
+
+impl Clock {
+    /// Create a clock at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Move the time forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+
+    /// Move the time forward by `frames` frames at `sample_rate`.
+    pub fn advance_frames(&self, frames: u64, sample_rate: SampleRate) {
+        let nanos = frames as u128 * 1_000_000_000 / sample_rate.0 as u128;
+        self.advance(Duration::from_nanos(nanos as u64));
+    }
+
+    /// The time elapsed since the clock was created.
+    pub fn elapsed(&self) -> Duration {
+        *self.now.lock().unwrap()
+    }
+}
+
+impl PartialEq for Clock {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.now, &other.now)
+    }
+}
+
+impl Eq for Clock {}
+
+impl HostTrait for Host {
+    type Devices = Devices;
+    type Device = Device;
+
+    fn is_available() -> bool {
+        true
+    }
+
+    fn devices(&self) -> Result<Self::Devices, DevicesError> {
+        Ok(Devices(self.devices.clone().into_iter()))
+    }
+
+    fn default_input_device(&self) -> Option<Device> {
+        self.devices
+            .iter()
+            .find(|device| !device.input_configs.is_empty())
+            .cloned()
+    }
+
+    fn default_output_device(&self) -> Option<Device> {
+        self.devices
+            .iter()
+            .find(|device| !device.output_configs.is_empty())
+            .cloned()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.devices
+            .iter()
+            .fold(Capabilities::default(), |all, device| {
+                let device = device.capabilities;
+                Capabilities {
+                    exclusive_mode: all.exclusive_mode || device.exclusive_mode,
+                    loopback: all.loopback || device.loopback,
+                    device_events: all.device_events || device.device_events,
+                    stream_volume: all.stream_volume || device.stream_volume,
+                }
+            })
+    }
+}
+
+impl DeviceTrait for Device {
+    type SupportedInputConfigs = SupportedInputConfigs;
+    type SupportedOutputConfigs = SupportedOutputConfigs;
+    type Stream = Stream;
+
+    fn name(&self) -> Result<String, DeviceNameError> {
+        Ok(self.name.clone())
+    }
+
+    fn supported_input_configs(
+        &self,
+    ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
+        self.supported_configs(&self.input_configs)
+    }
+
+    fn supported_output_configs(
+        &self,
+    ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError> {
+        self.supported_configs(&self.output_configs)
+    }
+
+    fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        self.default_config(&self.input_configs)
+    }
+
+    fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        self.default_config(&self.output_configs)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    fn build_input_stream_raw<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+        _timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_stream(
+            &self.input_configs,
+            config,
+            sample_format,
+            DataCallback::Input(Box::new(data_callback)),
+            Box::new(error_callback),
+        )
+    }
+
+    fn build_output_stream_raw<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+        _timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_stream(
+            &self.output_configs,
+            config,
+            sample_format,
+            DataCallback::Output(Box::new(data_callback)),
+            Box::new(error_callback),
+        )
+    }
+}
+
+impl StreamTrait for Stream {
+    fn play(&self) -> Result<(), PlayStreamError> {
+        let mut stream = self.inner.lock().unwrap();
+        match stream.failed {
+            Some(Fault::DeviceRemoved) => Err(PlayStreamError::DeviceNotAvailable),
+            Some(_) => Err(BackendSpecificError {
+                description: "the stream must be rebuilt".to_string(),
+            }
+            .into()),
+            None => {
+                stream.playing = true;
+                Ok(())
+            }
+        }
+    }
+
+    fn pause(&self) -> Result<(), PauseStreamError> {
+        self.inner.lock().unwrap().playing = false;
+        Ok(())
+    }
+}
+
+impl Iterator for Devices {
+    type Item = Device;
+
+    fn next(&mut self) -> Option<Device> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+#[test]
+fn test_devices() {
+    let mut input = Device::new("Microphone");
+    input.set_input_configs(vec![SupportedStreamConfigRange {
+        channels: 1,
+        min_sample_rate: SampleRate(16_000),
+        max_sample_rate: SampleRate(48_000),
+        buffer_size: SupportedBufferSize::Unknown,
+        sample_format: SampleFormat::I16,
+    }]);
+    let mut output = Device::new("Speakers");
+    output.set_output_configs(vec![SupportedStreamConfigRange {
+        channels: 2,
+        min_sample_rate: SampleRate(44_100),
+        max_sample_rate: SampleRate(96_000),
+        buffer_size: SupportedBufferSize::Range { min: 64, max: 1024 },
+        sample_format: SampleFormat::F32,
+    }]);
+    let mut unplugged = Device::new("Headset");
+    unplugged.set_output_configs(output.output_configs.clone());
+    unplugged.set_available(false);
+    let mut host = Host::new().unwrap();
+    host.set_devices(vec![input.clone(), output.clone(), unplugged.clone()]);
+
+    let names: Vec<_> = host.devices().unwrap().map(|d| d.name().unwrap()).collect();
+    assert_eq!(names, ["Microphone", "Speakers", "Headset"]);
+    assert_eq!(host.default_input_device(), Some(input.clone()));
+    assert_eq!(host.default_output_device(), Some(output.clone()));
+
+    assert_eq!(
+        input.supported_input_configs().unwrap().collect::<Vec<_>>(),
+        input.input_configs
+    );
+    assert_eq!(input.supported_output_configs().unwrap().count(), 0);
+    let config = output.default_output_config().unwrap();
+    assert_eq!(config.channels(), 2);
+    assert_eq!(config.sample_rate(), SampleRate(96_000));
+    assert_eq!(config.sample_format(), SampleFormat::F32);
+    assert!(matches!(
+        input.default_output_config(),
+        Err(DefaultStreamConfigError::StreamTypeNotSupported)
+    ));
+    assert!(matches!(
+        unplugged.supported_output_configs(),
+        Err(SupportedStreamConfigsError::DeviceNotAvailable)
+    ));
+    assert!(matches!(
+        unplugged.default_output_config(),
+        Err(DefaultStreamConfigError::DeviceNotAvailable)
+    ));
+    assert!(matches!(
+        unplugged.build_output_stream(
+            &config.config(),
+            |_: &mut [f32], _: &OutputCallbackInfo| (),
+            |_| (),
+            None,
+        ),
+        Err(BuildStreamError::DeviceNotAvailable)
+    ));
+}
+
+#[test]
+fn test_faults_reach_error_callback() {
+    let device = Host::new().unwrap().default_output_device().unwrap();
+    let config = device.default_output_config().unwrap().config();
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let calls = Arc::new(Mutex::new(0));
+    let stream = device
+        .build_output_stream(
+            &config,
+            {
+                let calls = calls.clone();
+                move |data: &mut [f32], _: &OutputCallbackInfo| {
+                    *calls.lock().unwrap() += 1;
+                    data.fill(1.0);
+                }
+            },
+            {
+                let errors = errors.clone();
+                move |err| errors.lock().unwrap().push(err)
+            },
+            None,
+        )
+        .unwrap();
+    stream.play().unwrap();
+    stream.schedule_fault(20, Fault::DeviceRemoved);
+    stream.schedule_fault(10, Fault::Hresult(0x8889_0004_u32 as i32));
+    stream.schedule_fault(10, Fault::Underrun);
+
+    stream.inject_error(StreamError::Xrun);
+    assert_eq!(stream.render::<f32>(10), Some(vec![1.0; 20]));
+    // Both faults at frame 10 are reported, and the underrun skips the callback.
+    assert_eq!(stream.render::<f32>(10), Some(vec![0.0; 20]));
+    assert_eq!(stream.failed(), None);
+    assert_eq!(stream.render::<f32>(10), None);
+    assert_eq!(stream.failed(), Some(Fault::DeviceRemoved));
+    assert_eq!(stream.frames_processed(), 20);
+    assert_eq!(*calls.lock().unwrap(), 1);
+    assert!(matches!(
+        stream.play(),
+        Err(PlayStreamError::DeviceNotAvailable)
+    ));
+
+    let errors = errors.lock().unwrap();
+    assert!(matches!(errors[0], StreamError::Xrun));
+    assert!(matches!(
+        &errors[1],
+        StreamError::BackendSpecific { err } if err.description.contains("0x88890004")
+    ));
+    assert!(matches!(errors[2], StreamError::Xrun));
+    assert!(matches!(errors[3], StreamError::DeviceNotAvailable));
+    assert_eq!(errors.len(), 4);
+}
+
+#[test]
+fn test_clock() {
+    let mut device = Host::new().unwrap().default_output_device().unwrap();
+    let config = device.default_output_config().unwrap().config();
+    let rate = config.sample_rate;
+    let build = |device: &Device, timestamps: &Arc<Mutex<Vec<OutputStreamTimestamp>>>| {
+        let timestamps = timestamps.clone();
+        let stream = device
+            .build_output_stream(
+                &config,
+                move |_: &mut [f32], info: &OutputCallbackInfo| {
+                    timestamps.lock().unwrap().push(info.timestamp());
+                },
+                |_| (),
+                None,
+            )
+            .unwrap();
+        stream.play().unwrap();
+        stream
+    };
+    let at = |nanos: i128| StreamInstant::from_nanos_i128(nanos).unwrap();
+
+    // Without a clock, the time advances with the frames the stream processes.
+    let timestamps = Arc::new(Mutex::new(Vec::new()));
+    let stream = build(&device, &timestamps);
+    stream.render::<f32>(480).unwrap();
+    stream.render::<f32>(480).unwrap();
+    let callbacks: Vec<_> = timestamps
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|t| t.callback)
+        .collect();
+    assert_eq!(callbacks, [at(0), at(10_000_000)]);
+
+    // With a clock, the time only advances with it and is shared by the streams.
+    let clock = Clock::new();
+    device.set_clock(Some(clock.clone()));
+    device.set_latency(Duration::from_millis(5));
+    let first = Arc::new(Mutex::new(Vec::new()));
+    let second = Arc::new(Mutex::new(Vec::new()));
+    let streams = [build(&device, &first), build(&device, &second)];
+    for stream in &streams {
+        stream.render::<f32>(480).unwrap();
+    }
+    clock.advance_frames(480, rate);
+    clock.advance(Duration::from_millis(2));
+    for stream in &streams {
+        stream.render::<f32>(480).unwrap();
+        stream.render::<f32>(480).unwrap();
+    }
+    assert_eq!(clock.elapsed(), Duration::from_millis(12));
+    for timestamps in [first, second] {
+        let timestamps = timestamps.lock().unwrap();
+        let callbacks: Vec<_> = timestamps.iter().map(|t| t.callback).collect();
+        assert_eq!(callbacks, [at(0), at(12_000_000), at(12_000_000)]);
+        assert_eq!(timestamps[1].playback, at(17_000_000));
+    }
+}
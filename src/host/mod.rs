@@ -11,6 +11,8 @@ pub(crate) mod asio;
 pub(crate) mod coreaudio;
 #[cfg(target_os = "emscripten")]
 pub(crate) mod emscripten;
+#[cfg(feature = "file")]
+pub(crate) mod file;
 #[cfg(all(
     any(
         target_os = "linux",
@@ -21,9 +23,21 @@ pub(crate) mod emscripten;
     feature = "jack"
 ))]
 pub(crate) mod jack;
+#[cfg(feature = "mock")]
+pub(crate) mod mock;
 pub(crate) mod null;
 #[cfg(target_os = "android")]
 pub(crate) mod oboe;
+#[cfg(all(
+    any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd"
+    ),
+    feature = "pulseaudio"
+))]
+pub(crate) mod pulseaudio;
 #[cfg(windows)]
 pub(crate) mod wasapi;
 #[cfg(all(target_arch = "wasm32", feature = "wasm-bindgen"))]
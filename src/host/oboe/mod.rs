@@ -8,11 +8,11 @@ extern crate oboe;
 
 use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
 use crate::{
-    BackendSpecificError, BufferSize, BuildStreamError, Data, DefaultStreamConfigError,
-    DeviceNameError, DevicesError, InputCallbackInfo, OutputCallbackInfo, PauseStreamError,
-    PlayStreamError, SampleFormat, SampleRate, SizedSample, StreamConfig, StreamError,
-    SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
-    SupportedStreamConfigsError,
+    BackendSpecificError, BufferSize, BuildStreamError, Capabilities, Data,
+    DefaultStreamConfigError, DeviceNameError, DevicesError, InputCallbackInfo, OutputCallbackInfo,
+    PauseStreamError, PerformanceMode, PlayStreamError, SampleFormat, SampleRate, SizedSample,
+    StreamConfig, StreamError, StreamUsage, SupportedBufferSize, SupportedStreamConfig,
+    SupportedStreamConfigRange, SupportedStreamConfigsError,
 };
 
 mod android_media;
@@ -38,7 +38,13 @@ const SAMPLE_RATES: [i32; 13] = [
 
 pub struct Host;
 #[derive(Clone)]
-pub struct Device(Option<oboe::AudioDeviceInfo>);
+pub struct Device {
+    info: Option<oboe::AudioDeviceInfo>,
+    /// If streams request exclusive access to the device.
+    exclusive: bool,
+    /// If streams use OpenSL ES rather than the audio API Oboe picks.
+    open_sl_es: bool,
+}
 pub enum Stream {
     Input(Box<RefCell<dyn AudioInputStream>>),
     Output(Box<RefCell<dyn AudioOutputStream>>),
@@ -53,6 +59,33 @@ impl Host {
     }
 }
 
+impl Device {
+    fn new(info: Option<oboe::AudioDeviceInfo>) -> Self {
+        Device {
+            info,
+            exclusive: false,
+            open_sl_es: false,
+        }
+    }
+
+    /// Set whether streams request the exclusive AAudio sharing mode, which skips the mixer for
+    /// lower latency (default is false).
+    ///
+    /// Android falls back to shared mode when the device can't be opened exclusively.
+    pub fn set_exclusive_mode(&mut self, exclusive: bool) {
+        self.exclusive = exclusive;
+    }
+
+    /// Set whether streams use OpenSL ES even where AAudio is available, e.g. to work around
+    /// AAudio bugs of a device (default is false).
+    ///
+    /// Oboe uses OpenSL ES on Android versions before 8.1 either way, with buffers sized from the
+    /// device's native frames per buffer.
+    pub fn set_open_sl_es(&mut self, open_sl_es: bool) {
+        self.open_sl_es = open_sl_es;
+    }
+}
+
 impl HostTrait for Host {
     type Devices = Devices;
     type Device = Device;
@@ -66,20 +99,28 @@ impl HostTrait for Host {
         {
             Ok(devices
                 .into_iter()
-                .map(|d| Device(Some(d)))
+                .map(|d| Device::new(Some(d)))
                 .collect::<Vec<_>>()
                 .into_iter())
         } else {
-            Ok(vec![Device(None)].into_iter())
+            Ok(vec![Device::new(None)].into_iter())
         }
     }
 
     fn default_input_device(&self) -> Option<Self::Device> {
-        Some(Device(None))
+        Some(Device::new(None))
     }
 
     fn default_output_device(&self) -> Option<Self::Device> {
-        Some(Device(None))
+        Some(Device::new(None))
+    }
+
+    // See `Host::set_exclusive_mode`.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            exclusive_mode: true,
+            ..Default::default()
+        }
     }
 }
 
@@ -207,18 +248,68 @@ fn configure_for_device<D, C, I>(
     device: &Device,
     config: &StreamConfig,
 ) -> oboe::AudioStreamBuilder<D, C, I> {
-    let mut builder = if let Some(info) = &device.0 {
+    let mut builder = if let Some(info) = &device.info {
         builder.set_device_id(info.id)
     } else {
         builder
     };
     builder = builder.set_sample_rate(config.sample_rate.0.try_into().unwrap());
+    builder = match config.performance_mode {
+        PerformanceMode::Default => builder,
+        PerformanceMode::LowLatency => {
+            builder.set_performance_mode(oboe::PerformanceMode::LowLatency)
+        }
+        PerformanceMode::PowerSaving => {
+            builder.set_performance_mode(oboe::PerformanceMode::PowerSaving)
+        }
+    };
+    if device.exclusive {
+        builder = builder.set_sharing_mode(oboe::SharingMode::Exclusive);
+    }
+    if device.open_sl_es {
+        builder = builder.set_audio_api(oboe::AudioApi::OpenSLES);
+    }
     match &config.buffer_size {
         BufferSize::Default => builder,
         BufferSize::Fixed(size) => builder.set_buffer_capacity_in_frames(*size as i32),
     }
 }
 
+fn configure_output_usage<C, T>(
+    builder: oboe::AudioStreamBuilder<oboe::Output, C, T>,
+    usage: StreamUsage,
+) -> oboe::AudioStreamBuilder<oboe::Output, C, T> {
+    match usage {
+        StreamUsage::Default => builder,
+        StreamUsage::Media => builder
+            .set_usage(oboe::Usage::Media)
+            .set_content_type(oboe::ContentType::Music),
+        StreamUsage::Game => builder.set_usage(oboe::Usage::Game),
+        StreamUsage::VoiceCommunication => builder
+            .set_usage(oboe::Usage::VoiceCommunication)
+            .set_content_type(oboe::ContentType::Speech),
+        StreamUsage::Alarm => builder
+            .set_usage(oboe::Usage::Alarm)
+            .set_content_type(oboe::ContentType::Sonification),
+        StreamUsage::Notification => builder
+            .set_usage(oboe::Usage::Notification)
+            .set_content_type(oboe::ContentType::Sonification),
+    }
+}
+
+fn configure_input_usage<C, T>(
+    builder: oboe::AudioStreamBuilder<oboe::Input, C, T>,
+    usage: StreamUsage,
+) -> oboe::AudioStreamBuilder<oboe::Input, C, T> {
+    // Usage and content type only apply to output streams, input streams are tuned with a preset.
+    match usage {
+        StreamUsage::VoiceCommunication => {
+            builder.set_input_preset(oboe::InputPreset::VoiceCommunication)
+        }
+        _ => builder,
+    }
+}
+
 fn build_input_stream<D, E, C, T>(
     device: &Device,
     config: &StreamConfig,
@@ -234,6 +325,7 @@ where
     E: FnMut(StreamError) + Send + 'static,
 {
     let builder = configure_for_device(builder, device, config);
+    let builder = configure_input_usage(builder, config.usage);
     let stream = builder
         .set_callback(CpalInputCallback::<T, C>::new(
             data_callback,
@@ -258,6 +350,7 @@ where
     E: FnMut(StreamError) + Send + 'static,
 {
     let builder = configure_for_device(builder, device, config);
+    let builder = configure_output_usage(builder, config.usage);
     let stream = builder
         .set_callback(CpalOutputCallback::<T, C>::new(
             data_callback,
@@ -273,7 +366,7 @@ impl DeviceTrait for Device {
     type Stream = Stream;
 
     fn name(&self) -> Result<String, DeviceNameError> {
-        match &self.0 {
+        match &self.info {
             None => Ok("default".to_owned()),
             Some(info) => Ok(info.product_name.clone()),
         }
@@ -282,7 +375,7 @@ impl DeviceTrait for Device {
     fn supported_input_configs(
         &self,
     ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
-        if let Some(info) = &self.0 {
+        if let Some(info) = &self.info {
             Ok(device_supported_configs(info, false))
         } else {
             Ok(default_supported_configs(false))
@@ -292,7 +385,7 @@ impl DeviceTrait for Device {
     fn supported_output_configs(
         &self,
     ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError> {
-        if let Some(info) = &self.0 {
+        if let Some(info) = &self.info {
             Ok(device_supported_configs(info, true))
         } else {
             Ok(default_supported_configs(true))
@@ -321,6 +414,13 @@ impl DeviceTrait for Device {
         Ok(config)
     }
 
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            exclusive_mode: true,
+            ..Default::default()
+        }
+    }
+
     fn build_input_stream_raw<D, E>(
         &self,
         config: &StreamConfig,
@@ -0,0 +1,102 @@
+//! The parts of `libpulse-simple` and `libpulse` the host uses.
+
+#![allow(non_camel_case_types)]
+
+use std::ffi::{c_char, c_int, c_void, CStr};
+
+/// `pa_sample_format_t`, in the byte order of the target.
+pub const PA_SAMPLE_U8: c_int = 0;
+#[cfg(target_endian = "little")]
+pub const PA_SAMPLE_S16NE: c_int = 3;
+#[cfg(target_endian = "big")]
+pub const PA_SAMPLE_S16NE: c_int = 4;
+#[cfg(target_endian = "little")]
+pub const PA_SAMPLE_FLOAT32NE: c_int = 5;
+#[cfg(target_endian = "big")]
+pub const PA_SAMPLE_FLOAT32NE: c_int = 6;
+#[cfg(target_endian = "little")]
+pub const PA_SAMPLE_S32NE: c_int = 7;
+#[cfg(target_endian = "big")]
+pub const PA_SAMPLE_S32NE: c_int = 8;
+
+/// `pa_stream_direction_t`.
+pub const PA_STREAM_PLAYBACK: c_int = 1;
+pub const PA_STREAM_RECORD: c_int = 2;
+
+/// `pa_error_code_t`.
+pub const PA_ERR_INVALID: c_int = 3;
+pub const PA_ERR_NOENTITY: c_int = 5;
+pub const PA_ERR_CONNECTIONREFUSED: c_int = 6;
+pub const PA_ERR_CONNECTIONTERMINATED: c_int = 11;
+pub const PA_ERR_KILLED: c_int = 12;
+pub const PA_ERR_NOTSUPPORTED: c_int = 19;
+
+/// `pa_sample_spec`.
+#[repr(C)]
+pub struct pa_sample_spec {
+    pub format: c_int,
+    pub rate: u32,
+    pub channels: u8,
+}
+
+/// `pa_buffer_attr`, `u32::MAX` picks the server's default for a field.
+#[repr(C)]
+pub struct pa_buffer_attr {
+    pub maxlength: u32,
+    pub tlength: u32,
+    pub prebuf: u32,
+    pub minreq: u32,
+    pub fragsize: u32,
+}
+
+#[repr(C)]
+pub struct pa_simple {
+    _private: [u8; 0],
+}
+
+#[link(name = "pulse-simple")]
+extern "C" {
+    pub fn pa_simple_new(
+        server: *const c_char,
+        name: *const c_char,
+        dir: c_int,
+        dev: *const c_char,
+        stream_name: *const c_char,
+        ss: *const pa_sample_spec,
+        map: *const c_void,
+        attr: *const pa_buffer_attr,
+        error: *mut c_int,
+    ) -> *mut pa_simple;
+    pub fn pa_simple_free(s: *mut pa_simple);
+    pub fn pa_simple_write(
+        s: *mut pa_simple,
+        data: *const c_void,
+        bytes: usize,
+        error: *mut c_int,
+    ) -> c_int;
+    pub fn pa_simple_read(
+        s: *mut pa_simple,
+        data: *mut c_void,
+        bytes: usize,
+        error: *mut c_int,
+    ) -> c_int;
+    pub fn pa_simple_flush(s: *mut pa_simple, error: *mut c_int) -> c_int;
+    pub fn pa_simple_get_latency(s: *mut pa_simple, error: *mut c_int) -> u64;
+}
+
+#[link(name = "pulse")]
+extern "C" {
+    fn pa_strerror(error: c_int) -> *const c_char;
+}
+
+/// The message of a `pa_error_code_t`.
+pub fn strerror(error: c_int) -> String {
+    unsafe {
+        let message = pa_strerror(error);
+        if message.is_null() {
+            format!("PulseAudio error {}", error)
+        } else {
+            CStr::from_ptr(message).to_string_lossy().into_owned()
+        }
+    }
+}
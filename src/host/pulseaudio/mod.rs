@@ -0,0 +1,629 @@
+//! A host that plays and records through a PulseAudio server, or PipeWire's implementation of
+//! it, with the simple API of `libpulse-simple`.
+//!
+//! Unlike ALSA `hw:` devices, streams share the sound card with the other applications of the
+//! desktop, which the server mixes. The simple API can't list sinks and sources, so the host has
+//! a single device that plays on the default sink and records from the default source, and
+//! [`Device::named`] picks another sink or source by its name on the server.
+//!
+//! The simple API can't cork a stream either. Pausing drops the audio buffered on the server and
+//! stops writing, and [`suspend`](StreamTrait::suspend) closes the connection, so the server sees
+//! the sink or source idle and can suspend it, and reconnects when the stream plays again. The
+//! server's suspended and moved events are not reported, as the simple API doesn't receive them.
+//!
+//! For the same reason the server's volume of a stream, which desktop mixers show, is left
+//! alone. [`Stream::set_volume`](crate::Stream::set_volume) scales the samples in cpal instead,
+//! and the devices don't report [`Capabilities::stream_volume`](crate::Capabilities).
+
+mod ffi;
+
+use std::ffi::{c_int, CString};
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::shutdown::Worker;
+use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
+use crate::{
+    BackendSpecificError, BufferSize, BuildStreamError, Data, DefaultStreamConfigError,
+    DeviceNameError, DevicesError, FrameCount, InputCallbackInfo, InputStreamTimestamp,
+    OutputCallbackInfo, OutputStreamTimestamp, PauseStreamError, PlayStreamError, SampleFormat,
+    SampleRate, StreamConfig, StreamError, StreamInstant, SupportedBufferSize,
+    SupportedStreamConfig, SupportedStreamConfigRange, SupportedStreamConfigsError,
+};
+
+pub type SupportedInputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+pub type SupportedOutputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+
+const SUPPORTED_SAMPLE_FORMATS: [SampleFormat; 4] = [
+    SampleFormat::U8,
+    SampleFormat::I16,
+    SampleFormat::I32,
+    SampleFormat::F32,
+];
+const MIN_CHANNELS: u16 = 1;
+// `PA_CHANNELS_MAX`.
+const MAX_CHANNELS: u16 = 32;
+const MIN_SAMPLE_RATE: SampleRate = SampleRate(8_000);
+const MAX_SAMPLE_RATE: SampleRate = SampleRate(192_000);
+const DEFAULT_SAMPLE_RATE: SampleRate = SampleRate(48_000);
+const MIN_BUFFER_SIZE: FrameCount = 16;
+const MAX_BUFFER_SIZE: FrameCount = 65_536;
+// Without buffer attributes the server buffers about two seconds of playback.
+const DEFAULT_BUFFER_SIZE: FrameCount = 1_024;
+const CLIENT_NAME: &str = "cpal";
+
+/// The host, available while a PulseAudio server runs for the user.
+#[derive(Debug)]
+pub struct Host;
+
+pub struct Devices(std::vec::IntoIter<Device>);
+
+/// The default sink and source of the server, or a sink or source picked by name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Device {
+    name: Option<String>,
+}
+
+pub struct Stream {
+    shared: Arc<Shared>,
+}
+
+/// State shared with the thread of a stream.
+struct Shared {
+    state: Mutex<State>,
+    condvar: Condvar,
+    /// The thread of the stream, `None` once it was joined.
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+struct State {
+    playing: bool,
+    /// Whether the connection is closed while the stream is paused, see [`StreamTrait::suspend`].
+    suspended: bool,
+    stop: bool,
+}
+
+/// A stream on the server, used by the thread of a [`Stream`].
+struct Simple(*mut ffi::pa_simple);
+
+// The connection is used by one thread at a time.
+unsafe impl Send for Simple {}
+
+impl Host {
+    pub fn new() -> Result<Self, crate::HostUnavailable> {
+        if Host::is_available() {
+            Ok(Host)
+        } else {
+            Err(crate::HostUnavailable)
+        }
+    }
+}
+
+impl Device {
+    /// The sink or source with the given name on the server, e.g. from `pactl list short sinks`.
+    ///
+    /// Output streams built on the device play on the sink and input streams record from the
+    /// source, so a device named after a sink can't record unless a source has the same name.
+    pub fn named(name: impl Into<String>) -> Self {
+        Device {
+            name: Some(name.into()),
+        }
+    }
+
+    fn supported_configs() -> Vec<SupportedStreamConfigRange> {
+        let mut configs = vec![];
+        for &sample_format in SUPPORTED_SAMPLE_FORMATS.iter() {
+            for channels in MIN_CHANNELS..=MAX_CHANNELS {
+                configs.push(SupportedStreamConfigRange {
+                    channels,
+                    min_sample_rate: MIN_SAMPLE_RATE,
+                    max_sample_rate: MAX_SAMPLE_RATE,
+                    buffer_size: SupportedBufferSize::Range {
+                        min: MIN_BUFFER_SIZE,
+                        max: MAX_BUFFER_SIZE,
+                    },
+                    sample_format,
+                });
+            }
+        }
+        configs
+    }
+
+    fn default_config() -> SupportedStreamConfig {
+        SupportedStreamConfig {
+            channels: 2,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            buffer_size: SupportedBufferSize::Range {
+                min: MIN_BUFFER_SIZE,
+                max: MAX_BUFFER_SIZE,
+            },
+            sample_format: SampleFormat::F32,
+        }
+    }
+
+    /// Connect a stream with `config` to the server.
+    fn connect(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        direction: c_int,
+    ) -> Result<(Simple, FrameCount), BuildStreamError> {
+        let buffer_size = match config.buffer_size {
+            BufferSize::Fixed(size) if (MIN_BUFFER_SIZE..=MAX_BUFFER_SIZE).contains(&size) => size,
+            BufferSize::Fixed(_) => return Err(BuildStreamError::StreamConfigNotSupported),
+            BufferSize::Default => DEFAULT_BUFFER_SIZE,
+        };
+        let format =
+            pa_sample_format(sample_format).ok_or(BuildStreamError::StreamConfigNotSupported)?;
+        if !(MIN_CHANNELS..=MAX_CHANNELS).contains(&config.channels)
+            || config.sample_rate < MIN_SAMPLE_RATE
+            || config.sample_rate > MAX_SAMPLE_RATE
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+
+        let spec = ffi::pa_sample_spec {
+            format,
+            rate: config.sample_rate.0,
+            channels: config.channels as u8,
+        };
+        let period = buffer_size * config.channels as u32 * sample_format.sample_size() as u32;
+        let playback = direction == ffi::PA_STREAM_PLAYBACK;
+        // Playback keeps two periods queued on the server, recording delivers one at a time.
+        let attr = ffi::pa_buffer_attr {
+            maxlength: u32::MAX,
+            tlength: if playback { period * 2 } else { u32::MAX },
+            prebuf: u32::MAX,
+            minreq: if playback { period } else { u32::MAX },
+            fragsize: if playback { u32::MAX } else { period },
+        };
+        let c_string = |s: &str| CString::new(s).map_err(|_| BuildStreamError::InvalidArgument);
+        let client_name = c_string(CLIENT_NAME)?;
+        let stream_name = c_string(&config.thread_name(CLIENT_NAME, ""))?;
+        let device_name = self.name.as_deref().map(c_string).transpose()?;
+
+        let mut error = 0;
+        let simple = unsafe {
+            ffi::pa_simple_new(
+                std::ptr::null(),
+                client_name.as_ptr(),
+                direction,
+                device_name
+                    .as_ref()
+                    .map_or(std::ptr::null(), |name| name.as_ptr()),
+                stream_name.as_ptr(),
+                &spec,
+                std::ptr::null(),
+                &attr,
+                &mut error,
+            )
+        };
+        if simple.is_null() {
+            return Err(build_error(error));
+        }
+        Ok((Simple(simple), buffer_size))
+    }
+
+    /// Connect a stream again with the same config, from the thread of a suspended stream.
+    fn reconnect(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        direction: c_int,
+    ) -> impl Fn() -> Result<Simple, StreamError> + Send + 'static {
+        let (device, config) = (self.clone(), config.clone());
+        move || match device.connect(&config, sample_format, direction) {
+            Ok((simple, _)) => Ok(simple),
+            Err(BuildStreamError::DeviceNotAvailable) => Err(StreamError::DeviceNotAvailable),
+            Err(err) => Err(BackendSpecificError {
+                description: err.to_string(),
+            }
+            .into()),
+        }
+    }
+
+    fn spawn<F>(
+        &self,
+        config: &StreamConfig,
+        prefix: &str,
+        run: F,
+    ) -> Result<Stream, BuildStreamError>
+    where
+        F: FnOnce(&Shared) + Send + 'static,
+    {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                playing: false,
+                suspended: false,
+                stop: false,
+            }),
+            condvar: Condvar::new(),
+            thread: Mutex::new(None),
+        });
+        let thread_shared = shared.clone();
+        let name = self.name.as_deref().unwrap_or("default");
+        let thread = thread::Builder::new()
+            .name(config.thread_name(prefix, name))
+            .spawn(move || run(&thread_shared))
+            .map_err(|err| BackendSpecificError {
+                description: err.to_string(),
+            })?;
+        *shared.thread.lock().unwrap() = Some(thread);
+        crate::shutdown::register(crate::HostId::PulseAudio, &shared);
+        Ok(Stream { shared })
+    }
+}
+
+impl Simple {
+    fn write(&self, bytes: &[u8]) -> Result<(), StreamError> {
+        let mut error = 0;
+        match unsafe { ffi::pa_simple_write(self.0, bytes.as_ptr() as _, bytes.len(), &mut error) }
+        {
+            0 => Ok(()),
+            _ => Err(stream_error(error)),
+        }
+    }
+
+    fn read(&self, bytes: &mut [u8]) -> Result<(), StreamError> {
+        let mut error = 0;
+        match unsafe {
+            ffi::pa_simple_read(self.0, bytes.as_mut_ptr() as _, bytes.len(), &mut error)
+        } {
+            0 => Ok(()),
+            _ => Err(stream_error(error)),
+        }
+    }
+
+    /// Drop the audio buffered on the server.
+    fn flush(&self) {
+        let mut error = 0;
+        unsafe {
+            ffi::pa_simple_flush(self.0, &mut error);
+        }
+    }
+
+    /// The time until written audio is played, or since read audio was recorded.
+    fn latency(&self) -> Option<Duration> {
+        let mut error = 0;
+        match unsafe { ffi::pa_simple_get_latency(self.0, &mut error) } {
+            u64::MAX => None,
+            usec => Some(Duration::from_micros(usec)),
+        }
+    }
+}
+
+impl Drop for Simple {
+    fn drop(&mut self) {
+        unsafe { ffi::pa_simple_free(self.0) }
+    }
+}
+
+impl Shared {
+    /// Wait while the stream is paused. Returns `None` once the stream stops, and otherwise
+    /// whether it was paused.
+    ///
+    /// When the stream is paused the audio buffered on the server is dropped first, so playback
+    /// stops right away rather than running the server's buffer dry. When it is suspended the
+    /// connection is closed, so the server can suspend the idle sink or source, and `simple` is
+    /// left empty for the caller to connect again.
+    fn wait_until_playing(&self, simple: &mut Option<Simple>) -> Option<bool> {
+        let mut state = self.state.lock().unwrap();
+        if !state.playing && !state.stop {
+            let suspended = state.suspended;
+            drop(state);
+            match simple {
+                Some(_) if suspended => *simple = None,
+                Some(simple) => simple.flush(),
+                None => (),
+            }
+            state = self.state.lock().unwrap();
+        }
+        let mut paused = false;
+        while !state.playing && !state.stop {
+            paused = true;
+            state = self.condvar.wait(state).unwrap();
+        }
+        (!state.stop).then_some(paused)
+    }
+}
+
+impl HostTrait for Host {
+    type Devices = Devices;
+    type Device = Device;
+
+    fn is_available() -> bool {
+        // The server is found through `PULSE_SERVER` or the socket in the runtime directory.
+        if std::env::var_os("PULSE_SERVER").is_some() {
+            return true;
+        }
+        std::env::var_os("XDG_RUNTIME_DIR")
+            .map(|dir| PathBuf::from(dir).join("pulse/native"))
+            .into_iter()
+            .chain(Some(PathBuf::from("/var/run/pulse/native")))
+            .any(|socket| socket.exists())
+    }
+
+    fn devices(&self) -> Result<Self::Devices, DevicesError> {
+        Ok(Devices(vec![Device { name: None }].into_iter()))
+    }
+
+    fn default_input_device(&self) -> Option<Device> {
+        Some(Device { name: None })
+    }
+
+    fn default_output_device(&self) -> Option<Device> {
+        Some(Device { name: None })
+    }
+}
+
+impl DeviceTrait for Device {
+    type SupportedInputConfigs = SupportedInputConfigs;
+    type SupportedOutputConfigs = SupportedOutputConfigs;
+    type Stream = Stream;
+
+    fn name(&self) -> Result<String, DeviceNameError> {
+        Ok(self.name.clone().unwrap_or_else(|| "default".to_owned()))
+    }
+
+    fn supported_input_configs(
+        &self,
+    ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
+        Ok(Device::supported_configs().into_iter())
+    }
+
+    fn supported_output_configs(
+        &self,
+    ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError> {
+        Ok(Device::supported_configs().into_iter())
+    }
+
+    fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        Ok(Device::default_config())
+    }
+
+    fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        Ok(Device::default_config())
+    }
+
+    fn build_input_stream_raw<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        mut data_callback: D,
+        mut error_callback: E,
+        _timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let (simple, buffer_size) = self.connect(config, sample_format, ffi::PA_STREAM_RECORD)?;
+        let connect = self.reconnect(config, sample_format, ffi::PA_STREAM_RECORD);
+        let len = buffer_size as usize * config.channels as usize;
+        let mut buffer = vec![0u8; len * sample_format.sample_size()];
+        self.spawn(config, "cpal_pulseaudio_in", move |shared| {
+            let mut simple = Some(simple);
+            while let Some(paused) = shared.wait_until_playing(&mut simple) {
+                let simple = match &mut simple {
+                    Some(simple) => simple,
+                    None => match connect() {
+                        Ok(connected) => simple.insert(connected),
+                        Err(err) => {
+                            error_callback(err);
+                            break;
+                        }
+                    },
+                };
+                if paused {
+                    // Recording went on while paused, drop what the server buffered meanwhile.
+                    simple.flush();
+                }
+                if let Err(err) = simple.read(&mut buffer) {
+                    error_callback(err);
+                    break;
+                }
+                let callback = stream_instant();
+                let capture = simple
+                    .latency()
+                    .and_then(|latency| callback.sub(latency))
+                    .unwrap_or(callback);
+                let info = InputCallbackInfo {
+                    timestamp: InputStreamTimestamp { callback, capture },
+                };
+                let data =
+                    unsafe { Data::from_parts(buffer.as_mut_ptr() as *mut (), len, sample_format) };
+                data_callback(&data, &info);
+            }
+        })
+    }
+
+    fn build_output_stream_raw<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        mut data_callback: D,
+        mut error_callback: E,
+        _timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let (simple, buffer_size) = self.connect(config, sample_format, ffi::PA_STREAM_PLAYBACK)?;
+        let connect = self.reconnect(config, sample_format, ffi::PA_STREAM_PLAYBACK);
+        let len = buffer_size as usize * config.channels as usize;
+        let mut buffer = vec![0u8; len * sample_format.sample_size()];
+        self.spawn(config, "cpal_pulseaudio_out", move |shared| {
+            let mut simple = Some(simple);
+            while shared.wait_until_playing(&mut simple).is_some() {
+                let simple = match &mut simple {
+                    Some(simple) => simple,
+                    None => match connect() {
+                        Ok(connected) => simple.insert(connected),
+                        Err(err) => {
+                            error_callback(err);
+                            break;
+                        }
+                    },
+                };
+                let callback = stream_instant();
+                let playback = simple
+                    .latency()
+                    .and_then(|latency| callback.add(latency))
+                    .unwrap_or(callback);
+                let info = OutputCallbackInfo {
+                    timestamp: OutputStreamTimestamp { callback, playback },
+                };
+                let mut data =
+                    unsafe { Data::from_parts(buffer.as_mut_ptr() as *mut (), len, sample_format) };
+                data.fill_equilibrium();
+                data_callback(&mut data, &info);
+                if let Err(err) = simple.write(&buffer) {
+                    error_callback(err);
+                    break;
+                }
+            }
+        })
+    }
+}
+
+impl StreamTrait for Stream {
+    fn play(&self) -> Result<(), PlayStreamError> {
+        let mut state = self.shared.state.lock().unwrap();
+        state.playing = true;
+        state.suspended = false;
+        self.shared.condvar.notify_one();
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), PauseStreamError> {
+        self.shared.state.lock().unwrap().playing = false;
+        Ok(())
+    }
+
+    /// Pause the stream and close its connection to the server, which lets the server's
+    /// power management suspend the sink or source once no other stream uses it. Playing the
+    /// stream connects it again with the same config.
+    fn suspend(&self) -> Result<(), PauseStreamError> {
+        let mut state = self.shared.state.lock().unwrap();
+        state.playing = false;
+        state.suspended = true;
+        Ok(())
+    }
+}
+
+impl Worker for Shared {
+    fn stop(&self) {
+        self.state.lock().unwrap().stop = true;
+        self.condvar.notify_one();
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        self.shared.stop();
+    }
+}
+
+impl Iterator for Devices {
+    type Item = Device;
+
+    fn next(&mut self) -> Option<Device> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+/// The time since the first stream of the host started. All streams share the epoch, so their
+/// timestamps compare, e.g. to start them together or to measure the drift between them.
+fn stream_instant() -> StreamInstant {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    let epoch = *EPOCH.get_or_init(Instant::now);
+    StreamInstant::from_nanos_i128(epoch.elapsed().as_nanos() as i128)
+        .expect("stream time out of range")
+}
+
+/// The `pa_sample_format_t` of a sample format, if the server supports it.
+fn pa_sample_format(sample_format: SampleFormat) -> Option<c_int> {
+    match sample_format {
+        SampleFormat::U8 => Some(ffi::PA_SAMPLE_U8),
+        SampleFormat::I16 => Some(ffi::PA_SAMPLE_S16NE),
+        SampleFormat::I32 => Some(ffi::PA_SAMPLE_S32NE),
+        SampleFormat::F32 => Some(ffi::PA_SAMPLE_FLOAT32NE),
+        _ => None,
+    }
+}
+
+fn build_error(error: c_int) -> BuildStreamError {
+    match error {
+        ffi::PA_ERR_NOENTITY | ffi::PA_ERR_CONNECTIONREFUSED => {
+            BuildStreamError::DeviceNotAvailable
+        }
+        ffi::PA_ERR_INVALID | ffi::PA_ERR_NOTSUPPORTED => {
+            BuildStreamError::StreamConfigNotSupported
+        }
+        _ => pulse_error(error).into(),
+    }
+}
+
+fn pulse_error(error: c_int) -> BackendSpecificError {
+    BackendSpecificError {
+        description: ffi::strerror(error),
+    }
+}
+
+fn stream_error(error: c_int) -> StreamError {
+    match error {
+        ffi::PA_ERR_NOENTITY | ffi::PA_ERR_CONNECTIONTERMINATED | ffi::PA_ERR_KILLED => {
+            StreamError::DeviceNotAvailable
+        }
+        _ => pulse_error(error).into(),
+    }
+}
+
+#[test]
+fn test_sample_formats() {
+    for &sample_format in SUPPORTED_SAMPLE_FORMATS.iter() {
+        assert!(pa_sample_format(sample_format).is_some());
+    }
+    assert_eq!(pa_sample_format(SampleFormat::U8), Some(0));
+    assert_eq!(pa_sample_format(SampleFormat::F64), None);
+    assert_eq!(pa_sample_format(SampleFormat::I64), None);
+    #[cfg(target_endian = "little")]
+    assert_eq!(
+        [SampleFormat::I16, SampleFormat::F32, SampleFormat::I32].map(pa_sample_format),
+        [Some(3), Some(5), Some(7)]
+    );
+}
+
+#[test]
+fn test_error_mapping() {
+    assert!(matches!(
+        build_error(ffi::PA_ERR_CONNECTIONREFUSED),
+        BuildStreamError::DeviceNotAvailable
+    ));
+    assert!(matches!(
+        build_error(ffi::PA_ERR_NOENTITY),
+        BuildStreamError::DeviceNotAvailable
+    ));
+    assert!(matches!(
+        build_error(ffi::PA_ERR_NOTSUPPORTED),
+        BuildStreamError::StreamConfigNotSupported
+    ));
+    assert!(matches!(
+        stream_error(ffi::PA_ERR_CONNECTIONTERMINATED),
+        StreamError::DeviceNotAvailable
+    ));
+    assert!(matches!(
+        stream_error(ffi::PA_ERR_KILLED),
+        StreamError::DeviceNotAvailable
+    ));
+}
@@ -0,0 +1,136 @@
+//! Activating an audio client through `ActivateAudioInterfaceAsync`, see
+//! [`Device::activate_async`](super::Device::activate_async).
+//!
+//! The completion handler is a hand written COM object like the notification clients. It must
+//! be agile, so it also answers to `IAgileObject`, and it is called once on a worker thread of
+//! Windows.
+
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use windows::core::{IUnknown, Interface, GUID, HRESULT, HSTRING};
+use windows::Win32::Foundation::{E_NOINTERFACE, E_POINTER, S_OK};
+use windows::Win32::Media::Audio;
+use windows::Win32::System::Com;
+
+type Callback = Box<dyn FnOnce(windows::core::Result<Audio::IAudioClient>) + Send>;
+
+/// Start activating the `IAudioClient` of the device interface at `path`, `callback` receives
+/// the client once the activation completed.
+pub(super) fn activate_audio_client(path: &str, callback: Callback) -> windows::core::Result<()> {
+    let handler = CompletionHandler::create(callback);
+    unsafe {
+        Audio::ActivateAudioInterfaceAsync(
+            &HSTRING::from(path),
+            &Audio::IAudioClient::IID,
+            None,
+            &handler,
+        )?;
+    }
+    Ok(())
+}
+
+#[repr(C)]
+struct CompletionHandlerVtbl {
+    query_interface:
+        unsafe extern "system" fn(*mut c_void, *const GUID, *mut *mut c_void) -> HRESULT,
+    add_ref: unsafe extern "system" fn(*mut c_void) -> u32,
+    release: unsafe extern "system" fn(*mut c_void) -> u32,
+    activate_completed: unsafe extern "system" fn(*mut c_void, *mut c_void) -> HRESULT,
+}
+
+static VTBL: CompletionHandlerVtbl = CompletionHandlerVtbl {
+    query_interface,
+    add_ref,
+    release,
+    activate_completed,
+};
+
+/// The COM object. The vtable pointer must stay the first field.
+#[repr(C)]
+struct CompletionHandler {
+    vtbl: *const CompletionHandlerVtbl,
+    refs: AtomicU32,
+    /// `None` once the activation completed.
+    callback: Mutex<Option<Callback>>,
+}
+
+impl CompletionHandler {
+    /// Allocate a handler and return the interface holding its only reference.
+    fn create(callback: Callback) -> Audio::IActivateAudioInterfaceCompletionHandler {
+        let handler = Box::new(CompletionHandler {
+            vtbl: &VTBL,
+            refs: AtomicU32::new(1),
+            callback: Mutex::new(Some(callback)),
+        });
+        // The interface takes over the initial reference.
+        unsafe {
+            Audio::IActivateAudioInterfaceCompletionHandler::from_raw(
+                Box::into_raw(handler) as *mut c_void
+            )
+        }
+    }
+}
+
+unsafe extern "system" fn query_interface(
+    this: *mut c_void,
+    iid: *const GUID,
+    object: *mut *mut c_void,
+) -> HRESULT {
+    if iid.is_null() || object.is_null() {
+        return E_POINTER;
+    }
+    if *iid == IUnknown::IID
+        || *iid == Audio::IActivateAudioInterfaceCompletionHandler::IID
+        || *iid == Com::IAgileObject::IID
+    {
+        add_ref(this);
+        *object = this;
+        S_OK
+    } else {
+        *object = std::ptr::null_mut();
+        E_NOINTERFACE
+    }
+}
+
+unsafe extern "system" fn add_ref(this: *mut c_void) -> u32 {
+    let handler = &*(this as *const CompletionHandler);
+    handler.refs.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+unsafe extern "system" fn release(this: *mut c_void) -> u32 {
+    let handler = &*(this as *const CompletionHandler);
+    let refs = handler.refs.fetch_sub(1, Ordering::Release) - 1;
+    if refs == 0 {
+        std::sync::atomic::fence(Ordering::Acquire);
+        drop(Box::from_raw(this as *mut CompletionHandler));
+    }
+    refs
+}
+
+unsafe extern "system" fn activate_completed(this: *mut c_void, operation: *mut c_void) -> HRESULT {
+    let handler = &*(this as *const CompletionHandler);
+    let callback = match handler.callback.lock().ok().and_then(|mut c| c.take()) {
+        Some(callback) => callback,
+        None => return S_OK,
+    };
+    // The operation is borrowed for the duration of the call.
+    let result = match Audio::IActivateAudioInterfaceAsyncOperation::from_raw_borrowed(&operation) {
+        Some(operation) => activate_result(operation),
+        None => Err(windows::core::Error::from(E_POINTER)),
+    };
+    callback(result);
+    S_OK
+}
+
+unsafe fn activate_result(
+    operation: &Audio::IActivateAudioInterfaceAsyncOperation,
+) -> windows::core::Result<Audio::IAudioClient> {
+    let mut result = S_OK;
+    let mut interface: Option<IUnknown> = None;
+    operation.GetActivateResult(&mut result, &mut interface)?;
+    result.ok()?;
+    interface
+        .ok_or_else(|| windows::core::Error::from(E_NOINTERFACE))?
+        .cast()
+}
@@ -1,8 +1,9 @@
 use crate::FrameCount;
 use crate::{
-    BackendSpecificError, BufferSize, Data, DefaultStreamConfigError, DeviceNameError,
-    DevicesError, InputCallbackInfo, OutputCallbackInfo, SampleFormat, SampleRate, StreamConfig,
-    SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
+    BackendSpecificError, BufferSize, Capabilities, ChannelPosition, Data,
+    DefaultStreamConfigError, DeviceNameError, DeviceRole, DevicesError, InputCallbackInfo,
+    OutputCallbackInfo, PerformanceMode, SampleFormat, SampleRate, ShareMode, StreamConfig,
+    StreamUsage, SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
     SupportedStreamConfigsError, COMMON_SAMPLE_RATES,
 };
 use std::ffi::OsString;
@@ -15,6 +16,7 @@ use std::sync::OnceLock;
 use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::Duration;
 
+use super::activation;
 use super::com;
 use super::{windows_err_to_cpal_err, windows_err_to_cpal_err_message};
 use windows::core::Interface;
@@ -29,6 +31,7 @@ use windows::Win32::System::Threading;
 use windows::Win32::System::Variant::VT_LPWSTR;
 
 use super::stream::{AudioClientFlow, Stream, StreamInner};
+use crate::trace::trace_call;
 use crate::{traits::DeviceTrait, BuildStreamError, StreamError};
 
 pub type SupportedInputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
@@ -40,6 +43,20 @@ struct IAudioClientWrapper(Audio::IAudioClient);
 unsafe impl Send for IAudioClientWrapper {}
 unsafe impl Sync for IAudioClientWrapper {}
 
+/// The periods of the shared-mode audio engine for a device in frames, see
+/// [`Device::shared_mode_engine_periods`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EnginePeriods {
+    /// The period the engine uses for streams that don't ask for another one.
+    pub default: FrameCount,
+    /// The granularity of the periods, every period above `min` is a multiple of it.
+    pub fundamental: FrameCount,
+    /// The smallest period.
+    pub min: FrameCount,
+    /// The largest period.
+    pub max: FrameCount,
+}
+
 /// An opaque type that identifies an end point.
 #[derive(Clone)]
 pub struct Device {
@@ -47,6 +64,16 @@ pub struct Device {
     /// We cache an uninitialized `IAudioClient` so that we can call functions from it without
     /// having to create/destroy audio clients all the time.
     future_audio_client: Arc<Mutex<Option<IAudioClientWrapper>>>, // TODO: add NonZero around the ptr
+    /// If streams opt out of the ducking applied when a communications stream starts.
+    ducking_opt_out: bool,
+    /// If streams are opened in exclusive mode.
+    exclusive: bool,
+    /// If shared-mode streams let the audio engine convert their format to the mix format.
+    auto_convert_pcm: bool,
+    /// If output streams pad their buffer with silence when their thread wakes up late.
+    silence_padding: bool,
+    /// How long output streams play silence before they are stopped.
+    idle_timeout: Option<Duration>,
 }
 
 impl DeviceTrait for Device {
@@ -58,6 +85,10 @@ impl DeviceTrait for Device {
         Device::name(self)
     }
 
+    fn id(&self) -> Result<String, DeviceNameError> {
+        Device::id(self)
+    }
+
     fn supported_input_configs(
         &self,
     ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
@@ -70,6 +101,20 @@ impl DeviceTrait for Device {
         Device::supported_output_configs(self)
     }
 
+    fn supported_input_configs_with_mode(
+        &self,
+        mode: ShareMode,
+    ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
+        Device::supported_input_configs_with_mode(self, mode)
+    }
+
+    fn supported_output_configs_with_mode(
+        &self,
+        mode: ShareMode,
+    ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError> {
+        Device::supported_output_configs_with_mode(self, mode)
+    }
+
     fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
         Device::default_input_config(self)
     }
@@ -78,6 +123,41 @@ impl DeviceTrait for Device {
         Device::default_output_config(self)
     }
 
+    fn is_held_exclusively(&self) -> Option<bool> {
+        // initializing COM because we call `CoTaskMemFree` to release the format.
+        com::com_initialized();
+        // A shared client can't be initialized while another application holds the device in
+        // exclusive mode.
+        let audio_client = self.build_audioclient().ok()?;
+        unsafe {
+            let format = WaveFormatExPtr(audio_client.GetMixFormat().ok()?);
+            let result =
+                audio_client.Initialize(Audio::AUDCLNT_SHAREMODE_SHARED, 0, 0, 0, format.0, None);
+            match result {
+                Ok(()) => Some(false),
+                Err(ref e) if e.code() == Audio::AUDCLNT_E_DEVICE_IN_USE => Some(true),
+                Err(_) => None,
+            }
+        }
+    }
+
+    fn bit_perfect(&self) -> Option<Self> {
+        let mut device = self.clone();
+        device.exclusive = true;
+        device.auto_convert_pcm = false;
+        Some(device)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        // Input streams on render devices record in loopback mode.
+        Capabilities {
+            exclusive_mode: true,
+            loopback: self.data_flow() == Audio::eRender,
+            device_events: true,
+            ..Default::default()
+        }
+    }
+
     fn build_input_stream_raw<D, E>(
         &self,
         config: &StreamConfig,
@@ -91,8 +171,15 @@ impl DeviceTrait for Device {
         E: FnMut(StreamError) + Send + 'static,
     {
         let stream_inner = self.build_input_stream_raw_inner(config, sample_format)?;
+        let (device, rebuild_config) = (self.clone(), config.clone());
+        let rebuild =
+            Box::new(move || device.build_input_stream_raw_inner(&rebuild_config, sample_format));
+        let thread_name = config.thread_name("cpal_wasapi_in", &self.name().unwrap_or_default());
         Ok(Stream::new_input(
+            self.device.clone(),
             stream_inner,
+            rebuild,
+            thread_name,
             data_callback,
             error_callback,
         ))
@@ -111,8 +198,15 @@ impl DeviceTrait for Device {
         E: FnMut(StreamError) + Send + 'static,
     {
         let stream_inner = self.build_output_stream_raw_inner(config, sample_format)?;
+        let (device, rebuild_config) = (self.clone(), config.clone());
+        let rebuild =
+            Box::new(move || device.build_output_stream_raw_inner(&rebuild_config, sample_format));
+        let thread_name = config.thread_name("cpal_wasapi_out", &self.name().unwrap_or_default());
         Ok(Stream::new_output(
+            self.device.clone(),
             stream_inner,
+            rebuild,
+            thread_name,
             data_callback,
             error_callback,
         ))
@@ -153,10 +247,14 @@ pub unsafe fn is_format_supported(
 ) -> Result<bool, SupportedStreamConfigsError> {
     // Check if the given format is supported.
     let is_supported = |waveformatex_ptr, closest_waveformatex_ptr| {
-        let result = client.IsFormatSupported(
-            Audio::AUDCLNT_SHAREMODE_SHARED,
-            waveformatex_ptr,
-            Some(closest_waveformatex_ptr),
+        let result = trace_call!(
+            "IAudioClient::IsFormatSupported",
+            (Audio::AUDCLNT_SHAREMODE_SHARED, waveformatex_ptr),
+            client.IsFormatSupported(
+                Audio::AUDCLNT_SHAREMODE_SHARED,
+                waveformatex_ptr,
+                Some(closest_waveformatex_ptr),
+            )
         );
         // `IsFormatSupported` can return `S_FALSE` (which means that a compatible format
         // has been found, but not an exact match) so we also treat this as unsupported.
@@ -192,6 +290,24 @@ pub unsafe fn is_format_supported(
     }
 }
 
+// Given the audio client and format, returns whether or not the format is supported in exclusive
+// mode, where the device has to take the format as is.
+pub unsafe fn is_format_supported_exclusive(
+    client: &Audio::IAudioClient,
+    waveformatex_ptr: *const Audio::WAVEFORMATEX,
+) -> Result<bool, SupportedStreamConfigsError> {
+    let result = trace_call!(
+        "IAudioClient::IsFormatSupported",
+        (Audio::AUDCLNT_SHAREMODE_EXCLUSIVE, waveformatex_ptr),
+        client.IsFormatSupported(Audio::AUDCLNT_SHAREMODE_EXCLUSIVE, waveformatex_ptr, None)
+    );
+    match result {
+        Audio::AUDCLNT_E_DEVICE_INVALIDATED => Err(SupportedStreamConfigsError::DeviceNotAvailable),
+        Foundation::S_OK => Ok(true),
+        _ => Ok(false),
+    }
+}
+
 // Get a cpal Format from a WAVEFORMATEX.
 unsafe fn format_from_waveformatex_ptr(
     waveformatex_ptr: *const Audio::WAVEFORMATEX,
@@ -206,21 +322,30 @@ unsafe fn format_from_waveformatex_ptr(
     ) {
         (8, Audio::WAVE_FORMAT_PCM) => SampleFormat::U8,
         (16, Audio::WAVE_FORMAT_PCM) => SampleFormat::I16,
+        (32, Audio::WAVE_FORMAT_PCM) => SampleFormat::I32,
         (32, Multimedia::WAVE_FORMAT_IEEE_FLOAT) => SampleFormat::F32,
+        (64, Multimedia::WAVE_FORMAT_IEEE_FLOAT) => SampleFormat::F64,
         (n_bits, KernelStreaming::WAVE_FORMAT_EXTENSIBLE) => {
             let waveformatextensible_ptr = waveformatex_ptr as *const Audio::WAVEFORMATEXTENSIBLE;
             let sub = (*waveformatextensible_ptr).SubFormat;
 
+            // The container size decides the sample format. Fewer valid bits, e.g. 24 bits in a
+            // 32 bit container, are left-justified and can be handled as the container type.
             if cmp_guid(&sub, &KernelStreaming::KSDATAFORMAT_SUBTYPE_PCM) {
                 match n_bits {
                     8 => SampleFormat::U8,
                     16 => SampleFormat::I16,
                     32 => SampleFormat::I32,
                     64 => SampleFormat::I64,
+                    // Packed 24 bit samples have no matching `SampleFormat`.
+                    _ => return None,
+                }
+            } else if cmp_guid(&sub, &Multimedia::KSDATAFORMAT_SUBTYPE_IEEE_FLOAT) {
+                match n_bits {
+                    32 => SampleFormat::F32,
+                    64 => SampleFormat::F64,
                     _ => return None,
                 }
-            } else if n_bits == 32 && cmp_guid(&sub, &Multimedia::KSDATAFORMAT_SUBTYPE_IEEE_FLOAT) {
-                SampleFormat::F32
             } else {
                 return None;
             }
@@ -262,7 +387,7 @@ unsafe fn format_from_waveformatex_ptr(
     } else {
         SupportedBufferSize::Range {
             min: 0,
-            max: u32::max_value(),
+            max: u32::MAX,
         }
     };
 
@@ -331,18 +456,147 @@ impl Device {
         }
     }
 
+    /// The endpoint ID string of the device, as used by [`DeviceEvent`](crate::DeviceEvent).
+    pub fn id(&self) -> Result<String, DeviceNameError> {
+        unsafe {
+            let id = self
+                .device
+                .GetId()
+                .map_err(|err| DeviceNameError::from(BackendSpecificError::from(err)))?;
+            let result = id.to_string().map_err(|err| {
+                let description = format!("device id is not valid UTF-16: {}", err);
+                DeviceNameError::from(BackendSpecificError { description })
+            });
+            Com::CoTaskMemFree(Some(id.0 as *mut _));
+            result
+        }
+    }
+
     #[inline]
     fn from_immdevice(device: Audio::IMMDevice) -> Self {
         Device {
             device,
             future_audio_client: Arc::new(Mutex::new(None)),
+            ducking_opt_out: false,
+            exclusive: false,
+            auto_convert_pcm: false,
+            silence_padding: false,
+            idle_timeout: None,
         }
     }
 
+    /// Set whether streams are opened in exclusive mode, bypassing the audio engine (default is
+    /// false).
+    ///
+    /// Exclusive streams must use a format the device supports natively and keep other
+    /// applications from using the device while they exist.
+    pub fn set_exclusive_mode(&mut self, exclusive: bool) {
+        self.exclusive = exclusive;
+    }
+
+    /// Set whether shared-mode streams are created with `AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM`
+    /// (default is false).
+    ///
+    /// The audio engine then converts the sample format, channel count and sample rate of the
+    /// stream to its mix format, so almost any config can be used. When unset, only configs the
+    /// engine accepts as they are can be used and building fails with `StreamConfigNotSupported`
+    /// otherwise. Has no effect on exclusive streams.
+    pub fn set_auto_convert_pcm(&mut self, auto_convert_pcm: bool) {
+        self.auto_convert_pcm = auto_convert_pcm;
+    }
+
+    /// Set whether streams opt out of being ducked (attenuated) by Windows while a communications
+    /// stream, e.g. a VoIP call, is running (default is false).
+    ///
+    /// The preference applies to the audio session of the stream, which is shared by all streams
+    /// of the process by default.
+    pub fn set_ducking_opt_out(&mut self, opt_out: bool) {
+        self.ducking_opt_out = opt_out;
+    }
+
+    /// Set whether output streams pad their buffer with silence when their thread wakes up late
+    /// (default is false).
+    ///
+    /// When less than half the buffer is queued before the data callback runs, silence is
+    /// written up to half the buffer first, so the audio engine keeps playing while the callback
+    /// produces the next audio instead of glitching. Useful for applications that produce audio
+    /// sporadically, at the cost of the extra latency the silence adds.
+    pub fn set_silence_padding(&mut self, silence_padding: bool) {
+        self.silence_padding = silence_padding;
+    }
+
+    /// Set how long an output stream plays nothing but silence before it is stopped to save
+    /// power (default is `None`).
+    ///
+    /// While stopped, the data callback keeps running every half buffer on the stream's thread.
+    /// As soon as it renders audio that is not silent, the stream is started again with that
+    /// audio.
+    pub fn set_idle_timeout(&mut self, idle_timeout: Option<Duration>) {
+        self.idle_timeout = idle_timeout;
+    }
+
+    /// The periods the shared-mode audio engine supports for the device's mix format, from
+    /// `IAudioClient3::GetSharedModeEnginePeriod`.
+    ///
+    /// Requires Windows 10 or later, older versions fail because they lack `IAudioClient3`.
+    pub fn shared_mode_engine_periods(&self) -> Result<EnginePeriods, BackendSpecificError> {
+        // initializing COM because we call `CoTaskMemFree` to release the format.
+        com::com_initialized();
+        let audio_client = self.build_audioclient()?.cast::<Audio::IAudioClient3>()?;
+        unsafe {
+            let format = WaveFormatExPtr(audio_client.GetMixFormat()?);
+            let mut periods = EnginePeriods {
+                default: 0,
+                fundamental: 0,
+                min: 0,
+                max: 0,
+            };
+            audio_client.GetSharedModeEnginePeriod(
+                format.0,
+                &mut periods.default,
+                &mut periods.fundamental,
+                &mut periods.min,
+                &mut periods.max,
+            )?;
+            Ok(periods)
+        }
+    }
+
+    /// Activate the audio client of the device through `ActivateAudioInterfaceAsync` and call
+    /// `callback` once the activation completed.
+    ///
+    /// This is the activation Windows allows in restricted contexts such as AppContainer
+    /// processes, where the synchronous activation fails. The next stream built from this device
+    /// or one of its clones uses the activated client. The callback runs on a thread owned by
+    /// Windows.
+    pub fn activate_async<F>(&self, callback: F) -> Result<(), BackendSpecificError>
+    where
+        F: FnOnce(Result<(), BackendSpecificError>) + Send + 'static,
+    {
+        let id = self
+            .id()
+            .map_err(|DeviceNameError::BackendSpecific { err }| err)?;
+        let future_audio_client = self.future_audio_client.clone();
+        activation::activate_audio_client(
+            &id,
+            Box::new(move |result| {
+                callback(
+                    result
+                        .map(|audio_client| {
+                            *future_audio_client.lock().unwrap() =
+                                Some(IAudioClientWrapper(audio_client));
+                        })
+                        .map_err(BackendSpecificError::from),
+                )
+            }),
+        )?;
+        Ok(())
+    }
+
     /// Ensures that `future_audio_client` contains a `Some` and returns a locked mutex to it.
     fn ensure_future_audio_client(
         &self,
-    ) -> Result<MutexGuard<Option<IAudioClientWrapper>>, windows::core::Error> {
+    ) -> Result<MutexGuard<'_, Option<IAudioClientWrapper>>, windows::core::Error> {
         let mut lock = self.future_audio_client.lock().unwrap();
         if lock.is_some() {
             return Ok(lock);
@@ -375,8 +629,12 @@ impl Device {
     // When calling `IsFormatSupported` with the shared-mode audio engine, only the default
     // number of channels seems to be supported. Any, more or less returns an invalid
     // parameter error. Thus, we just assume that the default number of channels is the only
-    // number supported.
-    fn supported_formats(&self) -> Result<SupportedInputConfigs, SupportedStreamConfigsError> {
+    // number supported. In exclusive mode the driver is asked directly, so common channel
+    // counts are trialed as well.
+    fn supported_formats(
+        &self,
+        mode: ShareMode,
+    ) -> Result<SupportedInputConfigs, SupportedStreamConfigsError> {
         // initializing COM because we call `CoTaskMemFree` to release the format.
         com::com_initialized();
 
@@ -426,30 +684,48 @@ impl Device {
                 sample_rates.push(format.sample_rate)
             }
 
+            let mut channel_counts = vec![format.channels];
+            if mode == ShareMode::Exclusive {
+                // Multichannel interfaces are only probed for counts up to their own.
+                let max_channels = format.channels.max(8);
+                for channels in [1, 2, 4, 6, 8, 10, 12, 16, 18, 24, 32, 64] {
+                    if channels <= max_channels && !channel_counts.contains(&channels) {
+                        channel_counts.push(channels);
+                    }
+                }
+            }
+
             let mut supported_formats = Vec::new();
 
-            for sample_rate in sample_rates {
-                for sample_format in [
-                    SampleFormat::U8,
-                    SampleFormat::I16,
-                    SampleFormat::I32,
-                    SampleFormat::I64,
-                    SampleFormat::F32,
-                ] {
-                    if let Some(waveformat) = config_to_waveformatextensible(
-                        &StreamConfig {
-                            channels: format.channels,
-                            sample_rate,
-                            buffer_size: BufferSize::Default,
-                        },
-                        sample_format,
-                    ) {
-                        if is_format_supported(
-                            client,
-                            &waveformat.Format as *const Audio::WAVEFORMATEX,
-                        )? {
+            for &channels in &channel_counts {
+                for &sample_rate in &sample_rates {
+                    for sample_format in [
+                        SampleFormat::U8,
+                        SampleFormat::I16,
+                        SampleFormat::I32,
+                        SampleFormat::I64,
+                        SampleFormat::F32,
+                    ] {
+                        let Some(waveformat) = config_to_waveformatextensible(
+                            &StreamConfig {
+                                channels,
+                                sample_rate,
+                                ..StreamConfig::default()
+                            },
+                            sample_format,
+                        ) else {
+                            continue;
+                        };
+                        let waveformatex_ptr = &waveformat.Format as *const Audio::WAVEFORMATEX;
+                        let is_supported = match mode {
+                            ShareMode::Shared => is_format_supported(client, waveformatex_ptr)?,
+                            ShareMode::Exclusive => {
+                                is_format_supported_exclusive(client, waveformatex_ptr)?
+                            }
+                        };
+                        if is_supported {
                             supported_formats.push(SupportedStreamConfigRange {
-                                channels: format.channels,
+                                channels,
                                 min_sample_rate: sample_rate,
                                 max_sample_rate: sample_rate,
                                 buffer_size: format.buffer_size,
@@ -465,9 +741,19 @@ impl Device {
 
     pub fn supported_input_configs(
         &self,
+    ) -> Result<SupportedInputConfigs, SupportedStreamConfigsError> {
+        self.supported_input_configs_with_mode(ShareMode::Shared)
+    }
+
+    /// The input configs the device supports in shared mode, or in exclusive mode as set with
+    /// [`set_exclusive_mode`](Self::set_exclusive_mode), where the driver takes the format as is
+    /// and more channel counts may be available.
+    pub fn supported_input_configs_with_mode(
+        &self,
+        mode: ShareMode,
     ) -> Result<SupportedInputConfigs, SupportedStreamConfigsError> {
         if self.data_flow() == Audio::eCapture {
-            self.supported_formats()
+            self.supported_formats(mode)
         // If it's an output device, assume no input formats.
         } else {
             Ok(vec![].into_iter())
@@ -476,9 +762,19 @@ impl Device {
 
     pub fn supported_output_configs(
         &self,
+    ) -> Result<SupportedOutputConfigs, SupportedStreamConfigsError> {
+        self.supported_output_configs_with_mode(ShareMode::Shared)
+    }
+
+    /// The output configs the device supports in shared mode, or in exclusive mode as set with
+    /// [`set_exclusive_mode`](Self::set_exclusive_mode), where the driver takes the format as is
+    /// and more channel counts may be available.
+    pub fn supported_output_configs_with_mode(
+        &self,
+        mode: ShareMode,
     ) -> Result<SupportedOutputConfigs, SupportedStreamConfigsError> {
         if self.data_flow() == Audio::eRender {
-            self.supported_formats()
+            self.supported_formats(mode)
         // If it's an input device, assume no output formats.
         } else {
             Ok(vec![].into_iter())
@@ -522,6 +818,9 @@ impl Device {
         endpoint.data_flow()
     }
 
+    /// The engine's shared-mode mix format, as returned by `IAudioClient::GetMixFormat`.
+    ///
+    /// Shared-mode streams opened with this config are not converted by the audio engine.
     pub fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
         if self.data_flow() == Audio::eCapture {
             self.default_format()
@@ -530,6 +829,9 @@ impl Device {
         }
     }
 
+    /// The engine's shared-mode mix format, as returned by `IAudioClient::GetMixFormat`.
+    ///
+    /// Shared-mode streams opened with this config are not converted by the audio engine.
     pub fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
         let data_flow = self.data_flow();
         if data_flow == Audio::eRender {
@@ -539,6 +841,147 @@ impl Device {
         }
     }
 
+    /// Initialize `audio_client` for a stream with the given format.
+    ///
+    /// Exclusive streams need a buffer duration that is aligned to the device period. If the
+    /// driver rejects the requested one with `AUDCLNT_E_BUFFER_SIZE_NOT_ALIGNED`, a new client is
+    /// initialized with the aligned buffer size it reports instead, so the returned client may not
+    /// be the one passed in.
+    unsafe fn initialize_audio_client(
+        &self,
+        audio_client: Audio::IAudioClient,
+        stream_flags: u32,
+        config: &StreamConfig,
+        waveformatex: &Audio::WAVEFORMATEX,
+    ) -> Result<Audio::IAudioClient, BuildStreamError> {
+        let offload = set_client_properties(&audio_client, config, !self.exclusive);
+
+        // Ensure the format is supported. The engine converts any PCM or float format if asked to.
+        let auto_convert_pcm = self.auto_convert_pcm && !self.exclusive;
+        let is_supported = if self.exclusive {
+            is_format_supported_exclusive(&audio_client, waveformatex)
+        } else if auto_convert_pcm {
+            Ok(true)
+        } else {
+            is_format_supported(&audio_client, waveformatex)
+        };
+        match is_supported {
+            Ok(false) => return Err(BuildStreamError::StreamConfigNotSupported),
+            Err(_) => return Err(BuildStreamError::DeviceNotAvailable),
+            _ => (),
+        }
+
+        let mut buffer_duration =
+            buffer_size_to_duration(&config.buffer_size, config.sample_rate.0);
+        if !self.exclusive {
+            if let (BufferSize::Default, PerformanceMode::PowerSaving) =
+                (config.buffer_size, config.performance_mode)
+            {
+                buffer_duration = POWER_SAVING_BUFFER_DURATION;
+            }
+            if offload {
+                // Offloaded streams only support the buffer sizes of the hardware.
+                let (mut min, mut max) = (0, 0);
+                let limits = audio_client
+                    .cast::<Audio::IAudioClient2>()
+                    .and_then(|c| c.GetBufferSizeLimits(waveformatex, true, &mut min, &mut max));
+                if limits.is_ok() {
+                    buffer_duration = buffer_duration.clamp(min, max);
+                }
+            }
+            let stream_flags = if auto_convert_pcm {
+                stream_flags
+                    | Audio::AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM
+                    | Audio::AUDCLNT_STREAMFLAGS_SRC_DEFAULT_QUALITY
+            } else {
+                stream_flags
+            };
+            trace_call!(
+                "IAudioClient::Initialize",
+                (
+                    Audio::AUDCLNT_SHAREMODE_SHARED,
+                    stream_flags,
+                    buffer_duration,
+                    0
+                ),
+                audio_client.Initialize(
+                    Audio::AUDCLNT_SHAREMODE_SHARED,
+                    stream_flags,
+                    buffer_duration,
+                    0,
+                    waveformatex,
+                    None,
+                )
+            )
+            .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
+            return Ok(audio_client);
+        }
+
+        // Event driven exclusive streams require the buffer duration and the periodicity to match.
+        let buffer_duration = match config.buffer_size {
+            BufferSize::Fixed(_) => buffer_duration,
+            BufferSize::Default => {
+                let mut default_period = 0;
+                audio_client
+                    .GetDevicePeriod(Some(&mut default_period), None)
+                    .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
+                default_period
+            }
+        };
+        let result = trace_call!(
+            "IAudioClient::Initialize",
+            (
+                Audio::AUDCLNT_SHAREMODE_EXCLUSIVE,
+                stream_flags,
+                buffer_duration,
+                buffer_duration
+            ),
+            audio_client.Initialize(
+                Audio::AUDCLNT_SHAREMODE_EXCLUSIVE,
+                stream_flags,
+                buffer_duration,
+                buffer_duration,
+                waveformatex,
+                None,
+            )
+        );
+        match result {
+            Err(ref e) if e.code() == Audio::AUDCLNT_E_BUFFER_SIZE_NOT_ALIGNED => {
+                // The failed client reports the closest aligned buffer size, but can't be
+                // initialized again.
+                let frames = audio_client
+                    .GetBufferSize()
+                    .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
+                let buffer_duration = aligned_buffer_duration(frames, config.sample_rate.0);
+                let audio_client = self
+                    .build_audioclient()
+                    .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
+                set_client_properties(&audio_client, config, false);
+                trace_call!(
+                    "IAudioClient::Initialize",
+                    (
+                        Audio::AUDCLNT_SHAREMODE_EXCLUSIVE,
+                        stream_flags,
+                        buffer_duration,
+                        buffer_duration
+                    ),
+                    audio_client.Initialize(
+                        Audio::AUDCLNT_SHAREMODE_EXCLUSIVE,
+                        stream_flags,
+                        buffer_duration,
+                        buffer_duration,
+                        waveformatex,
+                        None,
+                    )
+                )
+                .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
+                Ok(audio_client)
+            }
+            Err(e) => Err(windows_err_to_cpal_err(e)),
+            Ok(()) => Ok(audio_client),
+        }
+    }
+
     pub(crate) fn build_input_stream_raw_inner(
         &self,
         config: &StreamConfig,
@@ -562,9 +1005,6 @@ impl Device {
                 }
             };
 
-            let buffer_duration =
-                buffer_size_to_duration(&config.buffer_size, config.sample_rate.0);
-
             let mut stream_flags = Audio::AUDCLNT_STREAMFLAGS_EVENTCALLBACK;
 
             if self.data_flow() == Audio::eRender {
@@ -572,47 +1012,21 @@ impl Device {
             }
 
             // Computing the format and initializing the device.
-            let waveformatex = {
-                let format_attempt = config_to_waveformatextensible(config, sample_format)
-                    .ok_or(BuildStreamError::StreamConfigNotSupported)?;
-                let share_mode = Audio::AUDCLNT_SHAREMODE_SHARED;
-
-                // Ensure the format is supported.
-                match super::device::is_format_supported(&audio_client, &format_attempt.Format) {
-                    Ok(false) => return Err(BuildStreamError::StreamConfigNotSupported),
-                    Err(_) => return Err(BuildStreamError::DeviceNotAvailable),
-                    _ => (),
-                }
-
-                // Finally, initializing the audio client
-                let hresult = audio_client.Initialize(
-                    share_mode,
-                    stream_flags,
-                    buffer_duration,
-                    0,
-                    &format_attempt.Format,
-                    None,
-                );
-                match hresult {
-                    Err(ref e) if e.code() == Audio::AUDCLNT_E_DEVICE_INVALIDATED => {
-                        return Err(BuildStreamError::DeviceNotAvailable);
-                    }
-                    Err(e) => {
-                        let description = format!("{}", e);
-                        let err = BackendSpecificError { description };
-                        return Err(err.into());
-                    }
-                    Ok(()) => (),
-                };
-
-                format_attempt.Format
-            };
+            let format_attempt = config_to_waveformatextensible(config, sample_format)
+                .ok_or(BuildStreamError::StreamConfigNotSupported)?;
+            let waveformatex = &format_attempt.Format;
+            let audio_client =
+                self.initialize_audio_client(audio_client, stream_flags, config, waveformatex)?;
 
             // obtaining the size of the samples buffer in number of frames
             let max_frames_in_buffer = audio_client
                 .GetBufferSize()
                 .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
 
+            if self.ducking_opt_out {
+                set_ducking_opt_out(&audio_client)?;
+            }
+
             // Creating the event that will be signalled whenever we need to submit some samples.
             let event = {
                 let event =
@@ -658,6 +1072,8 @@ impl Device {
                 bytes_per_frame: waveformatex.nBlockAlign,
                 config: config.clone(),
                 sample_format,
+                silence_padding: None,
+                idle_timeout: None,
             })
         }
     }
@@ -677,36 +1093,20 @@ impl Device {
                 .build_audioclient()
                 .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
 
-            let buffer_duration =
-                buffer_size_to_duration(&config.buffer_size, config.sample_rate.0);
-
             // Computing the format and initializing the device.
-            let waveformatex = {
-                let format_attempt = config_to_waveformatextensible(config, sample_format)
-                    .ok_or(BuildStreamError::StreamConfigNotSupported)?;
-                let share_mode = Audio::AUDCLNT_SHAREMODE_SHARED;
-
-                // Ensure the format is supported.
-                match super::device::is_format_supported(&audio_client, &format_attempt.Format) {
-                    Ok(false) => return Err(BuildStreamError::StreamConfigNotSupported),
-                    Err(_) => return Err(BuildStreamError::DeviceNotAvailable),
-                    _ => (),
-                }
-
-                // Finally, initializing the audio client
-                audio_client
-                    .Initialize(
-                        share_mode,
-                        Audio::AUDCLNT_STREAMFLAGS_EVENTCALLBACK,
-                        buffer_duration,
-                        0,
-                        &format_attempt.Format,
-                        None,
-                    )
-                    .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
+            let format_attempt = config_to_waveformatextensible(config, sample_format)
+                .ok_or(BuildStreamError::StreamConfigNotSupported)?;
+            let waveformatex = &format_attempt.Format;
+            let audio_client = self.initialize_audio_client(
+                audio_client,
+                Audio::AUDCLNT_STREAMFLAGS_EVENTCALLBACK,
+                config,
+                waveformatex,
+            )?;
 
-                format_attempt.Format
-            };
+            if self.ducking_opt_out {
+                set_ducking_opt_out(&audio_client)?;
+            }
 
             // Creating the event that will be signalled whenever we need to submit some samples.
             let event = {
@@ -761,6 +1161,8 @@ impl Device {
                 bytes_per_frame: waveformatex.nBlockAlign,
                 config: config.clone(),
                 sample_format,
+                silence_padding: self.silence_padding.then_some(max_frames_in_buffer / 2),
+                idle_timeout: self.idle_timeout,
             })
         }
     }
@@ -835,7 +1237,7 @@ impl Endpoint {
 
 static ENUMERATOR: OnceLock<Enumerator> = OnceLock::new();
 
-fn get_enumerator() -> &'static Enumerator {
+pub(super) fn get_enumerator() -> &'static Enumerator {
     ENUMERATOR.get_or_init(|| {
         // COM initialization is thread local, but we only need to have COM initialized in the
         // thread we create the objects in
@@ -856,7 +1258,7 @@ fn get_enumerator() -> &'static Enumerator {
 }
 
 /// Send/Sync wrapper around `IMMDeviceEnumerator`.
-struct Enumerator(Audio::IMMDeviceEnumerator);
+pub(super) struct Enumerator(pub(super) Audio::IMMDeviceEnumerator);
 
 unsafe impl Send for Enumerator {}
 unsafe impl Sync for Enumerator {}
@@ -914,23 +1316,82 @@ impl Iterator for Devices {
     }
 }
 
-fn default_device(data_flow: Audio::EDataFlow) -> Option<Device> {
+fn default_device(data_flow: Audio::EDataFlow, role: DeviceRole) -> Option<Device> {
+    let role = match role {
+        DeviceRole::Console => Audio::eConsole,
+        DeviceRole::Multimedia => Audio::eMultimedia,
+        DeviceRole::Communications => Audio::eCommunications,
+    };
     unsafe {
         let device = get_enumerator()
             .0
-            .GetDefaultAudioEndpoint(data_flow, Audio::eConsole)
+            .GetDefaultAudioEndpoint(data_flow, role)
             .ok()?;
         // TODO: check specifically for `E_NOTFOUND`, and panic otherwise
         Some(Device::from_immdevice(device))
     }
 }
 
-pub fn default_input_device() -> Option<Device> {
-    default_device(Audio::eCapture)
+pub fn default_input_device(role: DeviceRole) -> Option<Device> {
+    default_device(Audio::eCapture, role)
+}
+
+pub fn default_output_device(role: DeviceRole) -> Option<Device> {
+    default_device(Audio::eRender, role)
+}
+
+/// Opt the session of the initialized audio client out of communications ducking.
+unsafe fn set_ducking_opt_out(audio_client: &Audio::IAudioClient) -> Result<(), BuildStreamError> {
+    audio_client
+        .GetService::<Audio::IAudioSessionControl>()
+        .and_then(|session| session.cast::<Audio::IAudioSessionControl2>())
+        .and_then(|session| session.SetDuckingPreference(true))
+        .map_err(|e| {
+            windows_err_to_cpal_err_message::<BuildStreamError>(
+                e,
+                "failed to set the ducking preference: ",
+            )
+        })
 }
 
-pub fn default_output_device() -> Option<Device> {
-    default_device(Audio::eRender)
+/// The buffer duration of shared streams in power saving mode with the default buffer size, in
+/// 100 nanosecond units.
+const POWER_SAVING_BUFFER_DURATION: i64 = 2_000_000;
+
+/// Set the audio stream category matching the usage on the uninitialized audio client, so
+/// Windows applies the right volume policies and ducking, and request hardware offload for
+/// shared streams in power saving mode where the device supports it.
+///
+/// The usage and performance mode are hints, so failures, e.g. on Windows 7 without
+/// `IAudioClient2`, are ignored. Returns whether the stream is offloaded.
+unsafe fn set_client_properties(
+    audio_client: &Audio::IAudioClient,
+    config: &StreamConfig,
+    shared: bool,
+) -> bool {
+    let power_saving = config.performance_mode == PerformanceMode::PowerSaving && shared;
+    let category = match config.usage {
+        StreamUsage::Default if !power_saving => return false,
+        StreamUsage::Default => Audio::AudioCategory_Other,
+        StreamUsage::Media => Audio::AudioCategory_Media,
+        StreamUsage::Game => Audio::AudioCategory_GameEffects,
+        StreamUsage::VoiceCommunication => Audio::AudioCategory_Communications,
+        StreamUsage::Alarm | StreamUsage::Notification => Audio::AudioCategory_Alerts,
+    };
+    let Ok(audio_client) = audio_client.cast::<Audio::IAudioClient2>() else {
+        return false;
+    };
+    let offload = power_saving
+        && audio_client
+            .IsOffloadCapable(category)
+            .is_ok_and(|capable| capable.as_bool());
+    let properties = Audio::AudioClientProperties {
+        cbSize: mem::size_of::<Audio::AudioClientProperties>() as u32,
+        bIsOffload: offload.into(),
+        eCategory: category,
+        Options: Audio::AUDCLNT_STREAMOPTIONS_NONE,
+    };
+    audio_client.SetClientProperties(&properties).is_ok() && offload
 }
 
 /// Get the audio clock used to produce `StreamInstant`s.
@@ -951,10 +1412,13 @@ fn config_to_waveformatextensible(
     config: &StreamConfig,
     sample_format: SampleFormat,
 ) -> Option<Audio::WAVEFORMATEXTENSIBLE> {
+    // `WAVE_FORMAT_PCM` only describes mono and stereo, more channels need the extensible
+    // format.
     let format_tag = match sample_format {
-        SampleFormat::U8 | SampleFormat::I16 => Audio::WAVE_FORMAT_PCM,
+        SampleFormat::U8 | SampleFormat::I16 if config.channels <= 2 => Audio::WAVE_FORMAT_PCM,
+        SampleFormat::U8 | SampleFormat::I16 => KernelStreaming::WAVE_FORMAT_EXTENSIBLE,
 
-        SampleFormat::I32 | SampleFormat::I64 | SampleFormat::F32 => {
+        SampleFormat::I32 | SampleFormat::I64 | SampleFormat::F32 | SampleFormat::F64 => {
             KernelStreaming::WAVE_FORMAT_EXTENSIBLE
         }
 
@@ -963,8 +1427,8 @@ fn config_to_waveformatextensible(
     let channels = config.channels;
     let sample_rate = config.sample_rate.0;
     let sample_bytes = sample_format.sample_size() as u16;
-    let avg_bytes_per_sec = u32::from(channels) * sample_rate * u32::from(sample_bytes);
-    let block_align = channels * sample_bytes;
+    let block_align = channels.checked_mul(sample_bytes)?;
+    let avg_bytes_per_sec = u32::from(block_align).checked_mul(sample_rate)?;
     let bits_per_sample = 8 * sample_bytes;
 
     let cb_size = if format_tag == Audio::WAVE_FORMAT_PCM {
@@ -985,15 +1449,21 @@ fn config_to_waveformatextensible(
         cbSize: cb_size,
     };
 
-    // CPAL does not care about speaker positions, so pass audio right through.
-    let channel_mask = KernelStreaming::KSAUDIO_SPEAKER_DIRECTOUT;
+    // The speaker bits are in the order of `ChannelPosition`. Other channel counts are passed
+    // right through.
+    let channel_mask = match ChannelPosition::standard_layout(channels) {
+        Some(layout) => layout
+            .iter()
+            .fold(0, |mask, &position| mask | 1 << position as u32),
+        None => KernelStreaming::KSAUDIO_SPEAKER_DIRECTOUT,
+    };
 
     let sub_format = match sample_format {
         SampleFormat::U8 | SampleFormat::I16 | SampleFormat::I32 | SampleFormat::I64 => {
             KernelStreaming::KSDATAFORMAT_SUBTYPE_PCM
         }
 
-        SampleFormat::F32 => Multimedia::KSDATAFORMAT_SUBTYPE_IEEE_FLOAT,
+        SampleFormat::F32 | SampleFormat::F64 => Multimedia::KSDATAFORMAT_SUBTYPE_IEEE_FLOAT,
         _ => return None,
     };
 
@@ -1016,6 +1486,12 @@ fn buffer_size_to_duration(buffer_size: &BufferSize, sample_rate: u32) -> i64 {
     }
 }
 
+/// The buffer duration for an aligned buffer size, as computed in the `IAudioClient::Initialize`
+/// documentation.
+fn aligned_buffer_duration(frames: FrameCount, sample_rate: u32) -> i64 {
+    (10_000_000.0 * frames as f64 / sample_rate as f64 + 0.5) as i64
+}
+
 fn buffer_duration_to_frames(buffer_duration: i64, sample_rate: u32) -> FrameCount {
     (buffer_duration * sample_rate as i64 * 100 / 1_000_000_000) as FrameCount
 }
@@ -1,16 +1,23 @@
 pub use self::device::{
-    default_input_device, default_output_device, Device, Devices, SupportedInputConfigs,
-    SupportedOutputConfigs,
+    default_input_device, default_output_device, Device, Devices, EnginePeriods,
+    SupportedInputConfigs, SupportedOutputConfigs,
 };
-pub use self::stream::Stream;
+pub use self::notification::DeviceWatcher;
+pub use self::stream::{Stream, StreamExt, StreamLatency, StreamPosition};
 use crate::traits::HostTrait;
 use crate::BackendSpecificError;
+use crate::Capabilities;
+use crate::DeviceEvent;
+use crate::DeviceRole;
 use crate::DevicesError;
 use std::io::Error as IoError;
 use windows::Win32::Media::Audio;
 
+mod activation;
 mod com;
 mod device;
+mod notification;
+mod session;
 mod stream;
 
 /// The WASAPI host, the default windows host type.
@@ -25,6 +32,17 @@ impl Host {
     pub fn new() -> Result<Self, crate::HostUnavailable> {
         Ok(Host)
     }
+
+    /// Call `callback` whenever an endpoint is added, removed or becomes the default device.
+    ///
+    /// The callback runs on a thread owned by Windows and must not block. Events stop when the
+    /// returned watcher is dropped.
+    pub fn watch_devices<F>(&self, callback: F) -> Result<DeviceWatcher, BackendSpecificError>
+    where
+        F: FnMut(DeviceEvent) + Send + 'static,
+    {
+        DeviceWatcher::new(callback)
+    }
 }
 
 impl HostTrait for Host {
@@ -41,12 +59,94 @@ impl HostTrait for Host {
     }
 
     fn default_input_device(&self) -> Option<Self::Device> {
-        default_input_device()
+        default_input_device(DeviceRole::Console)
     }
 
     fn default_output_device(&self) -> Option<Self::Device> {
-        default_output_device()
+        default_output_device(DeviceRole::Console)
+    }
+
+    fn default_input_device_for_role(&self, role: DeviceRole) -> Option<Self::Device> {
+        default_input_device(role)
+    }
+
+    fn default_output_device_for_role(&self, role: DeviceRole) -> Option<Self::Device> {
+        default_output_device(role)
+    }
+
+    fn version(&self) -> Option<String> {
+        windows_version()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            exclusive_mode: true,
+            loopback: true,
+            device_events: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// The Windows version, e.g. "Windows 10.0.22631.3447", read from the registry because
+/// `GetVersionEx` reports the version the executable is manifested for.
+fn windows_version() -> Option<String> {
+    use std::ffi::c_void;
+    use windows::core::{w, PCWSTR};
+    use windows::Win32::Foundation::ERROR_SUCCESS;
+    use windows::Win32::System::Registry::{
+        RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_DWORD, RRF_RT_REG_SZ,
+    };
+
+    const KEY: PCWSTR = w!("SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion");
+    let dword = |name: PCWSTR| {
+        let mut value = 0u32;
+        let mut size = std::mem::size_of::<u32>() as u32;
+        let result = unsafe {
+            RegGetValueW(
+                HKEY_LOCAL_MACHINE,
+                KEY,
+                name,
+                RRF_RT_REG_DWORD,
+                None,
+                Some(&mut value as *mut u32 as *mut c_void),
+                Some(&mut size),
+            )
+        };
+        (result == ERROR_SUCCESS).then_some(value)
+    };
+
+    let mut build = [0u16; 32];
+    let mut size = std::mem::size_of_val(&build) as u32;
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            KEY,
+            w!("CurrentBuildNumber"),
+            RRF_RT_REG_SZ,
+            None,
+            Some(build.as_mut_ptr() as *mut c_void),
+            Some(&mut size),
+        )
+    };
+    if result != ERROR_SUCCESS {
+        return None;
+    }
+    let len = build.iter().position(|&c| c == 0).unwrap_or(build.len());
+    let build = String::from_utf16_lossy(&build[..len]);
+
+    // Windows 7 and 8 only have the build number.
+    let mut version = match (
+        dword(w!("CurrentMajorVersionNumber")),
+        dword(w!("CurrentMinorVersionNumber")),
+    ) {
+        (Some(major), Some(minor)) => format!("Windows {}.{}.{}", major, minor, build),
+        _ => format!("Windows build {}", build),
+    };
+    if let Some(revision) = dword(w!("UBR")) {
+        version.push_str(&format!(".{}", revision));
     }
+    Some(version)
 }
 
 impl From<windows::core::Error> for BackendSpecificError {
@@ -0,0 +1,214 @@
+//! Endpoint notifications through `IMMNotificationClient`.
+//!
+//! The client is a hand written COM object: it is only ever called through its vtable by the
+//! device enumerator, so implementing the handful of methods directly avoids pulling in the
+//! `windows` crate's `implement` machinery.
+
+use super::device::get_enumerator;
+use crate::{BackendSpecificError, DeviceEvent};
+use std::ffi::c_void;
+use std::slice;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use windows::core::{IUnknown, Interface, GUID, HRESULT};
+use windows::Win32::Foundation::{E_NOINTERFACE, E_POINTER, S_OK};
+use windows::Win32::Media::Audio;
+
+/// Keeps a device event callback registered with the device enumerator.
+///
+/// The callback is unregistered when this is dropped.
+pub struct DeviceWatcher {
+    client: Audio::IMMNotificationClient,
+}
+
+// The enumerator calls the notification client from its own threads anyway.
+unsafe impl Send for DeviceWatcher {}
+unsafe impl Sync for DeviceWatcher {}
+
+impl DeviceWatcher {
+    pub(super) fn new<F>(callback: F) -> Result<Self, BackendSpecificError>
+    where
+        F: FnMut(DeviceEvent) + Send + 'static,
+    {
+        let client = NotificationClient::create(Box::new(callback));
+        unsafe {
+            get_enumerator()
+                .0
+                .RegisterEndpointNotificationCallback(&client)?;
+        }
+        Ok(DeviceWatcher { client })
+    }
+}
+
+impl Drop for DeviceWatcher {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = get_enumerator()
+                .0
+                .UnregisterEndpointNotificationCallback(&self.client);
+        }
+    }
+}
+
+/// The `PROPERTYKEY` passed by value to `OnPropertyValueChanged`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PropertyKey {
+    fmtid: GUID,
+    pid: u32,
+}
+
+#[repr(C)]
+struct NotificationClientVtbl {
+    query_interface:
+        unsafe extern "system" fn(*mut c_void, *const GUID, *mut *mut c_void) -> HRESULT,
+    add_ref: unsafe extern "system" fn(*mut c_void) -> u32,
+    release: unsafe extern "system" fn(*mut c_void) -> u32,
+    on_device_state_changed: unsafe extern "system" fn(*mut c_void, *const u16, u32) -> HRESULT,
+    on_device_added: unsafe extern "system" fn(*mut c_void, *const u16) -> HRESULT,
+    on_device_removed: unsafe extern "system" fn(*mut c_void, *const u16) -> HRESULT,
+    on_default_device_changed:
+        unsafe extern "system" fn(*mut c_void, i32, i32, *const u16) -> HRESULT,
+    on_property_value_changed:
+        unsafe extern "system" fn(*mut c_void, *const u16, PropertyKey) -> HRESULT,
+}
+
+static VTBL: NotificationClientVtbl = NotificationClientVtbl {
+    query_interface,
+    add_ref,
+    release,
+    on_device_state_changed,
+    on_device_added,
+    on_device_removed,
+    on_default_device_changed,
+    on_property_value_changed,
+};
+
+/// The COM object. The vtable pointer must stay the first field.
+#[repr(C)]
+struct NotificationClient {
+    vtbl: *const NotificationClientVtbl,
+    refs: AtomicU32,
+    callback: Mutex<Box<dyn FnMut(DeviceEvent) + Send>>,
+}
+
+impl NotificationClient {
+    /// Allocate a client and return the interface holding its only reference.
+    fn create(callback: Box<dyn FnMut(DeviceEvent) + Send>) -> Audio::IMMNotificationClient {
+        let client = Box::new(NotificationClient {
+            vtbl: &VTBL,
+            refs: AtomicU32::new(1),
+            callback: Mutex::new(callback),
+        });
+        // The interface takes over the initial reference.
+        unsafe { Audio::IMMNotificationClient::from_raw(Box::into_raw(client) as *mut c_void) }
+    }
+
+    unsafe fn emit(this: *mut c_void, event: DeviceEvent) -> HRESULT {
+        let client = &*(this as *const NotificationClient);
+        if let Ok(mut callback) = client.callback.lock() {
+            callback(event);
+        }
+        S_OK
+    }
+}
+
+unsafe extern "system" fn query_interface(
+    this: *mut c_void,
+    iid: *const GUID,
+    object: *mut *mut c_void,
+) -> HRESULT {
+    if iid.is_null() || object.is_null() {
+        return E_POINTER;
+    }
+    if *iid == IUnknown::IID || *iid == Audio::IMMNotificationClient::IID {
+        add_ref(this);
+        *object = this;
+        S_OK
+    } else {
+        *object = std::ptr::null_mut();
+        E_NOINTERFACE
+    }
+}
+
+unsafe extern "system" fn add_ref(this: *mut c_void) -> u32 {
+    let client = &*(this as *const NotificationClient);
+    client.refs.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+unsafe extern "system" fn release(this: *mut c_void) -> u32 {
+    let client = &*(this as *const NotificationClient);
+    let refs = client.refs.fetch_sub(1, Ordering::Release) - 1;
+    if refs == 0 {
+        std::sync::atomic::fence(Ordering::Acquire);
+        drop(Box::from_raw(this as *mut NotificationClient));
+    }
+    refs
+}
+
+unsafe extern "system" fn on_device_state_changed(
+    this: *mut c_void,
+    device_id: *const u16,
+    new_state: u32,
+) -> HRESULT {
+    let id = wide_to_string(device_id);
+    let event = if new_state == Audio::DEVICE_STATE_ACTIVE.0 {
+        DeviceEvent::Added { id }
+    } else {
+        DeviceEvent::Removed { id }
+    };
+    NotificationClient::emit(this, event)
+}
+
+unsafe extern "system" fn on_device_added(this: *mut c_void, device_id: *const u16) -> HRESULT {
+    let id = wide_to_string(device_id);
+    NotificationClient::emit(this, DeviceEvent::Added { id })
+}
+
+unsafe extern "system" fn on_device_removed(this: *mut c_void, device_id: *const u16) -> HRESULT {
+    let id = wide_to_string(device_id);
+    NotificationClient::emit(this, DeviceEvent::Removed { id })
+}
+
+unsafe extern "system" fn on_default_device_changed(
+    this: *mut c_void,
+    flow: i32,
+    role: i32,
+    device_id: *const u16,
+) -> HRESULT {
+    // The notification is sent once per role, the console role is the one cpal opens.
+    if role != Audio::eConsole.0 {
+        return S_OK;
+    }
+    let id = if device_id.is_null() {
+        None
+    } else {
+        Some(wide_to_string(device_id))
+    };
+    let event = if flow == Audio::eCapture.0 {
+        DeviceEvent::DefaultInputChanged { id }
+    } else {
+        DeviceEvent::DefaultOutputChanged { id }
+    };
+    NotificationClient::emit(this, event)
+}
+
+unsafe extern "system" fn on_property_value_changed(
+    _this: *mut c_void,
+    _device_id: *const u16,
+    _key: PropertyKey,
+) -> HRESULT {
+    S_OK
+}
+
+/// Read a null-terminated UTF-16 string.
+unsafe fn wide_to_string(ptr: *const u16) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    let mut len = 0;
+    while *ptr.add(len) != 0 {
+        len += 1;
+    }
+    String::from_utf16_lossy(slice::from_raw_parts(ptr, len))
+}
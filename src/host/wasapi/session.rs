@@ -0,0 +1,297 @@
+//! Mute and ducking notifications of a stream's audio session, through `IAudioSessionEvents` and
+//! `IAudioVolumeDuckNotification`.
+//!
+//! Like the endpoint notification client, both clients are hand written COM objects. They share
+//! one layout and differ only in their vtable and the interface they answer to.
+
+use super::stream::Command;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use windows::core::{IUnknown, Interface, GUID, HRESULT, PCWSTR};
+use windows::Win32::Foundation::{self, BOOL, E_NOINTERFACE, E_POINTER, S_OK};
+use windows::Win32::Media::Audio;
+use windows::Win32::System::Com;
+use windows::Win32::System::Threading;
+
+/// The mute and ducking state of a session, passed to the stream's thread as a command.
+pub(super) struct SessionNotifier {
+    muted: AtomicBool,
+    ducked: AtomicBool,
+    commands: Mutex<Sender<Command>>,
+    // The `pending_scheduled_event` of the stream's thread.
+    event: Foundation::HANDLE,
+}
+
+// The handle is only signalled, which is allowed from any thread.
+unsafe impl Send for SessionNotifier {}
+unsafe impl Sync for SessionNotifier {}
+
+impl SessionNotifier {
+    pub(super) fn new(commands: Sender<Command>, event: Foundation::HANDLE) -> Arc<Self> {
+        Arc::new(SessionNotifier {
+            muted: AtomicBool::new(false),
+            ducked: AtomicBool::new(false),
+            commands: Mutex::new(commands),
+            event,
+        })
+    }
+
+    fn set(&self, flag: &AtomicBool, value: bool) {
+        if flag.swap(value, Ordering::Relaxed) == value {
+            return;
+        }
+        let command = Command::SessionStateChanged {
+            muted: self.muted.load(Ordering::Relaxed),
+            ducked: self.ducked.load(Ordering::Relaxed),
+        };
+        if let Ok(commands) = self.commands.lock() {
+            if commands.send(command).is_ok() {
+                unsafe {
+                    let _ = Threading::SetEvent(self.event);
+                }
+            }
+        }
+    }
+}
+
+/// Keeps the notification clients registered with the session of an audio client.
+///
+/// The clients are unregistered when this is dropped.
+pub(super) struct SessionEvents {
+    control: Audio::IAudioSessionControl,
+    events: Audio::IAudioSessionEvents,
+    // The session manager and client of the ducking notifications, if they could be registered.
+    duck: Option<(
+        Audio::IAudioSessionManager2,
+        Audio::IAudioVolumeDuckNotification,
+    )>,
+}
+
+impl SessionEvents {
+    /// Register for the notifications of the session of `audio_client`, an initialized client of
+    /// `device`.
+    pub(super) unsafe fn register(
+        device: &Audio::IMMDevice,
+        audio_client: &Audio::IAudioClient,
+        notifier: &Arc<SessionNotifier>,
+    ) -> windows::core::Result<Self> {
+        let control = audio_client.GetService::<Audio::IAudioSessionControl>()?;
+        let events: Audio::IAudioSessionEvents = Client::create(&SESSION_EVENTS_VTBL, notifier);
+        control.RegisterAudioSessionNotification(&events)?;
+        // Ducking is only reported where the session manager supports it.
+        let duck = register_duck_notification(device, &control, notifier).ok();
+        Ok(SessionEvents {
+            control,
+            events,
+            duck,
+        })
+    }
+}
+
+impl Drop for SessionEvents {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self
+                .control
+                .UnregisterAudioSessionNotification(&self.events);
+            if let Some((manager, client)) = &self.duck {
+                let _ = manager.UnregisterDuckNotification(client);
+            }
+        }
+    }
+}
+
+unsafe fn register_duck_notification(
+    device: &Audio::IMMDevice,
+    control: &Audio::IAudioSessionControl,
+    notifier: &Arc<SessionNotifier>,
+) -> windows::core::Result<(
+    Audio::IAudioSessionManager2,
+    Audio::IAudioVolumeDuckNotification,
+)> {
+    let manager: Audio::IAudioSessionManager2 = device.Activate(Com::CLSCTX_ALL, None)?;
+    let id = control
+        .cast::<Audio::IAudioSessionControl2>()?
+        .GetSessionInstanceIdentifier()?;
+    let client: Audio::IAudioVolumeDuckNotification =
+        Client::create(&DUCK_NOTIFICATION_VTBL, notifier);
+    let result = manager.RegisterDuckNotification(PCWSTR(id.0), &client);
+    Com::CoTaskMemFree(Some(id.0 as *mut _));
+    result.map(|()| (manager, client))
+}
+
+type QueryInterface =
+    unsafe extern "system" fn(*mut c_void, *const GUID, *mut *mut c_void) -> HRESULT;
+
+#[repr(C)]
+struct SessionEventsVtbl {
+    query_interface: QueryInterface,
+    add_ref: unsafe extern "system" fn(*mut c_void) -> u32,
+    release: unsafe extern "system" fn(*mut c_void) -> u32,
+    on_display_name_changed:
+        unsafe extern "system" fn(*mut c_void, *const u16, *const GUID) -> HRESULT,
+    on_icon_path_changed:
+        unsafe extern "system" fn(*mut c_void, *const u16, *const GUID) -> HRESULT,
+    on_simple_volume_changed:
+        unsafe extern "system" fn(*mut c_void, f32, BOOL, *const GUID) -> HRESULT,
+    on_channel_volume_changed:
+        unsafe extern "system" fn(*mut c_void, u32, *const f32, u32, *const GUID) -> HRESULT,
+    on_grouping_param_changed:
+        unsafe extern "system" fn(*mut c_void, *const GUID, *const GUID) -> HRESULT,
+    on_state_changed: unsafe extern "system" fn(*mut c_void, i32) -> HRESULT,
+    on_session_disconnected: unsafe extern "system" fn(*mut c_void, i32) -> HRESULT,
+}
+
+static SESSION_EVENTS_VTBL: SessionEventsVtbl = SessionEventsVtbl {
+    query_interface,
+    add_ref,
+    release,
+    on_display_name_changed: on_name_changed,
+    on_icon_path_changed: on_name_changed,
+    on_simple_volume_changed,
+    on_channel_volume_changed,
+    on_grouping_param_changed,
+    on_state_changed: on_session_change,
+    on_session_disconnected: on_session_change,
+};
+
+#[repr(C)]
+struct DuckNotificationVtbl {
+    query_interface: QueryInterface,
+    add_ref: unsafe extern "system" fn(*mut c_void) -> u32,
+    release: unsafe extern "system" fn(*mut c_void) -> u32,
+    on_volume_duck_notification: unsafe extern "system" fn(*mut c_void, *const u16, u32) -> HRESULT,
+    on_volume_unduck_notification: unsafe extern "system" fn(*mut c_void, *const u16) -> HRESULT,
+}
+
+static DUCK_NOTIFICATION_VTBL: DuckNotificationVtbl = DuckNotificationVtbl {
+    query_interface,
+    add_ref,
+    release,
+    on_volume_duck_notification,
+    on_volume_unduck_notification,
+};
+
+/// The COM object of both clients. The vtable pointer must stay the first field.
+#[repr(C)]
+struct Client {
+    vtbl: *const c_void,
+    refs: AtomicU32,
+    /// The interface implemented by `vtbl`.
+    iid: GUID,
+    notifier: Arc<SessionNotifier>,
+}
+
+impl Client {
+    /// Allocate a client and return the interface holding its only reference.
+    unsafe fn create<I: Interface, V>(vtbl: &'static V, notifier: &Arc<SessionNotifier>) -> I {
+        let client = Box::new(Client {
+            vtbl: vtbl as *const V as *const c_void,
+            refs: AtomicU32::new(1),
+            iid: I::IID,
+            notifier: notifier.clone(),
+        });
+        // The interface takes over the initial reference.
+        I::from_raw(Box::into_raw(client) as *mut c_void)
+    }
+
+    unsafe fn notifier<'a>(this: *mut c_void) -> &'a SessionNotifier {
+        &(*(this as *const Client)).notifier
+    }
+}
+
+unsafe extern "system" fn query_interface(
+    this: *mut c_void,
+    iid: *const GUID,
+    object: *mut *mut c_void,
+) -> HRESULT {
+    if iid.is_null() || object.is_null() {
+        return E_POINTER;
+    }
+    let client = &*(this as *const Client);
+    if *iid == IUnknown::IID || *iid == client.iid {
+        add_ref(this);
+        *object = this;
+        S_OK
+    } else {
+        *object = std::ptr::null_mut();
+        E_NOINTERFACE
+    }
+}
+
+unsafe extern "system" fn add_ref(this: *mut c_void) -> u32 {
+    let client = &*(this as *const Client);
+    client.refs.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+unsafe extern "system" fn release(this: *mut c_void) -> u32 {
+    let client = &*(this as *const Client);
+    let refs = client.refs.fetch_sub(1, Ordering::Release) - 1;
+    if refs == 0 {
+        std::sync::atomic::fence(Ordering::Acquire);
+        drop(Box::from_raw(this as *mut Client));
+    }
+    refs
+}
+
+unsafe extern "system" fn on_simple_volume_changed(
+    this: *mut c_void,
+    _new_volume: f32,
+    new_mute: BOOL,
+    _context: *const GUID,
+) -> HRESULT {
+    let notifier = Client::notifier(this);
+    notifier.set(&notifier.muted, new_mute.as_bool());
+    S_OK
+}
+
+unsafe extern "system" fn on_volume_duck_notification(
+    this: *mut c_void,
+    _session_id: *const u16,
+    _communication_sessions: u32,
+) -> HRESULT {
+    let notifier = Client::notifier(this);
+    notifier.set(&notifier.ducked, true);
+    S_OK
+}
+
+unsafe extern "system" fn on_volume_unduck_notification(
+    this: *mut c_void,
+    _session_id: *const u16,
+) -> HRESULT {
+    let notifier = Client::notifier(this);
+    notifier.set(&notifier.ducked, false);
+    S_OK
+}
+
+unsafe extern "system" fn on_name_changed(
+    _this: *mut c_void,
+    _name: *const u16,
+    _context: *const GUID,
+) -> HRESULT {
+    S_OK
+}
+
+unsafe extern "system" fn on_channel_volume_changed(
+    _this: *mut c_void,
+    _channels: u32,
+    _volumes: *const f32,
+    _changed_channel: u32,
+    _context: *const GUID,
+) -> HRESULT {
+    S_OK
+}
+
+unsafe extern "system" fn on_grouping_param_changed(
+    _this: *mut c_void,
+    _grouping_param: *const GUID,
+    _context: *const GUID,
+) -> HRESULT {
+    S_OK
+}
+
+unsafe extern "system" fn on_session_change(_this: *mut c_void, _value: i32) -> HRESULT {
+    S_OK
+}
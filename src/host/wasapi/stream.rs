@@ -1,13 +1,19 @@
+use super::session::{SessionEvents, SessionNotifier};
 use super::windows_err_to_cpal_err;
+use crate::shutdown::Worker;
+use crate::trace::trace_call;
 use crate::traits::StreamTrait;
 use crate::{
-    BackendSpecificError, Data, InputCallbackInfo, OutputCallbackInfo, PauseStreamError,
-    PlayStreamError, SampleFormat, StreamError,
+    BackendSpecificError, BuildStreamError, Data, InputCallbackInfo, OutputCallbackInfo,
+    PauseStreamError, PlayStreamError, SampleFormat, StreamError,
 };
 use std::mem;
 use std::ptr;
 use std::sync::mpsc::{channel, Receiver, SendError, Sender};
+use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use windows::core::Interface;
 use windows::Win32::Foundation;
 use windows::Win32::Foundation::HANDLE;
 use windows::Win32::Foundation::WAIT_OBJECT_0;
@@ -17,38 +23,130 @@ use windows::Win32::System::Threading;
 
 pub struct Stream {
     /// The high-priority audio processing thread calling callbacks.
-    /// Option used for moving out in destructor.
     ///
     /// TODO: Actually set the thread priority.
-    thread: Option<JoinHandle<()>>,
+    thread: Arc<StreamThread>,
+
+    // Handles to the client and clock of the stream, so its position and latency can be queried
+    // from the user's thread.
+    clients: Arc<Mutex<Option<Clients>>>,
+}
+
+// The client and clock are only used for queries, which WASAPI allows from any thread.
+unsafe impl Send for Stream {}
+unsafe impl Sync for Stream {}
+
+/// The client and clock of a stream shared with the user's thread, `None` while the stream is
+/// suspended.
+struct Clients {
+    audio_client: Audio::IAudioClient,
+    audio_clock: Audio::IAudioClock,
+}
+
+unsafe impl Send for Clients {}
+
+impl Clients {
+    fn new(stream: &StreamInner) -> Self {
+        Clients {
+            audio_client: stream.audio_client.clone(),
+            audio_clock: stream.audio_clock.clone(),
+        }
+    }
+}
+
+/// Builds the client of a stream again when it resumes after being suspended.
+pub(crate) type Rebuild = Box<dyn FnMut() -> Result<StreamInner, BuildStreamError> + Send>;
+
+struct RunContext {
+    // The registration for the mute and ducking notifications of the stream's session, declared
+    // first so it is released before the stream.
+    session_events: Option<SessionEvents>,
+
+    // The device of the stream and the notifier of its session, to register again on resume.
+    device: Audio::IMMDevice,
+    session_notifier: Arc<SessionNotifier>,
+
+    // The stream created in this event loop, `None` while it is suspended.
+    stream: Option<StreamInner>,
+
+    // Handles corresponding to the `event` field of `stream`, except that the first element is
+    // always `pending_scheduled_event`.
+    handles: Vec<Foundation::HANDLE>,
+
+    commands: Receiver<Command>,
+
+    rebuild: Rebuild,
+
+    clients: Arc<Mutex<Option<Clients>>>,
+}
+
+// Once we start running the eventloop, the RunContext will not be moved.
+unsafe impl Send for RunContext {}
+
+/// The audio thread of a stream, stopped when the stream is dropped or the host shut down.
+struct StreamThread {
+    /// `None` once the thread was joined.
+    handle: Mutex<Option<JoinHandle<()>>>,
 
     // Commands processed by the `run()` method that is currently running.
     // `pending_scheduled_event` must be signalled whenever a command is added here, so that it
     // will get picked up.
-    commands: Sender<Command>,
+    commands: Mutex<Sender<Command>>,
 
     // This event is signalled after a new entry is added to `commands`, so that the `run()`
     // method can be notified.
     pending_scheduled_event: Foundation::HANDLE,
 }
 
-struct RunContext {
-    // Streams that have been created in this event loop.
-    stream: StreamInner,
+impl StreamThread {
+    fn new(
+        handle: JoinHandle<()>,
+        commands: Sender<Command>,
+        pending_scheduled_event: Foundation::HANDLE,
+    ) -> Arc<Self> {
+        let thread = Arc::new(StreamThread {
+            handle: Mutex::new(Some(handle)),
+            commands: Mutex::new(commands),
+            pending_scheduled_event,
+        });
+        crate::shutdown::register(crate::HostId::Wasapi, &thread);
+        thread
+    }
 
-    // Handles corresponding to the `event` field of each element of `voices`. Must always be in
-    // sync with `voices`, except that the first element is always `pending_scheduled_event`.
-    handles: Vec<Foundation::HANDLE>,
+    #[inline]
+    fn push_command(&self, command: Command) -> Result<(), SendError<Command>> {
+        self.commands.lock().unwrap().send(command)?;
+        unsafe {
+            Threading::SetEvent(self.pending_scheduled_event).unwrap();
+        }
+        Ok(())
+    }
+}
 
-    commands: Receiver<Command>,
+impl Worker for StreamThread {
+    fn stop(&self) {
+        if let Some(handle) = self.handle.lock().unwrap().take() {
+            if self.push_command(Command::Terminate).is_ok() {
+                handle.join().unwrap();
+            }
+        }
+    }
 }
 
-// Once we start running the eventloop, the RunContext will not be moved.
-unsafe impl Send for RunContext {}
+impl Drop for StreamThread {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe {
+            let _ = Foundation::CloseHandle(self.pending_scheduled_event);
+        }
+    }
+}
 
 pub enum Command {
     PlayStream,
     PauseStream,
+    SuspendStream,
+    SessionStateChanged { muted: bool, ducked: bool },
     Terminate,
 }
 
@@ -77,11 +175,19 @@ pub struct StreamInner {
     pub config: crate::StreamConfig,
     // The sample format with which the stream was created.
     pub sample_format: SampleFormat,
+    // The number of frames an output stream keeps queued by writing silence before the data
+    // callback runs, if silence padding is enabled.
+    pub silence_padding: Option<u32>,
+    // How long an output stream plays silence before it is stopped, if idling is enabled.
+    pub idle_timeout: Option<Duration>,
 }
 
 impl Stream {
     pub(crate) fn new_input<D, E>(
+        device: Audio::IMMDevice,
         stream_inner: StreamInner,
+        rebuild: Rebuild,
+        thread_name: String,
         mut data_callback: D,
         mut error_callback: E,
     ) -> Stream
@@ -95,26 +201,38 @@ impl Stream {
         .expect("cpal: could not create input stream event");
         let (tx, rx) = channel();
 
+        let clients = Arc::new(Mutex::new(Some(Clients::new(&stream_inner))));
+        let session_notifier = SessionNotifier::new(tx.clone(), pending_scheduled_event);
+        let session_events = unsafe {
+            SessionEvents::register(&device, &stream_inner.audio_client, &session_notifier).ok()
+        };
         let run_context = RunContext {
+            session_events,
+            device,
+            session_notifier,
             handles: vec![pending_scheduled_event, stream_inner.event],
-            stream: stream_inner,
+            stream: Some(stream_inner),
             commands: rx,
+            rebuild,
+            clients: clients.clone(),
         };
 
         let thread = thread::Builder::new()
-            .name("cpal_wasapi_in".to_owned())
+            .name(thread_name)
             .spawn(move || run_input(run_context, &mut data_callback, &mut error_callback))
             .unwrap();
 
         Stream {
-            thread: Some(thread),
-            commands: tx,
-            pending_scheduled_event,
+            thread: StreamThread::new(thread, tx, pending_scheduled_event),
+            clients,
         }
     }
 
     pub(crate) fn new_output<D, E>(
+        device: Audio::IMMDevice,
         stream_inner: StreamInner,
+        rebuild: Rebuild,
+        thread_name: String,
         mut data_callback: D,
         mut error_callback: E,
     ) -> Stream
@@ -128,54 +246,186 @@ impl Stream {
         .expect("cpal: could not create output stream event");
         let (tx, rx) = channel();
 
+        let clients = Arc::new(Mutex::new(Some(Clients::new(&stream_inner))));
+        let session_notifier = SessionNotifier::new(tx.clone(), pending_scheduled_event);
+        let session_events = unsafe {
+            SessionEvents::register(&device, &stream_inner.audio_client, &session_notifier).ok()
+        };
         let run_context = RunContext {
+            session_events,
+            device,
+            session_notifier,
             handles: vec![pending_scheduled_event, stream_inner.event],
-            stream: stream_inner,
+            stream: Some(stream_inner),
             commands: rx,
+            rebuild,
+            clients: clients.clone(),
         };
 
         let thread = thread::Builder::new()
-            .name("cpal_wasapi_out".to_owned())
+            .name(thread_name)
             .spawn(move || run_output(run_context, &mut data_callback, &mut error_callback))
             .unwrap();
 
         Stream {
-            thread: Some(thread),
-            commands: tx,
-            pending_scheduled_event,
+            thread: StreamThread::new(thread, tx, pending_scheduled_event),
+            clients,
         }
     }
+}
 
-    #[inline]
-    fn push_command(&self, command: Command) -> Result<(), SendError<Command>> {
-        self.commands.send(command)?;
+/// The position of a stream as reported by `IAudioClock` and `IAudioClock2`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamPosition {
+    /// The position of the stream in units of `frequency`.
+    ///
+    /// The position stops advancing while the stream is paused and continues from the same value
+    /// when it is played again.
+    pub position: u64,
+    /// The number of `position` units per second.
+    pub frequency: u64,
+    /// The position of the device in frames, if the client supports `IAudioClock2`.
+    pub device_position: Option<u64>,
+    /// The performance counter value, in 100 nanosecond units, at which the position was read.
+    pub qpc_position: u64,
+}
+
+impl StreamPosition {
+    /// The amount of audio played or captured at this position.
+    pub fn elapsed(&self) -> Duration {
+        position_to_duration(self.position, self.frequency)
+    }
+
+    /// The amount of audio played or captured between `earlier` and this position.
+    ///
+    /// Handles a wrap of the 64-bit position counter between the two queries.
+    pub fn elapsed_since(&self, earlier: &StreamPosition) -> Duration {
+        position_to_duration(self.position.wrapping_sub(earlier.position), self.frequency)
+    }
+}
+
+fn position_to_duration(position: u64, frequency: u64) -> Duration {
+    if frequency == 0 {
+        return Duration::ZERO;
+    }
+    let nanos = position as u128 * 1_000_000_000 / frequency as u128;
+    Duration::new(
+        (nanos / 1_000_000_000) as u64,
+        (nanos % 1_000_000_000) as u32,
+    )
+}
+
+/// The latency and engine periods of a stream as reported by its `IAudioClient`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamLatency {
+    /// The maximum latency of the stream (`GetStreamLatency`), not including the buffer.
+    pub stream_latency: Duration,
+    /// The interval at which the audio engine processes the stream in shared mode.
+    pub default_period: Duration,
+    /// The shortest interval the device supports in exclusive mode.
+    pub minimum_period: Duration,
+}
+
+/// Extension trait exposing WASAPI specific information about a stream.
+pub trait StreamExt {
+    /// Query the current position of the stream from its audio clock.
+    fn position(&self) -> Result<StreamPosition, StreamError>;
+
+    /// Query the latency and engine periods of the stream.
+    fn latency(&self) -> Result<StreamLatency, StreamError>;
+}
+
+impl StreamExt for Stream {
+    fn position(&self) -> Result<StreamPosition, StreamError> {
+        let clients = self.clients.lock().unwrap();
+        let clients = clients.as_ref().ok_or_else(suspended)?;
         unsafe {
-            Threading::SetEvent(self.pending_scheduled_event).unwrap();
+            let frequency = clients
+                .audio_clock
+                .GetFrequency()
+                .map_err(windows_err_to_cpal_err::<StreamError>)?;
+            let mut position: u64 = 0;
+            let mut qpc_position: u64 = 0;
+            clients
+                .audio_clock
+                .GetPosition(&mut position, Some(&mut qpc_position))
+                .map_err(windows_err_to_cpal_err::<StreamError>)?;
+            let device_position = clients
+                .audio_clock
+                .cast::<Audio::IAudioClock2>()
+                .ok()
+                .and_then(|clock| {
+                    let mut device_position: u64 = 0;
+                    clock
+                        .GetDevicePosition(&mut device_position, None)
+                        .ok()
+                        .map(|_| device_position)
+                });
+            Ok(StreamPosition {
+                position,
+                frequency,
+                device_position,
+                qpc_position,
+            })
+        }
+    }
+
+    fn latency(&self) -> Result<StreamLatency, StreamError> {
+        let clients = self.clients.lock().unwrap();
+        let clients = clients.as_ref().ok_or_else(suspended)?;
+        unsafe {
+            let stream_latency = clients
+                .audio_client
+                .GetStreamLatency()
+                .map_err(windows_err_to_cpal_err::<StreamError>)?;
+            let mut default_period: i64 = 0;
+            let mut minimum_period: i64 = 0;
+            clients
+                .audio_client
+                .GetDevicePeriod(Some(&mut default_period), Some(&mut minimum_period))
+                .map_err(windows_err_to_cpal_err::<StreamError>)?;
+            Ok(StreamLatency {
+                stream_latency: reference_time_to_duration(stream_latency),
+                default_period: reference_time_to_duration(default_period),
+                minimum_period: reference_time_to_duration(minimum_period),
+            })
         }
-        Ok(())
     }
 }
 
+fn suspended() -> StreamError {
+    let description = "the stream is suspended".to_string();
+    BackendSpecificError { description }.into()
+}
+
+/// Convert a `REFERENCE_TIME` in 100 nanosecond units to a `Duration`.
+fn reference_time_to_duration(reference_time: i64) -> Duration {
+    Duration::from_nanos(reference_time.max(0) as u64 * 100)
+}
+
 impl Drop for Stream {
     #[inline]
     fn drop(&mut self) {
-        if self.push_command(Command::Terminate).is_ok() {
-            self.thread.take().unwrap().join().unwrap();
-            unsafe {
-                let _ = Foundation::CloseHandle(self.pending_scheduled_event);
-            }
-        }
+        self.thread.stop();
     }
 }
 
 impl StreamTrait for Stream {
     fn play(&self) -> Result<(), PlayStreamError> {
-        self.push_command(Command::PlayStream)
+        self.thread
+            .push_command(Command::PlayStream)
             .map_err(|_| crate::error::PlayStreamError::DeviceNotAvailable)?;
         Ok(())
     }
     fn pause(&self) -> Result<(), PauseStreamError> {
-        self.push_command(Command::PauseStream)
+        self.thread
+            .push_command(Command::PauseStream)
+            .map_err(|_| crate::error::PauseStreamError::DeviceNotAvailable)?;
+        Ok(())
+    }
+    fn suspend(&self) -> Result<(), PauseStreamError> {
+        self.thread
+            .push_command(Command::SuspendStream)
             .map_err(|_| crate::error::PauseStreamError::DeviceNotAvailable)?;
         Ok(())
     }
@@ -192,30 +442,47 @@ impl Drop for StreamInner {
 
 // Process any pending commands that are queued within the `RunContext`.
 // Returns `true` if the loop should continue running, `false` if it should terminate.
-fn process_commands(run_context: &mut RunContext) -> Result<bool, StreamError> {
-    // Process the pending commands.
-    for command in run_context.commands.try_iter() {
+fn process_commands(
+    run_context: &mut RunContext,
+    error_callback: &mut dyn FnMut(StreamError),
+) -> Result<bool, StreamError> {
+    // Process the pending commands. `try_recv` doesn't hold on to the receiver, so the commands
+    // can replace the stream of the run context.
+    while let Ok(command) = run_context.commands.try_recv() {
         match command {
             Command::PlayStream => unsafe {
-                if !run_context.stream.playing {
-                    run_context
-                        .stream
-                        .audio_client
-                        .Start()
+                if run_context.stream.is_none() {
+                    resume(run_context)?;
+                }
+                let stream = run_context.stream.as_mut().unwrap();
+                if !stream.playing {
+                    trace_call!("IAudioClient::Start", (), stream.audio_client.Start())
                         .map_err(windows_err_to_cpal_err::<StreamError>)?;
-                    run_context.stream.playing = true;
+                    stream.playing = true;
                 }
             },
             Command::PauseStream => unsafe {
-                if run_context.stream.playing {
-                    run_context
-                        .stream
-                        .audio_client
-                        .Stop()
+                if let Some(stream) = run_context.stream.as_mut().filter(|s| s.playing) {
+                    trace_call!("IAudioClient::Stop", (), stream.audio_client.Stop())
                         .map_err(windows_err_to_cpal_err::<StreamError>)?;
-                    run_context.stream.playing = false;
+                    stream.playing = false;
                 }
             },
+            Command::SuspendStream => unsafe {
+                if let Some(stream) = run_context.stream.take() {
+                    run_context.session_events = None;
+                    // Releasing the client releases the device, stop it first so the audio ends
+                    // cleanly.
+                    if stream.playing {
+                        let _ = trace_call!("IAudioClient::Stop", (), stream.audio_client.Stop());
+                    }
+                    run_context.handles.truncate(1);
+                    *run_context.clients.lock().unwrap() = None;
+                }
+            },
+            Command::SessionStateChanged { muted, ducked } => {
+                error_callback(StreamError::SessionStateChanged { muted, ducked });
+            }
             Command::Terminate => {
                 return Ok(false);
             }
@@ -224,6 +491,30 @@ fn process_commands(run_context: &mut RunContext) -> Result<bool, StreamError> {
 
     Ok(true)
 }
+
+// Build the client of a suspended stream again.
+fn resume(run_context: &mut RunContext) -> Result<(), StreamError> {
+    let stream = (run_context.rebuild)().map_err(|err| match err {
+        BuildStreamError::DeviceNotAvailable => StreamError::DeviceNotAvailable,
+        err => {
+            let description = format!("failed to resume the stream: {}", err);
+            BackendSpecificError { description }.into()
+        }
+    })?;
+    run_context.handles.push(stream.event);
+    run_context.session_events = unsafe {
+        SessionEvents::register(
+            &run_context.device,
+            &stream.audio_client,
+            &run_context.session_notifier,
+        )
+        .ok()
+    };
+    *run_context.clients.lock().unwrap() = Some(Clients::new(&stream));
+    run_context.stream = Some(stream);
+    Ok(())
+}
+
 // Wait for any of the given handles to be signalled.
 //
 // Returns the index of the `handle` that was signalled, or an `Err` if
@@ -256,10 +547,12 @@ fn wait_for_handle_signal(handles: &[Foundation::HANDLE]) -> Result<usize, Backe
 // Get the number of available frames that are available for writing/reading.
 fn get_available_frames(stream: &StreamInner) -> Result<u32, StreamError> {
     unsafe {
-        let padding = stream
-            .audio_client
-            .GetCurrentPadding()
-            .map_err(windows_err_to_cpal_err::<StreamError>)?;
+        let padding = trace_call!(
+            "IAudioClient::GetCurrentPadding",
+            (),
+            stream.audio_client.GetCurrentPadding()
+        )
+        .map_err(windows_err_to_cpal_err::<StreamError>)?;
         Ok(stream.max_frames_in_buffer - padding)
     }
 }
@@ -277,16 +570,13 @@ fn run_input(
             Some(ControlFlow::Continue) => continue,
             None => (),
         }
-        let capture_client = match run_ctxt.stream.client_flow {
+        // The stream's event is only waited on while it is not suspended.
+        let stream = run_ctxt.stream.as_ref().unwrap();
+        let capture_client = match stream.client_flow {
             AudioClientFlow::Capture { ref capture_client } => capture_client.clone(),
             _ => unreachable!(),
         };
-        match process_input(
-            &run_ctxt.stream,
-            capture_client,
-            data_callback,
-            error_callback,
-        ) {
+        match process_input(stream, capture_client, data_callback, error_callback) {
             ControlFlow::Break => break,
             ControlFlow::Continue => continue,
         }
@@ -300,28 +590,147 @@ fn run_output(
 ) {
     boost_current_thread_priority();
 
+    // The number of frames of silence the data callback rendered in a row.
+    let mut silent_frames = 0;
     loop {
         match process_commands_and_await_signal(&mut run_ctxt, error_callback) {
             Some(ControlFlow::Break) => break,
             Some(ControlFlow::Continue) => continue,
             None => (),
         }
-        let render_client = match run_ctxt.stream.client_flow {
+        // The stream's event is only waited on while it is not suspended.
+        let stream = run_ctxt.stream.as_ref().unwrap();
+        let render_client = match stream.client_flow {
             AudioClientFlow::Render { ref render_client } => render_client.clone(),
             _ => unreachable!(),
         };
-        match process_output(
-            &run_ctxt.stream,
+        let flow = process_output(
+            stream,
             render_client,
+            &mut silent_frames,
             data_callback,
             error_callback,
-        ) {
-            ControlFlow::Break => break,
-            ControlFlow::Continue => continue,
+        );
+        if let ControlFlow::Break = flow {
+            break;
+        }
+        if let Some(idle_timeout) = stream.idle_timeout {
+            if frames_to_duration(silent_frames, stream.config.sample_rate) >= idle_timeout {
+                silent_frames = 0;
+                if let ControlFlow::Break =
+                    idle_output(&mut run_ctxt, data_callback, error_callback)
+                {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// Stop an output stream that played silence for its idle timeout, then run the data callback
+// once per half buffer until it renders audio that is not silent and start the stream again with
+// that audio.
+fn idle_output(
+    run_ctxt: &mut RunContext,
+    data_callback: &mut dyn FnMut(&mut Data, &OutputCallbackInfo),
+    error_callback: &mut dyn FnMut(StreamError),
+) -> ControlFlow {
+    let stream = run_ctxt.stream.as_ref().unwrap();
+    let result = unsafe {
+        trace_call!("IAudioClient::Stop", (), stream.audio_client.Stop())
+            .and_then(|()| trace_call!("IAudioClient::Reset", (), stream.audio_client.Reset()))
+    };
+    if let Err(err) = result {
+        error_callback(windows_err_to_cpal_err(err));
+        return ControlFlow::Break;
+    }
+    let frames = stream.max_frames_in_buffer / 2;
+    let period = frames_to_duration(frames, stream.config.sample_rate);
+
+    loop {
+        // Wait a period, or until a command arrives.
+        let result = unsafe {
+            Threading::WaitForSingleObjectEx(
+                run_ctxt.handles[0],
+                period.as_millis().max(1) as u32,
+                false,
+            )
+        };
+        if result == Foundation::WAIT_FAILED {
+            let err = unsafe { Foundation::GetLastError() };
+            let description = format!("`WaitForSingleObjectEx` failed: {:?}", err);
+            error_callback(BackendSpecificError { description }.into());
+            return ControlFlow::Break;
         }
+        if result == WAIT_OBJECT_0 {
+            match process_commands(run_ctxt, error_callback) {
+                Ok(true) => (),
+                Ok(false) => return ControlFlow::Break,
+                Err(err) => {
+                    error_callback(err);
+                    return ControlFlow::Break;
+                }
+            }
+            // A paused or suspended stream stops idling, playing it starts the client again.
+            match &run_ctxt.stream {
+                Some(stream) if stream.playing => continue,
+                _ => return ControlFlow::Continue,
+            }
+        }
+
+        let stream = run_ctxt.stream.as_ref().unwrap();
+        let render_client = match stream.client_flow {
+            AudioClientFlow::Render { ref render_client } => render_client,
+            _ => unreachable!(),
+        };
+        let result = unsafe { render_idle(stream, render_client, frames, data_callback) };
+        match result {
+            Ok(true) => (),
+            Ok(false) => continue,
+            Err(err) => {
+                error_callback(err);
+                return ControlFlow::Break;
+            }
+        }
+        let result = unsafe { trace_call!("IAudioClient::Start", (), stream.audio_client.Start()) };
+        if let Err(err) = result {
+            error_callback(windows_err_to_cpal_err(err));
+            return ControlFlow::Break;
+        }
+        return ControlFlow::Continue;
     }
 }
 
+// Render `frames` frames of an idle, stopped output stream. The audio is only queued if it is not
+// silent, returns whether it was.
+unsafe fn render_idle(
+    stream: &StreamInner,
+    render_client: &Audio::IAudioRenderClient,
+    frames: u32,
+    data_callback: &mut dyn FnMut(&mut Data, &OutputCallbackInfo),
+) -> Result<bool, StreamError> {
+    let buffer = trace_call!(
+        "IAudioRenderClient::GetBuffer",
+        (frames,),
+        render_client.GetBuffer(frames)
+    )
+    .map_err(windows_err_to_cpal_err::<StreamError>)?;
+    let len =
+        frames as usize * stream.bytes_per_frame as usize / stream.sample_format.sample_size();
+    let mut data = Data::from_parts(buffer as *mut (), len, stream.sample_format);
+    let timestamp = output_timestamp(stream, frames, stream.config.sample_rate)?;
+    data_callback(&mut data, &OutputCallbackInfo { timestamp });
+    // Releasing no frames discards the silence.
+    let written = if data.is_equilibrium() { 0 } else { frames };
+    trace_call!(
+        "IAudioRenderClient::ReleaseBuffer",
+        (written, 0),
+        render_client.ReleaseBuffer(written, 0)
+    )
+    .map_err(windows_err_to_cpal_err::<StreamError>)?;
+    Ok(written > 0)
+}
+
 fn boost_current_thread_priority() {
     unsafe {
         let thread_id = Threading::GetCurrentThreadId();
@@ -343,7 +752,7 @@ fn process_commands_and_await_signal(
     error_callback: &mut dyn FnMut(StreamError),
 ) -> Option<ControlFlow> {
     // Process queued commands.
-    match process_commands(run_context) {
+    match process_commands(run_context, error_callback) {
         Ok(true) => (),
         Ok(false) => return Some(ControlFlow::Break),
         Err(err) => {
@@ -382,7 +791,12 @@ fn process_input(
         let mut buffer: *mut u8 = ptr::null_mut();
         let mut flags = mem::MaybeUninit::uninit();
         loop {
-            let mut frames_available = match capture_client.GetNextPacketSize() {
+            let next_packet_size = trace_call!(
+                "IAudioCaptureClient::GetNextPacketSize",
+                (),
+                capture_client.GetNextPacketSize()
+            );
+            let mut frames_available = match next_packet_size {
                 Ok(0) => return ControlFlow::Continue,
                 Ok(f) => f,
                 Err(err) => {
@@ -391,12 +805,17 @@ fn process_input(
                 }
             };
             let mut qpc_position: u64 = 0;
-            let result = capture_client.GetBuffer(
-                &mut buffer,
-                &mut frames_available,
-                flags.as_mut_ptr(),
-                None,
-                Some(&mut qpc_position),
+            let result = trace_call!(
+                "IAudioCaptureClient::GetBuffer",
+                (),
+                capture_client.GetBuffer(
+                    &mut buffer,
+                    &mut frames_available,
+                    flags.as_mut_ptr(),
+                    None,
+                    Some(&mut qpc_position),
+                ),
+                |r| r.as_ref().map(|_| (frames_available, qpc_position))
             );
 
             match result {
@@ -428,9 +847,12 @@ fn process_input(
             data_callback(&data, &info);
 
             // Release the buffer.
-            let result = capture_client
-                .ReleaseBuffer(frames_available)
-                .map_err(windows_err_to_cpal_err);
+            let result = trace_call!(
+                "IAudioCaptureClient::ReleaseBuffer",
+                (frames_available,),
+                capture_client.ReleaseBuffer(frames_available)
+            )
+            .map_err(windows_err_to_cpal_err);
             if let Err(err) = result {
                 error_callback(err);
                 return ControlFlow::Break;
@@ -443,11 +865,12 @@ fn process_input(
 fn process_output(
     stream: &StreamInner,
     render_client: Audio::IAudioRenderClient,
+    silent_frames: &mut u32,
     data_callback: &mut dyn FnMut(&mut Data, &OutputCallbackInfo),
     error_callback: &mut dyn FnMut(StreamError),
 ) -> ControlFlow {
     // The number of frames available for writing.
-    let frames_available = match get_available_frames(stream) {
+    let mut frames_available = match get_available_frames(stream) {
         Ok(0) => return ControlFlow::Continue, // TODO: Can this happen?
         Ok(n) => n,
         Err(err) => {
@@ -456,8 +879,28 @@ fn process_output(
         }
     };
 
+    if let Some(padding) = stream.silence_padding {
+        let queued = stream.max_frames_in_buffer - frames_available;
+        let silent_frames = padding.saturating_sub(queued).min(frames_available);
+        if silent_frames > 0 {
+            if let Err(err) = write_silence(&render_client, silent_frames) {
+                error_callback(err);
+                return ControlFlow::Break;
+            }
+            frames_available -= silent_frames;
+            if frames_available == 0 {
+                return ControlFlow::Continue;
+            }
+        }
+    }
+
     unsafe {
-        let buffer = match render_client.GetBuffer(frames_available) {
+        let buffer = trace_call!(
+            "IAudioRenderClient::GetBuffer",
+            (frames_available,),
+            render_client.GetBuffer(frames_available)
+        );
+        let buffer = match buffer {
             Ok(b) => b,
             Err(e) => {
                 error_callback(windows_err_to_cpal_err(e));
@@ -481,8 +924,18 @@ fn process_output(
         };
         let info = OutputCallbackInfo { timestamp };
         data_callback(&mut data, &info);
+        *silent_frames = if data.is_equilibrium() {
+            silent_frames.saturating_add(frames_available)
+        } else {
+            0
+        };
 
-        if let Err(err) = render_client.ReleaseBuffer(frames_available, 0) {
+        let result = trace_call!(
+            "IAudioRenderClient::ReleaseBuffer",
+            (frames_available, 0),
+            render_client.ReleaseBuffer(frames_available, 0)
+        );
+        if let Err(err) = result {
             error_callback(windows_err_to_cpal_err(err));
             return ControlFlow::Break;
         }
@@ -491,6 +944,28 @@ fn process_output(
     ControlFlow::Continue
 }
 
+// Queue silence to keep an output stream playing while the data callback is late.
+fn write_silence(
+    render_client: &Audio::IAudioRenderClient,
+    frames: u32,
+) -> Result<(), StreamError> {
+    unsafe {
+        trace_call!(
+            "IAudioRenderClient::GetBuffer",
+            (frames,),
+            render_client.GetBuffer(frames)
+        )
+        .map_err(windows_err_to_cpal_err::<StreamError>)?;
+        let flags = Audio::AUDCLNT_BUFFERFLAGS_SILENT.0 as u32;
+        trace_call!(
+            "IAudioRenderClient::ReleaseBuffer",
+            (frames, flags),
+            render_client.ReleaseBuffer(frames, flags)
+        )
+        .map_err(windows_err_to_cpal_err::<StreamError>)
+    }
+}
+
 /// Convert the given duration in frames at the given sample rate to a `std::time::Duration`.
 fn frames_to_duration(frames: u32, rate: crate::SampleRate) -> std::time::Duration {
     let secsf = frames as f64 / rate.0 as f64;
@@ -0,0 +1,132 @@
+//! The browser's media devices, for device pickers and the microphone permission prompt.
+//!
+//! Browsers only report the labels of devices once the page was granted access to them, so an
+//! application usually calls [`request_microphone_permission`] before
+//! [`enumerate_media_devices`]. Both are also exported to JavaScript.
+
+use crate::BackendSpecificError;
+use js_sys::Reflect;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{MediaDeviceInfo, MediaDevices, MediaStream, MediaStreamTrack};
+
+/// Whether a [`MediaDevice`] records or plays audio.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MediaDeviceKind {
+    AudioInput,
+    AudioOutput,
+}
+
+/// An audio device reported by `navigator.mediaDevices.enumerateDevices()`.
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MediaDevice {
+    id: String,
+    group_id: String,
+    label: String,
+    kind: MediaDeviceKind,
+}
+
+#[wasm_bindgen]
+impl MediaDevice {
+    /// The browser's identifier of the device, stable for the page's origin.
+    #[wasm_bindgen(getter)]
+    pub fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    /// The identifier shared by the inputs and outputs of the same physical device.
+    #[wasm_bindgen(getter, js_name = groupId)]
+    pub fn group_id(&self) -> String {
+        self.group_id.clone()
+    }
+
+    /// The human readable name of the device, empty until the page was granted access.
+    #[wasm_bindgen(getter)]
+    pub fn label(&self) -> String {
+        self.label.clone()
+    }
+
+    /// Whether the device is an input or an output.
+    #[wasm_bindgen(getter)]
+    pub fn kind(&self) -> MediaDeviceKind {
+        self.kind
+    }
+}
+
+/// List the audio inputs and outputs of the browser.
+pub async fn enumerate_media_devices() -> Result<Vec<MediaDevice>, BackendSpecificError> {
+    let devices = JsFuture::from(media_devices()?.enumerate_devices().map_err(js_error)?)
+        .await
+        .map_err(js_error)?;
+    let devices = js_sys::Array::from(&devices)
+        .iter()
+        .filter_map(|info| {
+            let info = info.dyn_into::<MediaDeviceInfo>().ok()?;
+            let kind = match info.kind() {
+                web_sys::MediaDeviceKind::Audioinput => MediaDeviceKind::AudioInput,
+                web_sys::MediaDeviceKind::Audiooutput => MediaDeviceKind::AudioOutput,
+                _ => return None,
+            };
+            Some(MediaDevice {
+                id: info.device_id(),
+                group_id: info.group_id(),
+                label: info.label(),
+                kind,
+            })
+        })
+        .collect();
+    Ok(devices)
+}
+
+/// Prompt the user for access to the microphone, unless it was already granted or denied.
+///
+/// The stream opened for the prompt is stopped right away, it only unlocks the device labels
+/// and input devices for the page.
+pub async fn request_microphone_permission() -> Result<(), BackendSpecificError> {
+    let constraints = web_sys::MediaStreamConstraints::new();
+    Reflect::set(&constraints, &"audio".into(), &JsValue::TRUE).map_err(js_error)?;
+    let promise = media_devices()?
+        .get_user_media_with_constraints(&constraints)
+        .map_err(js_error)?;
+    let stream: MediaStream = JsFuture::from(promise)
+        .await
+        .map_err(js_error)?
+        .unchecked_into();
+    for track in stream.get_tracks().iter() {
+        track.unchecked_into::<MediaStreamTrack>().stop();
+    }
+    Ok(())
+}
+
+/// [`enumerate_media_devices`] for JavaScript, resolving to an array of `MediaDevice`.
+#[wasm_bindgen(js_name = enumerateMediaDevices)]
+pub async fn enumerate_media_devices_js() -> Result<js_sys::Array, JsValue> {
+    let devices = enumerate_media_devices()
+        .await
+        .map_err(|err| err.description)?;
+    Ok(devices.into_iter().map(JsValue::from).collect())
+}
+
+/// [`request_microphone_permission`] for JavaScript.
+#[wasm_bindgen(js_name = requestMicrophonePermission)]
+pub async fn request_microphone_permission_js() -> Result<(), JsValue> {
+    request_microphone_permission()
+        .await
+        .map_err(|err| err.description.into())
+}
+
+fn media_devices() -> Result<MediaDevices, BackendSpecificError> {
+    let window = web_sys::window().ok_or_else(|| BackendSpecificError {
+        description: "no global `window` object".to_owned(),
+    })?;
+    window.navigator().media_devices().map_err(js_error)
+}
+
+fn js_error(err: JsValue) -> BackendSpecificError {
+    BackendSpecificError {
+        description: format!("{:?}", err),
+    }
+}
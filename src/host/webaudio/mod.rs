@@ -5,7 +5,7 @@ extern crate web_sys;
 use self::js_sys::eval;
 use self::wasm_bindgen::prelude::*;
 use self::wasm_bindgen::JsCast;
-use self::web_sys::{AudioContext, AudioContextOptions};
+use self::web_sys::{AudioContext, AudioContextOptions, AudioContextState};
 use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
 use crate::{
     BackendSpecificError, BufferSize, BuildStreamError, Data, DefaultStreamConfigError,
@@ -17,6 +17,12 @@ use std::ops::DerefMut;
 use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 
+pub use self::media_devices::{
+    enumerate_media_devices, request_microphone_permission, MediaDevice, MediaDeviceKind,
+};
+
+mod media_devices;
+
 /// Content is false if the iterator is empty.
 pub struct Devices(bool);
 
@@ -28,10 +34,23 @@ pub struct Host;
 pub struct Stream {
     ctx: Arc<AudioContext>,
     on_ended_closures: Vec<Arc<RwLock<Option<Closure<dyn FnMut()>>>>>,
+    gesture_closure: Mutex<Option<Closure<dyn FnMut()>>>,
     config: StreamConfig,
     buffer_size_frames: usize,
 }
 
+/// The state of the `AudioContext` behind a stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamState {
+    /// The context is processing audio.
+    Running,
+    /// The context is suspended, either by `pause` or by the browser until the user interacts
+    /// with the page.
+    Suspended,
+    /// The context has been closed and can't be resumed.
+    Closed,
+}
+
 pub type SupportedInputConfigs = ::std::vec::IntoIter<SupportedStreamConfigRange>;
 pub type SupportedOutputConfigs = ::std::vec::IntoIter<SupportedStreamConfigRange>;
 
@@ -44,6 +63,8 @@ const MIN_BUFFER_SIZE: u32 = 1;
 const MAX_BUFFER_SIZE: u32 = u32::MAX;
 const DEFAULT_BUFFER_SIZE: usize = 2048;
 const SUPPORTED_SAMPLE_FORMAT: SampleFormat = SampleFormat::F32;
+// The events that count as a user gesture for the autoplay policies of current browsers.
+const GESTURE_EVENTS: [&str; 4] = ["click", "keydown", "pointerup", "touchend"];
 
 impl Host {
     pub fn new() -> Result<Self, crate::HostUnavailable> {
@@ -388,6 +409,7 @@ impl DeviceTrait for Device {
         Ok(Stream {
             ctx,
             on_ended_closures,
+            gesture_closure: Mutex::new(None),
             config: config.clone(),
             buffer_size_frames,
         })
@@ -400,6 +422,65 @@ impl Stream {
     pub fn audio_context(&self) -> &AudioContext {
         &*self.ctx
     }
+
+    /// The current state of the stream's `AudioContext`.
+    ///
+    /// Browsers create contexts in the suspended state until the page has received a user
+    /// gesture, so a stream may still be `Suspended` after `play`.
+    pub fn state(&self) -> StreamState {
+        match self.ctx.state() {
+            AudioContextState::Running => StreamState::Running,
+            AudioContextState::Closed => StreamState::Closed,
+            _ => StreamState::Suspended,
+        }
+    }
+
+    /// Resume the stream's `AudioContext` on the next user gesture on the page.
+    ///
+    /// Call this after `play` when `state` reports `Suspended` because of the browser's autoplay
+    /// policy. The listeners stay registered until the stream is paused or dropped, so a context
+    /// that the browser suspends again is resumed by the following gesture too.
+    pub fn resume_on_gesture(&self) -> Result<(), BackendSpecificError> {
+        let window = web_sys::window().ok_or_else(|| BackendSpecificError {
+            description: "no global `window` object".to_owned(),
+        })?;
+        let mut gesture_closure = self.gesture_closure.lock().unwrap();
+        if gesture_closure.is_some() {
+            return Ok(());
+        }
+
+        let ctx = self.ctx.clone();
+        let closure = Closure::wrap(Box::new(move || {
+            if ctx.state() == AudioContextState::Suspended {
+                let _ = ctx.resume();
+            }
+        }) as Box<dyn FnMut()>);
+        for event in GESTURE_EVENTS {
+            window
+                .add_event_listener_with_callback(event, closure.as_ref().unchecked_ref())
+                .map_err(|err| BackendSpecificError {
+                    description: format!("{:?}", err),
+                })?;
+        }
+        *gesture_closure = Some(closure);
+        Ok(())
+    }
+
+    /// Remove the listeners registered by `resume_on_gesture`.
+    fn remove_gesture_listeners(&self) {
+        let gesture_closure = match self.gesture_closure.lock().unwrap().take() {
+            Some(closure) => closure,
+            None => return,
+        };
+        if let Some(window) = web_sys::window() {
+            for event in GESTURE_EVENTS {
+                let _ = window.remove_event_listener_with_callback(
+                    event,
+                    gesture_closure.as_ref().unchecked_ref(),
+                );
+            }
+        }
+    }
 }
 
 impl StreamTrait for Stream {
@@ -439,6 +520,7 @@ impl StreamTrait for Stream {
     }
 
     fn pause(&self) -> Result<(), PauseStreamError> {
+        self.remove_gesture_listeners();
         match self.ctx.suspend() {
             Ok(_) => Ok(()),
             Err(err) => {
@@ -452,6 +534,7 @@ impl StreamTrait for Stream {
 
 impl Drop for Stream {
     fn drop(&mut self) {
+        self.remove_gesture_listeners();
         let _ = self.ctx.close();
     }
 }
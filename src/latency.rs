@@ -0,0 +1,199 @@
+//! Round-trip latency measurement between an output and an input device.
+
+use std::f32::consts::PI;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::traits::{DeviceTrait, StreamTrait};
+use crate::{
+    BuildStreamError, InputCallbackInfo, LatencyTestError, OutputCallbackInfo, StreamConfig,
+    StreamInstant,
+};
+
+/// Silence played before the chirp, so both streams are running when it starts.
+const LEAD_IN: Duration = Duration::from_millis(250);
+/// The longest latency that can be measured.
+const MAX_LATENCY: Duration = Duration::from_secs(1);
+/// The number of frames of the chirp.
+const CHIRP_FRAMES: usize = 2048;
+const CHIRP_START_HZ: f32 = 300.0;
+const CHIRP_END_HZ: f32 = 6000.0;
+const CHIRP_AMPLITUDE: f32 = 0.5;
+/// The normalized cross-correlation above which the chirp counts as found.
+const DETECTION_THRESHOLD: f32 = 0.3;
+
+/// Measure the round-trip latency from `output` to `input`, which must be connected, e.g. with a
+/// loopback cable or by the speakers playing into the microphone.
+///
+/// A short chirp is played on an `f32` output stream and searched in what an `f32` input stream
+/// records, by cross-correlation. The result is the time between the output data callback
+/// writing the chirp and the input data callback receiving it, so it includes the buffering of
+/// both streams. Both configs must have the same sample rate.
+///
+/// This blocks for about a second and a half.
+pub fn measure_round_trip_latency<D>(
+    input: &D,
+    input_config: &StreamConfig,
+    output: &D,
+    output_config: &StreamConfig,
+) -> Result<Duration, LatencyTestError>
+where
+    D: DeviceTrait,
+{
+    if input_config.sample_rate != output_config.sample_rate || input_config.channels == 0 {
+        return Err(BuildStreamError::StreamConfigNotSupported.into());
+    }
+    let sample_rate = output_config.sample_rate.0;
+    let chirp = chirp(sample_rate);
+    let lead_in_frames = duration_to_frames(LEAD_IN, sample_rate);
+    let recording_frames =
+        lead_in_frames + CHIRP_FRAMES + duration_to_frames(MAX_LATENCY, sample_rate);
+
+    let error = Arc::new(Mutex::new(None));
+
+    // Record the first channel, along with the time of the first callback.
+    let recording = Arc::new(Mutex::new((None, Vec::with_capacity(recording_frames))));
+    let input_stream = {
+        let recording = recording.clone();
+        let error = error.clone();
+        let channels = input_config.channels as usize;
+        input.build_input_stream(
+            input_config,
+            move |data: &[f32], info: &InputCallbackInfo| {
+                let mut recording = recording.lock().unwrap();
+                let (first_callback, samples) = &mut *recording;
+                first_callback.get_or_insert(info.timestamp().callback);
+                let remaining = recording_frames - samples.len();
+                samples.extend(data.iter().step_by(channels).take(remaining));
+            },
+            move |err| {
+                error.lock().unwrap().get_or_insert(err);
+            },
+            None,
+        )?
+    };
+
+    // Play the chirp on all channels after the lead-in, noting when it was written.
+    let chirp_written: Arc<Mutex<Option<StreamInstant>>> = Arc::new(Mutex::new(None));
+    let output_stream = {
+        let chirp = chirp.clone();
+        let chirp_written = chirp_written.clone();
+        let error = error.clone();
+        let channels = output_config.channels as usize;
+        let mut frame: usize = 0;
+        output.build_output_stream(
+            output_config,
+            move |data: &mut [f32], info: &OutputCallbackInfo| {
+                for (i, samples) in data.chunks_mut(channels).enumerate() {
+                    let value = match frame.checked_sub(lead_in_frames) {
+                        Some(0) => {
+                            let offset = frames_to_duration(i, sample_rate);
+                            *chirp_written.lock().unwrap() = info.timestamp().callback.add(offset);
+                            chirp[0]
+                        }
+                        Some(n) if n < CHIRP_FRAMES => chirp[n],
+                        _ => 0.0,
+                    };
+                    samples.fill(value);
+                    frame += 1;
+                }
+            },
+            move |err| {
+                error.lock().unwrap().get_or_insert(err);
+            },
+            None,
+        )?
+    };
+
+    input_stream.play()?;
+    output_stream.play()?;
+
+    // Leave generous room for streams that take a while to start.
+    let deadline = Instant::now() + LEAD_IN + MAX_LATENCY + Duration::from_secs(2);
+    while recording.lock().unwrap().1.len() < recording_frames && Instant::now() < deadline {
+        if let Some(err) = error.lock().unwrap().take() {
+            return Err(err.into());
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    drop(output_stream);
+    drop(input_stream);
+
+    let (first_callback, samples) = &*recording.lock().unwrap();
+    let chirp_written = *chirp_written.lock().unwrap();
+    let (first_callback, chirp_written) = match (first_callback, chirp_written) {
+        (Some(first_callback), Some(chirp_written)) => (*first_callback, chirp_written),
+        _ => return Err(LatencyTestError::NotDetected),
+    };
+    let position = find_chirp(samples, &chirp).ok_or(LatencyTestError::NotDetected)?;
+    first_callback
+        .add(frames_to_duration(position, sample_rate))
+        .and_then(|received| received.duration_since(&chirp_written))
+        .ok_or(LatencyTestError::NotDetected)
+}
+
+/// A linear sine sweep with faded edges.
+fn chirp(sample_rate: u32) -> Vec<f32> {
+    let length = CHIRP_FRAMES as f32 / sample_rate as f32;
+    let sweep = (CHIRP_END_HZ - CHIRP_START_HZ) / (2.0 * length);
+    (0..CHIRP_FRAMES)
+        .map(|i| {
+            let t = i as f32 / sample_rate as f32;
+            let fade = (PI * i as f32 / CHIRP_FRAMES as f32).sin();
+            let phase = 2.0 * PI * (CHIRP_START_HZ * t + sweep * t * t);
+            CHIRP_AMPLITUDE * fade * phase.sin()
+        })
+        .collect()
+}
+
+/// The position in `recording` at which `chirp` correlates best, if it correlates well enough.
+fn find_chirp(recording: &[f32], chirp: &[f32]) -> Option<usize> {
+    if recording.len() < chirp.len() {
+        return None;
+    }
+    let chirp_energy: f32 = chirp.iter().map(|s| s * s).sum();
+    let mut window_energy: f32 = recording[..chirp.len()].iter().map(|s| s * s).sum();
+    let mut best = (0.0, 0);
+    for start in 0..=recording.len() - chirp.len() {
+        if start > 0 {
+            let (removed, added) = (recording[start - 1], recording[start + chirp.len() - 1]);
+            window_energy = (window_energy - removed * removed + added * added).max(0.0);
+        }
+        let window = &recording[start..start + chirp.len()];
+        let correlation: f32 = window.iter().zip(chirp).map(|(a, b)| a * b).sum();
+        let norm = (chirp_energy * window_energy).sqrt();
+        if norm > 0.0 && correlation / norm > best.0 {
+            best = (correlation / norm, start);
+        }
+    }
+    if best.0 >= DETECTION_THRESHOLD {
+        Some(best.1)
+    } else {
+        None
+    }
+}
+
+fn duration_to_frames(duration: Duration, sample_rate: u32) -> usize {
+    (duration.as_secs_f64() * sample_rate as f64) as usize
+}
+
+fn frames_to_duration(frames: usize, sample_rate: u32) -> Duration {
+    Duration::from_secs_f64(frames as f64 / sample_rate as f64)
+}
+
+#[test]
+fn test_find_chirp() {
+    let chirp = chirp(48_000);
+    let mut recording = vec![0.0; 10_000];
+    for (i, s) in chirp.iter().enumerate() {
+        recording[3_000 + i] += 0.2 * s;
+    }
+    // Some noise that doesn't look like the chirp.
+    for (i, s) in recording.iter_mut().enumerate() {
+        *s += 0.01 * ((i * 7919 % 200) as f32 / 100.0 - 1.0);
+    }
+    assert_eq!(find_chirp(&recording, &chirp), Some(3_000));
+    assert_eq!(find_chirp(&vec![0.0; 10_000], &chirp), None);
+    assert_eq!(find_chirp(&recording[..100], &chirp), None);
+}
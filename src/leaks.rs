@@ -0,0 +1,96 @@
+//! Counting of live streams, see [`live_streams`].
+//!
+//! A stream that outlives the last host of its backend often hides a missing cleanup, and on
+//! some backends crashes the process on exit when the backend's libraries tear down their state
+//! under the still running audio thread. Applications and tests that want to catch this check
+//! [`live_streams`] before they exit or drop their host. Nothing is reported on its own.
+
+use std::sync::Mutex;
+
+use crate::HostId;
+
+struct Counts {
+    host: HostId,
+    /// Incremented by each shutdown of the host, so the streams it stopped are no longer counted.
+    generation: u64,
+    /// The live streams built since the last shutdown.
+    streams: usize,
+}
+
+static COUNTS: Mutex<Vec<Counts>> = Mutex::new(Vec::new());
+
+fn with_counts<R>(host: HostId, f: impl FnOnce(&mut Counts) -> R) -> R {
+    let mut all = COUNTS.lock().unwrap_or_else(|e| e.into_inner());
+    let index = match all.iter().position(|counts| counts.host == host) {
+        Some(index) => index,
+        None => {
+            all.push(Counts {
+                host,
+                generation: 0,
+                streams: 0,
+            });
+            all.len() - 1
+        }
+    };
+    f(&mut all[index])
+}
+
+/// The number of streams of `host` that are alive and were not stopped by
+/// [`Host::shutdown`](crate::Host::shutdown).
+///
+/// Only streams of the platform [`Stream`](crate::Stream) type are counted. Check that it is zero
+/// before the process exits, e.g. at the end of a test:
+///
+/// ```no_run
+/// # let host = cpal::default_host();
+/// // ... build, play and drop streams ...
+/// assert_eq!(cpal::live_streams(host.id()), 0, "a stream was leaked");
+/// ```
+pub fn live_streams(host: HostId) -> usize {
+    with_counts(host, |counts| counts.streams)
+}
+
+/// Counts a stream of a host while it is alive.
+#[derive(Default)]
+pub(crate) struct Live {
+    stream: Option<(HostId, u64)>,
+}
+
+impl Live {
+    pub(crate) fn new(host: HostId) -> Self {
+        let generation = with_counts(host, |counts| {
+            counts.streams += 1;
+            counts.generation
+        });
+        Live {
+            stream: Some((host, generation)),
+        }
+    }
+
+    /// Whether the host of the stream was shut down since the stream was built.
+    pub(crate) fn is_shut_down(&self) -> bool {
+        self.stream.is_some_and(|(host, generation)| {
+            with_counts(host, |counts| counts.generation != generation)
+        })
+    }
+}
+
+impl Drop for Live {
+    fn drop(&mut self) {
+        if let Some((host, generation)) = self.stream {
+            with_counts(host, |counts| {
+                if counts.generation == generation {
+                    counts.streams -= 1;
+                }
+            });
+        }
+    }
+}
+
+/// Stop counting the streams of `host` that are alive, as they no longer run.
+pub(crate) fn shut_down(host: HostId) {
+    with_counts(host, |counts| {
+        counts.generation += 1;
+        counts.streams = 0;
+    });
+}
@@ -40,8 +40,9 @@
 //! actual [`SupportedStreamConfig`] structs.
 //!
 //! If you don't want to query the list of configs,
-//! you can also build your own [`StreamConfig`] manually, but doing so could lead to an error when
-//! building the stream if the config is not supported by the device.
+//! you can also build your own [`StreamConfig`] manually, starting from its [`Default`], but doing
+//! so could lead to an error when building the stream if the config is not supported by the
+//! device.
 //!
 //! > **Note**: the `supported_input/output_configs()` methods
 //! > could return an error for example if the device has been disconnected.
@@ -164,23 +165,49 @@ extern crate js_sys;
 #[cfg(target_os = "emscripten")]
 extern crate web_sys;
 
+pub use capture::CaptureVoice;
+pub use diagnostics::{diagnostics, DeviceReport, Diagnostics, HostReport};
+pub use duplex::DuplexStream;
 pub use error::*;
+pub use latency::measure_round_trip_latency;
+pub use leaks::live_streams;
 pub use platform::{
     available_hosts, default_host, host_from_id, Device, Devices, Host, HostId, Stream,
     SupportedInputConfigs, SupportedOutputConfigs, ALL_HOSTS,
 };
 pub use samples_formats::{FromSample, Sample, SampleFormat, SizedSample, I24, I48, U24, U48};
+pub use stats::{FillLevel, StreamStats};
 use std::convert::TryInto;
 use std::ops::{Div, Mul};
 use std::time::Duration;
+pub use sync::SyncedOutput;
+#[cfg(feature = "api-trace")]
+pub use trace::{take_api_trace, ApiCall};
+pub use voice::Voice;
 #[cfg(target_os = "emscripten")]
 use wasm_bindgen::prelude::*;
 
+mod capture;
+mod convert;
+mod diagnostics;
+mod drift;
+mod duplex;
 mod error;
 mod host;
+mod latency;
+mod leaks;
 pub mod platform;
+mod relay;
 mod samples_formats;
+mod schedule;
+mod shutdown;
+mod stats;
+mod swap;
+mod sync;
+mod trace;
 pub mod traits;
+pub mod voice;
+mod volume;
 
 /// A host's device iterator yielding only *input* devices.
 pub type InputDevices<I> = std::iter::Filter<I, fn(&<I as Iterator>::Item) -> bool>;
@@ -253,15 +280,320 @@ impl wasm_bindgen::convert::IntoWasmAbi for BufferSize {
     }
 }
 
+/// A hint describing what a stream is used for.
+///
+/// Platforms route audio and pick a volume group based on what the application says it is
+/// playing. On iOS the usage selects the `AVAudioSession` category, on Android it selects the
+/// AAudio usage and content type (or the input preset for input streams) and on WASAPI it selects
+/// the audio stream category. Hosts without such a concept ignore the hint.
+///
+/// [`Default`] leaves the platform's default behaviour untouched.
+///
+/// [`Default`]: StreamUsage::Default
+#[cfg_attr(target_os = "emscripten", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum StreamUsage {
+    #[default]
+    Default,
+    /// Music or other media playback.
+    Media,
+    /// Game audio, mixed with other applications where the platform allows it.
+    Game,
+    /// Two-way voice communication, e.g. VoIP or video calls.
+    VoiceCommunication,
+    /// Alarms that should be heard even when the device is muted.
+    Alarm,
+    /// Short notification sounds, e.g. for incoming messages.
+    Notification,
+}
+
+/// The optional features a host or device supports, so applications can offer them without
+/// trying to build streams first.
+///
+/// The capabilities of a device are those of its host, narrowed down to the device. See
+/// [`HostTrait::capabilities`](traits::HostTrait::capabilities) and
+/// [`DeviceTrait::capabilities`](traits::DeviceTrait::capabilities).
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// Streams can take exclusive access to the device, bypassing the system mixer.
+    pub exclusive_mode: bool,
+    /// Input streams can record what an output device plays.
+    pub loopback: bool,
+    /// The host reports devices being added, removed or becoming the default.
+    pub device_events: bool,
+    /// Streams have a volume of their own, separate from the device volume.
+    pub stream_volume: bool,
+}
+
+/// A hint trading latency against power consumption for a stream.
+///
+/// On Android this selects the AAudio performance mode, the default path of many phones adds
+/// a lot of latency unless `LowLatency` is requested. With [`BufferSize::Default`], ALSA and
+/// WASAPI pick large buffers and fewer wakeups for `PowerSaving` streams, and WASAPI offloads
+/// them to the hardware where the device supports it. Other hosts ignore the hint.
+#[cfg_attr(target_os = "emscripten", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum PerformanceMode {
+    #[default]
+    Default,
+    /// Prefer the lowest latency the device can provide.
+    LowLatency,
+    /// Prefer lower power consumption over latency, e.g. for music or podcast players.
+    PowerSaving,
+}
+
+/// Whether a stream shares its device with other applications through the system mixer, or
+/// takes exclusive access to it.
+///
+/// Devices often support other formats in exclusive mode, where the stream has to use a format
+/// of the hardware as is. See [`DeviceTrait::supported_output_configs_with_mode`].
+///
+/// [`DeviceTrait::supported_output_configs_with_mode`]: traits::DeviceTrait::supported_output_configs_with_mode
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum ShareMode {
+    /// The stream is mixed with the streams of other applications.
+    #[default]
+    Shared,
+    /// The stream has the device to itself, bypassing the system mixer.
+    Exclusive,
+}
+
+/// What a device is used for, for hosts that keep a separate default device per use. See
+/// [`HostTrait::default_output_device_for_role`].
+///
+/// On WASAPI these are the `eConsole`, `eMultimedia` and `eCommunications` roles. Other hosts
+/// have one default device and return it for every role.
+///
+/// [`HostTrait::default_output_device_for_role`]: traits::HostTrait::default_output_device_for_role
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum DeviceRole {
+    /// Games, system sounds and voice commands. The default of
+    /// [`default_output_device`](traits::HostTrait::default_output_device).
+    #[default]
+    Console,
+    /// Music, movies and recorded speech.
+    Multimedia,
+    /// Voice calls.
+    Communications,
+}
+
+/// A change to the audio devices of a host.
+///
+/// Devices are identified by the host's device ID, which on WASAPI is the endpoint ID string. The
+/// device of a `Removed` event may no longer be queryable.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum DeviceEvent {
+    /// A device was plugged in or enabled.
+    Added { id: String },
+    /// A device was unplugged, disabled or removed.
+    Removed { id: String },
+    /// The default input device changed, `None` if there is no input device left.
+    DefaultInputChanged { id: Option<String> },
+    /// The default output device changed, `None` if there is no output device left.
+    DefaultOutputChanged { id: Option<String> },
+}
+
+/// A clock source of a device, e.g. its internal clock, word clock or S/PDIF.
+///
+/// Listed and selected by the `clock_sources` and `set_clock_source` methods of the CoreAudio
+/// and ASIO devices.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ClockSource {
+    /// The backend's identifier of the source, passed to `set_clock_source`.
+    pub id: u32,
+    /// The name of the source as reported by the driver.
+    pub name: String,
+    /// Whether the device currently runs on this source.
+    pub current: bool,
+}
+
+/// The position of the speaker a channel is meant for, see
+/// [`standard_layout`](ChannelPosition::standard_layout).
+///
+/// The variants are in the order of the speaker bits of a `WAVEFORMATEXTENSIBLE` channel mask.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ChannelPosition {
+    FrontLeft,
+    FrontRight,
+    FrontCenter,
+    LowFrequency,
+    BackLeft,
+    BackRight,
+    FrontLeftOfCenter,
+    FrontRightOfCenter,
+    BackCenter,
+    SideLeft,
+    SideRight,
+}
+
+impl ChannelPosition {
+    /// The order of the channels in the samples of a stream with `channels` channels, or `None`
+    /// when there is no standard layout for that many channels.
+    ///
+    /// | Channels | Layout |
+    /// |----------|--------|
+    /// | 1 | `FC` |
+    /// | 2 | `FL FR` |
+    /// | 4 | `FL FR BL BR` |
+    /// | 6 | `FL FR FC LFE BL BR` (5.1) |
+    /// | 8 | `FL FR FC LFE BL BR SL SR` (7.1) |
+    ///
+    /// WASAPI and ALSA streams with a standard layout are mapped to the device's speakers in this
+    /// order, whatever order the device uses natively. ALSA falls back to the side speakers for
+    /// the back positions of devices without back speakers. Other channel counts, and the other
+    /// hosts, pass the channels in the device's order.
+    pub fn standard_layout(channels: ChannelCount) -> Option<&'static [ChannelPosition]> {
+        use ChannelPosition::*;
+        match channels {
+            1 => Some(&[FrontCenter]),
+            2 => Some(&[FrontLeft, FrontRight]),
+            4 => Some(&[FrontLeft, FrontRight, BackLeft, BackRight]),
+            6 => Some(&[
+                FrontLeft,
+                FrontRight,
+                FrontCenter,
+                LowFrequency,
+                BackLeft,
+                BackRight,
+            ]),
+            8 => Some(&[
+                FrontLeft,
+                FrontRight,
+                FrontCenter,
+                LowFrequency,
+                BackLeft,
+                BackRight,
+                SideLeft,
+                SideRight,
+            ]),
+            _ => None,
+        }
+    }
+}
+
 /// The set of parameters used to describe how to open a stream.
 ///
 /// The sample format is omitted in favour of using a sample type.
+///
+/// The [`Default`] config is stereo at 48 kHz with every other field at its default. Fill in
+/// the fields you care about and take the rest from it, so that code keeps compiling when
+/// fields are added:
+///
+/// ```
+/// use cpal::{BufferSize, SampleRate, StreamConfig, StreamUsage};
+///
+/// let config = StreamConfig {
+///     channels: 1,
+///     sample_rate: SampleRate(44_100),
+///     ..StreamConfig::default()
+/// }
+/// .with_buffer_size(BufferSize::Fixed(256))
+/// .with_usage(StreamUsage::Game);
+/// ```
 #[cfg_attr(target_os = "emscripten", wasm_bindgen)]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct StreamConfig {
     pub channels: ChannelCount,
     pub sample_rate: SampleRate,
     pub buffer_size: BufferSize,
+    /// The number of frames of each period of the buffer, for hosts that split the buffer into
+    /// periods (default is none, deriving it from `buffer_size`).
+    ///
+    /// Only ALSA uses it, picking the closest size the hardware supports, e.g. to trade latency
+    /// for fewer underruns on small boards. Other hosts ignore it.
+    #[cfg_attr(target_os = "emscripten", wasm_bindgen(skip))]
+    pub period_size: Option<FrameCount>,
+    /// The number of periods of the buffer (default is none, letting the host choose).
+    ///
+    /// Only ALSA uses it, picking the closest count the hardware supports. Other hosts ignore it.
+    #[cfg_attr(target_os = "emscripten", wasm_bindgen(skip))]
+    pub periods: Option<u32>,
+    pub usage: StreamUsage,
+    pub performance_mode: PerformanceMode,
+    /// A name for the stream, used in the names of the threads cpal spawns for it (default is
+    /// none, in which case the device name is used).
+    #[cfg_attr(target_os = "emscripten", wasm_bindgen(skip))]
+    pub name: Option<String>,
+    /// Run the data callback on a thread cpal spawns for it rather than on the backend's audio
+    /// thread, relaying at most this many periods of audio between the two (default is none).
+    ///
+    /// The audio thread then never waits for the data callback: when the callback falls behind
+    /// the stream plays silence or drops input and reports [`StreamError::Xrun`], and when it
+    /// panics the stream reports an error and stays silent. This keeps untrusted or slow DSP
+    /// from wedging the system's audio service, at the cost of up to this many periods of added
+    /// latency. Ignored on `wasm32-unknown-unknown`, which has no threads.
+    #[cfg_attr(target_os = "emscripten", wasm_bindgen(skip))]
+    pub callback_relay: Option<u32>,
+    /// For input streams, the channels of the device to capture, by index (default is none,
+    /// capturing all of them).
+    ///
+    /// The device is still opened with `channels` channels, e.g. all 18 inputs of an interface,
+    /// and the data callback receives only the selected ones, interleaved in the given order,
+    /// e.g. `Some(vec![2, 3])` for inputs 3 and 4. Building the stream fails with
+    /// [`BuildStreamError::InvalidArgument`] if an index is not below `channels`. Ignored for
+    /// output streams.
+    #[cfg_attr(target_os = "emscripten", wasm_bindgen(skip))]
+    pub input_channels: Option<Vec<ChannelCount>>,
+}
+
+impl StreamConfig {
+    /// Set the [`buffer_size`](Self::buffer_size).
+    pub fn with_buffer_size(mut self, buffer_size: BufferSize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// Set the [`period_size`](Self::period_size).
+    pub fn with_period_size(mut self, period_size: FrameCount) -> Self {
+        self.period_size = Some(period_size);
+        self
+    }
+
+    /// Set the number of [`periods`](Self::periods).
+    pub fn with_periods(mut self, periods: u32) -> Self {
+        self.periods = Some(periods);
+        self
+    }
+
+    /// Set the [`usage`](Self::usage).
+    pub fn with_usage(mut self, usage: StreamUsage) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    /// Set the [`performance_mode`](Self::performance_mode).
+    pub fn with_performance_mode(mut self, performance_mode: PerformanceMode) -> Self {
+        self.performance_mode = performance_mode;
+        self
+    }
+
+    /// Set the [`name`](Self::name).
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Set the [`callback_relay`](Self::callback_relay) periods.
+    pub fn with_callback_relay(mut self, periods: u32) -> Self {
+        self.callback_relay = Some(periods);
+        self
+    }
+
+    /// Set the [`input_channels`](Self::input_channels).
+    pub fn with_input_channels(mut self, input_channels: Vec<ChannelCount>) -> Self {
+        self.input_channels = Some(input_channels);
+        self
+    }
+
+    /// The name for a thread spawned for the stream: `prefix` followed by the name of the
+    /// stream, or by the name of the device if the stream has none.
+    pub(crate) fn thread_name(&self, prefix: &str, device_name: &str) -> String {
+        match self.name.as_deref().unwrap_or(device_name) {
+            "" => prefix.to_owned(),
+            name => format!("{}: {}", prefix, name),
+        }
+    }
 }
 
 /// Describes the minimum and maximum supported buffer size for the device
@@ -302,6 +634,18 @@ pub struct SupportedStreamConfig {
     sample_format: SampleFormat,
 }
 
+/// Whether a device supports a stream configuration, see
+/// [`DeviceTrait::check_output_config`](traits::DeviceTrait::check_output_config).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSupport {
+    /// A stream can be built with the configuration as is.
+    Supported,
+    /// The configuration is not supported, this is the closest one that is.
+    Closest(SupportedStreamConfig),
+    /// The device supports no configuration at all in this direction.
+    Unsupported,
+}
+
 /// A buffer of dynamically typed audio data, passed to raw stream callbacks.
 ///
 /// Raw input stream callbacks receive `&Data`, while raw output stream callbacks expect `&mut
@@ -408,7 +752,7 @@ impl SupportedStreamConfig {
         StreamConfig {
             channels: self.channels,
             sample_rate: self.sample_rate,
-            buffer_size: BufferSize::Default,
+            ..StreamConfig::default()
         }
     }
 }
@@ -590,6 +934,56 @@ impl Data {
             None
         }
     }
+
+    /// Fill the buffer with silence, the equilibrium of its sample format.
+    pub(crate) fn fill_equilibrium(&mut self) {
+        fn fill<T: SizedSample>(data: &mut Data) {
+            if let Some(samples) = data.as_slice_mut::<T>() {
+                samples.fill(T::EQUILIBRIUM);
+            }
+        }
+        match self.sample_format {
+            SampleFormat::I8 => fill::<i8>(self),
+            SampleFormat::I16 => fill::<i16>(self),
+            SampleFormat::I32 => fill::<i32>(self),
+            SampleFormat::I64 => fill::<i64>(self),
+            SampleFormat::U8 => fill::<u8>(self),
+            SampleFormat::U16 => fill::<u16>(self),
+            SampleFormat::U32 => fill::<u32>(self),
+            SampleFormat::U64 => fill::<u64>(self),
+            SampleFormat::F32 => fill::<f32>(self),
+            SampleFormat::F64 => fill::<f64>(self),
+        }
+    }
+
+    /// Whether the buffer holds nothing but silence, the equilibrium of its sample format.
+    ///
+    /// Used by the ALSA and WASAPI hosts to detect silent periods.
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        windows
+    ))]
+    pub(crate) fn is_equilibrium(&self) -> bool {
+        fn check<T: SizedSample>(data: &Data) -> bool {
+            data.as_slice::<T>()
+                .is_some_and(|samples| samples.iter().all(|&s| s == T::EQUILIBRIUM))
+        }
+        match self.sample_format {
+            SampleFormat::I8 => check::<i8>(self),
+            SampleFormat::I16 => check::<i16>(self),
+            SampleFormat::I32 => check::<i32>(self),
+            SampleFormat::I64 => check::<i64>(self),
+            SampleFormat::U8 => check::<u8>(self),
+            SampleFormat::U16 => check::<u16>(self),
+            SampleFormat::U32 => check::<u32>(self),
+            SampleFormat::U64 => check::<u64>(self),
+            SampleFormat::F32 => check::<f32>(self),
+            SampleFormat::F64 => check::<f64>(self),
+        }
+    }
 }
 
 impl SupportedStreamConfigRange {
@@ -738,6 +1132,201 @@ impl SupportedStreamConfigRange {
 
         self.max_sample_rate.cmp(&other.max_sample_rate)
     }
+
+    /// The sample rate [`cmp_preferred`](Self::cmp_preferred) rates this range by: the highest
+    /// rate of the range up to 48 kHz, or its minimum if all its rates are higher.
+    pub fn preferred_sample_rate(&self) -> SampleRate {
+        const HZ_48000: SampleRate = SampleRate(48_000);
+        self.max_sample_rate.min(HZ_48000).max(self.min_sample_rate)
+    }
+
+    /// Turns this [`SupportedStreamConfigRange`] into a [`SupportedStreamConfig`] at its
+    /// [`preferred_sample_rate`](Self::preferred_sample_rate).
+    pub fn with_preferred_sample_rate(self) -> SupportedStreamConfig {
+        let sample_rate = self.preferred_sample_rate();
+        self.with_sample_rate(sample_rate)
+    }
+
+    /// A comparison function ranking [`SupportedStreamConfigRange`]s by how well they suit
+    /// typical playback and recording, so that
+    /// `supported_output_configs()?.max_by(SupportedStreamConfigRange::cmp_preferred)` picks
+    /// the same kind of config on every host. Use it with
+    /// [`with_preferred_sample_rate`](Self::with_preferred_sample_rate).
+    ///
+    /// Unlike [`cmp_default_heuristics`](Self::cmp_default_heuristics), which mirrors what hosts
+    /// report as their default, the sample format comes first:
+    ///
+    /// **Sample format**: f32, i16, i32, u16, then any other format.
+    ///
+    /// **Sample rate**: the [`preferred_sample_rate`](Self::preferred_sample_rate) closest to
+    /// 48 kHz, preferring rates up to 48 kHz over higher ones.
+    ///
+    /// **Channels**: stereo, mono, then more channels.
+    pub fn cmp_preferred(&self, other: &Self) -> std::cmp::Ordering {
+        use std::cmp::Ordering::Equal;
+
+        fn format_rank(sample_format: SampleFormat) -> u8 {
+            match sample_format {
+                SampleFormat::F32 => 4,
+                SampleFormat::I16 => 3,
+                SampleFormat::I32 => 2,
+                SampleFormat::U16 => 1,
+                _ => 0,
+            }
+        }
+        let cmp_format = format_rank(self.sample_format).cmp(&format_rank(other.sample_format));
+        if cmp_format != Equal {
+            return cmp_format;
+        }
+
+        // Rates up to 48 kHz rank above higher ones, and the closer to 48 kHz the better.
+        fn rate_rank(sample_rate: SampleRate) -> (bool, u32) {
+            match sample_rate.0 {
+                rate if rate <= 48_000 => (true, rate),
+                rate => (false, u32::MAX - rate),
+            }
+        }
+        let cmp_rate =
+            rate_rank(self.preferred_sample_rate()).cmp(&rate_rank(other.preferred_sample_rate()));
+        if cmp_rate != Equal {
+            return cmp_rate;
+        }
+
+        let cmp_stereo = (self.channels == 2).cmp(&(other.channels == 2));
+        if cmp_stereo != Equal {
+            return cmp_stereo;
+        }
+
+        let cmp_mono = (self.channels == 1).cmp(&(other.channels == 1));
+        if cmp_mono != Equal {
+            return cmp_mono;
+        }
+
+        self.channels.cmp(&other.channels)
+    }
+}
+
+impl ConfigSupport {
+    /// Check `config` and `sample_format` against the ranges a device reports.
+    ///
+    /// The closest alternative keeps the sample format if possible, then the channel count,
+    /// then picks the nearest sample rate.
+    pub(crate) fn of<I>(ranges: I, config: &StreamConfig, sample_format: SampleFormat) -> Self
+    where
+        I: IntoIterator<Item = SupportedStreamConfigRange>,
+    {
+        let buffer_size_supported =
+            |range: &SupportedStreamConfigRange| match (config.buffer_size, range.buffer_size) {
+                (BufferSize::Fixed(frames), SupportedBufferSize::Range { min, max }) => {
+                    min <= frames && frames <= max
+                }
+                _ => true,
+            };
+        let distance = |range: &SupportedStreamConfigRange| {
+            let rate = config.sample_rate.0;
+            let rate_distance = if rate < range.min_sample_rate.0 {
+                range.min_sample_rate.0 - rate
+            } else {
+                rate.saturating_sub(range.max_sample_rate.0)
+            };
+            (
+                range.sample_format != sample_format,
+                range.channels.abs_diff(config.channels),
+                rate_distance,
+                !buffer_size_supported(range),
+            )
+        };
+        let closest = match ranges.into_iter().min_by_key(distance) {
+            Some(range) => range,
+            None => return ConfigSupport::Unsupported,
+        };
+        if distance(&closest) == (false, 0, 0, false) {
+            return ConfigSupport::Supported;
+        }
+        let sample_rate = config
+            .sample_rate
+            .max(closest.min_sample_rate)
+            .min(closest.max_sample_rate);
+        ConfigSupport::Closest(closest.with_sample_rate(sample_rate))
+    }
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        StreamConfig {
+            channels: 2,
+            sample_rate: SampleRate(48_000),
+            buffer_size: BufferSize::Default,
+            period_size: None,
+            periods: None,
+            usage: StreamUsage::Default,
+            performance_mode: PerformanceMode::Default,
+            name: None,
+            callback_relay: None,
+            input_channels: None,
+        }
+    }
+}
+
+impl From<SupportedStreamConfig> for StreamConfig {
+    fn from(conf: SupportedStreamConfig) -> Self {
+        conf.config()
+    }
+}
+
+// If a backend does not provide an API for retrieving supported formats, we query it with a bunch
+// of commonly used rates. This is always the case for wasapi and is sometimes the case for alsa.
+//
+// If a rate you desire is missing from this list, feel free to add it!
+#[cfg(target_os = "windows")]
+const COMMON_SAMPLE_RATES: &[SampleRate] = &[
+    SampleRate(5512),
+    SampleRate(8000),
+    SampleRate(11025),
+    SampleRate(16000),
+    SampleRate(22050),
+    SampleRate(32000),
+    SampleRate(44100),
+    SampleRate(48000),
+    SampleRate(64000),
+    SampleRate(88200),
+    SampleRate(96000),
+    SampleRate(176400),
+    SampleRate(192000),
+];
+
+#[test]
+fn test_stream_instant() {
+    let a = StreamInstant::new(2, 0);
+    let b = StreamInstant::new(-2, 0);
+    let min = StreamInstant::new(i64::MIN, 0);
+    let max = StreamInstant::new(i64::MAX, 0);
+    assert_eq!(
+        a.sub(Duration::from_secs(1)),
+        Some(StreamInstant::new(1, 0))
+    );
+    assert_eq!(
+        a.sub(Duration::from_secs(2)),
+        Some(StreamInstant::new(0, 0))
+    );
+    assert_eq!(
+        a.sub(Duration::from_secs(3)),
+        Some(StreamInstant::new(-1, 0))
+    );
+    assert_eq!(min.sub(Duration::from_secs(1)), None);
+    assert_eq!(
+        b.add(Duration::from_secs(1)),
+        Some(StreamInstant::new(-1, 0))
+    );
+    assert_eq!(
+        b.add(Duration::from_secs(2)),
+        Some(StreamInstant::new(0, 0))
+    );
+    assert_eq!(
+        b.add(Duration::from_secs(3)),
+        Some(StreamInstant::new(1, 0))
+    );
+    assert_eq!(max.add(Duration::from_secs(1)), None);
 }
 
 #[test]
@@ -809,63 +1398,69 @@ fn test_cmp_default_heuristics() {
     assert_eq!(formats[4].channels(), 2);
 }
 
-impl From<SupportedStreamConfig> for StreamConfig {
-    fn from(conf: SupportedStreamConfig) -> Self {
-        conf.config()
-    }
+#[test]
+fn test_cmp_preferred() {
+    let range = |channels, min, max, sample_format| SupportedStreamConfigRange {
+        buffer_size: SupportedBufferSize::Unknown,
+        channels,
+        min_sample_rate: SampleRate(min),
+        max_sample_rate: SampleRate(max),
+        sample_format,
+    };
+    let mut formats = [
+        range(2, 44_100, 44_100, SampleFormat::I16),
+        range(6, 8_000, 192_000, SampleFormat::F32),
+        range(2, 96_000, 192_000, SampleFormat::F32),
+        range(1, 8_000, 48_000, SampleFormat::F32),
+        range(2, 44_100, 48_000, SampleFormat::F32),
+    ];
+    formats.sort_by(SupportedStreamConfigRange::cmp_preferred);
+    assert_eq!(formats[4], range(2, 44_100, 48_000, SampleFormat::F32));
+    assert_eq!(formats[3], range(1, 8_000, 48_000, SampleFormat::F32));
+    assert_eq!(formats[2], range(6, 8_000, 192_000, SampleFormat::F32));
+    assert_eq!(formats[1], range(2, 96_000, 192_000, SampleFormat::F32));
+    assert_eq!(formats[0], range(2, 44_100, 44_100, SampleFormat::I16));
+
+    assert_eq!(formats[2].preferred_sample_rate(), SampleRate(48_000));
+    assert_eq!(formats[1].preferred_sample_rate(), SampleRate(96_000));
+    assert_eq!(formats[0].preferred_sample_rate(), SampleRate(44_100));
 }
 
-// If a backend does not provide an API for retrieving supported formats, we query it with a bunch
-// of commonly used rates. This is always the case for wasapi and is sometimes the case for alsa.
-//
-// If a rate you desire is missing from this list, feel free to add it!
-#[cfg(target_os = "windows")]
-const COMMON_SAMPLE_RATES: &[SampleRate] = &[
-    SampleRate(5512),
-    SampleRate(8000),
-    SampleRate(11025),
-    SampleRate(16000),
-    SampleRate(22050),
-    SampleRate(32000),
-    SampleRate(44100),
-    SampleRate(48000),
-    SampleRate(64000),
-    SampleRate(88200),
-    SampleRate(96000),
-    SampleRate(176400),
-    SampleRate(192000),
-];
-
 #[test]
-fn test_stream_instant() {
-    let a = StreamInstant::new(2, 0);
-    let b = StreamInstant::new(-2, 0);
-    let min = StreamInstant::new(i64::MIN, 0);
-    let max = StreamInstant::new(i64::MAX, 0);
-    assert_eq!(
-        a.sub(Duration::from_secs(1)),
-        Some(StreamInstant::new(1, 0))
-    );
-    assert_eq!(
-        a.sub(Duration::from_secs(2)),
-        Some(StreamInstant::new(0, 0))
-    );
+fn test_config_support() {
+    let range = |channels, min, max, sample_format| SupportedStreamConfigRange {
+        buffer_size: SupportedBufferSize::Range { min: 64, max: 4096 },
+        channels,
+        min_sample_rate: SampleRate(min),
+        max_sample_rate: SampleRate(max),
+        sample_format,
+    };
+    let ranges = [
+        range(2, 44_100, 48_000, SampleFormat::I16),
+        range(6, 8_000, 96_000, SampleFormat::F32),
+    ];
+    let mut config = ranges[1].with_sample_rate(SampleRate(48_000)).config();
+    config.buffer_size = BufferSize::Fixed(256);
+    let check =
+        |config: &StreamConfig, sample_format| ConfigSupport::of(ranges, config, sample_format);
+    assert_eq!(check(&config, SampleFormat::F32), ConfigSupport::Supported);
     assert_eq!(
-        a.sub(Duration::from_secs(3)),
-        Some(StreamInstant::new(-1, 0))
+        check(&config, SampleFormat::I16),
+        ConfigSupport::Closest(ranges[0].with_sample_rate(SampleRate(48_000)))
     );
-    assert_eq!(min.sub(Duration::from_secs(1)), None);
+    config.sample_rate = SampleRate(192_000);
     assert_eq!(
-        b.add(Duration::from_secs(1)),
-        Some(StreamInstant::new(-1, 0))
+        check(&config, SampleFormat::F32),
+        ConfigSupport::Closest(ranges[1].with_sample_rate(SampleRate(96_000)))
     );
+    config.sample_rate = SampleRate(48_000);
+    config.buffer_size = BufferSize::Fixed(16);
     assert_eq!(
-        b.add(Duration::from_secs(2)),
-        Some(StreamInstant::new(0, 0))
+        check(&config, SampleFormat::F32),
+        ConfigSupport::Closest(ranges[1].with_sample_rate(SampleRate(48_000)))
     );
     assert_eq!(
-        b.add(Duration::from_secs(3)),
-        Some(StreamInstant::new(1, 0))
+        ConfigSupport::of([], &config, SampleFormat::F32),
+        ConfigSupport::Unsupported
     );
-    assert_eq!(max.add(Duration::from_secs(1)), None);
 }
@@ -7,6 +7,20 @@
 #[doc(inline)]
 pub use self::platform_impl::*;
 
+#[cfg(feature = "mock")]
+pub use crate::host::mock::{
+    Clock as MockClock, Device as MockDevice, Devices as MockDevices, Fault as MockFault,
+    Host as MockHost, Stream as MockStream, SupportedInputConfigs as MockSupportedInputConfigs,
+    SupportedOutputConfigs as MockSupportedOutputConfigs,
+};
+
+#[cfg(feature = "file")]
+pub use crate::host::file::{
+    Device as FileDevice, Devices as FileDevices, FileFormat, Host as FileHost,
+    Stream as FileStream, SupportedInputConfigs as FileSupportedInputConfigs,
+    SupportedOutputConfigs as FileSupportedOutputConfigs,
+};
+
 /// A macro to assist with implementing a platform's dynamically dispatched [`Host`] type.
 ///
 /// These dynamically dispatched types are necessary to allow for users to switch between hosts at
@@ -70,7 +84,19 @@ macro_rules! impl_platform_host {
         //
         // TODO: Confirm this and add more specific detail and references.
         #[must_use = "If the stream is not stored it will not play."]
-        pub struct Stream(StreamInner, crate::platform::NotSendSyncAcrossAllPlatforms);
+        pub struct Stream(
+            StreamInner,
+            crate::stats::StreamMonitor,
+            #[allow(dead_code)] crate::leaks::Live,
+            // The relay running the data callback, if the config asked for one.
+            #[allow(dead_code)] Option<std::sync::Arc<dyn crate::shutdown::Worker>>,
+            std::sync::Arc<crate::volume::Volume>,
+            std::sync::Arc<crate::convert::FormatChanges>,
+            // The callbacks of an output stream, to move it to another device.
+            Option<crate::swap::Swap>,
+            std::sync::Arc<crate::schedule::ScheduledStart>,
+            crate::platform::NotSendSyncAcrossAllPlatforms,
+        );
 
         /// The `SupportedInputConfigs` iterator associated with the platform's dynamically
         /// dispatched [`Host`] type.
@@ -167,6 +193,46 @@ macro_rules! impl_platform_host {
         }
 
         impl Device {
+            /// Build an output stream of the next generation of `swap` on this device.
+            #[allow(clippy::type_complexity)]
+            fn build_swap_stream(
+                &self,
+                swap: &crate::swap::Swap,
+                volume: &std::sync::Arc<crate::volume::Volume>,
+            ) -> Result<
+                (u64, StreamInner, HostId, Option<std::sync::Arc<dyn crate::shutdown::Worker>>),
+                crate::BuildStreamError,
+            > {
+                let (generation, data_callback, error_callback) = swap.callbacks();
+                let (data_callback, error_callback, relay) = crate::relay::output(
+                    &swap.config,
+                    swap.sample_format,
+                    data_callback,
+                    error_callback,
+                )?;
+                let data_callback = volume.output_callback(data_callback);
+                let (inner, host) = match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref d) => {
+                            let s = crate::traits::DeviceTrait::build_output_stream_raw(
+                                d,
+                                &swap.config,
+                                swap.sample_format,
+                                data_callback,
+                                error_callback,
+                                swap.timeout,
+                            )?;
+                            (StreamInner::$HostVariant(s), HostId::$HostVariant)
+                        }
+                    )*
+                };
+                if let Some(relay) = &relay {
+                    crate::shutdown::register_dyn(host, relay);
+                }
+                Ok((generation, inner, host, relay))
+            }
+
             /// Returns a reference to the underlying platform specific implementation of this
             /// `Device`.
             pub fn as_inner(&self) -> &DeviceInner {
@@ -196,6 +262,26 @@ macro_rules! impl_platform_host {
                 }
             }
 
+            /// The human-readable name of this `Host`, as in [`HostId::name`].
+            pub fn name(&self) -> &'static str {
+                self.id().name()
+            }
+
+            /// Stop the streams built from the devices of this host and join their threads,
+            /// including watchdogs, the most recently started first.
+            ///
+            /// Call this before the process exits, so no audio thread is still running while the
+            /// backend's libraries tear down their state. The streams stay valid but never call
+            /// their callbacks again, and playing them fails. Hosts without threads of their own
+            /// only have their watchdogs stopped.
+            ///
+            /// Must not be called from a callback of one of the streams, which would wait for
+            /// its own thread to exit.
+            pub fn shutdown(&self) {
+                crate::shutdown::shutdown(self.id());
+                crate::leaks::shut_down(self.id());
+            }
+
             /// Returns a reference to the underlying platform specific implementation of this
             /// `Host`.
             pub fn as_inner(&self) -> &HostInner {
@@ -215,6 +301,18 @@ macro_rules! impl_platform_host {
         }
 
         impl Stream {
+            /// Fails if the host of the stream was [shut down](Host::shutdown), which stopped the
+            /// stream for good.
+            fn check_host_running(&self) -> Result<(), crate::PlayStreamError> {
+                if self.2.is_shut_down() {
+                    return Err(crate::BackendSpecificError {
+                        description: "the host of the stream was shut down".to_string(),
+                    }
+                    .into());
+                }
+                Ok(())
+            }
+
             /// Returns a reference to the underlying platform specific implementation of this
             /// `Stream`.
             pub fn as_inner(&self) -> &StreamInner {
@@ -231,6 +329,273 @@ macro_rules! impl_platform_host {
             pub fn into_inner(self) -> StreamInner {
                 self.0
             }
+
+            /// Returns the runtime statistics of the stream.
+            ///
+            /// The counters are updated by the audio thread and can be read from any thread.
+            /// Streams converted from a platform specific stream rather than built through
+            /// [`Device`] have no counters and always report zeros.
+            pub fn stats(&self) -> crate::StreamStats {
+                self.1.stats()
+            }
+
+            /// The sample rate the device actually plays or captures at, measured from the
+            /// callback timestamps against the system clock, e.g. `48_000.5` for a device
+            /// running 10 ppm fast.
+            ///
+            /// The measurement spans the time since the stream last started, so it is `None` for
+            /// the first second and becomes more accurate the longer the stream runs. Like
+            /// [`stats`](Self::stats), this needs a stream built through [`Device`].
+            pub fn measured_sample_rate(&self) -> Option<f64> {
+                self.1.measured_sample_rate()
+            }
+
+            /// The deviation of the [measured](Self::measured_sample_rate) sample rate from the
+            /// rate of the stream's config in parts per million, positive when the device runs
+            /// fast, e.g. to drive adaptive resampling for network audio.
+            pub fn sample_rate_deviation_ppm(&self) -> Option<f64> {
+                self.1.sample_rate_deviation_ppm()
+            }
+
+            /// Call `callback` when the audio queued for playback by an output stream falls below
+            /// `low` frames or rises above `high` frames, or stop notifying with `None`.
+            ///
+            /// The queued audio is the period the data callback just rendered plus what the
+            /// device still holds, as reported by the callback timestamps. Each crossing is
+            /// reported once, until the level returns between the thresholds. The callback runs
+            /// on the audio thread right after the data callback, so it must not block, e.g. it
+            /// can wake a producer thread to top up its buffer. Like [`stats`](Self::stats), this
+            /// needs a stream built through [`Device`].
+            pub fn set_fill_level_callback<F>(&self, thresholds: Option<(u64, u64)>, callback: F)
+            where
+                F: FnMut(crate::FillLevel) + Send + 'static,
+            {
+                self.1.set_fill_level_callback(thresholds, Box::new(callback));
+            }
+
+            /// Start a watchdog that calls the error callback with
+            /// [`StreamError::Stalled`](crate::StreamError::Stalled) when the data callback has
+            /// not run for `periods` periods while the stream is playing, or stop it with `None`.
+            ///
+            /// This catches wedged drivers and data callbacks that block. The error is reported
+            /// once per stall. The watchdog runs on its own thread, so it is not available on
+            /// `wasm32-unknown-unknown`, and like [`stats`](Self::stats) it needs a stream built
+            /// through [`Device`].
+            pub fn set_watchdog(&self, periods: Option<u32>) {
+                self.1.set_watchdog(periods);
+            }
+
+            /// Set the software volume of an output stream, applied to the output of the data
+            /// callback: `1.0` leaves it unchanged (default) and `0.0` mutes it.
+            ///
+            /// Like [`stats`](Self::stats), the volume needs a stream built through [`Device`].
+            /// It has no effect on input streams.
+            pub fn set_volume(&self, volume: f32) {
+                self.4.set_volume(volume);
+            }
+
+            /// Set a gain for each channel of an output stream, applied on top of the
+            /// [volume](Self::set_volume). Channels without a gain in `gains` get `1.0`.
+            pub fn set_channel_gains(&self, gains: &[f32]) {
+                self.4.set_channel_gains(gains);
+            }
+
+            /// Set the balance between the first two channels of an output stream, from `-1.0`
+            /// for the left channel only to `1.0` for the right channel only, `0.0` by default.
+            ///
+            /// The channel towards which the balance leans keeps its full level. This replaces
+            /// the gains set with [`set_channel_gains`](Self::set_channel_gains).
+            pub fn set_balance(&self, balance: f32) {
+                self.4.set_balance(balance);
+            }
+
+            /// Schedule a change of the sample rate and channel count the data callback of an
+            /// output stream renders in, taking effect once the callback rendered `frame` frames
+            /// in total, e.g. at the boundary between two album tracks.
+            ///
+            /// The device keeps running in the stream's config: the output of the callback is
+            /// mapped to the stream's channels and resampled to the stream's rate, so playback
+            /// continues without a gap. The callback is passed a shorter buffer ending at `frame`,
+            /// so every buffer it fills is in a single format. A `frame` the callback already
+            /// passed takes effect with its next buffer. Like [`stats`](Self::stats), this needs
+            /// a stream built through [`Device`] and has no effect on input streams.
+            pub fn schedule_format_change(
+                &self,
+                frame: u64,
+                sample_rate: crate::SampleRate,
+                channels: crate::ChannelCount,
+            ) {
+                self.5.schedule(frame, sample_rate, channels);
+            }
+
+            /// Change the sample rate the data callback of an output stream renders in, starting
+            /// with the next buffer it fills, without rebuilding the stream.
+            ///
+            /// The device keeps running at the rate of the stream's config and the output of
+            /// the callback is resampled to it, see
+            /// [`schedule_format_change`](Self::schedule_format_change).
+            pub fn set_sample_rate(&self, sample_rate: crate::SampleRate) {
+                self.5.set_sample_rate(sample_rate);
+            }
+
+            /// Change the channel count the data callback of an output stream renders in,
+            /// starting with the next buffer it fills, without rebuilding the stream.
+            ///
+            /// The output of the callback is mapped to the channels of the stream's config: mono
+            /// is played on every channel, a mono stream plays the average of all channels, and
+            /// otherwise channels are matched by index, with the stream's extra channels silent.
+            pub fn set_channels(&self, channels: crate::ChannelCount) {
+                self.5.set_channels(channels);
+            }
+
+            /// Play an output stream with the first frame of its data callback played at
+            /// `start`, e.g. to begin a metronome or several streams on the same sample.
+            ///
+            /// `start` is on the clock of the stream's timestamps, e.g. the
+            /// [`playback`](crate::OutputStreamTimestamp::playback) timestamp of a callback plus
+            /// an offset. The stream plays silence until then without calling the data callback,
+            /// whose first buffer begins exactly at `start`. A `start` that has passed plays at
+            /// once, and [`play`](crate::traits::StreamTrait::play) drops a start that is still
+            /// pending. Like [`stats`](Self::stats), this needs a stream built through
+            /// [`Device`], on input streams it is the same as `play`.
+            pub fn play_at(&self, start: crate::StreamInstant) -> Result<(), crate::PlayStreamError> {
+                self.check_host_running()?;
+                self.7.set(start);
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        StreamInner::$HostVariant(ref s) => {
+                            crate::traits::StreamTrait::play(s)
+                        }
+                    )*
+                }?;
+                self.1.set_paused(false);
+                Ok(())
+            }
+
+            /// Play output streams of the same host so that their first frames are played at
+            /// the same time, e.g. the stems of a song.
+            ///
+            /// The streams play silence until each of them ran its data callback, and then
+            /// start on the same frame, one buffer of the slowest stream later, like
+            /// [`play_at`](Self::play_at). This blocks for up to a second while the streams
+            /// start; streams that don't run by then, and all streams on
+            /// `wasm32-unknown-unknown`, start right away. Fails if the streams are of different
+            /// hosts, whose clocks differ. Like [`stats`](Self::stats), this needs streams built
+            /// through [`Device`].
+            pub fn play_synchronized(streams: &[&Stream]) -> Result<(), crate::PlayStreamError> {
+                let host = |stream: &Stream| match stream.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        StreamInner::$HostVariant(_) => HostId::$HostVariant,
+                    )*
+                };
+                if let Some(first) = streams.first() {
+                    if streams.iter().any(|stream| host(stream) != host(first)) {
+                        return Err(crate::BackendSpecificError {
+                            description: "only streams of the same host can play in sync"
+                                .to_string(),
+                        }
+                        .into());
+                    }
+                }
+                for stream in streams {
+                    stream.check_host_running()?;
+                }
+                for stream in streams {
+                    stream.7.hold();
+                }
+                for stream in streams {
+                    let played = match stream.0 {
+                        $(
+                            $(#[cfg($feat)])?
+                            StreamInner::$HostVariant(ref s) => {
+                                crate::traits::StreamTrait::play(s)
+                            }
+                        )*
+                    };
+                    if let Err(err) = played {
+                        streams.iter().for_each(|stream| stream.7.cancel());
+                        return Err(err);
+                    }
+                    stream.1.set_paused(false);
+                }
+                let starts: Vec<_> = streams.iter().map(|stream| &*stream.7).collect();
+                crate::schedule::start_together(&starts);
+                Ok(())
+            }
+
+            /// Move an output stream to `device` while it plays, e.g. from a "switch output"
+            /// menu.
+            ///
+            /// A stream with the same config is built and, if this stream is playing, started on
+            /// `device`, playing silence until it takes over the data callback. Only then is the
+            /// stream on the old device released, so the gap is limited to the audio the old
+            /// device still had buffered. The stream keeps its volume, format changes and
+            /// statistics. Like [`stats`](Self::stats), this needs an output stream built through
+            /// [`Device`]. On error the stream keeps playing on its current device.
+            pub fn set_device(&mut self, device: &Device) -> Result<(), crate::BuildStreamError> {
+                let Some(swap) = &self.6 else {
+                    return Err(crate::BuildStreamError::BackendSpecific {
+                        err: crate::BackendSpecificError {
+                            description: "only output streams built through `Device` can move \
+                                          to another device"
+                                .to_string(),
+                        },
+                    });
+                };
+                let (generation, inner, host, relay) = device.build_swap_stream(swap, &self.4)?;
+                if !self.1.is_paused() {
+                    let played = match inner {
+                        $(
+                            $(#[cfg($feat)])?
+                            StreamInner::$HostVariant(ref s) => {
+                                crate::traits::StreamTrait::play(s)
+                            }
+                        )*
+                    };
+                    played.map_err(|err| match err {
+                        crate::PlayStreamError::DeviceNotAvailable => {
+                            crate::BuildStreamError::DeviceNotAvailable
+                        }
+                        crate::PlayStreamError::BackendSpecific { err } => {
+                            crate::BuildStreamError::BackendSpecific { err }
+                        }
+                    })?;
+                }
+                swap.activate(generation);
+                // Dropping the old stream releases the old device.
+                self.0 = inner;
+                self.3 = relay;
+                self.2 = crate::leaks::Live::new(host);
+                self.1.set_host(host);
+                self.1.clear_device_lost();
+                Ok(())
+            }
+
+            /// Move an output stream whose device was lost, e.g. unplugged, to the current default
+            /// output device of its host, keeping its config, volume and play state.
+            ///
+            /// The device counts as lost once the stream reported
+            /// [`StreamError::DeviceNotAvailable`](crate::StreamError::DeviceNotAvailable) to its
+            /// error callback. Call this e.g. from the application's main loop after such an
+            /// error. Returns whether the stream moved, so the application can tell the user
+            /// that the output changed, and does nothing while the device is still available. See
+            /// [`set_device`](Self::set_device) for the streams this works on.
+            pub fn recover(&mut self) -> Result<bool, crate::BuildStreamError> {
+                use crate::traits::HostTrait;
+                if !self.1.is_device_lost() {
+                    return Ok(false);
+                }
+                let device = self
+                    .1
+                    .host()
+                    .and_then(|id| host_from_id(id).ok())
+                    .and_then(|host| host.default_output_device())
+                    .ok_or(crate::BuildStreamError::DeviceNotAvailable)?;
+                self.set_device(&device)?;
+                Ok(true)
+            }
         }
 
         impl Iterator for Devices {
@@ -315,6 +680,44 @@ macro_rules! impl_platform_host {
                 }
             }
 
+            fn id(&self) -> Result<String, crate::DeviceNameError> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref d) => crate::traits::DeviceTrait::id(d),
+                    )*
+                }
+            }
+
+            fn capabilities(&self) -> crate::Capabilities {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref d) => d.capabilities(),
+                    )*
+                }
+            }
+
+            fn is_held_exclusively(&self) -> Option<bool> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref d) => d.is_held_exclusively(),
+                    )*
+                }
+            }
+
+            fn bit_perfect(&self) -> Option<Self> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref d) => d
+                            .bit_perfect()
+                            .map(|d| Device::from(DeviceInner::$HostVariant(d))),
+                    )*
+                }
+            }
+
             fn supported_input_configs(&self) -> Result<Self::SupportedInputConfigs, crate::SupportedStreamConfigsError> {
                 match self.0 {
                     $(
@@ -341,6 +744,32 @@ macro_rules! impl_platform_host {
                 }
             }
 
+            fn supported_input_configs_with_mode(&self, mode: crate::ShareMode) -> Result<Self::SupportedInputConfigs, crate::SupportedStreamConfigsError> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref d) => {
+                            d.supported_input_configs_with_mode(mode)
+                                .map(SupportedInputConfigsInner::$HostVariant)
+                                .map(SupportedInputConfigs)
+                        }
+                    )*
+                }
+            }
+
+            fn supported_output_configs_with_mode(&self, mode: crate::ShareMode) -> Result<Self::SupportedOutputConfigs, crate::SupportedStreamConfigsError> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref d) => {
+                            d.supported_output_configs_with_mode(mode)
+                                .map(SupportedOutputConfigsInner::$HostVariant)
+                                .map(SupportedOutputConfigs)
+                        }
+                    )*
+                }
+            }
+
             fn default_input_config(&self) -> Result<crate::SupportedStreamConfig, crate::DefaultStreamConfigError> {
                 match self.0 {
                     $(
@@ -359,6 +788,36 @@ macro_rules! impl_platform_host {
                 }
             }
 
+            fn check_input_config(
+                &self,
+                config: &crate::StreamConfig,
+                sample_format: crate::SampleFormat,
+            ) -> Result<crate::ConfigSupport, crate::SupportedStreamConfigsError> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref d) => {
+                            crate::traits::DeviceTrait::check_input_config(d, config, sample_format)
+                        }
+                    )*
+                }
+            }
+
+            fn check_output_config(
+                &self,
+                config: &crate::StreamConfig,
+                sample_format: crate::SampleFormat,
+            ) -> Result<crate::ConfigSupport, crate::SupportedStreamConfigsError> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref d) => {
+                            crate::traits::DeviceTrait::check_output_config(d, config, sample_format)
+                        }
+                    )*
+                }
+            }
+
             fn build_input_stream_raw<D, E>(
                 &self,
                 config: &crate::StreamConfig,
@@ -371,6 +830,13 @@ macro_rules! impl_platform_host {
                 D: FnMut(&crate::Data, &crate::InputCallbackInfo) + Send + 'static,
                 E: FnMut(crate::StreamError) + Send + 'static,
             {
+                let data_callback =
+                    crate::convert::input_channels(config, sample_format, data_callback)?;
+                let mut monitor = crate::stats::StreamMonitor::new(config);
+                let data_callback = monitor.input_callback(config.channels, data_callback);
+                let error_callback = monitor.error_callback(error_callback);
+                let (data_callback, error_callback, relay) =
+                    crate::relay::input(config, sample_format, data_callback, error_callback)?;
                 match self.0 {
                     $(
                         $(#[cfg($feat)])?
@@ -382,8 +848,26 @@ macro_rules! impl_platform_host {
                                 error_callback,
                                 timeout,
                             )
-                            .map(StreamInner::$HostVariant)
-                            .map(Stream::from),
+                            .map(|s| {
+                                monitor.set_host(HostId::$HostVariant);
+                                if let Some(relay) = &relay {
+                                    crate::shutdown::register_dyn(HostId::$HostVariant, relay);
+                                }
+                                let inner = StreamInner::$HostVariant(s);
+                                let live = crate::leaks::Live::new(HostId::$HostVariant);
+                                let (volume, changes, start) = Default::default();
+                                Stream(
+                                    inner,
+                                    monitor,
+                                    live,
+                                    relay,
+                                    volume,
+                                    changes,
+                                    None,
+                                    start,
+                                    Default::default(),
+                                )
+                            }),
                     )*
                 }
             }
@@ -400,21 +884,36 @@ macro_rules! impl_platform_host {
                 D: FnMut(&mut crate::Data, &crate::OutputCallbackInfo) + Send + 'static,
                 E: FnMut(crate::StreamError) + Send + 'static,
             {
-                match self.0 {
-                    $(
-                        $(#[cfg($feat)])?
-                        DeviceInner::$HostVariant(ref d) => d
-                            .build_output_stream_raw(
-                                config,
-                                sample_format,
-                                data_callback,
-                                error_callback,
-                                timeout,
-                            )
-                            .map(StreamInner::$HostVariant)
-                            .map(Stream::from),
-                    )*
-                }
+                let changes = std::sync::Arc::new(crate::convert::FormatChanges::default());
+                let data_callback = changes.output_callback(config, sample_format, data_callback);
+                let start = std::sync::Arc::new(crate::schedule::ScheduledStart::default());
+                let data_callback = start.output_callback(config, data_callback);
+                let mut monitor = crate::stats::StreamMonitor::new(config);
+                let data_callback = monitor.output_callback(config.channels, data_callback);
+                let error_callback = monitor.error_callback(error_callback);
+                let swap = crate::swap::Swap::new(
+                    config,
+                    sample_format,
+                    timeout,
+                    data_callback,
+                    error_callback,
+                );
+                let volume = crate::volume::Volume::new(config.channels);
+                let (_, inner, host, relay) = self.build_swap_stream(&swap, &volume)?;
+                monitor.set_host(host);
+                let live = crate::leaks::Live::new(host);
+                let swap = Some(swap);
+                Ok(Stream(
+                    inner,
+                    monitor,
+                    live,
+                    relay,
+                    volume,
+                    changes,
+                    swap,
+                    start,
+                    Default::default(),
+                ))
             }
         }
 
@@ -442,6 +941,9 @@ macro_rules! impl_platform_host {
             }
 
             fn default_input_device(&self) -> Option<Self::Device> {
+                if let Some(device) = device_from_env(self, true) {
+                    return Some(device);
+                }
                 match self.0 {
                     $(
                         $(#[cfg($feat)])?
@@ -453,6 +955,9 @@ macro_rules! impl_platform_host {
             }
 
             fn default_output_device(&self) -> Option<Self::Device> {
+                if let Some(device) = device_from_env(self, false) {
+                    return Some(device);
+                }
                 match self.0 {
                     $(
                         $(#[cfg($feat)])?
@@ -462,10 +967,61 @@ macro_rules! impl_platform_host {
                     )*
                 }
             }
+
+            fn default_input_device_for_role(&self, role: crate::DeviceRole) -> Option<Self::Device> {
+                if let Some(device) = device_from_env(self, true) {
+                    return Some(device);
+                }
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        HostInner::$HostVariant(ref h) => {
+                            crate::traits::HostTrait::default_input_device_for_role(h, role)
+                                .map(DeviceInner::$HostVariant)
+                                .map(Device::from)
+                        }
+                    )*
+                }
+            }
+
+            fn default_output_device_for_role(&self, role: crate::DeviceRole) -> Option<Self::Device> {
+                if let Some(device) = device_from_env(self, false) {
+                    return Some(device);
+                }
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        HostInner::$HostVariant(ref h) => {
+                            crate::traits::HostTrait::default_output_device_for_role(h, role)
+                                .map(DeviceInner::$HostVariant)
+                                .map(Device::from)
+                        }
+                    )*
+                }
+            }
+
+            fn capabilities(&self) -> crate::Capabilities {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        HostInner::$HostVariant(ref h) => h.capabilities(),
+                    )*
+                }
+            }
+
+            fn version(&self) -> Option<String> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        HostInner::$HostVariant(ref h) => h.version(),
+                    )*
+                }
+            }
         }
 
         impl crate::traits::StreamTrait for Stream {
             fn play(&self) -> Result<(), crate::PlayStreamError> {
+                self.check_host_running()?;
                 match self.0 {
                     $(
                         $(#[cfg($feat)])?
@@ -473,7 +1029,10 @@ macro_rules! impl_platform_host {
                             s.play()
                         }
                     )*
-                }
+                }?;
+                self.7.cancel();
+                self.1.set_paused(false);
+                Ok(())
             }
 
             fn pause(&self) -> Result<(), crate::PauseStreamError> {
@@ -484,7 +1043,22 @@ macro_rules! impl_platform_host {
                             s.pause()
                         }
                     )*
-                }
+                }?;
+                self.1.set_paused(true);
+                Ok(())
+            }
+
+            fn suspend(&self) -> Result<(), crate::PauseStreamError> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        StreamInner::$HostVariant(ref s) => {
+                            s.suspend()
+                        }
+                    )*
+                }?;
+                self.1.set_paused(true);
+                Ok(())
             }
         }
 
@@ -508,7 +1082,15 @@ macro_rules! impl_platform_host {
 
         impl From<StreamInner> for Stream {
             fn from(s: StreamInner) -> Self {
-                Stream(s, Default::default())
+                let id = match s {
+                    $(
+                        $(#[cfg($feat)])?
+                        StreamInner::$HostVariant(_) => HostId::$HostVariant,
+                    )*
+                };
+                let live = crate::leaks::Live::new(id);
+                let (monitor, volume, changes, start) = Default::default();
+                Stream(s, monitor, live, None, volume, changes, None, start, Default::default())
             }
         }
 
@@ -554,6 +1136,33 @@ macro_rules! impl_platform_host {
             host_ids
         }
 
+        /// The default host for the current compilation target platform.
+        ///
+        /// The `CPAL_HOST` environment variable overrides the default, e.g. `CPAL_HOST=jack`.
+        /// It is matched against the [`HostId::name`] of the hosts, ignoring case, and ignored if
+        /// no such host is available.
+        pub fn default_host() -> Host {
+            host_from_env().unwrap_or_else(platform_default_host)
+        }
+
+        fn host_from_env() -> Option<Host> {
+            let name = crate::platform::env_override(crate::platform::HOST_ENV_VAR)?;
+            let id = ALL_HOSTS.iter().find(|id| id.name().eq_ignore_ascii_case(&name))?;
+            host_from_id(*id).ok()
+        }
+
+        /// The device named by the `CPAL_DEVICE` environment variable, if the host has one.
+        fn device_from_env(host: &Host, input: bool) -> Option<Device> {
+            use crate::traits::{DeviceTrait, HostTrait};
+            let name = crate::platform::env_override(crate::platform::DEVICE_ENV_VAR)?;
+            let mut devices = if input {
+                host.input_devices().ok()?
+            } else {
+                host.output_devices().ok()?
+            };
+            devices.find(|d| d.name().map_or(false, |n| n == name))
+        }
+
         /// Given a unique host identifier, initialise and produce the host if it is available.
         pub fn host_from_id(id: HostId) -> Result<Host, crate::HostUnavailable> {
             match id {
@@ -570,7 +1179,6 @@ macro_rules! impl_platform_host {
     };
 }
 
-// TODO: Add pulseaudio and jack here eventually.
 #[cfg(any(
     target_os = "linux",
     target_os = "dragonfly",
@@ -579,9 +1187,10 @@ macro_rules! impl_platform_host {
 ))]
 mod platform_impl {
     pub use crate::host::alsa::{
-        Device as AlsaDevice, Devices as AlsaDevices, Host as AlsaHost, Stream as AlsaStream,
-        SupportedInputConfigs as AlsaSupportedInputConfigs,
-        SupportedOutputConfigs as AlsaSupportedOutputConfigs,
+        BusyPolicy as AlsaBusyPolicy, Device as AlsaDevice, Devices as AlsaDevices,
+        Host as AlsaHost, Stream as AlsaStream, StreamExt as AlsaStreamExt,
+        StreamStatus as AlsaStreamStatus, SupportedInputConfigs as AlsaSupportedInputConfigs,
+        SupportedOutputConfigs as AlsaSupportedOutputConfigs, XrunPolicy as AlsaXrunPolicy,
     };
     #[cfg(feature = "jack")]
     pub use crate::host::jack::{
@@ -589,11 +1198,67 @@ mod platform_impl {
         SupportedInputConfigs as JackSupportedInputConfigs,
         SupportedOutputConfigs as JackSupportedOutputConfigs,
     };
+    #[cfg(feature = "pulseaudio")]
+    pub use crate::host::pulseaudio::{
+        Device as PulseAudioDevice, Devices as PulseAudioDevices, Host as PulseAudioHost,
+        Stream as PulseAudioStream, SupportedInputConfigs as PulseAudioSupportedInputConfigs,
+        SupportedOutputConfigs as PulseAudioSupportedOutputConfigs,
+    };
 
-    impl_platform_host!(#[cfg(feature = "jack")] Jack jack "JACK", Alsa alsa "ALSA");
+    impl_platform_host!(
+        #[cfg(feature = "jack")] Jack jack "JACK",
+        #[cfg(feature = "pulseaudio")] PulseAudio pulseaudio "PulseAudio",
+        Alsa alsa "ALSA",
+        #[cfg(feature = "mock")] Mock mock "Mock",
+        #[cfg(feature = "file")] File file "File"
+    );
+
+    impl AlsaStreamExt for Stream {
+        fn status(&self) -> Result<AlsaStreamStatus, crate::StreamError> {
+            match self.as_inner() {
+                StreamInner::Alsa(s) => s.status(),
+                #[cfg(any(
+                    feature = "jack",
+                    feature = "pulseaudio",
+                    feature = "mock",
+                    feature = "file"
+                ))]
+                _ => Err(crate::BackendSpecificError {
+                    description: "stream is not an ALSA stream".to_owned(),
+                }
+                .into()),
+            }
+        }
+
+        fn pcm_name(&self) -> &str {
+            match self.as_inner() {
+                StreamInner::Alsa(s) => s.pcm_name(),
+                #[cfg(any(
+                    feature = "jack",
+                    feature = "pulseaudio",
+                    feature = "mock",
+                    feature = "file"
+                ))]
+                _ => "",
+            }
+        }
+
+        fn process_pending(&self, timeout: Option<std::time::Duration>) -> bool {
+            match self.as_inner() {
+                StreamInner::Alsa(s) => s.process_pending(timeout),
+                #[cfg(any(
+                    feature = "jack",
+                    feature = "pulseaudio",
+                    feature = "mock",
+                    feature = "file"
+                ))]
+                _ => false,
+            }
+        }
+    }
 
     /// The default host for the current compilation target platform.
-    pub fn default_host() -> Host {
+    fn platform_default_host() -> Host {
         AlsaHost::new()
             .expect("the default host should always be available")
             .into()
@@ -608,10 +1273,14 @@ mod platform_impl {
         SupportedOutputConfigs as CoreAudioSupportedOutputConfigs,
     };
 
-    impl_platform_host!(CoreAudio coreaudio "CoreAudio");
+    impl_platform_host!(
+        CoreAudio coreaudio "CoreAudio",
+        #[cfg(feature = "mock")] Mock mock "Mock",
+        #[cfg(feature = "file")] File file "File"
+    );
 
     /// The default host for the current compilation target platform.
-    pub fn default_host() -> Host {
+    fn platform_default_host() -> Host {
         CoreAudioHost::new()
             .expect("the default host should always be available")
             .into()
@@ -626,10 +1295,14 @@ mod platform_impl {
         SupportedOutputConfigs as EmscriptenSupportedOutputConfigs,
     };
 
-    impl_platform_host!(Emscripten emscripten "Emscripten");
+    impl_platform_host!(
+        Emscripten emscripten "Emscripten",
+        #[cfg(feature = "mock")] Mock mock "Mock",
+        #[cfg(feature = "file")] File file "File"
+    );
 
     /// The default host for the current compilation target platform.
-    pub fn default_host() -> Host {
+    fn platform_default_host() -> Host {
         EmscriptenHost::new()
             .expect("the default host should always be available")
             .into()
@@ -639,15 +1312,21 @@ mod platform_impl {
 #[cfg(all(target_arch = "wasm32", feature = "wasm-bindgen"))]
 mod platform_impl {
     pub use crate::host::webaudio::{
-        Device as WebAudioDevice, Devices as WebAudioDevices, Host as WebAudioHost,
-        Stream as WebAudioStream, SupportedInputConfigs as WebAudioSupportedInputConfigs,
+        enumerate_media_devices, request_microphone_permission, Device as WebAudioDevice,
+        Devices as WebAudioDevices, Host as WebAudioHost, MediaDevice as WebAudioMediaDevice,
+        MediaDeviceKind as WebAudioMediaDeviceKind, Stream as WebAudioStream,
+        StreamState as WebAudioStreamState, SupportedInputConfigs as WebAudioSupportedInputConfigs,
         SupportedOutputConfigs as WebAudioSupportedOutputConfigs,
     };
 
-    impl_platform_host!(WebAudio webaudio "WebAudio");
+    impl_platform_host!(
+        WebAudio webaudio "WebAudio",
+        #[cfg(feature = "mock")] Mock mock "Mock",
+        #[cfg(feature = "file")] File file "File"
+    );
 
     /// The default host for the current compilation target platform.
-    pub fn default_host() -> Host {
+    fn platform_default_host() -> Host {
         WebAudioHost::new()
             .expect("the default host should always be available")
             .into()
@@ -663,15 +1342,47 @@ mod platform_impl {
         SupportedOutputConfigs as AsioSupportedOutputConfigs,
     };
     pub use crate::host::wasapi::{
-        Device as WasapiDevice, Devices as WasapiDevices, Host as WasapiHost,
-        Stream as WasapiStream, SupportedInputConfigs as WasapiSupportedInputConfigs,
+        Device as WasapiDevice, DeviceWatcher as WasapiDeviceWatcher, Devices as WasapiDevices,
+        EnginePeriods as WasapiEnginePeriods, Host as WasapiHost, Stream as WasapiStream,
+        StreamExt as WasapiStreamExt, StreamLatency as WasapiStreamLatency,
+        StreamPosition as WasapiStreamPosition,
+        SupportedInputConfigs as WasapiSupportedInputConfigs,
         SupportedOutputConfigs as WasapiSupportedOutputConfigs,
     };
 
-    impl_platform_host!(#[cfg(feature = "asio")] Asio asio "ASIO", Wasapi wasapi "WASAPI");
+    impl_platform_host!(
+        #[cfg(feature = "asio")] Asio asio "ASIO",
+        Wasapi wasapi "WASAPI",
+        #[cfg(feature = "mock")] Mock mock "Mock",
+        #[cfg(feature = "file")] File file "File"
+    );
+
+    impl WasapiStreamExt for Stream {
+        fn position(&self) -> Result<WasapiStreamPosition, crate::StreamError> {
+            match self.as_inner() {
+                StreamInner::Wasapi(s) => s.position(),
+                #[cfg(any(feature = "asio", feature = "mock", feature = "file"))]
+                _ => Err(crate::BackendSpecificError {
+                    description: "stream is not a WASAPI stream".to_owned(),
+                }
+                .into()),
+            }
+        }
+
+        fn latency(&self) -> Result<WasapiStreamLatency, crate::StreamError> {
+            match self.as_inner() {
+                StreamInner::Wasapi(s) => s.latency(),
+                #[cfg(any(feature = "asio", feature = "mock", feature = "file"))]
+                _ => Err(crate::BackendSpecificError {
+                    description: "stream is not a WASAPI stream".to_owned(),
+                }
+                .into()),
+            }
+        }
+    }
 
     /// The default host for the current compilation target platform.
-    pub fn default_host() -> Host {
+    fn platform_default_host() -> Host {
         WasapiHost::new()
             .expect("the default host should always be available")
             .into()
@@ -686,10 +1397,14 @@ mod platform_impl {
         SupportedOutputConfigs as OboeSupportedOutputConfigs,
     };
 
-    impl_platform_host!(Oboe oboe "Oboe");
+    impl_platform_host!(
+        Oboe oboe "Oboe",
+        #[cfg(feature = "mock")] Mock mock "Mock",
+        #[cfg(feature = "file")] File file "File"
+    );
 
     /// The default host for the current compilation target platform.
-    pub fn default_host() -> Host {
+    fn platform_default_host() -> Host {
         OboeHost::new()
             .expect("the default host should always be available")
             .into()
@@ -715,16 +1430,37 @@ mod platform_impl {
         SupportedOutputConfigs as NullSupportedOutputConfigs,
     };
 
-    impl_platform_host!(Null null "Null");
+    impl_platform_host!(
+        Null null "Null",
+        #[cfg(feature = "mock")] Mock mock "Mock",
+        #[cfg(feature = "file")] File file "File"
+    );
 
     /// The default host for the current compilation target platform.
-    pub fn default_host() -> Host {
+    fn platform_default_host() -> Host {
         NullHost::new()
             .expect("the default host should always be available")
             .into()
     }
 }
 
+/// Environment variable naming the host returned by [`default_host`].
+const HOST_ENV_VAR: &str = "CPAL_HOST";
+
+/// Environment variable naming the device returned by the default device functions.
+const DEVICE_ENV_VAR: &str = "CPAL_DEVICE";
+
+/// Read an override from the environment, treating an empty value as unset.
+fn env_override(var: &str) -> Option<String> {
+    std::env::var(var).ok().filter(|value| !value.is_empty())
+}
+
+/// The device override from `CPAL_DEVICE`, for hosts that can open devices by name.
+#[allow(dead_code)]
+pub(crate) fn device_override() -> Option<String> {
+    env_override(DEVICE_ENV_VAR)
+}
+
 // The following zero-sized types are for applying Send/Sync restrictions to ensure
 // consistent behaviour across different platforms. These verbosely named types are used
 // (rather than using the markers directly) in the hope of making the compile errors
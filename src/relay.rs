@@ -0,0 +1,439 @@
+//! Running data callbacks on a thread of their own, see
+//! [`StreamConfig::callback_relay`](crate::StreamConfig::callback_relay).
+//!
+//! The backend's audio thread only copies samples to or from a bounded queue and never waits for
+//! the data callback. A slow callback lets the queue run dry, which plays silence or drops input,
+//! and a panicking one stops the relay thread, but neither can block the audio service's thread.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+
+use crate::shutdown::Worker;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use crate::BackendSpecificError;
+use crate::{
+    BuildStreamError, Data, InputCallbackInfo, OutputCallbackInfo, SampleFormat, StreamConfig,
+    StreamError,
+};
+
+type SharedErrorCallback = Arc<Mutex<dyn FnMut(StreamError) + Send>>;
+
+/// Where the backend's data callback sends the data.
+enum Target<D, I> {
+    /// The data callback runs on the backend's thread.
+    Direct(D),
+    /// The data callback runs on a relay thread.
+    Relay(Arc<Shared<I>>),
+}
+
+/// The state shared between the backend's thread and the relay thread.
+struct Shared<I> {
+    state: Mutex<State<I>>,
+    wake: Condvar,
+}
+
+struct State<I> {
+    /// The samples waiting to be played for output streams, or to be passed to the data callback
+    /// for input streams, as bytes.
+    queue: VecDeque<u8>,
+    /// The length in bytes and the callback info of each backend callback in `queue`, only used
+    /// by input streams.
+    chunks: VecDeque<(usize, I)>,
+    /// The number of bytes the backend asked for in its last callback, and its callback info.
+    /// Output streams render periods of this size.
+    period: Option<(usize, I)>,
+    /// The number of periods the queue holds at most.
+    periods: usize,
+    /// Set when the relay should stop, or stopped because the data callback panicked.
+    stopped: bool,
+    /// Whether an xrun was reported and the queue did not recover since.
+    xrun: bool,
+    /// Whether the queue has been filled once, so the output queue being empty at the start is
+    /// not reported as an xrun.
+    primed: bool,
+}
+
+/// The relay thread of a stream, stopped and joined when dropped.
+pub(crate) struct Relay<I> {
+    shared: Arc<Shared<I>>,
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    thread: Mutex<Option<std::thread::JoinHandle<()>>>,
+}
+
+/// Wrap the data and error callbacks of an output stream so the data callback runs on a relay
+/// thread if the config asks for one.
+///
+/// The returned relay must be kept alive as long as the stream.
+#[allow(clippy::type_complexity)]
+pub(crate) fn output<D, E>(
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    data_callback: D,
+    error_callback: E,
+) -> Result<
+    (
+        impl FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        impl FnMut(StreamError) + Send + 'static,
+        Option<Arc<dyn Worker>>,
+    ),
+    BuildStreamError,
+>
+where
+    D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let error_callback: SharedErrorCallback = Arc::new(Mutex::new(error_callback));
+    let (mut target, relay) = match config.callback_relay {
+        Some(periods) if Relay::<OutputCallbackInfo>::SUPPORTED => {
+            let relay = Relay::start(
+                config.thread_name("cpal_relay", ""),
+                periods,
+                error_callback.clone(),
+                move |shared| render(shared, sample_format, data_callback),
+            )?;
+            (Target::Relay(relay.shared.clone()), Some(relay))
+        }
+        _ => (Target::Direct(data_callback), None),
+    };
+    let report = error_callback.clone();
+    let data_callback = move |data: &mut Data, info: &OutputCallbackInfo| match &mut target {
+        Target::Direct(data_callback) => data_callback(data, info),
+        Target::Relay(shared) => {
+            if shared.pop_output(data, info) {
+                (report.lock().unwrap())(StreamError::Xrun);
+            }
+        }
+    };
+    let error_callback = move |err| (error_callback.lock().unwrap())(err);
+    Ok((
+        data_callback,
+        error_callback,
+        relay.map(|r| r as Arc<dyn Worker>),
+    ))
+}
+
+/// Wrap the data and error callbacks of an input stream so the data callback runs on a relay
+/// thread if the config asks for one.
+///
+/// The returned relay must be kept alive as long as the stream.
+#[allow(clippy::type_complexity)]
+pub(crate) fn input<D, E>(
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    data_callback: D,
+    error_callback: E,
+) -> Result<
+    (
+        impl FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        impl FnMut(StreamError) + Send + 'static,
+        Option<Arc<dyn Worker>>,
+    ),
+    BuildStreamError,
+>
+where
+    D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let error_callback: SharedErrorCallback = Arc::new(Mutex::new(error_callback));
+    let (mut target, relay) = match config.callback_relay {
+        Some(periods) if Relay::<InputCallbackInfo>::SUPPORTED => {
+            let relay = Relay::start(
+                config.thread_name("cpal_relay", ""),
+                periods,
+                error_callback.clone(),
+                move |shared| capture(shared, sample_format, data_callback),
+            )?;
+            (Target::Relay(relay.shared.clone()), Some(relay))
+        }
+        _ => (Target::Direct(data_callback), None),
+    };
+    let report = error_callback.clone();
+    let data_callback = move |data: &Data, info: &InputCallbackInfo| match &mut target {
+        Target::Direct(data_callback) => data_callback(data, info),
+        Target::Relay(shared) => {
+            if shared.push_input(data, info) {
+                (report.lock().unwrap())(StreamError::Xrun);
+            }
+        }
+    };
+    let error_callback = move |err| (error_callback.lock().unwrap())(err);
+    Ok((
+        data_callback,
+        error_callback,
+        relay.map(|r| r as Arc<dyn Worker>),
+    ))
+}
+
+impl<I: Clone + Send + 'static> Relay<I> {
+    /// Whether the platform has threads to relay on.
+    const SUPPORTED: bool = cfg!(not(all(target_arch = "wasm32", target_os = "unknown")));
+
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    fn start<F>(
+        thread_name: String,
+        periods: u32,
+        error_callback: SharedErrorCallback,
+        run: F,
+    ) -> Result<Arc<Self>, BuildStreamError>
+    where
+        F: FnOnce(&Shared<I>) + Send + 'static,
+    {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                chunks: VecDeque::with_capacity(periods.max(1) as usize),
+                period: None,
+                periods: periods.max(1) as usize,
+                stopped: false,
+                xrun: false,
+                primed: false,
+            }),
+            wake: Condvar::new(),
+        });
+        let thread_shared = shared.clone();
+        let thread = std::thread::Builder::new()
+            .name(thread_name)
+            .spawn(move || {
+                let panicked =
+                    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| run(&thread_shared)))
+                        .is_err();
+                if panicked {
+                    thread_shared.state.lock().unwrap().stopped = true;
+                    let err = BackendSpecificError {
+                        description: "the data callback panicked".to_owned(),
+                    };
+                    (error_callback.lock().unwrap())(err.into());
+                }
+            })
+            .map_err(|e| BackendSpecificError {
+                description: format!("failed to spawn the relay thread: {}", e),
+            })?;
+        Ok(Arc::new(Relay {
+            shared,
+            thread: Mutex::new(Some(thread)),
+        }))
+    }
+
+    /// There are no threads to relay on.
+    #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+    fn start<F>(
+        _thread_name: String,
+        _periods: u32,
+        _error_callback: SharedErrorCallback,
+        _run: F,
+    ) -> Result<Arc<Self>, BuildStreamError>
+    where
+        F: FnOnce(&Shared<I>) + Send + 'static,
+    {
+        unreachable!("relays are not supported on this platform")
+    }
+}
+
+impl Shared<OutputCallbackInfo> {
+    /// Fill `data` from the queue, or with silence if the queue is short or the relay thread
+    /// holds the lock. Returns whether a new xrun should be reported.
+    fn pop_output(&self, data: &mut Data, info: &OutputCallbackInfo) -> bool {
+        let mut state = match self.state.try_lock() {
+            Ok(state) => state,
+            Err(_) => {
+                data.fill_equilibrium();
+                return false;
+            }
+        };
+        let bytes = data.bytes_mut();
+        let len = bytes.len();
+        state.period = Some((len, info.clone()));
+        let report = if state.queue.len() >= len {
+            for (byte, queued) in bytes.iter_mut().zip(state.queue.drain(..len)) {
+                *byte = queued;
+            }
+            state.xrun = false;
+            false
+        } else {
+            data.fill_equilibrium();
+            let report = state.primed && !state.stopped && !state.xrun;
+            state.xrun = true;
+            report
+        };
+        drop(state);
+        self.wake.notify_one();
+        report
+    }
+}
+
+impl Shared<InputCallbackInfo> {
+    /// Queue `data` for the relay thread, or drop it if the queue is full or the relay thread
+    /// holds the lock. Returns whether a new xrun should be reported.
+    fn push_input(&self, data: &Data, info: &InputCallbackInfo) -> bool {
+        let mut state = match self.state.try_lock() {
+            Ok(state) => state,
+            Err(_) => return false,
+        };
+        let bytes = data.bytes();
+        state.period = Some((bytes.len(), info.clone()));
+        let report = if state.chunks.len() < state.periods {
+            state.queue.extend(bytes);
+            state.chunks.push_back((bytes.len(), info.clone()));
+            state.xrun = false;
+            false
+        } else {
+            let report = !state.stopped && !state.xrun;
+            state.xrun = true;
+            report
+        };
+        drop(state);
+        self.wake.notify_one();
+        report
+    }
+}
+
+/// Render periods into the queue of an output stream until the relay stops.
+fn render<D>(shared: &Shared<OutputCallbackInfo>, sample_format: SampleFormat, mut data_callback: D)
+where
+    D: FnMut(&mut Data, &OutputCallbackInfo),
+{
+    let mut buffer = Buffer::default();
+    loop {
+        let (len, info) = {
+            let mut state = shared.state.lock().unwrap();
+            loop {
+                if state.stopped {
+                    return;
+                }
+                if let Some((len, info)) = &state.period {
+                    if state.queue.len() + len <= len * state.periods {
+                        break (*len, info.clone());
+                    }
+                }
+                state = shared.wake.wait(state).unwrap();
+            }
+        };
+        let mut data = buffer.data(len, sample_format);
+        data_callback(&mut data, &info);
+        let mut state = shared.state.lock().unwrap();
+        state.queue.extend(data.bytes());
+        state.primed = true;
+    }
+}
+
+/// Pass the queued input of a stream to the data callback until the relay stops.
+fn capture<D>(shared: &Shared<InputCallbackInfo>, sample_format: SampleFormat, mut data_callback: D)
+where
+    D: FnMut(&Data, &InputCallbackInfo),
+{
+    let mut buffer = Buffer::default();
+    loop {
+        let (data, info) = {
+            let mut state = shared.state.lock().unwrap();
+            let (len, info) = loop {
+                if state.stopped {
+                    return;
+                }
+                if let Some(chunk) = state.chunks.pop_front() {
+                    break chunk;
+                }
+                state = shared.wake.wait(state).unwrap();
+            };
+            let mut data = buffer.data(len, sample_format);
+            for (byte, queued) in data.bytes_mut().iter_mut().zip(state.queue.drain(..len)) {
+                *byte = queued;
+            }
+            (data, info)
+        };
+        data_callback(&data, &info);
+    }
+}
+
+/// A buffer for the data passed to the data callback, aligned for every sample format.
+#[derive(Debug, Default)]
+pub(crate) struct Buffer(Vec<u64>);
+
+impl Buffer {
+    /// Reserve room for `bytes` bytes, so [`data`](Self::data) doesn't allocate up to that size.
+    pub(crate) fn reserve(&mut self, bytes: usize) {
+        let word = std::mem::size_of::<u64>();
+        let words = (bytes + word - 1) / word;
+        self.0.reserve(words.saturating_sub(self.0.len()));
+    }
+
+    pub(crate) fn data(&mut self, bytes: usize, sample_format: SampleFormat) -> Data {
+        let word = std::mem::size_of::<u64>();
+        let words = (bytes + word - 1) / word;
+        self.0.resize(words, 0);
+        let len = bytes / sample_format.sample_size();
+        // The buffer holds at least `bytes` bytes and is aligned for all sample types.
+        unsafe { Data::from_parts(self.0.as_mut_ptr() as *mut (), len, sample_format) }
+    }
+}
+
+impl<I> Relay<I> {
+    fn halt(&self) {
+        self.shared.state.lock().unwrap().stopped = true;
+        self.shared.wake.notify_all();
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<I: Send> Worker for Relay<I> {
+    fn stop(&self) {
+        self.halt();
+    }
+}
+
+impl<I> Drop for Relay<I> {
+    fn drop(&mut self) {
+        self.halt();
+    }
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn test_output_relay() {
+    use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+    let host = crate::platform::MockHost::new().unwrap();
+    let device = crate::Device::from(host.default_output_device().unwrap());
+    let config = device
+        .default_output_config()
+        .unwrap()
+        .config()
+        .with_callback_relay(2);
+    let mut next = 0.0;
+    let stream = device
+        .build_output_stream(
+            &config,
+            move |data: &mut [f32], _: &OutputCallbackInfo| {
+                for sample in data.iter_mut() {
+                    next += 1.0;
+                    *sample = next;
+                }
+            },
+            // The test renders faster than real time, so the relay may run dry.
+            |_| (),
+            None,
+        )
+        .unwrap();
+    stream.play().unwrap();
+    let crate::platform::StreamInner::Mock(mock) = stream.as_inner() else {
+        unreachable!()
+    };
+    // The relay only learns the period from the first callback, which plays silence.
+    assert_eq!(mock.render::<f32>(4), Some(vec![0.0; 8]));
+    let mut rendered = Vec::new();
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    while rendered.len() < 8 {
+        assert!(
+            std::time::Instant::now() < deadline,
+            "the relay thread rendered nothing within 10 seconds"
+        );
+        let samples = mock.render::<f32>(4).unwrap();
+        if samples[0] != 0.0 {
+            rendered = samples;
+        }
+        std::thread::yield_now();
+    }
+    assert_eq!(rendered, (1..=8).map(|s| s as f32).collect::<Vec<_>>());
+}
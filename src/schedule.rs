@@ -0,0 +1,211 @@
+//! Starting output streams at a given time, see [`Stream::play_at`](crate::Stream::play_at), and
+//! together, see [`Stream::play_synchronized`](crate::Stream::play_synchronized).
+//!
+//! The start is applied to the output of the data callback on the audio thread: until the
+//! buffer that contains the start is played, the stream plays silence without calling the data
+//! callback. That buffer is silent up to the start and the data callback fills the rest of it.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::{Data, OutputCallbackInfo, OutputStreamTimestamp, StreamConfig, StreamInstant};
+
+/// How long [`start_together`] waits for the held streams to run.
+const HOLD_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The scheduled start of a stream, shared with its audio thread.
+#[derive(Debug, Default)]
+pub(crate) struct ScheduledStart {
+    /// Whether `start` is set, so the audio thread only locks while a start is pending.
+    pending: AtomicBool,
+    start: Mutex<Option<Start>>,
+    /// The end of the latest buffer played silent while held, and the length of that buffer.
+    held: Mutex<Option<(StreamInstant, Duration)>>,
+    /// Whether a data callback was wrapped, which is only the case for output streams.
+    attached: AtomicBool,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Start {
+    /// Play silence until the start is set.
+    Hold,
+    At(StreamInstant),
+}
+
+impl ScheduledStart {
+    pub(crate) fn set(&self, start: StreamInstant) {
+        *self.start.lock().unwrap() = Some(Start::At(start));
+        self.pending.store(true, Ordering::Release);
+    }
+
+    /// Play silence until [`start_together`] sets the start.
+    pub(crate) fn hold(&self) {
+        *self.held.lock().unwrap() = None;
+        *self.start.lock().unwrap() = Some(Start::Hold);
+        self.pending.store(true, Ordering::Release);
+    }
+
+    /// Drop the pending start, if any, so the stream plays right away.
+    pub(crate) fn cancel(&self) {
+        if self.pending.load(Ordering::Acquire) {
+            *self.start.lock().unwrap() = None;
+            self.pending.store(false, Ordering::Release);
+        }
+    }
+
+    /// Wrap an output data callback to play silence until the scheduled start.
+    pub(crate) fn output_callback<D>(
+        self: &Arc<Self>,
+        config: &StreamConfig,
+        mut data_callback: D,
+    ) -> impl FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+    {
+        self.attached.store(true, Ordering::Relaxed);
+        let scheduled = self.clone();
+        let channels = config.channels.max(1) as usize;
+        let sample_rate = config.sample_rate.0 as u128;
+        move |data, info| {
+            if !scheduled.pending.load(Ordering::Acquire) {
+                return data_callback(data, info);
+            }
+            // The audio thread never waits for the stream's thread. A start is being scheduled,
+            // so this buffer is played before it.
+            let Ok(mut start) = scheduled.start.try_lock() else {
+                return data.fill_equilibrium();
+            };
+            let playback = info.timestamp().playback;
+            let frames = data.len() / channels;
+            let at = match *start {
+                None => return data_callback(data, info),
+                Some(Start::At(at)) => at,
+                Some(Start::Hold) => {
+                    drop(start);
+                    data.fill_equilibrium();
+                    let nanos = frames as u128 * 1_000_000_000 / sample_rate.max(1);
+                    let length = Duration::from_nanos(nanos as u64);
+                    if let (Some(end), Ok(mut held)) =
+                        (playback.add(length), scheduled.held.try_lock())
+                    {
+                        *held = Some((end, length));
+                    }
+                    return;
+                }
+            };
+            let silent = match at.duration_since(&playback) {
+                Some(delay) => {
+                    ((delay.as_nanos() * sample_rate + 500_000_000) / 1_000_000_000) as usize
+                }
+                None => 0,
+            };
+            if silent >= frames {
+                return data.fill_equilibrium();
+            }
+            *start = None;
+            scheduled.pending.store(false, Ordering::Release);
+            drop(start);
+            if silent == 0 {
+                return data_callback(data, info);
+            }
+
+            let sample_format = data.sample_format();
+            let offset = silent * channels;
+            let samples = data.bytes_mut().as_mut_ptr();
+            // The silence and the rest of the buffer are disjoint parts of `data`.
+            let (mut silence, mut rest) = unsafe {
+                (
+                    Data::from_parts(samples as *mut (), offset, sample_format),
+                    Data::from_parts(
+                        samples.add(offset * sample_format.sample_size()) as *mut (),
+                        data.len() - offset,
+                        sample_format,
+                    ),
+                )
+            };
+            silence.fill_equilibrium();
+            let info = OutputCallbackInfo {
+                timestamp: OutputStreamTimestamp {
+                    callback: info.timestamp().callback,
+                    playback: at,
+                },
+            };
+            data_callback(&mut rest, &info);
+        }
+    }
+}
+
+/// Start held streams on the same frame, one buffer of the slowest stream after the latest of
+/// them ran, once all of them ran. Streams that don't run within [`HOLD_TIMEOUT`] start at once.
+pub(crate) fn start_together(starts: &[&ScheduledStart]) {
+    let starts: Vec<_> = starts
+        .iter()
+        .filter(|start| start.attached.load(Ordering::Relaxed))
+        .collect();
+    // The audio callbacks of `wasm32-unknown-unknown` run on this thread, so they can't be
+    // waited for.
+    if cfg!(all(target_arch = "wasm32", target_os = "unknown")) || starts.is_empty() {
+        starts.iter().for_each(|start| start.cancel());
+        return;
+    }
+    let deadline = std::time::Instant::now() + HOLD_TIMEOUT;
+    while std::time::Instant::now() < deadline {
+        let held: Option<Vec<_>> = starts
+            .iter()
+            .map(|start| *start.held.lock().unwrap())
+            .collect();
+        if let Some(held) = held {
+            let end = held.iter().map(|&(end, _)| end).max();
+            let length = held.iter().map(|&(_, length)| length).max();
+            if let Some(at) = end.zip(length).and_then(|(end, length)| end.add(length)) {
+                for start in &starts {
+                    start.set(at);
+                }
+                return;
+            }
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    starts.iter().for_each(|start| start.cancel());
+}
+
+#[test]
+fn test_scheduled_start() {
+    use crate::{SampleFormat, SampleRate, StreamConfig};
+
+    let config = StreamConfig {
+        channels: 1,
+        sample_rate: SampleRate(1_000),
+        ..StreamConfig::default()
+    };
+    let scheduled = Arc::new(ScheduledStart::default());
+    let mut callback = scheduled.output_callback(&config, |data, info| {
+        assert_eq!(info.timestamp().playback, StreamInstant::new(0, 14_000_000));
+        data.as_slice_mut::<f32>().unwrap().fill(1.0);
+    });
+    scheduled.set(StreamInstant::new(0, 14_000_000));
+    let mut render = |playback_millis: u32| {
+        let mut samples = [0.5f32; 10];
+        let mut data = unsafe {
+            Data::from_parts(
+                samples.as_mut_ptr() as *mut (),
+                samples.len(),
+                SampleFormat::F32,
+            )
+        };
+        let playback = StreamInstant::new(0, playback_millis * 1_000_000);
+        let info = OutputCallbackInfo {
+            timestamp: OutputStreamTimestamp {
+                callback: playback,
+                playback,
+            },
+        };
+        callback(&mut data, &info);
+        samples
+    };
+    assert_eq!(render(0), [0.0; 10]);
+    let samples = render(10);
+    assert_eq!(samples[..4], [0.0; 4]);
+    assert_eq!(samples[4..], [1.0; 6]);
+}
@@ -0,0 +1,132 @@
+//! Ordered shutdown of the threads of a host, see [`Host::shutdown`](crate::Host::shutdown).
+//!
+//! Backends register the threads they spawn for a stream as a [`Worker`]. The registry only
+//! keeps weak references, so a worker is forgotten when the stream owning it is dropped.
+
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::HostId;
+
+/// A thread that is stopped and joined when its host shuts down.
+pub(crate) trait Worker: Send + Sync {
+    /// Stop the thread and wait for it to exit. Does nothing if it already stopped, as the owner
+    /// stops it again when dropped.
+    fn stop(&self);
+}
+
+static WORKERS: Mutex<Vec<(HostId, Weak<dyn Worker>)>> = Mutex::new(Vec::new());
+
+/// Register a worker of `host`, so that shutting down the host stops it while it is alive.
+pub(crate) fn register<W: Worker + 'static>(host: HostId, worker: &Arc<W>) {
+    let worker: Arc<dyn Worker> = worker.clone();
+    register_dyn(host, &worker);
+}
+
+/// Like [`register`], for workers whose type was erased.
+pub(crate) fn register_dyn(host: HostId, worker: &Arc<dyn Worker>) {
+    let mut workers = WORKERS.lock().unwrap_or_else(|e| e.into_inner());
+    workers.retain(|(_, worker)| worker.strong_count() > 0);
+    workers.push((host, Arc::downgrade(worker)));
+}
+
+/// Stop the live workers of `host`, the most recently registered first. Watchdogs are started
+/// after the streams they watch, so they stop before they could report the stream stalling.
+pub(crate) fn shutdown(host: HostId) {
+    let stopping: Vec<_> = {
+        let mut workers = WORKERS.lock().unwrap_or_else(|e| e.into_inner());
+        let (stopping, rest) = workers.drain(..).partition(|(id, _)| *id == host);
+        *workers = rest;
+        stopping
+    };
+    // The lock is released so workers can be dropped, and others registered, while stopping.
+    for (_, worker) in stopping.into_iter().rev() {
+        if let Some(worker) = worker.upgrade() {
+            worker.stop();
+        }
+    }
+}
+
+/// Held for writing by tests that shut down the file host, and for reading by tests whose file
+/// streams must not be stopped by them.
+#[cfg(all(test, feature = "file"))]
+pub(crate) static FILE_HOST_TESTS: std::sync::RwLock<()> = std::sync::RwLock::new(());
+
+#[cfg(feature = "file")]
+#[test]
+fn test_shutdown() {
+    use std::time::{Duration, Instant};
+
+    use crate::traits::{DeviceTrait, StreamTrait};
+
+    let _tests = FILE_HOST_TESTS.write().unwrap_or_else(|e| e.into_inner());
+    let host = crate::host_from_id(HostId::File).unwrap();
+    let (errors, received) = std::sync::mpsc::channel();
+    let build = |name: &str| {
+        let path = std::env::temp_dir().join(format!("cpal-{}-{}", std::process::id(), name));
+        let device = crate::Device::from(crate::platform::FileDevice::new(&path));
+        let config = device.default_output_config().unwrap().config();
+        let errors = errors.clone();
+        let stream = device
+            .build_output_stream(
+                &config,
+                |_: &mut [f32], _: &crate::OutputCallbackInfo| (),
+                move |err| errors.send(err).unwrap(),
+                None,
+            )
+            .unwrap();
+        stream.play().unwrap();
+        // About 100 ms, so a busy test machine doesn't look stalled.
+        stream.set_watchdog(Some(10));
+        (path, stream)
+    };
+    let streams = [build("shutdown-1.wav"), build("shutdown-2.wav")];
+    assert_eq!(crate::live_streams(HostId::File), 2);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while streams
+        .iter()
+        .any(|(_, stream)| stream.stats().callbacks == 0)
+    {
+        assert!(Instant::now() < deadline, "the streams didn't start");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    host.shutdown();
+    assert_eq!(crate::live_streams(HostId::File), 0);
+    let callbacks: Vec<_> = streams.iter().map(|(_, s)| s.stats().callbacks).collect();
+    std::thread::sleep(Duration::from_millis(300));
+    // The threads were joined, and the watchdogs stopped before they saw the streams stall.
+    assert_eq!(
+        streams
+            .iter()
+            .map(|(_, s)| s.stats().callbacks)
+            .collect::<Vec<_>>(),
+        callbacks
+    );
+    if let Ok(err) = received.try_recv() {
+        panic!("unexpected error: {}", err);
+    }
+    for (path, stream) in &streams {
+        // The threads finished the files on their way out.
+        let bytes = std::fs::read(path).unwrap();
+        let data_len = u32::from_le_bytes(bytes[40..44].try_into().unwrap());
+        assert_eq!(data_len as usize, bytes.len() - 44);
+        assert!(data_len > 0);
+        assert!(matches!(
+            stream.play(),
+            Err(crate::PlayStreamError::BackendSpecific { .. })
+        ));
+    }
+    let stream_refs: Vec<_> = streams.iter().map(|(_, s)| s).collect();
+    assert!(crate::Stream::play_synchronized(&stream_refs).is_err());
+
+    // Streams built after the shutdown play as usual.
+    let (path, stream) = build("shutdown-3.wav");
+    assert_eq!(crate::live_streams(HostId::File), 1);
+    drop(stream);
+    assert_eq!(crate::live_streams(HostId::File), 0);
+    std::fs::remove_file(path).unwrap();
+    for (path, stream) in streams {
+        drop(stream);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,662 @@
+//! Runtime statistics and the watchdog of streams.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::drift::RateMeter;
+use crate::shutdown::Worker;
+use crate::{
+    Data, HostId, InputCallbackInfo, OutputCallbackInfo, StreamConfig, StreamError, StreamInstant,
+};
+
+/// A pause between callbacks after which the rate is measured anew, as the stream was paused or
+/// stalled rather than playing frames.
+const RATE_GAP: Duration = Duration::from_millis(500);
+
+/// A snapshot of the counters of a stream, see [`Stream::stats`](crate::Stream::stats).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamStats {
+    /// The number of times the data callback ran.
+    pub callbacks: u64,
+    /// The number of frames passed to or from the data callback.
+    pub frames: u64,
+    /// The number of [`StreamError::Xrun`] errors reported.
+    pub xruns: u64,
+    /// The longest time the data callback took to return.
+    ///
+    /// Always zero on `wasm32-unknown-unknown`, where there is no clock to measure it with.
+    pub max_callback_duration: Duration,
+    /// The average amount of audio buffered between the data callback and the device, that is
+    /// the time from the callback to playback for output streams and from capture to the
+    /// callback for input streams. `None` until a callback with valid timestamps ran.
+    pub average_buffered: Option<Duration>,
+    /// The number of frames of the latest buffer passed to the data callback, that is the
+    /// period the host actually uses for a requested [`BufferSize`](crate::BufferSize). Zero
+    /// until the data callback ran.
+    pub buffer_frames: u64,
+}
+
+/// A crossing of a threshold of the audio queued for playback, see
+/// [`Stream::set_fill_level_callback`](crate::Stream::set_fill_level_callback).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum FillLevel {
+    /// Fewer frames than the low threshold are queued.
+    Below { frames: u64 },
+    /// More frames than the high threshold are queued.
+    Above { frames: u64 },
+}
+
+type SharedErrorCallback = Arc<Mutex<dyn FnMut(StreamError) + Send>>;
+
+/// The thresholds of the fill level and the callback notified when the level crosses them.
+struct FillWatch {
+    low: u64,
+    high: u64,
+    /// The latest crossing reported, so each is reported once.
+    reported: Option<FillLevel>,
+    callback: Box<dyn FnMut(FillLevel) + Send>,
+}
+
+/// Watches the callbacks of a platform stream: counts them for [`StreamStats`] and lets a
+/// watchdog report when they stop.
+#[derive(Default)]
+pub(crate) struct StreamMonitor {
+    counters: Arc<StreamCounters>,
+    sample_rate: u32,
+    /// The host of the stream, whose shutdown stops the watchdog.
+    host: Option<HostId>,
+    watchdog_thread_name: String,
+    /// The error callback of the stream, shared with the watchdog. `None` for streams that were
+    /// not built through the monitor.
+    error_callback: Option<SharedErrorCallback>,
+    watchdog: Mutex<Option<Arc<Watchdog>>>,
+}
+
+/// The counters behind [`StreamStats`], updated from the audio thread.
+#[derive(Debug, Default)]
+struct StreamCounters {
+    callbacks: AtomicU64,
+    frames: AtomicU64,
+    xruns: AtomicU64,
+    max_callback_nanos: AtomicU64,
+    buffered_nanos: AtomicU64,
+    buffered_samples: AtomicU64,
+    buffer_frames: AtomicU64,
+    paused: AtomicBool,
+    /// Whether the stream reported `DeviceNotAvailable` since it last moved to a device.
+    device_lost: AtomicBool,
+    /// The measured sample rate as `f64` bits, zero until measured.
+    measured_rate: AtomicU64,
+    fill_watch: Mutex<Option<FillWatch>>,
+}
+
+impl std::fmt::Debug for FillWatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FillWatch")
+            .field("low", &self.low)
+            .field("high", &self.high)
+            .finish_non_exhaustive()
+    }
+}
+
+impl StreamMonitor {
+    pub(crate) fn new(config: &StreamConfig) -> Self {
+        StreamMonitor {
+            sample_rate: config.sample_rate.0,
+            watchdog_thread_name: config.thread_name("cpal_watchdog", ""),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn set_host(&mut self, host: HostId) {
+        self.host = Some(host);
+    }
+
+    pub(crate) fn stats(&self) -> StreamStats {
+        self.counters.snapshot()
+    }
+
+    /// Called after the stream was played or paused, so the watchdog ignores paused streams.
+    pub(crate) fn set_paused(&self, paused: bool) {
+        self.counters.paused.store(paused, Ordering::Relaxed);
+    }
+
+    /// See [`Stream::measured_sample_rate`](crate::Stream::measured_sample_rate).
+    pub(crate) fn measured_sample_rate(&self) -> Option<f64> {
+        match self.counters.measured_rate.load(Ordering::Relaxed) {
+            0 => None,
+            bits => Some(f64::from_bits(bits)),
+        }
+    }
+
+    /// The deviation of the measured from the nominal sample rate in parts per million.
+    pub(crate) fn sample_rate_deviation_ppm(&self) -> Option<f64> {
+        if self.sample_rate == 0 {
+            return None;
+        }
+        Some((self.measured_sample_rate()? / self.sample_rate as f64 - 1.0) * 1e6)
+    }
+
+    /// See [`Stream::set_fill_level_callback`](crate::Stream::set_fill_level_callback).
+    pub(crate) fn set_fill_level_callback(
+        &self,
+        thresholds: Option<(u64, u64)>,
+        callback: Box<dyn FnMut(FillLevel) + Send>,
+    ) {
+        *self.counters.fill_watch.lock().unwrap() = thresholds.map(|(low, high)| FillWatch {
+            low,
+            high: high.max(low),
+            reported: None,
+            callback,
+        });
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.counters.paused.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn host(&self) -> Option<HostId> {
+        self.host
+    }
+
+    pub(crate) fn is_device_lost(&self) -> bool {
+        self.counters.device_lost.load(Ordering::Relaxed)
+    }
+
+    /// Called after the stream moved to another device.
+    pub(crate) fn clear_device_lost(&self) {
+        self.counters.device_lost.store(false, Ordering::Relaxed);
+    }
+
+    /// Start or stop the watchdog, see [`Stream::set_watchdog`](crate::Stream::set_watchdog).
+    pub(crate) fn set_watchdog(&self, periods: Option<u32>) {
+        let mut watchdog = self.watchdog.lock().unwrap();
+        // Stop the old watchdog before starting the new one.
+        if let Some(watchdog) = watchdog.take() {
+            watchdog.stop();
+        }
+        if let (Some(periods), Some(error_callback)) = (periods, &self.error_callback) {
+            *watchdog = Watchdog::start(
+                self.watchdog_thread_name.clone(),
+                self.counters.clone(),
+                self.sample_rate,
+                periods.max(1),
+                error_callback.clone(),
+            );
+            if let (Some(host), Some(watchdog)) = (self.host, &*watchdog) {
+                crate::shutdown::register(host, watchdog);
+            }
+        }
+    }
+
+    /// Wrap an input data callback to update the counters.
+    pub(crate) fn input_callback<D>(
+        &self,
+        channels: u16,
+        mut data_callback: D,
+    ) -> impl FnMut(&Data, &InputCallbackInfo) + Send + 'static
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+    {
+        let counters = self.counters.clone();
+        let mut rate = RateMeter::default();
+        move |data, info| {
+            let timer = Timer::start();
+            data_callback(data, info);
+            let timestamp = info.timestamp();
+            let frames = frames(data, channels);
+            counters.record_callback(
+                frames,
+                timestamp.callback.duration_since(&timestamp.capture),
+                timer.elapsed(),
+            );
+            counters.record_rate(&mut rate, timestamp.capture, frames);
+        }
+    }
+
+    /// Wrap an output data callback to update the counters.
+    pub(crate) fn output_callback<D>(
+        &self,
+        channels: u16,
+        mut data_callback: D,
+    ) -> impl FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+    {
+        let counters = self.counters.clone();
+        let sample_rate = self.sample_rate as u64;
+        let mut rate = RateMeter::default();
+        move |data, info| {
+            let timer = Timer::start();
+            data_callback(data, info);
+            let timestamp = info.timestamp();
+            let frames = frames(data, channels);
+            let buffered = timestamp.playback.duration_since(&timestamp.callback);
+            counters.record_callback(frames, buffered, timer.elapsed());
+            counters.record_rate(&mut rate, timestamp.playback, frames);
+            if let Some(buffered) = buffered {
+                // The period just rendered is queued on top of what the device still holds.
+                let queued = buffered.as_nanos() as u64 * sample_rate / 1_000_000_000 + frames;
+                counters.record_fill_level(queued);
+            }
+        }
+    }
+
+    /// Wrap an error callback to count xruns and share it with the watchdog.
+    pub(crate) fn error_callback<E>(
+        &mut self,
+        error_callback: E,
+    ) -> impl FnMut(StreamError) + Send + 'static
+    where
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let counters = self.counters.clone();
+        let error_callback: SharedErrorCallback = Arc::new(Mutex::new(error_callback));
+        self.error_callback = Some(error_callback.clone());
+        move |err| {
+            counters.record_error(&err);
+            (error_callback.lock().unwrap())(err);
+        }
+    }
+}
+
+impl StreamCounters {
+    fn snapshot(&self) -> StreamStats {
+        let buffered_samples = self.buffered_samples.load(Ordering::Relaxed);
+        let average_buffered = (buffered_samples > 0).then(|| {
+            Duration::from_nanos(self.buffered_nanos.load(Ordering::Relaxed) / buffered_samples)
+        });
+        StreamStats {
+            callbacks: self.callbacks.load(Ordering::Relaxed),
+            frames: self.frames.load(Ordering::Relaxed),
+            xruns: self.xruns.load(Ordering::Relaxed),
+            max_callback_duration: Duration::from_nanos(
+                self.max_callback_nanos.load(Ordering::Relaxed),
+            ),
+            average_buffered,
+            buffer_frames: self.buffer_frames.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record_callback(&self, frames: u64, buffered: Option<Duration>, duration: Duration) {
+        self.callbacks.fetch_add(1, Ordering::Relaxed);
+        self.frames.fetch_add(frames, Ordering::Relaxed);
+        self.buffer_frames.store(frames, Ordering::Relaxed);
+        if let Some(buffered) = buffered {
+            self.buffered_nanos
+                .fetch_add(buffered.as_nanos() as u64, Ordering::Relaxed);
+            self.buffered_samples.fetch_add(1, Ordering::Relaxed);
+        }
+        self.max_callback_nanos
+            .fetch_max(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Record a callback of `frames` frames played or captured at `instant` with the rate meter
+    /// of the audio thread, and publish the rate.
+    fn record_rate(&self, rate: &mut RateMeter, instant: StreamInstant, frames: u64) {
+        if rate.since_latest(instant).is_some_and(|gap| gap > RATE_GAP) {
+            *rate = RateMeter::default();
+        }
+        rate.record(instant, frames as usize);
+        let bits = rate.rate().map_or(0, f64::to_bits);
+        self.measured_rate.store(bits, Ordering::Relaxed);
+    }
+
+    /// Notify the fill level callback if `queued` frames cross one of its thresholds. A
+    /// callback being replaced is skipped rather than waited for.
+    fn record_fill_level(&self, queued: u64) {
+        let Ok(mut watch) = self.fill_watch.try_lock() else {
+            return;
+        };
+        let Some(watch) = watch.as_mut() else {
+            return;
+        };
+        let level = if queued < watch.low {
+            FillLevel::Below { frames: queued }
+        } else if queued > watch.high {
+            FillLevel::Above { frames: queued }
+        } else {
+            watch.reported = None;
+            return;
+        };
+        let discriminant = std::mem::discriminant(&level);
+        let crossed = watch.reported.as_ref().map(std::mem::discriminant) != Some(discriminant);
+        if crossed {
+            watch.reported = Some(level);
+            (watch.callback)(level);
+        }
+    }
+
+    fn record_error(&self, err: &StreamError) {
+        match err {
+            StreamError::Xrun => {
+                self.xruns.fetch_add(1, Ordering::Relaxed);
+            }
+            StreamError::DeviceNotAvailable => self.device_lost.store(true, Ordering::Relaxed),
+            _ => (),
+        }
+    }
+}
+
+fn frames(data: &Data, channels: u16) -> u64 {
+    (data.len() / channels.max(1) as usize) as u64
+}
+
+/// Measures how long a callback takes, where the platform has a clock.
+struct Timer {
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    start: std::time::Instant,
+}
+
+impl Timer {
+    fn start() -> Self {
+        Timer {
+            #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+            start: std::time::Instant::now(),
+        }
+    }
+
+    fn elapsed(&self) -> Duration {
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        return self.start.elapsed();
+        #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+        return Duration::ZERO;
+    }
+}
+
+/// A thread that reports [`StreamError::Stalled`] when the data callback stops running for a
+/// number of periods while the stream is playing.
+///
+/// The period is the average number of frames per callback so far, so the watchdog only starts
+/// watching after the first callback.
+struct Watchdog {
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    stop: Mutex<Option<std::sync::mpsc::Sender<()>>>,
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    thread: Mutex<Option<std::thread::JoinHandle<()>>>,
+}
+
+impl Watchdog {
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    fn start(
+        thread_name: String,
+        counters: Arc<StreamCounters>,
+        sample_rate: u32,
+        periods: u32,
+        error_callback: SharedErrorCallback,
+    ) -> Option<Arc<Self>> {
+        use std::sync::mpsc::{self, RecvTimeoutError};
+
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = std::thread::Builder::new()
+            .name(thread_name)
+            .spawn(move || {
+                let mut last_callbacks = counters.callbacks.load(Ordering::Relaxed);
+                let mut idle_periods = 0;
+                let mut reported = false;
+                loop {
+                    let callbacks = counters.callbacks.load(Ordering::Relaxed);
+                    let period = match counters
+                        .frames
+                        .load(Ordering::Relaxed)
+                        .checked_div(callbacks)
+                    {
+                        Some(frames) if sample_rate > 0 => {
+                            Duration::from_secs_f64(frames as f64 / sample_rate as f64)
+                        }
+                        _ => Duration::from_millis(10),
+                    }
+                    .max(Duration::from_millis(1));
+                    match stopped.recv_timeout(period) {
+                        Err(RecvTimeoutError::Timeout) => (),
+                        _ => return,
+                    }
+                    let now = counters.callbacks.load(Ordering::Relaxed);
+                    if now != last_callbacks || now == 0 || counters.paused.load(Ordering::Relaxed)
+                    {
+                        last_callbacks = now;
+                        idle_periods = 0;
+                        reported = false;
+                        continue;
+                    }
+                    idle_periods += 1;
+                    if idle_periods >= periods && !reported {
+                        reported = true;
+                        (error_callback.lock().unwrap())(StreamError::Stalled);
+                    }
+                }
+            })
+            .ok()?;
+        Some(Arc::new(Watchdog {
+            stop: Mutex::new(Some(stop)),
+            thread: Mutex::new(Some(thread)),
+        }))
+    }
+
+    /// There are no threads to run the watchdog on.
+    #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+    fn start(
+        _thread_name: String,
+        _counters: Arc<StreamCounters>,
+        _sample_rate: u32,
+        _periods: u32,
+        _error_callback: SharedErrorCallback,
+    ) -> Option<Arc<Self>> {
+        None
+    }
+}
+
+impl Worker for Watchdog {
+    fn stop(&self) {
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        {
+            // Disconnecting the channel wakes the thread up.
+            self.stop.lock().unwrap().take();
+            if let Some(thread) = self.thread.lock().unwrap().take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Build a playing stream on a mock device with `latency` and `clock`, and return it with the
+/// errors it reports.
+#[cfg(all(test, feature = "mock"))]
+fn mock_output_stream(
+    latency: Duration,
+    clock: Option<crate::platform::MockClock>,
+) -> (crate::Stream, std::sync::mpsc::Receiver<StreamError>) {
+    use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+    let mut device = crate::platform::MockHost::new()
+        .unwrap()
+        .default_output_device()
+        .unwrap();
+    device.set_latency(latency);
+    device.set_clock(clock);
+    let device = crate::Device::from(device);
+    let config = device.default_output_config().unwrap().config();
+    let (errors, received) = std::sync::mpsc::channel();
+    let stream = device
+        .build_output_stream(
+            &config,
+            |_: &mut [f32], _: &OutputCallbackInfo| (),
+            move |err| errors.send(err).unwrap(),
+            None,
+        )
+        .unwrap();
+    stream.play().unwrap();
+    (stream, received)
+}
+
+/// Call the data callback of a stream from [`mock_output_stream`] for `frames` frames.
+#[cfg(all(test, feature = "mock"))]
+fn render(stream: &crate::Stream, frames: usize) -> &crate::platform::MockStream {
+    let crate::platform::StreamInner::Mock(mock) = stream.as_inner() else {
+        unreachable!()
+    };
+    mock.render::<f32>(frames).unwrap();
+    mock
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn test_stats() {
+    let (stream, errors) = mock_output_stream(Duration::from_millis(5), None);
+    assert_eq!(stream.stats(), StreamStats::default());
+    render(&stream, 480).schedule_fault(960, crate::platform::MockFault::Underrun);
+    render(&stream, 480);
+    // The underrun skips the data callback.
+    render(&stream, 480);
+    render(&stream, 480).inject_error(StreamError::Xrun);
+
+    let stats = stream.stats();
+    assert_eq!(stats.callbacks, 3);
+    assert_eq!(stats.frames, 3 * 480);
+    assert_eq!(stats.xruns, 2);
+    assert_eq!(stats.average_buffered, Some(Duration::from_millis(5)));
+    assert!(stats.max_callback_duration < Duration::from_secs(1));
+    assert_eq!(errors.try_iter().count(), 2);
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn test_watchdog() {
+    use crate::traits::StreamTrait;
+
+    // Callbacks of 480 frames at 48 kHz make for 10 ms periods.
+    let (stream, errors) = mock_output_stream(Duration::ZERO, None);
+    stream.set_watchdog(Some(10));
+    let deadline = std::time::Instant::now() + Duration::from_millis(300);
+    while std::time::Instant::now() < deadline {
+        render(&stream, 480);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert!(
+        errors.try_recv().is_err(),
+        "the watchdog fired while running"
+    );
+
+    // A paused stream isn't stalled.
+    stream.pause().unwrap();
+    std::thread::sleep(Duration::from_millis(300));
+    assert!(
+        errors.try_recv().is_err(),
+        "the watchdog fired while paused"
+    );
+
+    stream.play().unwrap();
+    render(&stream, 480);
+    let err = errors.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert!(matches!(err, StreamError::Stalled));
+    // A stall is reported once.
+    assert!(errors.recv_timeout(Duration::from_millis(300)).is_err());
+    stream.set_watchdog(None);
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn test_measured_sample_rate() {
+    // Without a clock, the mock stream's time advances exactly with the frames it plays.
+    let (stream, _errors) = mock_output_stream(Duration::ZERO, None);
+    for _ in 0..100 {
+        render(&stream, 480);
+    }
+    // The callbacks span 990 ms, not yet enough to measure.
+    assert_eq!(stream.measured_sample_rate(), None);
+    render(&stream, 480);
+    assert_eq!(stream.measured_sample_rate(), Some(48_000.0));
+    assert_eq!(stream.sample_rate_deviation_ppm(), Some(0.0));
+
+    // A device playing 480 frames in 9.9995 ms runs 50 ppm fast.
+    let clock = crate::platform::MockClock::new();
+    let (stream, _errors) = mock_output_stream(Duration::ZERO, Some(clock.clone()));
+    for _ in 0..=200 {
+        render(&stream, 480);
+        clock.advance(Duration::from_nanos(9_999_500));
+    }
+    let rate = stream.measured_sample_rate().unwrap();
+    assert!((rate - 48_002.4).abs() < 0.01, "{}", rate);
+    let ppm = stream.sample_rate_deviation_ppm().unwrap();
+    assert!((ppm - 50.0).abs() < 0.1, "{}", ppm);
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn test_fill_level_callback() {
+    // 5 ms of latency at 48 kHz hold 240 frames on top of each rendered period.
+    let (stream, _errors) = mock_output_stream(Duration::from_millis(5), None);
+    let levels = Arc::new(Mutex::new(Vec::new()));
+    let reported = levels.clone();
+    stream.set_fill_level_callback(Some((800, 1000)), move |level| {
+        reported.lock().unwrap().push(level)
+    });
+    for frames in [480, 480, 1024, 1024, 600, 480] {
+        render(&stream, frames);
+    }
+    assert_eq!(
+        *levels.lock().unwrap(),
+        [
+            FillLevel::Below { frames: 720 },
+            FillLevel::Above { frames: 1264 },
+            FillLevel::Below { frames: 720 },
+        ]
+    );
+
+    stream.set_fill_level_callback(None, |_| unreachable!());
+    render(&stream, 480);
+    assert_eq!(levels.lock().unwrap().len(), 3);
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn test_buffer_frames() {
+    let (stream, _errors) = mock_output_stream(Duration::ZERO, None);
+    render(&stream, 480);
+    assert_eq!(stream.stats().buffer_frames, 480);
+    render(&stream, 256);
+    assert_eq!(stream.stats().buffer_frames, 256);
+}
+
+#[cfg(feature = "file")]
+#[test]
+fn test_buffer_frames_of_fixed_buffer_size() {
+    use crate::traits::{DeviceTrait, StreamTrait};
+
+    let _tests = crate::shutdown::FILE_HOST_TESTS
+        .read()
+        .unwrap_or_else(|e| e.into_inner());
+    let path = std::env::temp_dir().join(format!("cpal-{}-buffer-frames.raw", std::process::id()));
+    let mut device = crate::platform::FileDevice::new(&path);
+    device.set_real_time(false);
+    device.set_frame_limit(Some(4 * 64));
+    let device = crate::Device::from(device);
+    let mut config = device.default_output_config().unwrap().config();
+    config.buffer_size = crate::BufferSize::Fixed(64);
+    let stream = device
+        .build_output_stream(
+            &config,
+            |_: &mut [f32], _: &OutputCallbackInfo| (),
+            |err| panic!("{}", err),
+            None,
+        )
+        .unwrap();
+    stream.play().unwrap();
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    while stream.stats().frames < 4 * 64 {
+        assert!(std::time::Instant::now() < deadline, "the stream stopped");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let stats = stream.stats();
+    assert_eq!(stats.callbacks, 4);
+    assert_eq!(stats.buffer_frames, 64);
+    drop(stream);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,128 @@
+//! Moving an output stream to another device while it plays, see
+//! [`Stream::set_device`](crate::platform::Stream::set_device).
+//!
+//! The data callback is shared by the streams built on each device. Only the stream of the
+//! active generation runs it, the others play silence, so a new device can be started and
+//! prefilled before it takes over and the old one is released.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::{Data, OutputCallbackInfo, SampleFormat, StreamConfig, StreamError};
+
+type SharedDataCallback = Mutex<Box<dyn FnMut(&mut Data, &OutputCallbackInfo) + Send>>;
+type SharedErrorCallback = Arc<Mutex<dyn FnMut(StreamError) + Send>>;
+
+/// The callbacks and config of an output stream, to build it again on another device.
+pub(crate) struct Swap {
+    pub(crate) config: StreamConfig,
+    pub(crate) sample_format: SampleFormat,
+    pub(crate) timeout: Option<Duration>,
+    shared: Arc<Shared>,
+    error_callback: SharedErrorCallback,
+}
+
+struct Shared {
+    data_callback: SharedDataCallback,
+    /// The generation of the stream that runs the data callback.
+    active: AtomicU64,
+    /// The generation of the latest stream.
+    latest: AtomicU64,
+}
+
+impl Swap {
+    pub(crate) fn new<D, E>(
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        timeout: Option<Duration>,
+        data_callback: D,
+        error_callback: E,
+    ) -> Self
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        Swap {
+            config: config.clone(),
+            sample_format,
+            timeout,
+            shared: Arc::new(Shared {
+                data_callback: Mutex::new(Box::new(data_callback)),
+                active: AtomicU64::new(1),
+                latest: AtomicU64::new(0),
+            }),
+            error_callback: Arc::new(Mutex::new(error_callback)),
+        }
+    }
+
+    /// The callbacks for the stream of the next generation, which plays silence until it is
+    /// [activated](Self::activate). The first generation is active from the start.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn callbacks(
+        &self,
+    ) -> (
+        u64,
+        impl FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        impl FnMut(StreamError) + Send + 'static,
+    ) {
+        let generation = self.shared.latest.fetch_add(1, Ordering::Relaxed) + 1;
+        let shared = self.shared.clone();
+        let data_callback = move |data: &mut Data, info: &OutputCallbackInfo| {
+            if shared.active.load(Ordering::Acquire) != generation {
+                data.fill_equilibrium();
+                return;
+            }
+            // Only the previous stream can hold the lock, while it finishes its last callback.
+            match shared.data_callback.try_lock() {
+                Ok(mut data_callback) => data_callback(data, info),
+                Err(_) => data.fill_equilibrium(),
+            }
+        };
+        let error_callback = self.error_callback.clone();
+        let error_callback = move |err| (error_callback.lock().unwrap())(err);
+        (generation, data_callback, error_callback)
+    }
+
+    /// Hand the data callback to the stream of `generation`.
+    pub(crate) fn activate(&self, generation: u64) {
+        self.shared.active.store(generation, Ordering::Release);
+    }
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn test_set_device() {
+    use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+    let host = crate::platform::MockHost::new().unwrap();
+    let device = crate::Device::from(host.default_output_device().unwrap());
+    let config = device.default_output_config().unwrap().config();
+    let mut next = 0.0;
+    let mut stream = device
+        .build_output_stream(
+            &config,
+            move |data: &mut [f32], _: &OutputCallbackInfo| {
+                for sample in data.iter_mut() {
+                    next += 1.0;
+                    *sample = next;
+                }
+            },
+            |_| (),
+            None,
+        )
+        .unwrap();
+    stream.play().unwrap();
+    let render = |stream: &crate::Stream| {
+        let crate::platform::StreamInner::Mock(mock) = stream.as_inner() else {
+            unreachable!()
+        };
+        mock.render::<f32>(2).unwrap()
+    };
+    let channels = config.channels as usize;
+    assert_eq!(render(&stream)[0], 1.0);
+    stream.set_device(&device).unwrap();
+    // The data callback continues where it left off on the new stream.
+    let rendered = render(&stream);
+    assert_eq!(rendered[0], (2 * channels + 1) as f32);
+}
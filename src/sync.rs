@@ -0,0 +1,269 @@
+//! Playing one stream on several output devices in sync, see [`SyncedOutput`].
+
+use std::cell::Cell;
+use std::sync::{Arc, Mutex};
+
+use crate::drift::DriftQueue;
+use crate::traits::{DeviceTrait, StreamTrait};
+use crate::{
+    BuildStreamError, Device, FromSample, OutputCallbackInfo, PauseStreamError, PlayStreamError,
+    SizedSample, Stream, StreamConfig, StreamError,
+};
+
+/// One output stream played on several devices at once, e.g. for multi-room playback.
+///
+/// The data callback renders the audio once and every device plays it. The first device is the
+/// leader: its callback drives the data callback. The other devices run on clocks of their own,
+/// which drift apart from the leader's, so their audio is resampled slightly to stay aligned
+/// with it. All devices play the audio the same, fixed, time after it was rendered, compensating
+/// for the output latencies the devices report.
+///
+/// The devices start together with [`play`](Self::play). Until a device has received enough
+/// audio from the leader it plays silence, so the first moments of audio may be missing on the
+/// followers. If the leader stops, the followers run dry and play silence.
+///
+/// More followers can be attached while the output plays with
+/// [`add_device`](Self::add_device), e.g. to play on HDMI in addition to headphones. Each device
+/// has its own [volume](Stream::set_volume) through [`streams`](Self::streams).
+#[must_use = "If the streams are not stored they will not play."]
+pub struct SyncedOutput {
+    streams: Vec<Stream>,
+    shared: Arc<Mutex<Shared>>,
+    /// Builds the stream of a follower attached after `build`.
+    add_follower: AddFollower,
+    playing: Cell<bool>,
+}
+
+type AddFollower = Box<dyn Fn(&Device, usize) -> Result<Stream, BuildStreamError>>;
+
+struct Shared {
+    /// One queue per device, the leader's first.
+    queues: Vec<DriftQueue>,
+    /// The largest period of any device in frames, which the queues have to hold in addition to
+    /// their target.
+    max_period: usize,
+    /// The output latency of each device as last reported, in frames.
+    latencies: Vec<usize>,
+}
+
+impl SyncedOutput {
+    /// Build an output stream on each of `devices` with the same config.
+    ///
+    /// The error callback receives the index of the device whose stream failed. Fails if
+    /// `devices` is empty or any stream cannot be built.
+    pub fn build<T, D, E>(
+        devices: &[Device],
+        config: &StreamConfig,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Self, BuildStreamError>
+    where
+        T: SizedSample + FromSample<f32> + Send + 'static,
+        f32: FromSample<T>,
+        D: FnMut(&mut [T], &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(usize, StreamError) + Send + 'static,
+    {
+        if devices.is_empty() {
+            return Err(BuildStreamError::InvalidArgument);
+        }
+        let channels = config.channels as usize;
+        let shared = Arc::new(Mutex::new(Shared {
+            queues: devices.iter().map(|_| DriftQueue::new(channels)).collect(),
+            max_period: 0,
+            latencies: vec![0; devices.len()],
+        }));
+        let error_callback = Arc::new(Mutex::new(error_callback));
+        let leader = build_stream::<T, _, _>(
+            &devices[0],
+            config,
+            0,
+            &shared,
+            Some(data_callback),
+            &error_callback,
+        )?;
+        let mut streams = vec![leader];
+        for (index, device) in devices.iter().enumerate().skip(1) {
+            streams.push(build_stream::<T, D, _>(
+                device,
+                config,
+                index,
+                &shared,
+                None,
+                &error_callback,
+            )?);
+        }
+        let follower_config = config.clone();
+        let follower_shared = shared.clone();
+        let add_follower: AddFollower = Box::new(move |device: &Device, index| {
+            build_stream::<T, D, _>(
+                device,
+                &follower_config,
+                index,
+                &follower_shared,
+                None,
+                &error_callback,
+            )
+        });
+        Ok(SyncedOutput {
+            streams,
+            shared,
+            add_follower,
+            playing: Cell::new(false),
+        })
+    }
+
+    /// Attach `device` as another follower and return its index, starting its stream if the
+    /// output is playing.
+    ///
+    /// The device plays silence until it has received enough audio from the leader and then
+    /// joins the other devices, aligned to them as well as its latency allows.
+    pub fn add_device(&mut self, device: &Device) -> Result<usize, BuildStreamError> {
+        let index = self.streams.len();
+        {
+            let mut shared = self.shared.lock().unwrap();
+            let channels = shared.queues[0].channels();
+            shared.queues.push(DriftQueue::new(channels));
+            shared.latencies.push(0);
+        }
+        let stream = (self.add_follower)(device, index).and_then(|stream| {
+            if self.playing.get() {
+                stream.play().map_err(|err| match err {
+                    PlayStreamError::DeviceNotAvailable => BuildStreamError::DeviceNotAvailable,
+                    PlayStreamError::BackendSpecific { err } => {
+                        BuildStreamError::BackendSpecific { err }
+                    }
+                })?;
+            }
+            Ok(stream)
+        });
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                let mut shared = self.shared.lock().unwrap();
+                shared.queues.pop();
+                shared.latencies.pop();
+                return Err(err);
+            }
+        };
+        self.streams.push(stream);
+        Ok(index)
+    }
+
+    /// Start the streams of all devices, the followers first so they are ready for the audio
+    /// the leader renders.
+    pub fn play(&self) -> Result<(), PlayStreamError> {
+        for stream in self.streams.iter().rev() {
+            stream.play()?;
+        }
+        self.playing.set(true);
+        Ok(())
+    }
+
+    /// Pause the streams of all devices, the leader first.
+    pub fn pause(&self) -> Result<(), PauseStreamError> {
+        for stream in &self.streams {
+            stream.pause()?;
+        }
+        self.playing.set(false);
+        Ok(())
+    }
+
+    /// The streams of the devices, in the order of the devices passed to
+    /// [`build`](Self::build) followed by those attached with [`add_device`](Self::add_device).
+    pub fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+
+    /// The rate at which each device currently reads the leader's audio relative to its own
+    /// clock, in the order of the devices. `1.0` for the leader; a follower whose clock runs
+    /// slower than the leader's reads slightly faster, above `1.0`.
+    pub fn rate_ratios(&self) -> Vec<f64> {
+        let shared = self.shared.lock().unwrap();
+        shared.queues.iter().map(DriftQueue::ratio).collect()
+    }
+}
+
+impl Shared {
+    /// Keep the audio of every device a common delay behind the leader's rendering: two of the
+    /// largest periods, less the device's own output latency.
+    fn update_targets(&mut self) {
+        let max_latency = self.latencies.iter().copied().max().unwrap_or(0);
+        let base = 2 * self.max_period;
+        for (queue, latency) in self.queues.iter_mut().zip(&self.latencies) {
+            queue.set_target(base + max_latency - latency);
+        }
+    }
+
+    /// The most frames a queue holds before the oldest are dropped, e.g. because its device
+    /// stopped.
+    fn max_frames(&self) -> usize {
+        let max_target = self.queues.iter().map(DriftQueue::target).max();
+        2 * (max_target.unwrap_or(0) + self.max_period)
+    }
+}
+
+/// Build the stream of the device at `index`, which renders with `data_callback` if it is the
+/// leader.
+fn build_stream<T, D, E>(
+    device: &Device,
+    config: &StreamConfig,
+    index: usize,
+    shared: &Arc<Mutex<Shared>>,
+    mut data_callback: Option<D>,
+    error_callback: &Arc<Mutex<E>>,
+) -> Result<Stream, BuildStreamError>
+where
+    T: SizedSample + FromSample<f32> + Send + 'static,
+    f32: FromSample<T>,
+    D: FnMut(&mut [T], &OutputCallbackInfo) + Send + 'static,
+    E: FnMut(usize, StreamError) + Send + 'static,
+{
+    let channels = config.channels as usize;
+    let sample_rate = config.sample_rate.0 as u64;
+    let shared = shared.clone();
+    let mut rendered: Vec<T> = Vec::new();
+    let mut samples: Vec<f32> = Vec::new();
+    let error_callback = error_callback.clone();
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], info: &OutputCallbackInfo| {
+            let mut shared = shared.lock().unwrap();
+            let frames = data.len() / channels.max(1);
+            shared.max_period = shared.max_period.max(frames);
+            let timestamp = info.timestamp();
+            if let Some(latency) = timestamp.playback.duration_since(&timestamp.callback) {
+                shared.latencies[index] =
+                    (latency.as_nanos() as u64 * sample_rate / 1_000_000_000) as usize;
+            }
+            shared.update_targets();
+            if let Some(data_callback) = &mut data_callback {
+                // Render in periods of the leader until its own queue holds this period
+                // on top of its target.
+                let max_frames = shared.max_frames();
+                while shared.queues[0].frames() < frames + shared.queues[0].target() {
+                    rendered.clear();
+                    rendered.resize(data.len(), T::EQUILIBRIUM);
+                    data_callback(&mut rendered, info);
+                    for queue in &mut shared.queues {
+                        queue.push(rendered.iter().map(|&s| f32::from_sample_(s)), max_frames);
+                    }
+                }
+            }
+            samples.clear();
+            samples.resize(data.len(), 0.0);
+            if index == 0 {
+                shared.queues[0].pop_exact(&mut samples);
+            } else {
+                shared.queues[index].pop_resampled(&mut samples);
+            }
+            for (sample, &value) in data.iter_mut().zip(&samples) {
+                *sample = T::from_sample_(value);
+            }
+        },
+        move |err| (error_callback.lock().unwrap())(index, err),
+        None,
+    )?;
+    // Start all devices together in `play`.
+    let _ = stream.pause();
+    Ok(stream)
+}
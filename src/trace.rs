@@ -0,0 +1,138 @@
+//! Tracing of native backend calls, for diagnosing driver specific misbehavior.
+//!
+//! With the `api-trace` feature, the backends record their native calls with [`trace_call!`]
+//! into a ring buffer, which [`take_api_trace`] drains. Recording only formats the call and
+//! takes a short lock, so tracing barely slows down the audio thread. Without the feature the
+//! macro evaluates to the call alone.
+
+/// Evaluate a native call and, with the `api-trace` feature, record it along with the `Debug`
+/// output of its arguments and result.
+///
+/// The arguments are formatted before the call, so they may be moved into it. For results
+/// that aren't `Debug`, name the result and give an expression to format instead, as in
+/// `trace_call!("snd_pcm_open", (name,), PCM::new(..), |r| r.as_ref().map(|_| ()))`.
+///
+/// Only the ALSA and WASAPI backends trace their calls so far.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    windows
+))]
+macro_rules! trace_call {
+    ($name:expr, $args:expr, $call:expr) => {
+        $crate::trace::trace_call!($name, $args, $call, |result| result)
+    };
+    ($name:expr, $args:expr, $call:expr, |$result:ident| $summary:expr) => {{
+        #[cfg(feature = "api-trace")]
+        let args = format!("{:?}", $args);
+        let result = $call;
+        #[cfg(feature = "api-trace")]
+        {
+            let $result = &result;
+            $crate::trace::record($name, args, format!("{:?}", $summary));
+        }
+        result
+    }};
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    windows
+))]
+pub(crate) use trace_call;
+
+#[cfg(all(
+    feature = "api-trace",
+    any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        windows
+    )
+))]
+pub(crate) use self::ring::record;
+#[cfg(feature = "api-trace")]
+pub use self::ring::{take_api_trace, ApiCall};
+
+#[cfg(feature = "api-trace")]
+mod ring {
+    use std::collections::VecDeque;
+    use std::fmt::{Display, Formatter};
+    use std::sync::{Mutex, OnceLock};
+    use std::time::{Duration, Instant};
+
+    /// The number of calls kept, older calls are dropped.
+    const CAPACITY: usize = 4096;
+
+    static CALLS: Mutex<VecDeque<ApiCall>> = Mutex::new(VecDeque::new());
+    static START: OnceLock<Instant> = OnceLock::new();
+
+    /// A native call recorded with the `api-trace` feature.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct ApiCall {
+        /// The time of the call since the first recorded call.
+        pub time: Duration,
+        /// The name of the thread that made the call.
+        pub thread: Option<String>,
+        /// The name of the native function, e.g. `snd_pcm_writei`.
+        pub name: &'static str,
+        /// The `Debug` output of the arguments.
+        pub args: String,
+        /// The `Debug` output of the result.
+        pub result: String,
+    }
+
+    impl Display for ApiCall {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            write!(
+                f,
+                "[{:>12.6}s {}] {}{} -> {}",
+                self.time.as_secs_f64(),
+                self.thread.as_deref().unwrap_or("?"),
+                self.name,
+                self.args,
+                self.result
+            )
+        }
+    }
+
+    // Hosts without traced calls leave the trace empty.
+    #[cfg_attr(
+        not(any(
+            target_os = "linux",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            windows
+        )),
+        allow(dead_code)
+    )]
+    pub(crate) fn record(name: &'static str, args: String, result: String) {
+        let call = ApiCall {
+            time: START.get_or_init(Instant::now).elapsed(),
+            thread: std::thread::current().name().map(str::to_owned),
+            name,
+            args,
+            result,
+        };
+        let mut calls = CALLS.lock().unwrap_or_else(|e| e.into_inner());
+        if calls.len() == CAPACITY {
+            calls.pop_front();
+        }
+        calls.push_back(call);
+    }
+
+    /// Take the recorded native calls, oldest first, and clear the trace.
+    ///
+    /// Only the most recent 4096 calls are kept.
+    pub fn take_api_trace() -> Vec<ApiCall> {
+        let mut calls = CALLS.lock().unwrap_or_else(|e| e.into_inner());
+        calls.drain(..).collect()
+    }
+}
@@ -3,10 +3,11 @@
 use std::time::Duration;
 
 use crate::{
-    BuildStreamError, Data, DefaultStreamConfigError, DeviceNameError, DevicesError,
-    InputCallbackInfo, InputDevices, OutputCallbackInfo, OutputDevices, PauseStreamError,
-    PlayStreamError, SampleFormat, SizedSample, StreamConfig, StreamError, SupportedStreamConfig,
-    SupportedStreamConfigRange, SupportedStreamConfigsError,
+    BuildStreamError, Capabilities, ConfigSupport, Data, DefaultStreamConfigError, DeviceNameError,
+    DeviceRole, DevicesError, FromSample, InputCallbackInfo, InputDevices, OutputCallbackInfo,
+    OutputDevices, PauseStreamError, PlayStreamError, SampleFormat, ShareMode, SizedSample,
+    StreamConfig, StreamError, SupportedStreamConfig, SupportedStreamConfigRange,
+    SupportedStreamConfigsError,
 };
 
 /// A [`Host`] provides access to the available audio devices on the system.
@@ -54,6 +55,22 @@ pub trait HostTrait {
     /// Returns `None` if no output device is available.
     fn default_output_device(&self) -> Option<Self::Device>;
 
+    /// The default input device for `role`, e.g. the headset a VoIP application should record
+    /// from with [`DeviceRole::Communications`].
+    ///
+    /// Hosts without per-role defaults return the [`default_input_device`](Self::default_input_device).
+    fn default_input_device_for_role(&self, role: DeviceRole) -> Option<Self::Device> {
+        let _ = role;
+        self.default_input_device()
+    }
+
+    /// The default output device for `role`, see
+    /// [`default_input_device_for_role`](Self::default_input_device_for_role).
+    fn default_output_device_for_role(&self, role: DeviceRole) -> Option<Self::Device> {
+        let _ = role;
+        self.default_output_device()
+    }
+
     /// An iterator yielding all `Device`s currently available to the system that support one or more
     /// input stream formats.
     ///
@@ -81,6 +98,39 @@ pub trait HostTrait {
         }
         Ok(self.devices()?.filter(supports_output::<Self::Device>))
     }
+
+    /// The optional features the host supports on at least some of its devices.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    /// The version of the audio system behind the host, e.g. of alsa-lib and the ALSA driver, if
+    /// the host can find it out.
+    ///
+    /// The format is meant for logging and differs between hosts.
+    fn version(&self) -> Option<String> {
+        None
+    }
+
+    /// Whether `device` is the default input device, e.g. to mark it in a device picker.
+    ///
+    /// Devices are compared by their [`id`](DeviceTrait::id).
+    fn is_default_input_device(&self, device: &Self::Device) -> bool {
+        is_same_device(self.default_input_device(), device)
+    }
+
+    /// Whether `device` is the default output device, see
+    /// [`is_default_input_device`](Self::is_default_input_device).
+    fn is_default_output_device(&self, device: &Self::Device) -> bool {
+        is_same_device(self.default_output_device(), device)
+    }
+}
+
+fn is_same_device<D: DeviceTrait>(default: Option<D>, device: &D) -> bool {
+    match (default.map(|default| default.id()), device.id()) {
+        (Some(Ok(default)), Ok(id)) => default == id,
+        _ => false,
+    }
 }
 
 /// A device that is capable of audio input and/or output.
@@ -101,6 +151,14 @@ pub trait DeviceTrait {
     /// The human-readable name of the device.
     fn name(&self) -> Result<String, DeviceNameError>;
 
+    /// An identifier of the device that is stable while it is connected, e.g. to remember the
+    /// device a user picked. Not meant to be shown to users.
+    ///
+    /// This is the endpoint ID on WASAPI and the [`name`](Self::name) on other hosts.
+    fn id(&self) -> Result<String, DeviceNameError> {
+        self.name()
+    }
+
     /// An iterator yielding formats that are supported by the backend.
     ///
     /// Can return an error if the device is no longer valid (e.g. it has been disconnected).
@@ -115,12 +173,141 @@ pub trait DeviceTrait {
         &self,
     ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError>;
 
+    /// The input stream formats supported by the device in the given share mode.
+    ///
+    /// On hosts without an exclusive mode, or where it doesn't change the formats streams can
+    /// use, this returns the same as [`supported_input_configs`](Self::supported_input_configs)
+    /// for both modes.
+    fn supported_input_configs_with_mode(
+        &self,
+        mode: ShareMode,
+    ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
+        let _ = mode;
+        self.supported_input_configs()
+    }
+
+    /// The output stream formats supported by the device in the given share mode.
+    ///
+    /// On hosts without an exclusive mode, or where it doesn't change the formats streams can
+    /// use, this returns the same as [`supported_output_configs`](Self::supported_output_configs)
+    /// for both modes.
+    fn supported_output_configs_with_mode(
+        &self,
+        mode: ShareMode,
+    ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError> {
+        let _ = mode;
+        self.supported_output_configs()
+    }
+
     /// The default input stream format for the device.
     fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError>;
 
     /// The default output stream format for the device.
     fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError>;
 
+    /// Check whether an input stream can be built with `config` and `sample_format`, without
+    /// opening the device, e.g. to validate a settings screen.
+    ///
+    /// Returns the closest supported configuration if it can't. The check runs against
+    /// [`supported_input_configs`](Self::supported_input_configs), which hosts answer with
+    /// `IsFormatSupported` on WASAPI and a `hw_params` test on ALSA.
+    fn check_input_config(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+    ) -> Result<ConfigSupport, SupportedStreamConfigsError> {
+        Ok(ConfigSupport::of(
+            self.supported_input_configs()?,
+            config,
+            sample_format,
+        ))
+    }
+
+    /// Check whether an output stream can be built with `config` and `sample_format`, see
+    /// [`check_input_config`](Self::check_input_config).
+    fn check_output_config(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+    ) -> Result<ConfigSupport, SupportedStreamConfigsError> {
+        Ok(ConfigSupport::of(
+            self.supported_output_configs()?,
+            config,
+            sample_format,
+        ))
+    }
+
+    /// The optional features the device supports.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    /// Whether the device is held exclusively, e.g. by another application in WASAPI exclusive
+    /// mode or CoreAudio hog mode, so building a stream on it would fail. `None` where the host
+    /// can't tell.
+    ///
+    /// On ALSA a device is held exclusively while any stream, including one of this process,
+    /// has it open, which is the case for all `hw:` devices.
+    fn is_held_exclusively(&self) -> Option<bool> {
+        None
+    }
+
+    /// A copy of the device whose output streams take the most direct path to the hardware, or
+    /// `None` if the host can't guarantee that samples reach it unchanged.
+    ///
+    /// That is exclusive mode without format conversion on WASAPI, hog mode on CoreAudio, `hw:`
+    /// devices on ALSA and any ASIO device. See
+    /// [`build_bit_perfect_output_stream`](Self::build_bit_perfect_output_stream).
+    fn bit_perfect(&self) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
+    /// Create an output stream that feeds its samples to the hardware unchanged, for bit-perfect
+    /// playback, and return it with the format the hardware is fed.
+    ///
+    /// The stream is built on the [`bit_perfect`](Self::bit_perfect) copy of the device and
+    /// only if the device supports `config` with the sample format `T` natively, so no resampling,
+    /// remixing or sample format conversion takes place. Fails with
+    /// [`BuildStreamError::StreamConfigNotSupported`] otherwise. cpal itself leaves the samples
+    /// alone as long as the stream's volume, gains and format are not changed.
+    fn build_bit_perfect_output_stream<T, D, E>(
+        &self,
+        config: &StreamConfig,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<(Self::Stream, SupportedStreamConfig), BuildStreamError>
+    where
+        Self: Sized,
+        T: SizedSample,
+        D: FnMut(&mut [T], &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let device = self
+            .bit_perfect()
+            .ok_or(BuildStreamError::StreamConfigNotSupported)?;
+        let supported = device
+            .supported_output_configs_with_mode(ShareMode::Exclusive)
+            .map_err(build_error)?
+            .find(|range| {
+                range.channels == config.channels
+                    && range.sample_format == T::FORMAT
+                    && range.min_sample_rate <= config.sample_rate
+                    && config.sample_rate <= range.max_sample_rate
+            })
+            .ok_or(BuildStreamError::StreamConfigNotSupported)?;
+        let fed = SupportedStreamConfig {
+            channels: config.channels,
+            sample_rate: config.sample_rate,
+            buffer_size: supported.buffer_size,
+            sample_format: T::FORMAT,
+        };
+        let stream = device.build_output_stream(config, data_callback, error_callback, None)?;
+        Ok((stream, fed))
+    }
+
     /// Create an input stream.
     fn build_input_stream<T, D, E>(
         &self,
@@ -177,6 +364,79 @@ pub trait DeviceTrait {
         )
     }
 
+    /// Create an input stream that passes samples of type `T` to the data callback even if the
+    /// device doesn't support that sample format.
+    ///
+    /// The device is opened in `T`'s format if it supports it with `config`'s channels and sample
+    /// rate, otherwise in `f32` or another format it does support, and the samples are converted
+    /// on the audio thread. Fails with [`BuildStreamError::StreamConfigNotSupported`] if the
+    /// device supports none with those channels and sample rate.
+    fn build_input_stream_converted<T, D, E>(
+        &self,
+        config: &StreamConfig,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        T: SizedSample + FromSample<f32> + Send + 'static,
+        D: FnMut(&[T], &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let ranges: Vec<_> = self
+            .supported_input_configs()
+            .map_err(build_error)?
+            .collect();
+        match crate::convert::native_format(&ranges, config, T::FORMAT) {
+            Some(format) if format == T::FORMAT => {
+                self.build_input_stream(config, data_callback, error_callback, timeout)
+            }
+            Some(format) => self.build_input_stream_raw(
+                config,
+                format,
+                crate::convert::input_samples(&ranges, config, format, data_callback),
+                error_callback,
+                timeout,
+            ),
+            None => Err(BuildStreamError::StreamConfigNotSupported),
+        }
+    }
+
+    /// Create an output stream whose data callback renders samples of type `T` even if the
+    /// device doesn't support that sample format, see
+    /// [`build_input_stream_converted`](Self::build_input_stream_converted).
+    fn build_output_stream_converted<T, D, E>(
+        &self,
+        config: &StreamConfig,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        T: SizedSample + Send + 'static,
+        f32: FromSample<T>,
+        D: FnMut(&mut [T], &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let ranges: Vec<_> = self
+            .supported_output_configs()
+            .map_err(build_error)?
+            .collect();
+        match crate::convert::native_format(&ranges, config, T::FORMAT) {
+            Some(format) if format == T::FORMAT => {
+                self.build_output_stream(config, data_callback, error_callback, timeout)
+            }
+            Some(format) => self.build_output_stream_raw(
+                config,
+                format,
+                crate::convert::output_samples(&ranges, config, format, data_callback),
+                error_callback,
+                timeout,
+            ),
+            None => Err(BuildStreamError::StreamConfigNotSupported),
+        }
+    }
+
     /// Create a dynamically typed input stream.
     fn build_input_stream_raw<D, E>(
         &self,
@@ -204,6 +464,16 @@ pub trait DeviceTrait {
         E: FnMut(StreamError) + Send + 'static;
 }
 
+fn build_error(err: SupportedStreamConfigsError) -> BuildStreamError {
+    match err {
+        SupportedStreamConfigsError::DeviceNotAvailable => BuildStreamError::DeviceNotAvailable,
+        SupportedStreamConfigsError::InvalidArgument => BuildStreamError::InvalidArgument,
+        SupportedStreamConfigsError::BackendSpecific { err } => {
+            BuildStreamError::BackendSpecific { err }
+        }
+    }
+}
+
 /// A stream created from [`Device`](DeviceTrait), with methods to control playback.
 pub trait StreamTrait {
     /// Run the stream.
@@ -218,4 +488,17 @@ pub trait StreamTrait {
     /// Note: Not all devices support suspending the stream at the hardware level. This method may
     /// fail in these cases.
     fn pause(&self) -> Result<(), PauseStreamError>;
+
+    /// Pause the stream and release the device, so other applications, e.g. one that wants
+    /// exclusive access, can use it and the hardware can power down.
+    ///
+    /// Unlike [`pause`](Self::pause), which keeps the stream set up for an instant resume,
+    /// [`play`](Self::play) has to acquire the device again after this, which takes longer and
+    /// reports an error through the error callback if the device is no longer available.
+    ///
+    /// Hosts that cannot release the device while keeping the stream only pause it, which is
+    /// the default.
+    fn suspend(&self) -> Result<(), PauseStreamError> {
+        self.pause()
+    }
 }
@@ -0,0 +1,163 @@
+//! A stream whose play state is part of its type, see [`Voice`].
+
+use std::marker::PhantomData;
+
+use crate::traits::StreamTrait;
+use crate::{BuildStreamError, PauseStreamError, PlayStreamError, Stream};
+
+/// The state of a [`Voice`] that is paused.
+#[derive(Debug)]
+pub struct Paused;
+
+/// The state of a [`Voice`] that is playing.
+#[derive(Debug)]
+pub struct Playing;
+
+/// A [`Stream`] that tracks whether it plays in its type, for users who opt in to having a
+/// forgotten [`play`](Voice::play) caught by the compiler.
+///
+/// A `Voice<Paused>` is built from a stream and must be turned into a `Voice<Playing>` before it
+/// can be handed to code that expects the audio to run:
+///
+/// ```no_run
+/// # use cpal::traits::{DeviceTrait, HostTrait};
+/// # use cpal::voice::{Paused, Playing, Voice};
+/// # let device = cpal::default_host().default_output_device().unwrap();
+/// # let config = device.default_output_config().unwrap().config();
+/// fn keep_playing(voice: Voice<Playing>) { /* ... */ }
+///
+/// let stream = device.build_output_stream(
+///     &config,
+///     |data: &mut [f32], _: &cpal::OutputCallbackInfo| data.fill(0.0),
+///     |err| eprintln!("{}", err),
+///     None,
+/// )?;
+/// let voice: Voice<Paused> = Voice::new(stream)?;
+/// keep_playing(voice.play()?);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[must_use = "If the voice is not stored it will not play."]
+pub struct Voice<S> {
+    stream: Stream,
+    state: PhantomData<S>,
+}
+
+impl<S> Voice<S> {
+    /// The stream of the voice, e.g. to read its statistics or set its volume.
+    ///
+    /// Calling [`StreamTrait::play`] or [`StreamTrait::pause`] on it bypasses the voice's state.
+    pub fn stream(&self) -> &Stream {
+        &self.stream
+    }
+
+    /// Release the stream of the voice.
+    pub fn into_stream(self) -> Stream {
+        self.stream
+    }
+
+    /// Move the voice to the current default output device if its device was lost, keeping
+    /// its state, see [`Stream::recover`]. Returns whether the voice moved.
+    pub fn recover(&mut self) -> Result<bool, BuildStreamError> {
+        self.stream.recover()
+    }
+
+    fn with_state<T>(self) -> Voice<T> {
+        Voice {
+            stream: self.stream,
+            state: PhantomData,
+        }
+    }
+}
+
+impl Voice<Paused> {
+    /// Wrap `stream`, pausing it since some hosts start streams as soon as they are built.
+    pub fn new(stream: Stream) -> Result<Self, PauseStreamError> {
+        stream.pause()?;
+        Ok(Voice {
+            stream,
+            state: PhantomData,
+        })
+    }
+
+    /// Play the voice. The stream is dropped if it fails to play.
+    pub fn play(self) -> Result<Voice<Playing>, PlayStreamError> {
+        self.stream.play()?;
+        Ok(self.with_state())
+    }
+
+    /// Play voices of the same host so that their first frames are played at the same time,
+    /// see [`Stream::play_synchronized`]. The streams are dropped if they fail to play.
+    pub fn play_synchronized(voices: Vec<Self>) -> Result<Vec<Voice<Playing>>, PlayStreamError> {
+        let streams: Vec<_> = voices.iter().map(|voice| &voice.stream).collect();
+        Stream::play_synchronized(&streams)?;
+        Ok(voices.into_iter().map(Voice::with_state).collect())
+    }
+}
+
+impl Voice<Playing> {
+    /// Pause the voice. The stream is dropped if it fails to pause.
+    pub fn pause(self) -> Result<Voice<Paused>, PauseStreamError> {
+        self.stream.pause()?;
+        Ok(self.with_state())
+    }
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn test_voice_play_pause() {
+    use crate::traits::{DeviceTrait, HostTrait};
+
+    let host = crate::platform::MockHost::new().unwrap();
+    let device = crate::Device::from(host.default_output_device().unwrap());
+    let config = device.default_output_config().unwrap().config();
+    let stream = device
+        .build_output_stream(
+            &config,
+            |data: &mut [f32], _: &crate::OutputCallbackInfo| data.fill(0.5),
+            |_| (),
+            None,
+        )
+        .unwrap();
+    let render = |stream: &Stream| {
+        let crate::platform::StreamInner::Mock(mock) = stream.as_inner() else {
+            unreachable!()
+        };
+        mock.render::<f32>(1)
+    };
+    let voice = Voice::new(stream).unwrap();
+    assert_eq!(render(voice.stream()), None);
+    let voice = voice.play().unwrap();
+    assert_eq!(render(voice.stream()).unwrap()[0], 0.5);
+    let voice = voice.pause().unwrap();
+    assert_eq!(render(voice.stream()), None);
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn test_voice_recover() {
+    use crate::traits::{DeviceTrait, HostTrait};
+
+    let host = crate::host_from_id(crate::HostId::Mock).unwrap();
+    let device = host.default_output_device().unwrap();
+    let config = device.default_output_config().unwrap().config();
+    let stream = device
+        .build_output_stream(
+            &config,
+            |data: &mut [f32], _: &crate::OutputCallbackInfo| data.fill(0.5),
+            |_| (),
+            None,
+        )
+        .unwrap();
+    fn mock(stream: &Stream) -> &crate::platform::MockStream {
+        let crate::platform::StreamInner::Mock(mock) = stream.as_inner() else {
+            unreachable!()
+        };
+        mock
+    }
+    let mut voice = Voice::new(stream).unwrap().play().unwrap();
+    assert!(!voice.recover().unwrap());
+    mock(voice.stream()).inject_error(crate::StreamError::DeviceNotAvailable);
+    assert!(voice.recover().unwrap());
+    assert!(!voice.recover().unwrap());
+    assert_eq!(mock(voice.stream()).render::<f32>(1).unwrap()[0], 0.5);
+}
@@ -0,0 +1,131 @@
+//! The software volume of output streams, see [`Stream::set_volume`](crate::Stream::set_volume).
+//!
+//! The volume and the gain of each channel are applied to the output of the data callback on the
+//! audio thread, after the samples were rendered in the stream's format.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use crate::{Data, FromSample, OutputCallbackInfo, SampleFormat, SizedSample};
+
+/// The volume and per-channel gains of a stream, shared with its audio thread.
+#[derive(Debug, Default)]
+pub(crate) struct Volume {
+    /// The volume as `f32` bits.
+    volume: AtomicU32,
+    /// The gain of each channel as `f32` bits.
+    gains: Box<[AtomicU32]>,
+}
+
+impl Volume {
+    pub(crate) fn new(channels: u16) -> Arc<Self> {
+        Arc::new(Volume {
+            volume: AtomicU32::new(1f32.to_bits()),
+            gains: (0..channels)
+                .map(|_| AtomicU32::new(1f32.to_bits()))
+                .collect(),
+        })
+    }
+
+    pub(crate) fn set_volume(&self, volume: f32) {
+        self.volume
+            .store(volume.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Set the gain of each channel, channels beyond `gains` get a gain of `1.0`.
+    pub(crate) fn set_channel_gains(&self, gains: &[f32]) {
+        for (channel, gain) in self.gains.iter().enumerate() {
+            let value = gains.get(channel).copied().unwrap_or(1.0).max(0.0);
+            gain.store(value.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    /// Set the gains of the first two channels for a balance between `-1.0`, left only, and
+    /// `1.0`, right only. The louder side keeps its full level.
+    pub(crate) fn set_balance(&self, balance: f32) {
+        let balance = balance.clamp(-1.0, 1.0);
+        self.set_channel_gains(&[(1.0 - balance).min(1.0), (1.0 + balance).min(1.0)]);
+    }
+
+    /// Wrap an output data callback to apply the volume and gains to its output.
+    pub(crate) fn output_callback<D>(
+        self: &Arc<Self>,
+        mut data_callback: D,
+    ) -> impl FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+    {
+        let volume = self.clone();
+        let mut factors = Vec::with_capacity(self.gains.len());
+        move |data, info| {
+            data_callback(data, info);
+            let level = f32::from_bits(volume.volume.load(Ordering::Relaxed));
+            factors.clear();
+            factors.extend(
+                volume
+                    .gains
+                    .iter()
+                    .map(|gain| level * f32::from_bits(gain.load(Ordering::Relaxed))),
+            );
+            if factors.iter().any(|&factor| factor != 1.0) {
+                apply(data, &factors);
+            }
+        }
+    }
+}
+
+/// Scale each interleaved sample by the factor of its channel.
+fn apply(data: &mut Data, factors: &[f32]) {
+    fn scale<T>(data: &mut Data, factors: &[f32])
+    where
+        T: SizedSample + FromSample<f32>,
+        f32: FromSample<T>,
+    {
+        if let Some(samples) = data.as_slice_mut::<T>() {
+            for frame in samples.chunks_mut(factors.len()) {
+                for (sample, &factor) in frame.iter_mut().zip(factors) {
+                    *sample = T::from_sample(sample.to_sample::<f32>() * factor);
+                }
+            }
+        }
+    }
+    if factors.is_empty() {
+        return;
+    }
+    match data.sample_format() {
+        SampleFormat::I8 => scale::<i8>(data, factors),
+        SampleFormat::I16 => scale::<i16>(data, factors),
+        SampleFormat::I32 => scale::<i32>(data, factors),
+        SampleFormat::I64 => scale::<i64>(data, factors),
+        SampleFormat::U8 => scale::<u8>(data, factors),
+        SampleFormat::U16 => scale::<u16>(data, factors),
+        SampleFormat::U32 => scale::<u32>(data, factors),
+        SampleFormat::U64 => scale::<u64>(data, factors),
+        SampleFormat::F32 => scale::<f32>(data, factors),
+        SampleFormat::F64 => scale::<f64>(data, factors),
+    }
+}
+
+#[test]
+fn test_balance() {
+    let volume = Volume::new(2);
+    volume.set_volume(0.5);
+    volume.set_balance(0.5);
+    let mut callback = volume.output_callback(|data: &mut Data, _: &OutputCallbackInfo| {
+        data.as_slice_mut::<i16>().unwrap().fill(1000);
+    });
+    let mut samples = [0i16; 4];
+    let mut data = unsafe {
+        Data::from_parts(
+            samples.as_mut_ptr() as *mut (),
+            samples.len(),
+            SampleFormat::I16,
+        )
+    };
+    let timestamp = crate::OutputStreamTimestamp {
+        callback: crate::StreamInstant::new(0, 0),
+        playback: crate::StreamInstant::new(0, 0),
+    };
+    callback(&mut data, &OutputCallbackInfo { timestamp });
+    assert_eq!(samples, [250, 500, 250, 500]);
+}